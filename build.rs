@@ -0,0 +1,13 @@
+// "rls" フィーチャーが有効な場合のみ、Envoy RLS の gRPC コードを生成する
+//
+// NGINXモジュール本体のビルドには protoc を必要としない
+fn main() {
+    #[cfg(feature = "rls")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/envoy/service/ratelimit/v3/rls.proto"], &["proto"])
+            .expect("Failed to compile Envoy RLS protobuf definitions");
+    }
+}
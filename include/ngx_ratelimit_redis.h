@@ -0,0 +1,45 @@
+/*
+ * C API of ngx_ratelimit_redis for other NGINX modules.
+ *
+ * The functions are exported by the ngx_http_ratelimit_redis module, which
+ * must be loaded before the calling module.
+ */
+
+#ifndef _NGX_RATELIMIT_REDIS_H_INCLUDED_
+#define _NGX_RATELIMIT_REDIS_H_INCLUDED_
+
+#include <stdint.h>
+#include <ngx_http.h>
+
+#define NGX_RATELIMIT_REDIS_ALLOWED   0
+#define NGX_RATELIMIT_REDIS_LIMITED   1
+#define NGX_RATELIMIT_REDIS_ERROR    -1
+
+typedef struct {
+    int       allowed;    /* 1 if the request was allowed */
+    int       banned;     /* 1 if the key or client IP is banned */
+    int       fallback;   /* 1 if Redis failed and the request was allowed */
+    uint32_t  limit;      /* applied rate (requests per second) */
+    uint64_t  remaining;  /* requests left in the current window/bucket */
+    uint64_t  reset;      /* seconds until the limit is fully restored */
+} ngx_ratelimit_redis_decision_t;
+
+/*
+ * Counts a request for key against the limits of zone (a location of the
+ * ratelimit_redis configuration; NULL for the default limits) and stores the
+ * decision in the request context. Blocks the worker while Redis answers.
+ *
+ * Returns NGX_RATELIMIT_REDIS_ALLOWED, NGX_RATELIMIT_REDIS_LIMITED or
+ * NGX_RATELIMIT_REDIS_ERROR (invalid arguments).
+ */
+int ngx_ratelimit_redis_check(ngx_http_request_t *r, const char *key,
+    const char *zone);
+
+/*
+ * Copies the last decision made by ngx_ratelimit_redis_check for this request.
+ * Returns NGX_RATELIMIT_REDIS_ERROR if there is none.
+ */
+int ngx_ratelimit_redis_last_decision(ngx_http_request_t *r,
+    ngx_ratelimit_redis_decision_t *decision);
+
+#endif /* _NGX_RATELIMIT_REDIS_H_INCLUDED_ */
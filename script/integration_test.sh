@@ -0,0 +1,526 @@
+#!/bin/bash
+
+# RedisをDockerコンテナで起動し、全アルゴリズムをNGINX経由でエンドツーエンドに検証する統合テスト
+#
+# スタンドアロンとクラスタのRedisそれぞれに対して、次を確認する
+#   - バースト: 連続したリクエストのうち許可される数
+#   - 同時実行: 並列のクライアントから同じキーに送っても上限を超えて許可しないこと
+#   - 複数ワーカー: 複数のワーカープロセスに分散したリクエストでも、上限がワーカーごとではなく
+#     全体で適用されること
+#
+# 時刻に依存する次の項目は、ngx-ratelimit-ctl simulate で時計（ManualClock）を進めながら
+# スタンドアロンのRedisに対して確認する（実際の時間の経過は待たない）
+#   - ウィンドウの境界: 上限に達したキーが、次のウィンドウ（またはバケットの回復）の
+#     1ミリ秒前には拒否され、その時刻に再び許可されること
+#   - スライディングウィンドウの精度: 前のウィンドウのカウントで重み付けした上限を、
+#     同時の判定でも超えないこと
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+IMAGE_NAME="ngx-ratelimit-redis"
+NETWORK="ngx-ratelimit-it-$$"
+REDIS_CONTAINER="ngx-ratelimit-it-redis-$$"
+CLUSTER_CONTAINER="ngx-ratelimit-it-cluster-$$"
+NGINX_CONTAINER="ngx-ratelimit-it-nginx-$$"
+REDIS_IMAGE="redis:7-alpine"
+CLUSTER_IMAGE="grokzen/redis-cluster:7.0.10"
+PORT=18080
+# ngx-ratelimit-ctl から接続するスタンドアロンのRedisのポート
+REDIS_PORT=16391
+
+# テストで使用する上限
+RATE=1
+BURST=5
+WINDOW=10
+CONCURRENCY=20
+# スライディングウィンドウの精度のテストを繰り返す回数
+ACCURACY_ROUNDS=3
+# NGINXのワーカープロセスの数
+WORKERS=4
+
+ALGORITHMS="fixed_window sliding_window token_bucket leaky_bucket gcra sliding_log"
+BACKENDS="standalone cluster"
+
+CTL=""
+SKIP_BUILD=false
+KEEP=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --skip-build       Use the existing ${IMAGE_NAME} image"
+  echo "  --standalone-only  Do not start a Redis cluster"
+  echo "  --algorithm NAME   Test only one algorithm"
+  echo "  --rounds N         Rounds of the sliding window accuracy test (default: ${ACCURACY_ROUNDS})"
+  echo "  --workers N        NGINX worker processes (default: ${WORKERS})"
+  echo "  --ctl PATH         ngx-ratelimit-ctl binary (default: build target/release/ngx-ratelimit-ctl)"
+  echo "  --keep             Keep the containers running after the tests"
+  echo "  --help             Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --skip-build)
+      SKIP_BUILD=true
+      shift
+      ;;
+    --standalone-only)
+      BACKENDS="standalone"
+      shift
+      ;;
+    --algorithm)
+      ALGORITHMS="$2"
+      shift 2
+      ;;
+    --rounds)
+      ACCURACY_ROUNDS="$2"
+      shift 2
+      ;;
+    --workers)
+      WORKERS="$2"
+      shift 2
+      ;;
+    --ctl)
+      CTL="$2"
+      shift 2
+      ;;
+    --keep)
+      KEEP=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+for cmd in docker curl xargs; do
+  if ! command -v $cmd &> /dev/null; then
+    echo -e "${RED}Error: $cmd is not installed${NC}"
+    exit 1
+  fi
+done
+
+cleanup() {
+  if [ "$KEEP" = true ]; then
+    echo -e "\n${YELLOW}コンテナは実行されたままです: ${NGINX_CONTAINER} ${REDIS_CONTAINER} ${CLUSTER_CONTAINER}${NC}"
+    return
+  fi
+  docker rm -f ${NGINX_CONTAINER} ${REDIS_CONTAINER} ${CLUSTER_CONTAINER} &> /dev/null
+  docker network rm ${NETWORK} &> /dev/null
+}
+trap cleanup EXIT
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+# 許可された数が期待する範囲に収まっているかを確認する
+expect_range() {
+  local name=$1
+  local actual=$2
+  local min=$3
+  local max=$4
+  if [ "$actual" -ge "$min" ] && [ "$actual" -le "$max" ]; then
+    pass "${name}: ${actual} allowed (expected ${min}-${max})"
+  else
+    fail "${name}: ${actual} allowed (expected ${min}-${max})"
+  fi
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}Redisレートリミット 統合テスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+# Dockerイメージのビルド
+if [ "$SKIP_BUILD" = false ]; then
+  echo -e "${BLUE}Dockerイメージをビルドしています...${NC}"
+  docker build -t ${IMAGE_NAME} . || exit 1
+fi
+
+if [ -z "$CTL" ]; then
+  echo -e "${BLUE}ngx-ratelimit-ctl をビルドしています...${NC}"
+  cargo build --release --quiet --features lib --bin ngx-ratelimit-ctl || exit 1
+  CTL="$(pwd)/target/release/ngx-ratelimit-ctl"
+fi
+
+docker network create ${NETWORK} > /dev/null || exit 1
+
+# Redisの起動
+echo -e "\n${BLUE}Redisコンテナを起動しています...${NC}"
+docker run -d --name ${REDIS_CONTAINER} --network ${NETWORK} --network-alias redis \
+  -p ${REDIS_PORT}:6379 ${REDIS_IMAGE} > /dev/null || exit 1
+if [[ " $BACKENDS " == *" cluster "* ]]; then
+  docker run -d --name ${CLUSTER_CONTAINER} --network ${NETWORK} --network-alias redis-cluster \
+    -e IP=0.0.0.0 ${CLUSTER_IMAGE} > /dev/null || exit 1
+fi
+
+# Redisが応答するまで待機
+wait_for_redis() {
+  local container=$1
+  local port=$2
+  for _ in $(seq 1 30); do
+    if docker exec "$container" redis-cli -p "$port" ping 2> /dev/null | grep -q PONG; then
+      return 0
+    fi
+    sleep 1
+  done
+  echo -e "${RED}Redis in ${container} did not become ready${NC}"
+  docker logs "$container"
+  exit 1
+}
+wait_for_redis ${REDIS_CONTAINER} 6379
+if [[ " $BACKENDS " == *" cluster "* ]]; then
+  wait_for_redis ${CLUSTER_CONTAINER} 7000
+  # スロットの割り当てが終わるまで待機
+  for _ in $(seq 1 30); do
+    if docker exec ${CLUSTER_CONTAINER} redis-cli -p 7000 cluster info 2> /dev/null | grep -q "cluster_state:ok"; then
+      break
+    fi
+    sleep 1
+  done
+fi
+
+# アルゴリズムとRedisの組み合わせごとにLocationを生成する
+CONF_FILE=$(mktemp)
+{
+  echo "worker_processes ${WORKERS};"
+  echo "error_log /dev/stderr info;"
+  echo "events { worker_connections 1024; }"
+  echo "load_module modules/libngx_ratelimit_redis.so;"
+  echo "http {"
+  echo "    ratelimit_redis_check on;"
+  echo "    server {"
+  # reuseport でワーカーごとにソケットを持たせ、接続を全ワーカーに分散させる
+  echo "        listen 8080 reuseport;"
+  # 応答したワーカーを識別するため、拒否した応答にもPIDを付与する
+  echo "        add_header X-Worker-Pid \$pid always;"
+  for backend in $BACKENDS; do
+    if [ "$backend" = "cluster" ]; then
+      redis="redis_url=redis://redis-cluster:7000 redis_cluster_mode=on"
+    else
+      redis="redis_url=redis://redis:6379"
+    fi
+    for algorithm in $ALGORITHMS; do
+      echo "        location /${backend}/${algorithm} {"
+      echo "            ratelimit_redis on ${redis} key=http_x_test_key algorithm=${algorithm} rate=${RATE} burst=${BURST} window_size=${WINDOW};"
+      echo "            root /usr/share/nginx/html;"
+      echo "            try_files /index.html =404;"
+      echo "        }"
+    done
+  done
+  echo "    }"
+  echo "}"
+} > "$CONF_FILE"
+chmod 644 "$CONF_FILE"
+
+# NGINXの起動
+echo -e "\n${BLUE}NGINXコンテナを起動しています...${NC}"
+docker run -d --name ${NGINX_CONTAINER} --network ${NETWORK} -p ${PORT}:8080 \
+  -v "${CONF_FILE}:/etc/nginx/nginx.conf:ro" ${IMAGE_NAME} > /dev/null || exit 1
+
+for _ in $(seq 1 30); do
+  if curl -s -o /dev/null http://localhost:${PORT}/ 2> /dev/null; then
+    break
+  fi
+  sleep 1
+done
+if ! curl -s -o /dev/null http://localhost:${PORT}/; then
+  echo -e "${RED}NGINXからの応答がありません。コンテナログを確認してください:${NC}"
+  docker logs ${NGINX_CONTAINER}
+  exit 1
+fi
+
+# 1件送信してステータスコードを出力する
+request() {
+  curl -s -o /dev/null -w "%{http_code}\n" -H "X-Test-Key: $2" "http://localhost:${PORT}$1"
+}
+export -f request
+export PORT
+
+# 1件送信してステータスコードと応答したワーカーのPIDを出力する
+request_worker() {
+  curl -s -o /dev/null -D - -H "X-Test-Key: $2" "http://localhost:${PORT}$1" |
+    awk 'tolower($1) == "x-worker-pid:" { pid = $2 } /^HTTP\// { code = $2 } END { gsub(/\r/, "", pid); print code, pid }'
+}
+export -f request_worker
+
+# 1件送信して応答のステータス行とヘッダーを出力する
+request_headers() {
+  curl -s -o /dev/null -D - -H "X-Test-Key: $2" "http://localhost:${PORT}$1" | tr -d '\r'
+}
+
+# 連続して n 件送信し、許可された数を出力する
+sequential() {
+  local path=$1
+  local key=$2
+  local n=$3
+  for _ in $(seq 1 "$n"); do
+    request "$path" "$key"
+  done | grep -c "^200$"
+}
+
+# 並列に n 件送信し、許可された数を出力する
+concurrent() {
+  local path=$1
+  local key=$2
+  local n=$3
+  seq 1 "$n" | xargs -P ${CONCURRENCY} -I{} bash -c 'request "$0" "$1"' "$path" "$key" | grep -c "^200$"
+}
+
+# 次のウィンドウの開始直後まで待つ（テストの途中でウィンドウが切り替わらないようにする）
+wait_for_window_start() {
+  local now=$(date +%s)
+  local wait=$(( WINDOW - now % WINDOW ))
+  sleep $wait
+}
+
+# ウィンドウを使うアルゴリズムでは、次のウィンドウの開始まで待つ
+align_window() {
+  case $1 in
+    fixed_window|sliding_window) wait_for_window_start ;;
+  esac
+}
+
+# 新しいキーで、連続したリクエストのうち最初に許可される数
+#   fixed_window / sliding_window / sliding_log: rate + burst
+#   token_bucket: burst + 1（新しいキーの最初のリクエストはトークンを消費しない）
+#   leaky_bucket / gcra: burst
+# NGINX経由のテストでは、送信中に経過した時間で回復する分（rate）を上限の許容幅とする
+expected_burst() {
+  case $1 in
+    fixed_window|sliding_window|sliding_log) echo $((RATE + BURST)) ;;
+    token_bucket) echo $((BURST + 1)) ;;
+    leaky_bucket|gcra) echo ${BURST} ;;
+  esac
+}
+
+# 上限に達したキーがまだ拒否される時刻と、再び許可される時刻（ウィンドウの開始からのミリ秒）
+#
+# 時刻はウィンドウの開始に揃えた ManualClock で進めるため、境界の前後1ミリ秒で確認できる
+recovery_offsets() {
+  case $1 in
+    # 次のウィンドウの開始で / 最も古い記録が窓から外れた時点で許可される
+    fixed_window|sliding_log) echo $((WINDOW * 1000 - 1)) $((WINDOW * 1000)) ;;
+    # 次のウィンドウでは前のウィンドウのカウント（拒否した分を含む）が重みに残るため、
+    # その開始ではまだ拒否され、前のウィンドウが重みに含まれなくなると許可される
+    sliding_window) echo $((WINDOW * 1000)) $((WINDOW * 2000)) ;;
+    # トークン・水位・TATが1つ分回復した時点で許可される
+    token_bucket|leaky_bucket|gcra) echo $((1000 / RATE - 1)) $((1000 / RATE)) ;;
+  esac
+}
+
+# スライディングウィンドウで許可される数の上限: floor(limit - previous × (1 - 経過した割合))
+weighted_limit() {
+  awk -v limit=$1 -v previous=$2 -v elapsed_ms=$3 -v window=${WINDOW} 'BEGIN {
+    ratio = (elapsed_ms % (window * 1000)) / (window * 1000)
+    value = limit - previous * (1 - ratio)
+    print (value < 0 ? 0 : int(value))
+  }'
+}
+
+# スケジュール（"<ミリ秒> <キー>" の行）を標準入力から読み、時計を進めながら判定する
+simulate() {
+  "$CTL" --config "$SIM_CONFIG" --algorithm "$1" simulate -
+}
+
+# 同じ行を n 回出力する（simulate では同じ時刻の連続した行が並行して判定される）
+repeat_line() {
+  for _ in $(seq 1 "$2"); do
+    echo "$1"
+  done
+}
+
+# simulate の出力のうち、指定した時刻とキーで許可された数を出力する
+allowed_at() {
+  echo "$1" | awk -v offset=$2 -v key=$3 '$1 == offset && $2 == key && $3 == "allowed" { n++ } END { print n + 0 }'
+}
+
+# 上限まで送ったキーが、回復の1ミリ秒前（またはウィンドウの開始）にはまだ拒否され、
+# 回復した時刻に再び許可されるかを確認する
+#
+# 確認のリクエスト自体も状態を変える（拒否でも補充の時刻を進めるアルゴリズムがある）ため、
+# 前後の時刻はそれぞれ別のキーで確認する
+recovery_checks() {
+  local algorithm=$1
+  local expected=$(expected_burst $algorithm)
+  local total=$((expected * 3))
+  local offsets=($(recovery_offsets $algorithm))
+  local before="recovery-before-${algorithm}-${RUN_ID}"
+  local after="recovery-after-${algorithm}-${RUN_ID}"
+  local output=$( {
+    repeat_line "0 ${before}" $total
+    repeat_line "0 ${after}" $total
+    echo "${offsets[0]} ${before}"
+    echo "${offsets[1]} ${after}"
+  } | simulate $algorithm)
+  if [ -z "$output" ]; then
+    fail "ngx-ratelimit-ctl simulate returned no decisions"
+    return
+  fi
+
+  expect_range "burst of ${total} concurrent requests at the same instant" \
+    "$(allowed_at "$output" 0 "$before")" "$expected" "$expected"
+  if [ "$(allowed_at "$output" ${offsets[0]} "$before")" = 0 ]; then
+    pass "still limited at +${offsets[0]}ms"
+  else
+    fail "allowed at +${offsets[0]}ms, before the limit recovers"
+  fi
+  if [ "$(allowed_at "$output" ${offsets[1]} "$after")" = 1 ]; then
+    pass "allowed again at +${offsets[1]}ms"
+  else
+    fail "still limited at +${offsets[1]}ms"
+  fi
+}
+
+# 前のウィンドウにカウントが残ったキーを次のウィンドウの中間で同時に判定し、
+# 重み付けした上限ちょうどの数だけ許可されるかを確認する
+#
+# 時刻が固定されているため上限は1つに決まる。同時の判定で重み付けの計算が競合すれば、
+# 上限を超えて許可される
+sliding_window_accuracy() {
+  local limit=$((RATE + BURST))
+  local total=$((limit * 3))
+  local middle=$((WINDOW * 1000 + WINDOW * 500))
+  local expected=$(weighted_limit $limit $limit $middle)
+  for round in $(seq 1 "$ACCURACY_ROUNDS"); do
+    local key="accuracy-${round}-${RUN_ID}"
+    local output=$( {
+      repeat_line "0 ${key}" $limit
+      repeat_line "${middle} ${key}" $total
+    } | simulate sliding_window)
+
+    expect_range "round ${round}: ${limit} concurrent requests in the previous window" \
+      "$(allowed_at "$output" 0 "$key")" $limit $limit
+    expect_range "round ${round}: ${total} concurrent requests weighted by the previous window" \
+      "$(allowed_at "$output" $middle "$key")" "$expected" "$expected"
+  done
+}
+
+# 全ワーカーに分散した並列のリクエストでも、上限が全体で適用されるかを確認する
+#
+# カウンタをワーカーごとに持つ（またはRedisへの反映を遅らせる）実装では、
+# 許可される数がワーカーの数だけ増えるため上限を超える
+multi_worker_consistency() {
+  local path=$1
+  local key=$2
+  local expected=$3
+  local total=$((expected * WORKERS * 2))
+  local results=$(seq 1 "$total" | xargs -P ${CONCURRENCY} -I{} bash -c 'request_worker "$0" "$1"' "$path" "$key")
+
+  local workers=$(echo "$results" | awk '$2 != "" { print $2 }' | sort -u | wc -l)
+  local allowed=$(echo "$results" | grep -c "^200 ")
+  local allowed_by=$(echo "$results" | awk '$1 == 200 { count[$2]++ } END { for (pid in count) { printf "%s%s:%d", sep, pid, count[pid]; sep = " " } }')
+  if [ "$workers" -lt 2 ]; then
+    fail "${total} requests were served by ${workers} worker(s); cannot check limits across workers"
+    return
+  fi
+  expect_range "${total} requests across ${workers} workers (allowed per worker pid: ${allowed_by:-none})" \
+    "$allowed" "$expected" $((expected + RATE))
+}
+
+RUN_ID="$$-$(date +%s)"
+
+for backend in $BACKENDS; do
+  for algorithm in $ALGORITHMS; do
+    path="/${backend}/${algorithm}"
+    expected=$(expected_burst $algorithm)
+    total=$((expected * 3))
+    echo -e "\n${BLUE}${backend} / ${algorithm}${NC}"
+
+    # バースト
+    align_window $algorithm
+    allowed=$(sequential "$path" "burst-${backend}-${algorithm}-${RUN_ID}" $total)
+    expect_range "burst of ${total} sequential requests" "$allowed" "$expected" $((expected + RATE))
+
+    # 同時実行
+    align_window $algorithm
+    allowed=$(concurrent "$path" "concurrent-${backend}-${algorithm}-${RUN_ID}" $total)
+    expect_range "${total} requests from ${CONCURRENCY} concurrent clients" "$allowed" "$expected" $((expected + RATE))
+
+    # 複数ワーカー
+    if [ "$WORKERS" -gt 1 ]; then
+      align_window $algorithm
+      multi_worker_consistency "$path" "workers-${backend}-${algorithm}-${RUN_ID}" "$expected"
+    fi
+
+    # 許可した応答のヘッダー
+    headers=$(request_headers "$path" "headers-${backend}-${algorithm}-${RUN_ID}")
+    if echo "$headers" | grep -qi "^x-ratelimit-remaining: [0-9]" &&
+      echo "$headers" | grep -qi "^x-ratelimit-reset: [0-9]"; then
+      pass "X-RateLimit-Remaining and X-RateLimit-Reset sent with an allowed response"
+    else
+      fail "X-RateLimit-Remaining or X-RateLimit-Reset missing from an allowed response"
+    fi
+
+    # 上限に達した後の拒否（回復は simulate で確認する）
+    key="limited-${backend}-${algorithm}-${RUN_ID}"
+    align_window $algorithm
+    sequential "$path" "$key" $total > /dev/null
+    headers=$(request_headers "$path" "$key")
+    if echo "$headers" | grep -q "^HTTP/[0-9.]* 403"; then
+      pass "limited once the limit is reached"
+    else
+      fail "not limited after ${total} requests"
+    fi
+    if echo "$headers" | grep -qi "^retry-after: [1-9]"; then
+      pass "Retry-After sent with the rejection"
+    else
+      fail "Retry-After missing from the rejection"
+    fi
+  done
+done
+
+# 時刻に依存する判定（ngx-ratelimit-ctl simulate で時計を進める）
+SIM_CONFIG=$(mktemp)
+cat > "$SIM_CONFIG" << EOF
+{
+  "default": {
+    "enabled": true,
+    "redis_url": "redis://127.0.0.1:${REDIS_PORT}",
+    "rate": ${RATE},
+    "burst": ${BURST},
+    "window_size": ${WINDOW}
+  }
+}
+EOF
+
+for algorithm in $ALGORITHMS; do
+  echo -e "\n${BLUE}simulate / ${algorithm}${NC}"
+  recovery_checks $algorithm
+  if [ "$algorithm" = "sliding_window" ]; then
+    sliding_window_accuracy
+  fi
+done
+rm -f "$SIM_CONFIG"
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  echo -e "\n${YELLOW}NGINXのログ:${NC}"
+  docker logs --tail 50 ${NGINX_CONTAINER}
+  exit 1
+fi
@@ -0,0 +1,91 @@
+#!/bin/bash
+
+# 設定ファイルのマージ結果（Locationごとに実際に適用される設定）をゴールデンファイルと比較するテスト
+#
+# script/fixtures/config/*.json と config.json.example を ngx-ratelimit-ctl show-config で展開し、
+# script/fixtures/config/<ファイル名>.golden と一致するかを確認する。
+# マージの仕様を変更した場合は --update でゴールデンファイルを更新し、差分をレビューすること
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+FIXTURES="${ROOT}/script/fixtures/config"
+CTL=""
+UPDATE=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --ctl PATH   ngx-ratelimit-ctl binary (default: build target/release/ngx-ratelimit-ctl)"
+  echo "  --update     Rewrite the golden files with the current output"
+  echo "  --help       Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --ctl)
+      CTL="$2"
+      shift 2
+      ;;
+    --update)
+      UPDATE=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+if [ -z "$CTL" ]; then
+  echo -e "${BLUE}ngx-ratelimit-ctl をビルドしています...${NC}"
+  (cd "$ROOT" && cargo build --release --quiet --features lib --bin ngx-ratelimit-ctl) || exit 1
+  CTL="${ROOT}/target/release/ngx-ratelimit-ctl"
+fi
+
+PASSED=0
+FAILED=0
+
+for fixture in "${FIXTURES}"/*.json "${ROOT}/config.json.example"; do
+  name=$(basename "$fixture" .json)
+  golden="${FIXTURES}/${name}.golden"
+  actual=$("$CTL" show-config "$fixture" 2>&1)
+
+  if [ "$UPDATE" = true ]; then
+    echo "$actual" > "$golden"
+    echo -e "  ${YELLOW}updated ${golden#${ROOT}/}${NC}"
+    continue
+  fi
+
+  if [ ! -f "$golden" ]; then
+    echo -e "  ${RED}✗ ${name}: ${golden#${ROOT}/} does not exist (run with --update)${NC}"
+    FAILED=$((FAILED + 1))
+  elif diff -u "$golden" <(echo "$actual") > /dev/null; then
+    echo -e "  ${GREEN}✓ ${name}${NC}"
+    PASSED=$((PASSED + 1))
+  else
+    echo -e "  ${RED}✗ ${name}${NC}"
+    diff -u --label "${name}.golden" --label "show-config ${name}" "$golden" <(echo "$actual")
+    FAILED=$((FAILED + 1))
+  fi
+done
+
+if [ "$UPDATE" = true ]; then
+  exit 0
+fi
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  exit 1
+fi
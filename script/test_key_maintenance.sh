@@ -0,0 +1,179 @@
+#!/bin/bash
+
+# キーのクリーンアップと移行（ngx-ratelimit-ctl cleanup / migrate）のテスト
+#
+# RedisをDockerコンテナで起動し、旧形式のキー（v1: バージョンなし、v2: ハッシュタグなし）と
+# 現在の形式の残存キーを書き込んでから次を確認する
+#   - migrate の前に cleanup を実行しても、旧形式の無期限のBAN・上書き設定が削除されないこと
+#   - 有効期限のないカウンタなど、現在の形式の残存キーは削除されること
+#   - migrate の後、BANが現在の形式でTTLを保ったまま有効であること
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+REDIS_CONTAINER="ngx-ratelimit-keys-redis-$$"
+REDIS_IMAGE="redis:7-alpine"
+REDIS_PORT=16390
+CTL=""
+KEEP=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --ctl PATH   ngx-ratelimit-ctl binary (default: build target/release/ngx-ratelimit-ctl)"
+  echo "  --keep       Keep the Redis container running after the tests"
+  echo "  --help       Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --ctl)
+      CTL="$2"
+      shift 2
+      ;;
+    --keep)
+      KEEP=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+if ! command -v docker &> /dev/null; then
+  echo -e "${RED}Error: docker is not installed${NC}"
+  exit 1
+fi
+
+cleanup() {
+  if [ "$KEEP" = true ]; then
+    echo -e "${YELLOW}Redis container is kept: ${REDIS_CONTAINER}${NC}"
+    return
+  fi
+  docker rm -f ${REDIS_CONTAINER} &> /dev/null
+}
+trap cleanup EXIT
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+redis() {
+  docker exec ${REDIS_CONTAINER} redis-cli "$@"
+}
+
+ctl() {
+  "$CTL" --redis-url "redis://127.0.0.1:${REDIS_PORT}" "$@"
+}
+
+# キーが存在することを確認する
+expect_exists() {
+  if [ "$(redis EXISTS "$1")" = 1 ]; then
+    pass "$2"
+  else
+    fail "$2: $1 does not exist"
+  fi
+}
+
+# キーが存在しないことを確認する
+expect_missing() {
+  if [ "$(redis EXISTS "$1")" = 0 ]; then
+    pass "$2"
+  else
+    fail "$2: $1 still exists"
+  fi
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}キーのクリーンアップと移行のテスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+if [ -z "$CTL" ]; then
+  echo -e "${BLUE}ngx-ratelimit-ctl をビルドしています...${NC}"
+  (cd "$ROOT" && cargo build --release --quiet --features lib --bin ngx-ratelimit-ctl) || exit 1
+  CTL="${ROOT}/target/release/ngx-ratelimit-ctl"
+fi
+
+echo -e "${BLUE}Redisコンテナを起動しています...${NC}"
+docker run -d --name ${REDIS_CONTAINER} -p ${REDIS_PORT}:6379 ${REDIS_IMAGE} > /dev/null || exit 1
+for _ in $(seq 1 30); do
+  if redis ping 2> /dev/null | grep -q PONG; then
+    break
+  fi
+  sleep 1
+done
+
+# 旧形式のキー（無期限のBAN、上書き設定、期限付きのBAN）と現在の形式の残存キー
+redis SET "ratelimit:ban:192.0.2.10" manual > /dev/null
+redis HSET "ratelimit:override:/api" rate 5 burst 1 > /dev/null
+redis SET "ratelimit:v2:ban:192.0.2.11" manual EX 3600 > /dev/null
+redis SET "ratelimit:v3:fixed:{orphan}:60" 3 > /dev/null
+
+echo -e "\n${BLUE}migrate の前の cleanup${NC}"
+output=$(ctl cleanup 2>&1)
+echo "$output" | sed 's/^/      /'
+expect_exists "ratelimit:ban:192.0.2.10" "v1 permanent ban survives cleanup"
+expect_exists "ratelimit:override:/api" "v1 limit override survives cleanup"
+expect_exists "ratelimit:v2:ban:192.0.2.11" "v2 ban survives cleanup"
+expect_missing "ratelimit:v3:fixed:{orphan}:60" "counter without a TTL is deleted"
+
+echo -e "\n${BLUE}migrate${NC}"
+output=$(ctl migrate 2>&1)
+echo "$output" | sed 's/^/      /'
+expect_exists "ratelimit:v3:ban:{192.0.2.10}" "v1 ban is migrated"
+expect_exists "ratelimit:v3:override:/api" "v1 override is migrated"
+expect_exists "ratelimit:v3:ban:{192.0.2.11}" "v2 ban is migrated"
+
+ttl=$(redis TTL "ratelimit:v3:ban:{192.0.2.10}")
+if [ "$ttl" = -1 ]; then
+  pass "migrated v1 ban stays permanent"
+else
+  fail "migrated v1 ban: expected TTL -1, got ${ttl}"
+fi
+ttl=$(redis TTL "ratelimit:v3:ban:{192.0.2.11}")
+if [ "$ttl" -gt 0 ] 2> /dev/null && [ "$ttl" -le 3600 ]; then
+  pass "migrated v2 ban keeps its TTL (${ttl}s)"
+else
+  fail "migrated v2 ban: expected a TTL up to 3600s, got ${ttl}"
+fi
+
+if ctl export-bans 2>&1 | grep -q "^192.0.2.10"; then
+  pass "migrated ban is exported"
+else
+  fail "export-bans does not list 192.0.2.10"
+fi
+
+echo -e "\n${BLUE}migrate の後の cleanup${NC}"
+ctl cleanup > /dev/null 2>&1
+expect_exists "ratelimit:v3:ban:{192.0.2.10}" "migrated permanent ban survives cleanup"
+expect_exists "ratelimit:v3:override:/api" "migrated override survives cleanup"
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  exit 1
+fi
@@ -0,0 +1,212 @@
+#!/bin/bash
+
+# algorithm=limit_req が NGINX標準の limit_req と同じ判定をするかを比較するテスト
+#
+# 同じ時刻の並びのリクエストを、ngx-ratelimit-ctl simulate で時計（ManualClock）を進めながら
+# Redis上の limit_req.lua で判定し、ngx_http_limit_req_module の判定
+# （ngx_http_limit_req_lookup）を写した参照実装の結果と比べる。
+# リクエストごとの許可・拒否と超過量（遅延の計算に使用する）が一致することを確認する
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+REDIS_CONTAINER="ngx-ratelimit-lr-redis-$$"
+REDIS_IMAGE="redis:7-alpine"
+REDIS_PORT=16392
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+
+# 比較する上限（limit_req の rate=2r/s burst=5 に相当）
+RATE=2
+BURST=5
+# 各リクエストの時刻（最初のリクエストからのミリ秒）
+# 同じ時刻の連続したリクエストは同時に判定される
+SCHEDULE="0 0 0 0 0 0 0 0 0 0 1250 1250 1250 2750 4100 4100 4100 4100 4999 5000 5001 9000 9000"
+
+CTL=""
+KEEP=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --ctl PATH      ngx-ratelimit-ctl binary (default: build target/release/ngx-ratelimit-ctl)"
+  echo "  --keep          Keep the Redis container running after the tests"
+  echo "  --help          Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --ctl)
+      CTL="$2"
+      shift 2
+      ;;
+    --keep)
+      KEEP=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+for cmd in docker awk; do
+  if ! command -v $cmd &> /dev/null; then
+    echo -e "${RED}Error: $cmd is not installed${NC}"
+    exit 1
+  fi
+done
+
+CONFIG=$(mktemp)
+
+cleanup() {
+  rm -f "$CONFIG"
+  if [ "$KEEP" = true ]; then
+    echo -e "\n${YELLOW}コンテナは実行されたままです: ${REDIS_CONTAINER}${NC}"
+    return
+  fi
+  docker rm -f ${REDIS_CONTAINER} &> /dev/null
+}
+trap cleanup EXIT
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}limit_req 互換性テスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+if [ -z "$CTL" ]; then
+  echo -e "${BLUE}ngx-ratelimit-ctl をビルドしています...${NC}"
+  (cd "$ROOT" && cargo build --release --quiet --features lib --bin ngx-ratelimit-ctl) || exit 1
+  CTL="${ROOT}/target/release/ngx-ratelimit-ctl"
+fi
+
+# Redisの起動
+echo -e "${BLUE}Redisコンテナを起動しています...${NC}"
+docker run -d --name ${REDIS_CONTAINER} -p ${REDIS_PORT}:6379 ${REDIS_IMAGE} > /dev/null || exit 1
+for _ in $(seq 1 30); do
+  if docker exec ${REDIS_CONTAINER} redis-cli ping 2> /dev/null | grep -q PONG; then
+    break
+  fi
+  sleep 1
+done
+
+cat > "$CONFIG" << EOF
+{
+  "default": {
+    "enabled": true,
+    "redis_url": "redis://127.0.0.1:${REDIS_PORT}",
+    "algorithm": "limit_req",
+    "rate": ${RATE},
+    "burst": ${BURST}
+  }
+}
+EOF
+
+# ngx_http_limit_req_lookup と同じ計算で、"時刻 許可/拒否 超過量" を出力する
+#
+# rate・超過量・burst はリクエストの1/1000単位。拒否したリクエストは状態を更新しない
+reference() {
+  awk -v rate=$((RATE * 1000)) -v burst=$((BURST * 1000)) '{
+    now = $1
+    if (!seen) {
+      seen = 1
+      state = 0
+      last = now
+      print now, "allowed", 0
+      next
+    }
+    ms = now - last
+    if (ms < -60000) {
+      ms = 1
+    } else if (ms < 0) {
+      ms = 0
+    }
+    excess = state - int(rate * ms / 1000) + 1000
+    if (excess < 0) {
+      excess = 0
+    }
+    if (excess > burst) {
+      print now, "limited", excess
+      next
+    }
+    state = excess
+    if (ms != 0) {
+      last = now
+    }
+    print now, "allowed", excess
+  }'
+}
+
+# simulate で判定し、"時刻 許可/拒否 超過量" を出力する
+actual() {
+  "$CTL" --config "$CONFIG" simulate - |
+    awk '{ excess = $6; sub(/^excess=/, "", excess); print $1, $3, excess }'
+}
+
+# 同時に判定したリクエストの順序は決まらないため、時刻ごとに超過量の順に並べる
+normalize() {
+  sort -s -k1,1n -k3,3n
+}
+
+# 2つの結果をリクエストごとに並べ、一致しない行に印を付ける
+compare() {
+  paste -d ' ' <(echo "$1") <(echo "$2") |
+    awk '{
+      expected = $2 " " $3
+      actual = $5 " " $6
+      mark = (expected == actual) ? " " : "!"
+      printf "%s %6d ms  %-16s %-16s\n", mark, $1, expected, actual
+    }'
+}
+
+KEY="conformance-$$-$(date +%s)"
+schedule=$(for offset in $SCHEDULE; do echo "$offset $KEY"; done)
+
+expected=$(echo "$schedule" | reference | normalize)
+result=$(echo "$schedule" | actual)
+if [ -z "$result" ]; then
+  echo -e "${RED}ngx-ratelimit-ctl simulate returned no decisions${NC}"
+  exit 1
+fi
+result=$(echo "$result" | normalize)
+table=$(compare "$expected" "$result")
+
+echo -e "\n${BLUE}rate=${RATE}r/s burst=${BURST}${NC}"
+echo "              limit_req        ratelimit_redis"
+echo "$table" | sed 's/^/    /'
+mismatches=$(echo "$table" | grep -c '^!')
+if [ "$mismatches" -eq 0 ]; then
+  pass "all $(echo "$table" | wc -l) requests match"
+else
+  fail "${mismatches} requests differ"
+fi
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  exit 1
+fi
@@ -0,0 +1,209 @@
+#!/bin/bash
+
+# 組み込みのLuaエミュレータ（LuaExecutor）が、実際のRedisと同じ判定をするかを比較するテスト
+#
+# RedisをDockerコンテナで起動し、アルゴリズムごとに同じスケジュールを
+# ngx-ratelimit-ctl simulate（Redisでスクリプトを実行する）と simulate --emulate
+# （LuaExecutor でスクリプトを実行する）で判定して、次を確認する
+#   - Redisで最初に許可される数が、rate・burst から期待する数と一致すること
+#     （encode_check の引数の順序がスクリプトと食い違っていれば一致しない）
+#   - すべての判定がRedisとエミュレータで一致すること
+#     （エミュレータのコマンドの実装がRedisと異なれば一致しない）
+#
+# リセットまでの秒数は比較しない（Redisではキーの有効期限が実際の時刻で進むため）
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+REDIS_CONTAINER="ngx-ratelimit-emu-redis-$$"
+REDIS_IMAGE="redis:7-alpine"
+REDIS_PORT=16393
+CTL=""
+KEEP=false
+
+ALGORITHMS="fixed_window sliding_window token_bucket leaky_bucket limit_req gcra sliding_log"
+
+# 比較する上限（rate=2 で、バケットの補充が1秒の途中で起きるようにする）
+RATE=2
+BURST=3
+WINDOW=10
+# 各リクエストの時刻（最初のリクエストからのミリ秒）
+# バーストと拒否、補充の途中と直後、ウィンドウの境界の前後を含む。
+# エミュレータは有効期限も模擬した時刻で判定するため、最も短いキーの有効期限
+# （window_size × 2 秒）より前に終える
+SCHEDULE="0 0 0 0 0 0 0 0 250 499 500 501 1000 1000 1750 3000 3000 3000 9999 10000 10000 10000 10001 12500 12500 15000 15000 15000 15000 19999"
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --ctl PATH          ngx-ratelimit-ctl binary built with the lua-emulator feature"
+  echo "                      (default: build target/release/ngx-ratelimit-ctl)"
+  echo "  --algorithm NAME    Compare only one algorithm"
+  echo "  --keep              Keep the Redis container running after the tests"
+  echo "  --help              Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --ctl)
+      CTL="$2"
+      shift 2
+      ;;
+    --algorithm)
+      ALGORITHMS="$2"
+      shift 2
+      ;;
+    --keep)
+      KEEP=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+if ! command -v docker &> /dev/null; then
+  echo -e "${RED}Error: docker is not installed${NC}"
+  exit 1
+fi
+
+CONFIG=$(mktemp)
+
+cleanup() {
+  rm -f "$CONFIG"
+  if [ "$KEEP" = true ]; then
+    echo -e "${YELLOW}Redis container is kept: ${REDIS_CONTAINER}${NC}"
+    return
+  fi
+  docker rm -f ${REDIS_CONTAINER} &> /dev/null
+}
+trap cleanup EXIT
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}Luaエミュレータ と Redis の比較テスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+if [ -z "$CTL" ]; then
+  echo -e "${BLUE}ngx-ratelimit-ctl をビルドしています...${NC}"
+  (cd "$ROOT" && cargo build --release --quiet --features lib,lua-emulator --bin ngx-ratelimit-ctl) || exit 1
+  CTL="${ROOT}/target/release/ngx-ratelimit-ctl"
+fi
+
+echo -e "${BLUE}Redisコンテナを起動しています...${NC}"
+docker run -d --name ${REDIS_CONTAINER} -p ${REDIS_PORT}:6379 ${REDIS_IMAGE} > /dev/null || exit 1
+for _ in $(seq 1 30); do
+  if docker exec ${REDIS_CONTAINER} redis-cli ping 2> /dev/null | grep -q PONG; then
+    break
+  fi
+  sleep 1
+done
+
+cat > "$CONFIG" << EOF
+{
+  "default": {
+    "enabled": true,
+    "redis_url": "redis://127.0.0.1:${REDIS_PORT}",
+    "rate": ${RATE},
+    "burst": ${BURST},
+    "window_size": ${WINDOW}
+  }
+}
+EOF
+
+# 同じ時刻に送った最初のリクエストのうち許可される数
+#   fixed_window / sliding_window / sliding_log: rate + burst
+#   token_bucket / limit_req: burst + 1（最初のリクエストはトークン・超過量を消費しない）
+#   leaky_bucket / gcra: burst
+expected_burst() {
+  case $1 in
+    fixed_window|sliding_window|sliding_log) echo $((RATE + BURST)) ;;
+    token_bucket|limit_req) echo $((BURST + 1)) ;;
+    leaky_bucket|gcra) echo ${BURST} ;;
+  esac
+}
+
+# 同時に判定したリクエストの順序は決まらないため、時刻ごとに結果を並べ替える
+normalize() {
+  sort -s -k1,1n -k2
+}
+
+# 2つの結果を行ごとに並べ、一致しない行に印を付ける
+compare() {
+  paste -d '|' <(echo "$1") <(echo "$2") |
+    awk -F '|' '{
+      mark = ($1 == $2) ? " " : "!"
+      printf "%s %-72s %s\n", mark, $1, (($1 == $2) ? "" : "<> " $2)
+    }'
+}
+
+RUN_ID="$$-$(date +%s)"
+
+for algorithm in $ALGORITHMS; do
+  echo -e "\n${BLUE}${algorithm}${NC}"
+  key="emulator-${algorithm}-${RUN_ID}"
+  schedule=$(for offset in $SCHEDULE; do echo "$offset $key"; done)
+
+  redis_result=$(echo "$schedule" | "$CTL" --config "$CONFIG" --algorithm "$algorithm" simulate - 2>&1)
+  if [ $? -ne 0 ]; then
+    fail "simulate on Redis failed: ${redis_result}"
+    continue
+  fi
+  emulator_result=$(echo "$schedule" | "$CTL" --config "$CONFIG" --algorithm "$algorithm" --emulate simulate - 2>&1)
+  if [ $? -ne 0 ]; then
+    fail "simulate --emulate failed: ${emulator_result}"
+    continue
+  fi
+
+  expected=$(expected_burst $algorithm)
+  allowed=$(echo "$redis_result" | awk '$1 == 0 && $3 == "allowed"' | wc -l)
+  if [ "$allowed" -eq "$expected" ]; then
+    pass "Redis allows ${allowed} requests at once (expected ${expected})"
+  else
+    fail "Redis allows ${allowed} requests at once (expected ${expected})"
+  fi
+
+  # キーは同じなので比較から外す
+  table=$(compare "$(echo "$redis_result" | cut -d ' ' -f 1,3- | normalize)" \
+    "$(echo "$emulator_result" | cut -d ' ' -f 1,3- | normalize)")
+  mismatches=$(echo "$table" | grep -c '^!')
+  if [ "$mismatches" -eq 0 ]; then
+    pass "all $(echo "$table" | wc -l) decisions match"
+  else
+    echo "$table" | sed 's/^/    /'
+    fail "${mismatches} decisions differ between Redis and the emulator"
+  fi
+done
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  exit 1
+fi
@@ -0,0 +1,555 @@
+-- アルゴリズムのLuaスクリプト（src/scripts/*.lua）を、Redisを使わずに直接検証するテスト
+--
+-- スクリプトが使用するコマンドだけをメモリ上で実装した redis.call を用意し、
+-- ケースごとの手順（時刻・KEYS・ARGV）に対する応答を表で検証する。
+-- Redisと同じLua 5.1で実行すること
+--
+-- 使用方法: lua5.1 script/test_lua_scripts.lua [スクリプトのディレクトリ]
+
+local dir = arg[1] or "src/scripts"
+
+local RED = "\27[0;31m"
+local GREEN = "\27[0;32m"
+local BLUE = "\27[0;34m"
+local NC = "\27[0m"
+
+-- Redisのキー空間（有効期限は now の秒で判定する）
+local store = {}
+local now = 0
+
+-- redis.call に渡された値を文字列にする（Redisと同じく数値は %.17g で変換する）
+local function to_arg(value)
+  if type(value) == "number" then
+    return string.format("%.17g", value)
+  end
+  return tostring(value)
+end
+
+-- 有効期限の切れたキーは存在しないものとして扱う
+local function live(key)
+  local entry = store[key]
+  if entry and entry.expires_at and entry.expires_at <= now then
+    store[key] = nil
+    return nil
+  end
+  return entry
+end
+
+local function wrong_type()
+  error("WRONGTYPE Operation against a key holding the wrong kind of value")
+end
+
+local commands = {}
+
+function commands.INCR(key)
+  local entry = live(key)
+  if entry and entry.hash then
+    wrong_type()
+  end
+  local value = (entry and tonumber(entry.value) or 0) + 1
+  if entry then
+    entry.value = to_arg(value)
+  else
+    store[key] = { value = to_arg(value) }
+  end
+  return value
+end
+
+function commands.EXPIRE(key, seconds)
+  local entry = live(key)
+  if not entry then
+    return 0
+  end
+  entry.expires_at = now + tonumber(seconds)
+  return 1
+end
+
+function commands.PEXPIRE(key, ms)
+  local entry = live(key)
+  if not entry then
+    return 0
+  end
+  entry.expires_at = now + tonumber(ms) / 1000
+  return 1
+end
+
+function commands.TTL(key)
+  local entry = live(key)
+  if not entry then
+    return -2
+  end
+  if not entry.expires_at then
+    return -1
+  end
+  return entry.expires_at - now
+end
+
+function commands.EXISTS(key)
+  return live(key) and 1 or 0
+end
+
+function commands.GET(key)
+  local entry = live(key)
+  if not entry then
+    return false
+  end
+  if entry.hash then
+    wrong_type()
+  end
+  return entry.value
+end
+
+function commands.HSET(key, ...)
+  local entry = live(key)
+  if not entry then
+    entry = { hash = {} }
+    store[key] = entry
+  elseif not entry.hash then
+    wrong_type()
+  end
+  local fields = { ... }
+  local added = 0
+  for i = 1, #fields, 2 do
+    if entry.hash[fields[i]] == nil then
+      added = added + 1
+    end
+    entry.hash[fields[i]] = to_arg(fields[i + 1])
+  end
+  return added
+end
+
+function commands.HGET(key, field)
+  local entry = live(key)
+  if not entry then
+    return false
+  end
+  if not entry.hash then
+    wrong_type()
+  end
+  local value = entry.hash[field]
+  if value == nil then
+    return false
+  end
+  return value
+end
+
+-- ソート済みセットは { score, member } をスコア、メンバーの順に並べた配列で保持する
+local function sorted_set(key)
+  local entry = live(key)
+  if entry and not entry.zset then
+    wrong_type()
+  end
+  return entry
+end
+
+function commands.ZADD(key, score, member)
+  local entry = sorted_set(key)
+  if not entry then
+    entry = { zset = {} }
+    store[key] = entry
+  end
+  score = tonumber(score)
+  member = to_arg(member)
+  local added = 1
+  for i, item in ipairs(entry.zset) do
+    if item[2] == member then
+      table.remove(entry.zset, i)
+      added = 0
+      break
+    end
+  end
+  table.insert(entry.zset, { score, member })
+  table.sort(entry.zset, function(a, b)
+    if a[1] ~= b[1] then
+      return a[1] < b[1]
+    end
+    return a[2] < b[2]
+  end)
+  return added
+end
+
+function commands.ZCARD(key)
+  local entry = sorted_set(key)
+  return entry and #entry.zset or 0
+end
+
+function commands.ZRANGE(key, start, stop, with_scores)
+  local entry = sorted_set(key)
+  local reply = {}
+  if not entry then
+    return reply
+  end
+  local len = #entry.zset
+  start, stop = tonumber(start), tonumber(stop)
+  if start < 0 then
+    start = math.max(0, len + start)
+  end
+  if stop < 0 then
+    stop = len + stop
+  end
+  stop = math.min(stop, len - 1)
+  for i = start + 1, stop + 1 do
+    table.insert(reply, entry.zset[i][2])
+    if with_scores then
+      table.insert(reply, to_arg(entry.zset[i][1]))
+    end
+  end
+  return reply
+end
+
+-- 範囲は "-inf"、"+inf" または数値（境界を含む）
+local function score_bound(value)
+  if value == "-inf" then
+    return -math.huge
+  elseif value == "+inf" then
+    return math.huge
+  end
+  return tonumber(value)
+end
+
+function commands.ZREMRANGEBYSCORE(key, min, max)
+  local entry = sorted_set(key)
+  if not entry then
+    return 0
+  end
+  min, max = score_bound(to_arg(min)), score_bound(to_arg(max))
+  local kept, removed = {}, 0
+  for _, item in ipairs(entry.zset) do
+    if item[1] >= min and item[1] <= max then
+      removed = removed + 1
+    else
+      table.insert(kept, item)
+    end
+  end
+  entry.zset = kept
+  -- Redisと同じく、空になったソート済みセットは削除する
+  if #kept == 0 then
+    store[key] = nil
+  end
+  return removed
+end
+
+redis = {
+  call = function(command, key, ...)
+    local handler = commands[string.upper(command)]
+    if not handler then
+      error("Command not supported by the test runner: " .. command)
+    end
+    return handler(to_arg(key), ...)
+  end,
+}
+
+-- Redisと同じく、数値の応答は小数点以下を切り捨てて整数にする
+local function to_reply(value)
+  local reply = {}
+  for i, v in ipairs(value) do
+    if type(v) == "number" then
+      reply[i] = v >= 0 and math.floor(v) or math.ceil(v)
+    elseif type(v) == "boolean" then
+      reply[i] = v and 1 or 0
+    else
+      reply[i] = v
+    end
+  end
+  return reply
+end
+
+local function format_reply(reply)
+  local values = {}
+  for i, v in ipairs(reply) do
+    values[i] = tostring(v)
+  end
+  return "{" .. table.concat(values, ", ") .. "}"
+end
+
+local function same_reply(a, b)
+  if #a ~= #b then
+    return false
+  end
+  for i = 1, #a do
+    if a[i] ~= b[i] then
+      return false
+    end
+  end
+  return true
+end
+
+-- ケース: script のスクリプトを steps の順に実行する
+--   now   : コマンドの実行時刻（有効期限の判定に使用。ARGVの時刻とは別に指定する）
+--   keys  : KEYS
+--   argv  : ARGV
+--   reply : 期待する応答 {許可, 残り, リセットまでの秒数, カウント}（limit_req は5番目に超過量、gcra・sliding_log は再試行できるまでのミリ秒、gcra は6番目に超過量）
+--   ttl   : 実行後に期待するキーの残り秒数（省略可）
+local cases = {
+  {
+    name = "fixed_window: allows max_requests in a window",
+    script = "fixed_window",
+    steps = {
+      { now = 100, keys = { "fw" }, argv = { 3, 10 }, reply = { 1, 2, 10, 1 }, ttl = { fw = 10 } },
+      { now = 101, keys = { "fw" }, argv = { 3, 10 }, reply = { 1, 1, 9, 2 } },
+      { now = 102, keys = { "fw" }, argv = { 3, 10 }, reply = { 1, 0, 8, 3 } },
+      { now = 103, keys = { "fw" }, argv = { 3, 10 }, reply = { 0, 0, 7, 4 } },
+    },
+  },
+  {
+    name = "fixed_window: counter starts over after it expires",
+    script = "fixed_window",
+    steps = {
+      { now = 100, keys = { "fw" }, argv = { 1, 10 }, reply = { 1, 0, 10, 1 } },
+      { now = 105, keys = { "fw" }, argv = { 1, 10 }, reply = { 0, 0, 5, 2 } },
+      { now = 110, keys = { "fw" }, argv = { 1, 10 }, reply = { 1, 0, 10, 1 } },
+    },
+  },
+  {
+    name = "sliding_window: allows rate + burst without a previous window",
+    script = "sliding_window",
+    steps = {
+      { now = 110, keys = { "sw:110", "sw:100" }, argv = { 110, 10, 2, 1 }, reply = { 1, 2, 10, 1 }, ttl = { ["sw:110"] = 20 } },
+      { now = 111, keys = { "sw:110", "sw:100" }, argv = { 111, 10, 2, 1 }, reply = { 1, 1, 9, 2 } },
+      { now = 112, keys = { "sw:110", "sw:100" }, argv = { 112, 10, 2, 1 }, reply = { 1, 0, 8, 3 } },
+      { now = 113, keys = { "sw:110", "sw:100" }, argv = { 113, 10, 2, 1 }, reply = { 0, 0, 7, 4 } },
+    },
+  },
+  {
+    name = "sliding_window: weights the previous window by the remaining fraction",
+    script = "sliding_window",
+    steps = {
+      { now = 105, keys = { "sw:100", "sw:90" }, argv = { 105, 10, 2, 1 }, reply = { 1, 2, 5, 1 } },
+      { now = 105, keys = { "sw:100", "sw:90" }, argv = { 105, 10, 2, 1 }, reply = { 1, 1, 5, 2 } },
+      { now = 105, keys = { "sw:100", "sw:90" }, argv = { 105, 10, 2, 1 }, reply = { 1, 0, 5, 3 } },
+      -- 1 + 3 × 0.5 = 2.5
+      { now = 115, keys = { "sw:110", "sw:100" }, argv = { 115, 10, 2, 1 }, reply = { 1, 0, 5, 2 } },
+      -- 2 + 3 × 0.5 = 3.5
+      { now = 115, keys = { "sw:110", "sw:100" }, argv = { 115, 10, 2, 1 }, reply = { 0, 0, 5, 3 } },
+      -- 拒否したリクエストもカウントされる: 3 + 3 × 0.2 = 3.6
+      { now = 118, keys = { "sw:110", "sw:100" }, argv = { 118, 10, 2, 1 }, reply = { 0, 0, 2, 3 } },
+    },
+  },
+  {
+    name = "token_bucket: starts full and refills over time",
+    script = "token_bucket",
+    steps = {
+      { now = 100, keys = { "tb" }, argv = { 100, 1, 2, 10 }, reply = { 1, 2, 0, 0 }, ttl = { tb = 20 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 1, 2, 10 }, reply = { 1, 1, 1, 1 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 1, 2, 10 }, reply = { 1, 0, 2, 2 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 1, 2, 10 }, reply = { 0, 0, 2, 2 } },
+      { now = 101, keys = { "tb" }, argv = { 101, 1, 2, 10 }, reply = { 1, 0, 2, 2 } },
+      -- 補充はバケットの容量まで
+      { now = 105, keys = { "tb" }, argv = { 105, 1, 2, 10 }, reply = { 1, 1, 1, 1 } },
+    },
+  },
+  {
+    name = "token_bucket: refill_time below one second",
+    script = "token_bucket",
+    steps = {
+      { now = 100, keys = { "tb" }, argv = { 100, 0.5, 1, 10 }, reply = { 1, 1, 0, 0 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 0.5, 1, 10 }, reply = { 1, 0, 1, 1 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 0.5, 1, 10 }, reply = { 0, 0, 1, 1 } },
+      { now = 101, keys = { "tb" }, argv = { 101, 0.5, 1, 10 }, reply = { 1, 0, 1, 1 } },
+    },
+  },
+  {
+    name = "leaky_bucket: fills up and leaks at the rate",
+    script = "leaky_bucket",
+    steps = {
+      { now = 100, keys = { "lb" }, argv = { 100, 1, 2, 10 }, reply = { 1, 1, 1, 1 }, ttl = { lb = 20 } },
+      { now = 100, keys = { "lb" }, argv = { 100, 1, 2, 10 }, reply = { 1, 0, 2, 2 } },
+      { now = 100, keys = { "lb" }, argv = { 100, 1, 2, 10 }, reply = { 0, 0, 2, 2 } },
+      -- 1.5秒で1.5リークする: 2 - 1.5 + 1 = 1.5
+      { now = 101, keys = { "lb" }, argv = { 101.5, 1, 2, 10 }, reply = { 1, 0, 2, 2 } },
+      -- 空になった後の1件
+      { now = 105, keys = { "lb" }, argv = { 105, 1, 2, 10 }, reply = { 1, 1, 1, 1 } },
+    },
+  },
+  {
+    name = "limit_req: first request creates the key with no excess",
+    script = "limit_req",
+    steps = {
+      -- rate=2r/s burst=1: ARGV はミリ秒と1/1000単位
+      { now = 100, keys = { "lr" }, argv = { 100000, 2000, 1000 }, reply = { 1, 1, 0, 0, 0 }, ttl = { lr = 60 } },
+    },
+  },
+  {
+    name = "limit_req: excess accumulates up to burst and rejects without updating state",
+    script = "limit_req",
+    steps = {
+      { now = 100, keys = { "lr" }, argv = { 100000, 2000, 1000 }, reply = { 1, 1, 0, 0, 0 } },
+      -- 同じミリ秒: 0 - 0 + 1000
+      { now = 100, keys = { "lr" }, argv = { 100000, 2000, 1000 }, reply = { 1, 0, 1, 1, 1000 } },
+      -- 2000 > burst で拒否
+      { now = 100, keys = { "lr" }, argv = { 100000, 2000, 1000 }, reply = { 0, 0, 1, 1, 2000 } },
+      -- 拒否で状態は変わらないため、250ms後は 1000 - 500 + 1000 = 1500 で再び拒否
+      { now = 100, keys = { "lr" }, argv = { 100250, 2000, 1000 }, reply = { 0, 0, 1, 1, 1500 } },
+      -- 500ms後: 1000 - 1000 + 1000 = 1000
+      { now = 100, keys = { "lr" }, argv = { 100500, 2000, 1000 }, reply = { 1, 0, 1, 1, 1000 } },
+    },
+  },
+  {
+    name = "limit_req: excess leaks at the rate and the key expires 60s after it drains",
+    script = "limit_req",
+    steps = {
+      { now = 100, keys = { "lr" }, argv = { 100000, 1000, 5000 }, reply = { 1, 5, 0, 0, 0 } },
+      { now = 100, keys = { "lr" }, argv = { 100000, 1000, 5000 }, reply = { 1, 4, 1, 1, 1000 } },
+      { now = 100, keys = { "lr" }, argv = { 100000, 1000, 5000 }, reply = { 1, 3, 2, 2, 2000 }, ttl = { lr = 62 } },
+      -- 1.5秒で1500リークする: 2000 - 1500 + 1000 = 1500
+      { now = 101, keys = { "lr" }, argv = { 101500, 1000, 5000 }, reply = { 1, 3, 2, 2, 1500 } },
+      -- 空になった後は超過量が0に戻る（limit_req と同じく0未満にはならない）
+      { now = 110, keys = { "lr" }, argv = { 110000, 1000, 5000 }, reply = { 1, 5, 0, 0, 0 } },
+    },
+  },
+  {
+    name = "fixed_window: rate + burst above the 32-bit range",
+    script = "fixed_window",
+    steps = {
+      -- rate=4294967295 burst=4294967295
+      { now = 100, keys = { "fw" }, argv = { 8589934590, 10 }, reply = { 1, 8589934589, 10, 1 } },
+    },
+  },
+  {
+    name = "token_bucket: one token every 10 seconds with burst=0",
+    script = "token_bucket",
+    steps = {
+      -- rate=1 rate_period=10: refill_time=10、容量は max(burst, 1) = 1
+      { now = 100, keys = { "tb" }, argv = { 100, 10, 1, 10 }, reply = { 1, 1, 0, 0 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 10, 1, 10 }, reply = { 1, 0, 10, 1 } },
+      { now = 100, keys = { "tb" }, argv = { 100, 10, 1, 10 }, reply = { 0, 0, 10, 1 } },
+      { now = 110, keys = { "tb" }, argv = { 110, 10, 1, 10 }, reply = { 1, 0, 10, 1 } },
+    },
+  },
+  {
+    name = "leaky_bucket: leaks 0.1 requests per second",
+    script = "leaky_bucket",
+    steps = {
+      -- rate=1 rate_period=10 burst=0
+      { now = 100, keys = { "lb" }, argv = { 100, 0.1, 1, 10 }, reply = { 1, 0, 10, 1 } },
+      { now = 100, keys = { "lb" }, argv = { 100, 0.1, 1, 10 }, reply = { 0, 0, 10, 1 } },
+      { now = 110, keys = { "lb" }, argv = { 110, 0.1, 1, 10 }, reply = { 1, 0, 10, 1 } },
+    },
+  },
+  {
+    name = "limit_req: rate below one request per second",
+    script = "limit_req",
+    steps = {
+      -- rate=1 rate_period=10 burst=0: 1秒あたり 0.1 × 1000 = 100
+      { now = 100, keys = { "lr" }, argv = { 100000, 100, 0 }, reply = { 1, 0, 0, 0, 0 }, ttl = { lr = 60 } },
+      { now = 100, keys = { "lr" }, argv = { 100000, 100, 0 }, reply = { 0, 0, 0, 0, 1000 } },
+      -- 5秒で500リークする: 0 - 500 + 1000 = 500
+      { now = 105, keys = { "lr" }, argv = { 105000, 100, 0 }, reply = { 0, 0, 0, 0, 500 } },
+      { now = 110, keys = { "lr" }, argv = { 110000, 100, 0 }, reply = { 1, 0, 0, 0, 0 } },
+    },
+  },
+  {
+    name = "gcra: allows capacity requests at once and then one per interval",
+    script = "gcra",
+    steps = {
+      -- rate=1r/s burst=2: ARGV はマイクロ秒、5番目の値は再試行できるまでのミリ秒、6番目の値は超過量
+      { now = 100, keys = { "gcra" }, argv = { 100000000, 1000000, 2 }, reply = { 1, 1, 1, 1, 0, 0 }, ttl = { gcra = 1 } },
+      { now = 100, keys = { "gcra" }, argv = { 100000000, 1000000, 2 }, reply = { 1, 0, 2, 2, 0, 1000 } },
+      { now = 100, keys = { "gcra" }, argv = { 100000000, 1000000, 2 }, reply = { 0, 0, 2, 2, 1000, 2000 } },
+      -- 拒否で TAT は進まないため、400ms後は残り600ms
+      { now = 100, keys = { "gcra" }, argv = { 100400000, 1000000, 2 }, reply = { 0, 0, 2, 2, 600, 1600 } },
+      { now = 101, keys = { "gcra" }, argv = { 101000000, 1000000, 2 }, reply = { 1, 0, 2, 2, 0, 1000 } },
+    },
+  },
+  {
+    name = "gcra: key expires when the arrival time catches up with now",
+    script = "gcra",
+    steps = {
+      -- rate=1 rate_period=10 burst=0: 10秒に1件
+      { now = 100, keys = { "gcra" }, argv = { 100000000, 10000000, 1 }, reply = { 1, 0, 10, 1, 0, 0 }, ttl = { gcra = 10 } },
+      { now = 105, keys = { "gcra" }, argv = { 105000000, 10000000, 1 }, reply = { 0, 0, 5, 1, 5000, 500 } },
+      { now = 110, keys = { "gcra" }, argv = { 110000000, 10000000, 1 }, reply = { 1, 0, 10, 1, 0, 0 } },
+    },
+  },
+  {
+    name = "sliding_log: counts allowed requests in the last window exactly",
+    script = "sliding_log",
+    steps = {
+      -- window_size=10 rate=2 burst=1: ARGV はミリ秒、5番目の値は再試行できるまでのミリ秒
+      { now = 100, keys = { "sl" }, argv = { 100000, 10000, 3, 1 }, reply = { 1, 2, 10, 1, 0 }, ttl = { sl = 10 } },
+      { now = 102, keys = { "sl" }, argv = { 102000, 10000, 3, 2 }, reply = { 1, 1, 10, 2, 0 } },
+      { now = 104, keys = { "sl" }, argv = { 104000, 10000, 3, 3 }, reply = { 1, 0, 10, 3, 0 }, ttl = { sl = 10 } },
+      -- 最も古い記録（100秒）が外れるまで5秒、最も新しい記録（104秒）が外れるまで9秒
+      { now = 105, keys = { "sl" }, argv = { 105000, 10000, 3, 4 }, reply = { 0, 0, 9, 3, 5000 } },
+      -- 拒否したリクエストは記録されないため、100秒の記録が外れれば許可される
+      { now = 110, keys = { "sl" }, argv = { 110000, 10000, 3, 5 }, reply = { 1, 0, 10, 3, 0 } },
+    },
+  },
+  {
+    name = "sliding_log: requests in the same millisecond are recorded separately",
+    script = "sliding_log",
+    steps = {
+      { now = 100, keys = { "sl" }, argv = { 100000, 1000, 2, 1 }, reply = { 1, 1, 1, 1, 0 } },
+      { now = 100, keys = { "sl" }, argv = { 100000, 1000, 2, 2 }, reply = { 1, 0, 1, 2, 0 } },
+      { now = 100, keys = { "sl" }, argv = { 100000, 1000, 2, 3 }, reply = { 0, 0, 1, 2, 1000 } },
+    },
+  },
+}
+
+local scripts = {}
+
+local function load_script(name)
+  if not scripts[name] then
+    local chunk, err = loadfile(dir .. "/" .. name .. ".lua")
+    if not chunk then
+      error("Failed to load " .. name .. ": " .. err)
+    end
+    scripts[name] = chunk
+  end
+  return scripts[name]
+end
+
+local passed, failed = 0, 0
+
+print(BLUE .. "Luaスクリプトのテスト (" .. dir .. ")" .. NC)
+
+for _, case in ipairs(cases) do
+  store = {}
+  local failure
+  local ok, err = pcall(function()
+    local script = load_script(case.script)
+    for i, step in ipairs(case.steps) do
+      now = step.now
+      KEYS = step.keys
+      ARGV = {}
+      for j, value in ipairs(step.argv) do
+        ARGV[j] = to_arg(value)
+      end
+
+      local reply = to_reply(script())
+      if not same_reply(reply, step.reply) then
+        failure = string.format(
+          "step %d: expected %s, got %s",
+          i,
+          format_reply(step.reply),
+          format_reply(reply)
+        )
+        return
+      end
+      for key, expected in pairs(step.ttl or {}) do
+        local ttl = commands.TTL(key)
+        if ttl ~= expected then
+          failure = string.format("step %d: expected TTL %d for %s, got %d", i, expected, key, ttl)
+          return
+        end
+      end
+    end
+  end)
+  if not ok then
+    failure = tostring(err)
+  end
+
+  if failure then
+    print(RED .. "  ✗ " .. case.name .. ": " .. failure .. NC)
+    failed = failed + 1
+  else
+    print(GREEN .. "  ✓ " .. case.name .. NC)
+    passed = passed + 1
+  end
+end
+
+if failed > 0 then
+  print(RED .. failed .. " failed" .. NC .. ", " .. GREEN .. passed .. " passed" .. NC)
+  os.exit(1)
+end
+print(GREEN .. passed .. " passed" .. NC)
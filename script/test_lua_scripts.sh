@@ -0,0 +1,21 @@
+#!/bin/bash
+
+# アルゴリズムのLuaスクリプトを、Redisと同じLua 5.1でテストする
+# （テストケースは script/test_lua_scripts.lua）
+#
+# lua5.1 / luajit / lua の順に、最初に見つかったインタプリタで実行する
+
+# カラー表示用の設定
+RED='\033[0;31m'
+NC='\033[0m' # No Color
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+
+for lua in lua5.1 luajit lua; do
+  if command -v $lua &> /dev/null; then
+    cd "$ROOT" && exec $lua script/test_lua_scripts.lua src/scripts
+  fi
+done
+
+echo -e "${RED}Error: lua5.1, luajit or lua must be installed${NC}"
+exit 1
@@ -0,0 +1,275 @@
+#!/bin/bash
+
+# RedisへのTLS接続（redis_tls・redis_tls_*）のテスト
+#
+# 1. 不正な redis_tls_* の組み合わせで "nginx -t" が失敗することを確認する
+# 2. クライアント証明書を要求するTLSのRedisをDockerコンテナで起動し、CA証明書・クライアント証明書・
+#    redis_tls_insecure・rediss:// のURLと redis_tls=on による redis:// の置き換えで接続できること、
+#    証明書が足りない場合は接続できないことを確認する
+#
+# 接続できたかどうかは、上限を超えたリクエストが拒否されるかで判定する（接続できない場合は
+# failure_mode=open により全て許可される）
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+IMAGE_NAME="ngx-ratelimit-redis"
+NETWORK="ngx-ratelimit-tls-$$"
+REDIS_CONTAINER="ngx-ratelimit-tls-redis-$$"
+NGINX_CONTAINER="ngx-ratelimit-tls-nginx-$$"
+REDIS_IMAGE="redis:7-alpine"
+PORT=18081
+
+# テストで使用する上限（RATE + BURST を超えたリクエストが拒否される）
+RATE=1
+BURST=2
+REQUESTS=6
+
+SKIP_BUILD=false
+KEEP=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --skip-build    Use the existing ${IMAGE_NAME} image"
+  echo "  --keep          Keep the containers running after the tests"
+  echo "  --help          Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --skip-build)
+      SKIP_BUILD=true
+      shift
+      ;;
+    --keep)
+      KEEP=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+for cmd in docker curl openssl; do
+  if ! command -v $cmd &> /dev/null; then
+    echo -e "${RED}Error: $cmd is not installed${NC}"
+    exit 1
+  fi
+done
+
+WORK_DIR=$(mktemp -d)
+chmod 755 "$WORK_DIR"
+
+cleanup() {
+  if [ "$KEEP" = true ]; then
+    echo -e "${YELLOW}Containers are kept: ${REDIS_CONTAINER} ${NGINX_CONTAINER}${NC}"
+    return
+  fi
+  docker rm -f ${NGINX_CONTAINER} ${REDIS_CONTAINER} &> /dev/null
+  docker network rm ${NETWORK} &> /dev/null
+  rm -rf "$WORK_DIR"
+}
+trap cleanup EXIT
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+# 証明書の作成（CA、Redisのサーバー証明書、NGINXのクライアント証明書）
+#
+# サーバー証明書はネットワーク上の名前 redis-tls に対して発行する。秘密鍵は PKCS#8 で出力する
+CERTS="${WORK_DIR}/certs"
+mkdir -p "$CERTS"
+make_certs() {
+  openssl genpkey -algorithm RSA -out "${CERTS}/ca-key.pem" &&
+    openssl req -x509 -new -key "${CERTS}/ca-key.pem" -days 1 -subj "/CN=ratelimit-test-ca" \
+      -out "${CERTS}/ca.pem" &&
+    for name in server client; do
+      openssl genpkey -algorithm RSA -out "${CERTS}/${name}-key.pem" &&
+        openssl req -new -key "${CERTS}/${name}-key.pem" -subj "/CN=redis-tls" \
+          -out "${CERTS}/${name}.csr" &&
+        printf "subjectAltName=DNS:redis-tls\n" > "${CERTS}/${name}.ext" &&
+        openssl x509 -req -in "${CERTS}/${name}.csr" -CA "${CERTS}/ca.pem" \
+          -CAkey "${CERTS}/ca-key.pem" -CAcreateserial -days 1 \
+          -extfile "${CERTS}/${name}.ext" -out "${CERTS}/${name}.pem" || return 1
+    done
+}
+make_certs &> "${WORK_DIR}/openssl.log" || {
+  echo -e "${RED}Failed to create certificates${NC}"
+  cat "${WORK_DIR}/openssl.log"
+  exit 1
+}
+chmod 755 "$CERTS"
+chmod 644 "${CERTS}"/*
+
+# http ブロックの中身を受け取り、nginx.conf を出力する
+nginx_conf() {
+  echo "worker_processes 1;"
+  echo "error_log /dev/stderr info;"
+  echo "events { worker_connections 1024; }"
+  echo "load_module modules/libngx_ratelimit_redis.so;"
+  echo "http {"
+  echo "$1"
+  echo "}"
+}
+
+# 設定で "nginx -t" を実行し、失敗して出力に期待するメッセージが含まれることを確認する
+check_invalid() {
+  local name=$1
+  local message=$2
+  local options=$3
+
+  nginx_conf "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on ${options};
+        }
+    }" > "${WORK_DIR}/nginx.conf"
+  chmod 644 "${WORK_DIR}/nginx.conf"
+
+  local output status
+  output=$(docker run --rm --entrypoint nginx \
+    -v "${WORK_DIR}/nginx.conf:/etc/nginx/nginx.conf:ro" \
+    -v "${CERTS}:/etc/nginx/redis:ro" \
+    ${IMAGE_NAME} -t 2>&1)
+  status=$?
+
+  if [ $status -ne 0 ] && echo "$output" | grep -q "$message"; then
+    pass "${name}: nginx -t failed"
+  else
+    fail "${name}: expected nginx -t to fail with \"${message}\", exited with ${status}"
+    echo "$output" | tail -10 | sed 's/^/      /'
+  fi
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}Redis TLS テスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+# Dockerイメージのビルド
+if [ "$SKIP_BUILD" = false ]; then
+  echo -e "${BLUE}Dockerイメージをビルドしています...${NC}"
+  docker build -t ${IMAGE_NAME} . || exit 1
+fi
+
+echo -e "\n${BLUE}redis_tls_* の検証${NC}"
+check_invalid "cert without key" "tls_cert and tls_key must be set together" \
+  "redis_url=rediss://redis-tls:6379 redis_tls_cert=/etc/nginx/redis/client.pem"
+check_invalid "key without cert" "tls_cert and tls_key must be set together" \
+  "redis_url=rediss://redis-tls:6379 redis_tls_key=/etc/nginx/redis/client-key.pem"
+check_invalid "CA without TLS" "require tls_enabled or a rediss:// URL" \
+  "redis_url=redis://redis-tls:6379 redis_tls_ca=/etc/nginx/redis/ca.pem"
+check_invalid "insecure without TLS" "require tls_enabled or a rediss:// URL" \
+  "redis_url=redis://redis-tls:6379 redis_tls_insecure=on"
+check_invalid "missing CA file" "Cannot read tls_ca" \
+  "redis_url=rediss://redis-tls:6379 redis_tls_ca=/etc/nginx/redis/missing.pem"
+check_invalid "certificates in cluster mode" "not supported with cluster_mode" \
+  "redis_url=rediss://redis-tls:6379 redis_cluster_mode=on redis_tls_ca=/etc/nginx/redis/ca.pem"
+check_invalid "invalid redis_tls_insecure" "Invalid redis_tls_insecure value" \
+  "redis_url=rediss://redis-tls:6379 redis_tls_insecure=yes"
+
+# クライアント証明書を要求するTLSのRedisの起動
+docker network create ${NETWORK} > /dev/null || exit 1
+echo -e "\n${BLUE}Redisコンテナを起動しています...${NC}"
+docker run -d --name ${REDIS_CONTAINER} --network ${NETWORK} --network-alias redis-tls \
+  -v "${CERTS}:/certs:ro" ${REDIS_IMAGE} redis-server --port 0 --tls-port 6379 \
+  --tls-cert-file /certs/server.pem --tls-key-file /certs/server-key.pem \
+  --tls-ca-cert-file /certs/ca.pem --tls-auth-clients yes > /dev/null || exit 1
+
+for _ in $(seq 1 30); do
+  if docker exec ${REDIS_CONTAINER} redis-cli --tls --cacert /certs/ca.pem \
+    --cert /certs/client.pem --key /certs/client-key.pem -h redis-tls ping 2> /dev/null | grep -q PONG; then
+    break
+  fi
+  sleep 1
+done
+
+# ケースごとのLocation（名前、期待する結果、ratelimit_redis のオプション）
+CASES=(
+  "mtls|limited|redis_url=rediss://redis-tls:6379 redis_tls_ca=/etc/nginx/redis/ca.pem redis_tls_cert=/etc/nginx/redis/client.pem redis_tls_key=/etc/nginx/redis/client-key.pem"
+  "tls-on|limited|redis_url=redis://redis-tls:6379 redis_tls=on redis_tls_ca=/etc/nginx/redis/ca.pem redis_tls_cert=/etc/nginx/redis/client.pem redis_tls_key=/etc/nginx/redis/client-key.pem"
+  "insecure|limited|redis_url=rediss://redis-tls:6379 redis_tls_insecure=on redis_tls_cert=/etc/nginx/redis/client.pem redis_tls_key=/etc/nginx/redis/client-key.pem"
+  "no-client-cert|unlimited|redis_url=rediss://redis-tls:6379 redis_tls_ca=/etc/nginx/redis/ca.pem"
+  "untrusted-server|unlimited|redis_url=rediss://redis-tls:6379 redis_tls_cert=/etc/nginx/redis/client.pem redis_tls_key=/etc/nginx/redis/client-key.pem"
+  "plain|unlimited|redis_url=redis://redis-tls:6379"
+)
+
+{
+  echo "    server {"
+  echo "        listen 8080;"
+  for case in "${CASES[@]}"; do
+    IFS='|' read -r name _ options <<< "$case"
+    echo "        location /${name} {"
+    echo "            ratelimit_redis on ${options} redis_connect_timeout=1000 key=remote_addr rate=${RATE} burst=${BURST} status=429 failure_mode=open;"
+    echo "            return 200;"
+    echo "        }"
+  done
+  echo "    }"
+} > "${WORK_DIR}/http.conf"
+nginx_conf "$(cat "${WORK_DIR}/http.conf")" > "${WORK_DIR}/nginx.conf"
+chmod 644 "${WORK_DIR}/nginx.conf"
+
+echo -e "${BLUE}NGINXコンテナを起動しています...${NC}"
+docker run -d --name ${NGINX_CONTAINER} --network ${NETWORK} -p ${PORT}:8080 \
+  -v "${WORK_DIR}/nginx.conf:/etc/nginx/nginx.conf:ro" \
+  -v "${CERTS}:/etc/nginx/redis:ro" ${IMAGE_NAME} > /dev/null || exit 1
+
+for _ in $(seq 1 30); do
+  if curl -s -o /dev/null http://localhost:${PORT}/mtls 2> /dev/null; then
+    break
+  fi
+  sleep 1
+done
+
+echo -e "\n${BLUE}TLS接続${NC}"
+for case in "${CASES[@]}"; do
+  IFS='|' read -r name expected _ <<< "$case"
+  rejected=0
+  for _ in $(seq 1 ${REQUESTS}); do
+    code=$(curl -s -o /dev/null -w "%{http_code}" "http://localhost:${PORT}/${name}")
+    if [ "$code" = 429 ]; then
+      rejected=$((rejected + 1))
+    fi
+  done
+
+  if [ "$expected" = limited ] && [ $rejected -gt 0 ]; then
+    pass "${name}: connected (${rejected}/${REQUESTS} rejected)"
+  elif [ "$expected" = unlimited ] && [ $rejected -eq 0 ]; then
+    pass "${name}: not connected (all ${REQUESTS} allowed by failure_mode=open)"
+  else
+    fail "${name}: expected ${expected}, ${rejected}/${REQUESTS} rejected"
+  fi
+done
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  echo -e "${YELLOW}NGINX log:${NC}"
+  docker logs --tail 30 ${NGINX_CONTAINER} 2>&1 | sed 's/^/      /'
+  exit 1
+fi
@@ -0,0 +1,199 @@
+#!/bin/bash
+
+# strict=on の起動時の動作を確認するテスト
+#
+# 各ケースの設定で "nginx -t" を実行し、初期化の失敗がある場合に strict=on では
+# 設定の検証が失敗し、strict=on がない場合は警告だけで成功することを確認する。
+# Redisには接続しない（接続先は応答しないポートを指定する）
+
+set -u
+
+# カラー表示用の設定
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[0;33m'
+BLUE='\033[0;34m'
+NC='\033[0m' # No Color
+
+IMAGE_NAME="ngx-ratelimit-redis"
+# 接続できないRedis
+UNREACHABLE_REDIS="redis://127.0.0.1:1"
+
+SKIP_BUILD=false
+
+usage() {
+  echo "Usage: $0 [options]"
+  echo "Options:"
+  echo "  --skip-build    Use the existing ${IMAGE_NAME} image"
+  echo "  --help          Display this help message"
+  exit 1
+}
+
+while [[ $# -gt 0 ]]; do
+  case $1 in
+    --skip-build)
+      SKIP_BUILD=true
+      shift
+      ;;
+    --help)
+      usage
+      ;;
+    *)
+      echo "Unknown option: $1"
+      usage
+      ;;
+  esac
+done
+
+if ! command -v docker &> /dev/null; then
+  echo -e "${RED}Error: docker is not installed${NC}"
+  exit 1
+fi
+
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+chmod 755 "$WORK_DIR"
+
+PASSED=0
+FAILED=0
+
+pass() {
+  echo -e "  ${GREEN}✓ $1${NC}"
+  PASSED=$((PASSED + 1))
+}
+
+fail() {
+  echo -e "  ${RED}✗ $1${NC}"
+  FAILED=$((FAILED + 1))
+}
+
+# 不正なアルゴリズムを含む設定ファイル（起動時は既定値に置き換えられる）
+cat > "${WORK_DIR}/invalid.json" << 'EOF'
+{
+  "default": {
+    "enabled": true,
+    "backend": "memory",
+    "algorithm": "no_such_algorithm"
+  }
+}
+EOF
+chmod 644 "${WORK_DIR}/invalid.json"
+
+# http ブロックの中身を受け取り、nginx.conf を出力する
+nginx_conf() {
+  echo "worker_processes 1;"
+  echo "error_log /dev/stderr info;"
+  echo "events { worker_connections 1024; }"
+  echo "load_module modules/libngx_ratelimit_redis.so;"
+  echo "http {"
+  echo "$1"
+  echo "}"
+}
+
+# 設定で "nginx -t" を実行し、期待する結果（ok または fail）と比較する
+#
+# fail の場合は、出力に strict=on による中止のメッセージが含まれることも確認する
+check() {
+  local name=$1
+  local expected=$2
+  local http_block=$3
+
+  nginx_conf "$http_block" > "${WORK_DIR}/nginx.conf"
+  chmod 644 "${WORK_DIR}/nginx.conf"
+
+  local output status
+  output=$(docker run --rm --entrypoint nginx \
+    -v "${WORK_DIR}/nginx.conf:/etc/nginx/nginx.conf:ro" \
+    -v "${WORK_DIR}/invalid.json:/etc/nginx/invalid.json:ro" \
+    ${IMAGE_NAME} -t 2>&1)
+  status=$?
+
+  if [ "$expected" = ok ] && [ $status -eq 0 ]; then
+    pass "${name}: nginx -t succeeded"
+  elif [ "$expected" = fail ] && [ $status -ne 0 ] && echo "$output" | grep -q "strict=on"; then
+    pass "${name}: nginx -t failed"
+    echo "$output" | grep "strict=on" | head -3 | sed 's/^/      /'
+  else
+    fail "${name}: expected ${expected}, nginx -t exited with ${status}"
+    echo "$output" | tail -10 | sed 's/^/      /'
+  fi
+}
+
+echo -e "${BLUE}=====================================${NC}"
+echo -e "${BLUE}strict=on 起動テスト${NC}"
+echo -e "${BLUE}=====================================${NC}\n"
+
+# Dockerイメージのビルド
+if [ "$SKIP_BUILD" = false ]; then
+  echo -e "${BLUE}Dockerイメージをビルドしています...${NC}"
+  docker build -t ${IMAGE_NAME} . || exit 1
+fi
+
+echo -e "\n${BLUE}Redisに接続できない場合${NC}"
+check "warn" ok "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on redis_url=${UNREACHABLE_REDIS} redis_connect_timeout=500;
+        }
+    }"
+check "warn with strict=on" fail "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on redis_url=${UNREACHABLE_REDIS} redis_connect_timeout=500 strict=on;
+        }
+    }"
+# strict=on は後のディレクティブで発生した失敗にも適用される
+check "strict=on before the failing location" fail "
+    server {
+        listen 8080;
+        location /first {
+            ratelimit_redis on backend=memory strict=on;
+        }
+        location /second {
+            ratelimit_redis on redis_url=${UNREACHABLE_REDIS} redis_connect_timeout=500;
+        }
+    }"
+check "ratelimit_redis_check off with strict=on" fail "
+    ratelimit_redis_check off;
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on redis_url=${UNREACHABLE_REDIS} strict=on;
+        }
+    }"
+
+echo -e "\n${BLUE}設定ファイルに不正な値がある場合${NC}"
+check "invalid config file" ok "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on config_file=/etc/nginx/invalid.json;
+        }
+    }"
+check "invalid config file with strict=on" fail "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on strict=on config_file=/etc/nginx/invalid.json;
+        }
+    }"
+
+echo -e "\n${BLUE}初期化の失敗がない場合${NC}"
+check "memory backend with strict=on" ok "
+    server {
+        listen 8080;
+        location / {
+            ratelimit_redis on backend=memory strict=on;
+        }
+    }"
+
+echo -e "\n${BLUE}=====================================${NC}"
+if [ $FAILED -eq 0 ]; then
+  echo -e "${GREEN}${PASSED} passed${NC}"
+else
+  echo -e "${RED}${FAILED} failed${NC}, ${GREEN}${PASSED} passed${NC}"
+  echo -e "${YELLOW}Check the nginx -t output above${NC}"
+  exit 1
+fi
@@ -0,0 +1,165 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+/// CIDR表記のアドレス範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// "192.0.2.0/24" や "2001:db8::/32"、プレフィックス長なしの単一アドレスを解析する
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (addr_str, prefix_str) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = addr_str
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid IP address: {}", addr_str))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_str {
+            Some(p) => match p.parse::<u8>() {
+                Ok(len) if len <= max_len => len,
+                _ => return Err(format!("Invalid prefix length: {}", s)),
+            },
+            None => max_len,
+        };
+
+        Ok(Self {
+            network: mask(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    /// アドレスがこの範囲に含まれるか
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                mask(*addr, self.prefix_len) == self.network
+            }
+            // IPv4射影アドレス（::ffff:192.0.2.1）はIPv4として比較する
+            (IpAddr::V4(_), IpAddr::V6(v6)) => match v6.to_ipv4_mapped() {
+                Some(v4) => mask(IpAddr::V4(v4), self.prefix_len) == self.network,
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// 多数のCIDRに対して、アドレスが含まれるかを判定する集合
+///
+/// プレフィックス長ごとにネットワークアドレスをハッシュセットで保持し、判定ではアドレスを
+/// 登録済みのプレフィックス長でそれぞれマスクして検索する。判定の計算量はCIDRの数ではなく、
+/// プレフィックス長の種類の数（IPv4は最大33、IPv6は最大129）に比例する
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CidrSet {
+    // (プレフィックス長, ネットワークアドレス) をプレフィックス長の長い順に並べたもの
+    v4: Vec<(u8, HashSet<u32>)>,
+    v6: Vec<(u8, HashSet<u128>)>,
+}
+
+impl CidrSet {
+    /// CIDRの一覧（"10.0.0.0/8" や単一アドレス）から集合を作る
+    pub fn parse_all<S: AsRef<str>>(specs: &[S]) -> Result<Self, String> {
+        let mut set = CidrSet::default();
+        for spec in specs {
+            set.insert(Cidr::parse(spec.as_ref())?);
+        }
+        Ok(set)
+    }
+
+    /// CIDRを追加する
+    pub fn insert(&mut self, cidr: Cidr) {
+        match cidr.network {
+            IpAddr::V4(v4) => insert_network(&mut self.v4, cidr.prefix_len, u32::from(v4)),
+            IpAddr::V6(v6) => insert_network(&mut self.v6, cidr.prefix_len, u128::from(v6)),
+        }
+    }
+
+    /// CIDRが1つも登録されていないか
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    /// アドレスがいずれかのCIDRに含まれるか
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(v4) => self.contains_v4(u32::from(*v4)),
+            // IPv4射影アドレス（::ffff:192.0.2.1）はIPv4のCIDRとも比較する
+            IpAddr::V6(v6) => {
+                v6.to_ipv4_mapped()
+                    .is_some_and(|v4| self.contains_v4(u32::from(v4)))
+                    || self.v6.iter().any(|(prefix_len, networks)| {
+                        networks.contains(&(u128::from(*v6) & mask_bits_v6(*prefix_len)))
+                    })
+            }
+        }
+    }
+
+    fn contains_v4(&self, bits: u32) -> bool {
+        self.v4
+            .iter()
+            .any(|(prefix_len, networks)| networks.contains(&(bits & mask_bits_v4(*prefix_len))))
+    }
+}
+
+// プレフィックス長ごとの集合にネットワークアドレスを追加する（長いプレフィックスから判定するよう並べる）
+fn insert_network<T: std::hash::Hash + Eq>(
+    table: &mut Vec<(u8, HashSet<T>)>,
+    prefix_len: u8,
+    network: T,
+) {
+    match table.iter_mut().find(|(len, _)| *len == prefix_len) {
+        Some((_, networks)) => {
+            networks.insert(network);
+        }
+        None => {
+            table.push((prefix_len, HashSet::from([network])));
+            table.sort_by_key(|(len, _)| Reverse(*len));
+        }
+    }
+}
+
+fn mask_bits_v4(prefix_len: u8) -> u32 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u32::MAX << (32 - prefix_len as u32)
+    }
+}
+
+fn mask_bits_v6(prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u128::MAX << (128 - prefix_len as u32)
+    }
+}
+
+// プレフィックス長より下位のビットを0にする
+fn mask(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => IpAddr::V4((u32::from(v4) & mask_bits_v4(prefix_len)).into()),
+        IpAddr::V6(v6) => IpAddr::V6((u128::from(v6) & mask_bits_v6(prefix_len)).into()),
+    }
+}
+
+/// "192.0.2.1" や "192.0.2.1:12345"、"[2001:db8::1]:443" 形式の文字列からIPアドレスを取り出す
+pub fn parse_ip(s: &str) -> Option<IpAddr> {
+    s.parse::<IpAddr>()
+        .ok()
+        .or_else(|| s.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
@@ -0,0 +1,867 @@
+use log::{error, info, warn};
+use nginx_rs::bindings::*;
+use nginx_rs::http;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::IpAddr;
+
+use crate::acl::{self, Cidr};
+use crate::banlist;
+use crate::module::{admin_config, current_limiter, runtime};
+use crate::overrides;
+use crate::redis_client::{AuditEntry, CleanupOptions, LimitOverride, KEY_SCHEMA_VERSION};
+
+/// 管理用Locationの設定
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    /// アクセスを許可するクライアントアドレス
+    pub allow: Vec<Cidr>,
+    /// Authorization: Bearer で要求するトークン
+    pub token: Option<String>,
+}
+
+impl AdminConfig {
+    /// allowもtokenも指定されていない場合はループバックからのアクセスのみ許可する
+    fn effective_allow(&self) -> Vec<Cidr> {
+        if self.allow.is_empty() && self.token.is_none() {
+            ["127.0.0.0/8", "::1/128"]
+                .iter()
+                .filter_map(|c| Cidr::parse(c).ok())
+                .collect()
+        } else {
+            self.allow.clone()
+        }
+    }
+
+    // クライアントアドレスとトークンを検証する
+    fn authorize(
+        &self,
+        client_ip: Option<IpAddr>,
+        authorization: Option<&str>,
+    ) -> Result<(), Status> {
+        let allow = self.effective_allow();
+        if !allow.is_empty() {
+            match client_ip {
+                Some(ip) if allow.iter().any(|c| c.contains(&ip)) => {}
+                _ => return Err(Status::Forbidden),
+            }
+        }
+
+        if let Some(expected) = &self.token {
+            let presented = authorization
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .map(|t| t.trim())
+                .unwrap_or("");
+            if !constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+                return Err(Status::Unauthorized);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// タイミング攻撃を避けるため、一致しない位置に関係なく全バイトを比較する
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff |= (x ^ y) as usize;
+    }
+    diff == 0
+}
+
+/// 監査ログに記録するパラメータの最大長
+const AUDIT_PARAMS_MAX_LEN: usize = 512;
+
+/// BAN/BAN解除リクエストのボディ
+#[derive(Debug, Deserialize)]
+struct BanRequest {
+    key: String,
+    /// BAN期間（秒、0の場合は無期限）
+    #[serde(default)]
+    duration: u64,
+}
+
+/// TTL変更リクエストのボディ
+#[derive(Debug, Deserialize)]
+struct TtlRequest {
+    key: String,
+    /// ban, counters, fixed, sliding, token, leaky, limitreq, gcra, slidinglog
+    target: String,
+    /// 新しいTTL（秒、BANのみ0で無期限）
+    ttl: u64,
+}
+
+/// リミット上書きリクエストのボディ
+#[derive(Debug, Deserialize)]
+struct LimitsRequest {
+    location: String,
+    /// 省略時は上書きを削除する
+    rate: Option<u32>,
+    burst: Option<u32>,
+    /// 上書きの有効期間（秒、0の場合は削除されるまで有効）
+    #[serde(default)]
+    ttl: u64,
+}
+
+// "ratelimit_redis_admin" ディレクティブの引数を解析する
+pub fn parse_admin_args(args: &[String]) -> Result<AdminConfig, String> {
+    if args.is_empty() {
+        return Err(
+            "Syntax: ratelimit_redis_admin on|off [allow=CIDR,...] [token=...|token_file=...]"
+                .to_string(),
+        );
+    }
+
+    let enabled = match args[0].as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err("ratelimit_redis_admin should be 'on' or 'off'".to_string()),
+    };
+
+    let mut config = AdminConfig {
+        enabled,
+        ..Default::default()
+    };
+
+    for arg in &args[1..] {
+        if arg.starts_with("allow=") {
+            for cidr in arg.trim_start_matches("allow=").split(',') {
+                config.allow.push(Cidr::parse(cidr)?);
+            }
+        } else if arg.starts_with("token=") {
+            let token = arg.trim_start_matches("token=").to_string();
+            if token.len() < 16 {
+                return Err(
+                    "ratelimit_redis_admin token must be at least 16 characters".to_string()
+                );
+            }
+            config.token = Some(token);
+        } else if arg.starts_with("token_file=") {
+            let path = arg.trim_start_matches("token_file=");
+            let token = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read token_file {}: {}", path, e))?
+                .trim()
+                .to_string();
+            if token.len() < 16 {
+                return Err(
+                    "ratelimit_redis_admin token must be at least 16 characters".to_string()
+                );
+            }
+            config.token = Some(token);
+        } else {
+            return Err(format!("Unknown parameter: {}", arg));
+        }
+    }
+
+    Ok(config)
+}
+
+// クエリ文字列から指定したパラメータを取り出す（パーセントデコード済み）
+pub fn query_param(args: &str, name: &str) -> Option<String> {
+    args.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        let k = parts.next()?;
+        let v = parts.next().unwrap_or("");
+        if percent_decode(k) == name {
+            Some(percent_decode(v))
+        } else {
+            None
+        }
+    })
+}
+
+// application/x-www-form-urlencoded形式の値をデコードする
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+            {
+                (Some(hi), Some(lo)) => {
+                    decoded.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+// JSONレスポンスを書き込む
+fn respond_json(r: &mut Request, status: Status, body: serde_json::Value) -> Status {
+    r.set_status(status);
+    r.headers_out().set("Content-Type", "application/json");
+    r.headers_out().set("Cache-Control", "no-store");
+    r.write_body(body.to_string().as_bytes());
+    Status::Done
+}
+
+fn respond_error(r: &mut Request, status: Status, message: &str) -> Status {
+    respond_json(r, status, json!({ "error": message }))
+}
+
+// 管理APIのリクエストハンドラ
+#[nginx_handler]
+pub async fn ratelimit_admin_handler(r: &mut Request) -> Status {
+    let location_path = r.get_location_path().to_string();
+
+    let admin_config = match admin_config(&location_path) {
+        Some(cfg) if cfg.enabled => cfg,
+        _ => return Status::Declined,
+    };
+
+    // 周囲のNGINX設定とは独立してアクセス制御を行う
+    let client_ip = r
+        .connection()
+        .remote_addr()
+        .and_then(|addr| acl::parse_ip(&addr.to_string()));
+    let authorization = r.headers_in().get("Authorization").map(|v| v.to_string());
+    if let Err(status) = admin_config.authorize(client_ip, authorization.as_deref()) {
+        warn!(
+            "Rejected admin request from {}",
+            client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+        );
+        if status == Status::Unauthorized {
+            r.headers_out().set("WWW-Authenticate", "Bearer");
+        }
+        return respond_error(r, status, "forbidden");
+    }
+
+    // Locationからの相対パスでルーティングする
+    let uri = r.uri().to_string();
+    let action = uri
+        .strip_prefix(&location_path)
+        .unwrap_or(&uri)
+        .trim_matches('/')
+        .to_string();
+    let method = r.method().to_string();
+    let args = r.args().to_string();
+    let body = r
+        .request_body()
+        .map(|b| String::from_utf8_lossy(&b).into_owned())
+        .unwrap_or_default();
+
+    let status = match (method.as_str(), action.as_str()) {
+        ("GET", "audit") => handle_audit(r, &args),
+        ("GET", "status") => handle_status(r),
+        ("POST", "reset") => handle_reset(r, &args),
+        ("GET", "usage") => handle_usage(r, &args),
+        ("POST", "ban") => handle_ban(r, &args, &body),
+        ("POST", "unban") => handle_unban(r, &args, &body),
+        ("POST", "ttl") => handle_set_ttl(r, &body),
+        ("GET", "limits") => handle_get_limits(r, &args),
+        ("POST", "limits") => handle_set_limits(r, &body),
+        ("GET", "keys") => handle_list_keys(r, &args),
+        ("GET", "top") => handle_top(r, &args),
+        ("POST", "bans/import") => handle_import_bans(r, &args, &body),
+        ("GET", "bans/export") => handle_export_bans(r),
+        ("POST", "cleanup") => handle_cleanup(r, &args),
+        ("POST", "migrate") => handle_migrate(r, &args),
+        (_, "audit")
+        | (_, "status")
+        | (_, "reset")
+        | (_, "usage")
+        | (_, "ban")
+        | (_, "unban")
+        | (_, "ttl")
+        | (_, "limits")
+        | (_, "keys")
+        | (_, "top")
+        | (_, "bans/import")
+        | (_, "bans/export")
+        | (_, "cleanup")
+        | (_, "migrate") => respond_error(r, Status::MethodNotAllowed, "method not allowed"),
+        _ => respond_error(r, Status::NotFound, "unknown admin endpoint"),
+    };
+
+    // 状態を変更する操作はすべて監査ログに記録する
+    if method == "POST" {
+        let actor = audit_actor(&admin_config, r.headers_in().get("X-Ratelimit-Actor"));
+        let source = client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        let result = if r.get_status() == Status::Ok {
+            "ok"
+        } else {
+            "error"
+        };
+        let entry = AuditEntry::new(
+            &actor,
+            &source,
+            &action,
+            &audit_params(&args, &body),
+            result,
+        );
+        // 記録の完了は応答に影響しないため、ワーカーを待たせずにランタイムで書き込む
+        runtime().spawn(async move {
+            match current_limiter() {
+                Some(limiter) => {
+                    let _ = limiter.record_audit(&entry).await;
+                }
+                None => warn!(
+                    "Audit: actor={} source={} action={} params={} result={} (not stored, Redis unavailable)",
+                    entry.actor, entry.source, entry.action, entry.params, entry.result
+                ),
+            }
+        });
+    }
+
+    status
+}
+
+// 操作者を表す文字列を作る
+//
+// 認証方式に加え、X-Ratelimit-Actor ヘッダーで申告された名前があれば付加する（検証はしない）
+fn audit_actor(config: &AdminConfig, declared: Option<&str>) -> String {
+    let method = if config.token.is_some() {
+        "bearer"
+    } else {
+        "allowlist"
+    };
+    match declared.map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        Some(name) => format!("{}:{}", method, name),
+        None => method.to_string(),
+    }
+}
+
+// 監査ログに記録するパラメータ（長いボディは切り詰める）
+fn audit_params(args: &str, body: &str) -> String {
+    let mut params = args.to_string();
+    let body = body.trim();
+    if !body.is_empty() {
+        if !params.is_empty() {
+            params.push(' ');
+        }
+        params.push_str(body);
+    }
+
+    if params.len() > AUDIT_PARAMS_MAX_LEN {
+        let mut end = AUDIT_PARAMS_MAX_LEN;
+        while !params.is_char_boundary(end) {
+            end -= 1;
+        }
+        params.truncate(end);
+        params.push_str("...");
+    }
+    params
+}
+
+// GET /audit?count=100 : 最近の管理操作を新しい順に返す
+fn handle_audit(r: &mut Request, args: &str) -> Status {
+    let count = match query_param(args, "count") {
+        Some(c) => match c.parse::<usize>() {
+            Ok(c) if c > 0 && c <= 1000 => c,
+            _ => return respond_error(r, Status::BadRequest, "invalid 'count' parameter"),
+        },
+        None => 100,
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.recent_audit(count).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(entries) => respond_json(r, Status::Ok, json!({ "entries": entries })),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /reset?key=... : キーに関連するすべてのカウンタを削除する
+fn handle_reset(r: &mut Request, args: &str) -> Status {
+    let key = match query_param(args, "key") {
+        Some(key) if !key.is_empty() => key,
+        _ => return respond_error(r, Status::BadRequest, "missing 'key' parameter"),
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.reset_key(&key).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(deleted) => {
+            info!("Admin reset for key {}: {} keys deleted", key, deleted);
+            respond_json(r, Status::Ok, json!({ "key": key, "deleted": deleted }))
+        }
+        Err(e) => {
+            error!("Admin reset failed for key {}: {}", key, e);
+            respond_error(r, Status::ServiceUnavailable, &e)
+        }
+    }
+}
+
+// GET /usage?key=... : キーの現在の使用状況を返す
+fn handle_usage(r: &mut Request, args: &str) -> Status {
+    let key = match query_param(args, "key") {
+        Some(key) if !key.is_empty() => key,
+        _ => return respond_error(r, Status::BadRequest, "missing 'key' parameter"),
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.get_usage(&key).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(usage) => match serde_json::to_value(&usage) {
+            Ok(body) => respond_json(r, Status::Ok, body),
+            Err(e) => respond_error(r, Status::InternalServerError, &e.to_string()),
+        },
+        Err(e) => {
+            error!("Admin usage query failed for key {}: {}", key, e);
+            respond_error(r, Status::ServiceUnavailable, &e)
+        }
+    }
+}
+
+// BANリクエストをJSONボディまたはクエリ文字列から解析する
+fn parse_ban_request(args: &str, body: &str) -> Result<BanRequest, String> {
+    let request = if body.trim().is_empty() {
+        let key = query_param(args, "key").unwrap_or_default();
+        let duration = match query_param(args, "duration") {
+            Some(d) => d
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid duration value: {}", d))?,
+            None => 0,
+        };
+        BanRequest { key, duration }
+    } else {
+        serde_json::from_str::<BanRequest>(body)
+            .map_err(|e| format!("Invalid request body: {}", e))?
+    };
+
+    if request.key.is_empty() {
+        return Err("missing 'key' parameter".to_string());
+    }
+    Ok(request)
+}
+
+// POST /ban {key, duration} : キーを手動でBANする
+fn handle_ban(r: &mut Request, args: &str, body: &str) -> Status {
+    let request = match parse_ban_request(args, body) {
+        Ok(request) => request,
+        Err(e) => return respond_error(r, Status::BadRequest, &e),
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.ban(&request.key, request.duration).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(_) => respond_json(
+            r,
+            Status::Ok,
+            json!({ "key": request.key, "banned": true, "duration": request.duration }),
+        ),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /ttl {key, target, ttl} : カウンタやBANを削除せずにTTLを延長・短縮する
+fn handle_set_ttl(r: &mut Request, body: &str) -> Status {
+    let request: TtlRequest = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(e) => return respond_error(r, Status::BadRequest, &format!("invalid body: {}", e)),
+    };
+    if request.key.is_empty() {
+        return respond_error(r, Status::BadRequest, "missing 'key'");
+    }
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => {
+                limiter
+                    .set_key_ttl(&request.key, &request.target, request.ttl)
+                    .await
+            }
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(updated) => respond_json(
+            r,
+            Status::Ok,
+            json!({
+                "key": request.key,
+                "target": request.target,
+                "ttl": request.ttl,
+                "updated": updated,
+            }),
+        ),
+        Err(e) if e.starts_with("Unknown TTL target") || e.starts_with("ttl must") => {
+            respond_error(r, Status::BadRequest, &e)
+        }
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /unban {key} : キーのBANを解除する
+fn handle_unban(r: &mut Request, args: &str, body: &str) -> Status {
+    let request = match parse_ban_request(args, body) {
+        Ok(request) => request,
+        Err(e) => return respond_error(r, Status::BadRequest, &e),
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.unban(&request.key).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(was_banned) => respond_json(
+            r,
+            Status::Ok,
+            json!({ "key": request.key, "banned": false, "was_banned": was_banned }),
+        ),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// GET /limits?location=... : Locationの実行時リミット上書きを返す
+fn handle_get_limits(r: &mut Request, args: &str) -> Status {
+    let location = match query_param(args, "location") {
+        Some(location) if !location.is_empty() => location,
+        _ => return respond_error(r, Status::BadRequest, "missing 'location' parameter"),
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.get_limit_override(&location).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(limits) => respond_json(
+            r,
+            Status::Ok,
+            json!({ "location": location, "override": limits }),
+        ),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /limits {location, rate, burst, ttl} : Locationのrate/burstを実行時に上書きする
+fn handle_set_limits(r: &mut Request, body: &str) -> Status {
+    let request = match serde_json::from_str::<LimitsRequest>(body) {
+        Ok(request) if !request.location.is_empty() => request,
+        Ok(_) => return respond_error(r, Status::BadRequest, "missing 'location' field"),
+        Err(e) => {
+            return respond_error(
+                r,
+                Status::BadRequest,
+                &format!("Invalid request body: {}", e),
+            )
+        }
+    };
+
+    let limits = match (request.rate, request.burst) {
+        // rate=0 はLocationのすべてのリクエストを拒否する（burst は使われないため0に限る）
+        (Some(rate), Some(burst)) if rate > 0 || burst == 0 => Some(LimitOverride { rate, burst }),
+        (None, None) => None,
+        _ => {
+            return respond_error(
+                r,
+                Status::BadRequest,
+                "'rate' and 'burst' must be set together ('burst' must be 0 when 'rate' is 0)",
+            )
+        }
+    };
+
+    let result = runtime().block_on(async {
+        match (current_limiter(), limits) {
+            (Some(limiter), Some(limits)) => limiter
+                .set_limit_override(&request.location, limits, request.ttl)
+                .await
+                .map(|_| true),
+            (Some(limiter), None) => limiter.clear_limit_override(&request.location).await,
+            (None, _) => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(_) => {
+            overrides::invalidate(&request.location);
+            info!(
+                "Admin limit override for {}: {:?} (ttl={}s)",
+                request.location, limits, request.ttl
+            );
+            respond_json(
+                r,
+                Status::Ok,
+                json!({ "location": request.location, "override": limits, "ttl": request.ttl }),
+            )
+        }
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// GET /keys?cursor=0&count=100 : 追跡中のキーをページ単位で返す
+fn handle_list_keys(r: &mut Request, args: &str) -> Status {
+    let cursor = match query_param(args, "cursor").map(|c| c.parse::<u64>()) {
+        Some(Ok(cursor)) => cursor,
+        Some(Err(_)) => return respond_error(r, Status::BadRequest, "invalid 'cursor' parameter"),
+        None => 0,
+    };
+    let count = match query_param(args, "count").map(|c| c.parse::<usize>()) {
+        Some(Ok(count)) if count > 0 && count <= 1000 => count,
+        Some(_) => {
+            return respond_error(r, Status::BadRequest, "'count' must be between 1 and 1000")
+        }
+        None => 100,
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.list_keys(cursor, count).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok((next, keys)) => respond_json(
+            r,
+            Status::Ok,
+            json!({ "cursor": next, "complete": next == 0, "keys": keys }),
+        ),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// GET /top?count=10 : 時間窓のカウンタでリクエスト数の多いキーを返す
+fn handle_top(r: &mut Request, args: &str) -> Status {
+    let count = match query_param(args, "count").map(|c| c.parse::<usize>()) {
+        Some(Ok(count)) if count > 0 && count <= 1000 => count,
+        Some(_) => {
+            return respond_error(r, Status::BadRequest, "'count' must be between 1 and 1000")
+        }
+        None => 10,
+    };
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.top_keys(count).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(top) => {
+            let keys: Vec<_> = top
+                .into_iter()
+                .map(|(key, requests)| json!({ "key": key, "requests": requests }))
+                .collect();
+            respond_json(r, Status::Ok, json!({ "keys": keys }))
+        }
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /bans/import?duration=... : 改行区切り/CSVのBANリストを一括登録する
+fn handle_import_bans(r: &mut Request, args: &str, body: &str) -> Status {
+    let default_duration = match query_param(args, "duration").map(|d| d.parse::<u64>()) {
+        Some(Ok(d)) => d,
+        Some(Err(_)) => {
+            return respond_error(r, Status::BadRequest, "invalid 'duration' parameter")
+        }
+        None => 0,
+    };
+
+    let (entries, mut errors) = banlist::parse_ban_list(body, default_duration);
+    if entries.is_empty() && errors.is_empty() {
+        return respond_error(r, Status::BadRequest, "empty ban list");
+    }
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => Ok(banlist::import(limiter, &entries).await),
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(mut summary) => {
+            errors.append(&mut summary.errors);
+            summary.errors = errors;
+            info!(
+                "Admin ban import: {} keys, {} CIDRs, {} errors",
+                summary.keys,
+                summary.cidrs,
+                summary.errors.len()
+            );
+            match serde_json::to_value(&summary) {
+                Ok(body) => respond_json(r, Status::Ok, body),
+                Err(e) => respond_error(r, Status::InternalServerError, &e.to_string()),
+            }
+        }
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// GET /bans/export : 現在のBAN一覧をCSVで返す
+fn handle_export_bans(r: &mut Request) -> Status {
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => banlist::export(limiter).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(csv) => {
+            r.set_status(Status::Ok);
+            r.headers_out().set("Content-Type", "text/csv");
+            r.headers_out().set("Cache-Control", "no-store");
+            r.write_body(csv.as_bytes());
+            Status::Done
+        }
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /cleanup?cursor=0&dry_run=1&batch_size=100&max_age=86400 : 残存キーをSCANの1回分だけ削除する
+//
+// キー空間全体の走査はワーカーを長時間止めるため、1回の呼び出しでは1ページだけ処理する。
+// 応答の cursor を渡して complete になるまで繰り返すか、ngx-ratelimit-ctl cleanup を使う
+fn handle_cleanup(r: &mut Request, args: &str) -> Status {
+    let mut options = CleanupOptions::default();
+    let cursor = match query_param(args, "cursor").map(|c| c.parse::<u64>()) {
+        Some(Ok(cursor)) => cursor,
+        Some(Err(_)) => return respond_error(r, Status::BadRequest, "invalid 'cursor' parameter"),
+        None => 0,
+    };
+
+    if let Some(v) = query_param(args, "dry_run") {
+        options.dry_run = matches!(v.as_str(), "1" | "true" | "on");
+    }
+    if let Some(v) = query_param(args, "batch_size") {
+        match v.parse::<usize>() {
+            Ok(n) if n > 0 && n <= 1000 => options.batch_size = n,
+            _ => {
+                return respond_error(
+                    r,
+                    Status::BadRequest,
+                    "'batch_size' must be between 1 and 1000",
+                )
+            }
+        }
+    }
+    if let Some(v) = query_param(args, "max_age") {
+        match v.parse::<u64>() {
+            Ok(n) => options.max_window_age = n,
+            Err(_) => return respond_error(r, Status::BadRequest, "invalid 'max_age' parameter"),
+        }
+    }
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.cleanup_keys_page(cursor, &options).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok((next, report)) => match serde_json::to_value(&report) {
+            Ok(mut body) => {
+                body["cursor"] = json!(next);
+                body["complete"] = json!(next == 0);
+                respond_json(r, Status::Ok, body)
+            }
+            Err(e) => respond_error(r, Status::InternalServerError, &e.to_string()),
+        },
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// POST /migrate?dry_run=1 : 旧形式のキーを現在のスキーマバージョンに移行する
+fn handle_migrate(r: &mut Request, args: &str) -> Status {
+    let dry_run = query_param(args, "dry_run")
+        .map(|v| matches!(v.as_str(), "1" | "true" | "on"))
+        .unwrap_or(false);
+
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.migrate_keys(100, dry_run).await,
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok(report) => match serde_json::to_value(&report) {
+            Ok(body) => respond_json(r, Status::Ok, body),
+            Err(e) => respond_error(r, Status::InternalServerError, &e.to_string()),
+        },
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
+
+// GET /status : キー形式のバージョン、Redisの機能と判定の実行方法、有効なLuaスクリプトを返す
+//
+// スクリプトキャッシュから消えているスクリプトはここで再ロードされる
+fn handle_status(r: &mut Request) -> Status {
+    let result = runtime().block_on(async {
+        match current_limiter() {
+            Some(limiter) => limiter.preload_scripts().await.map(|scripts| {
+                (
+                    scripts,
+                    limiter.capabilities().clone(),
+                    limiter.execution_mode(),
+                )
+            }),
+            None => Err("Redis Rate Limiter not initialized".to_string()),
+        }
+    });
+
+    match result {
+        Ok((scripts, capabilities, mode)) => respond_json(
+            r,
+            Status::Ok,
+            json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "key_schema_version": KEY_SCHEMA_VERSION,
+                "redis": {
+                    "version": capabilities.version_string(),
+                    "functions": capabilities.functions,
+                    "scripting": capabilities.scripting,
+                    "resp3": capabilities.resp3,
+                    "cluster": capabilities.cluster,
+                },
+                "execution_mode": mode,
+                "scripts": scripts,
+            }),
+        ),
+        Err(e) => respond_error(r, Status::ServiceUnavailable, &e),
+    }
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+
+use crate::ban::BanPolicy;
+use crate::redis_client::{
+    CounterKey, LimitOverride, QuotaDecision, QuotaPeriod, RateLimitDecision, RedisRateLimiter,
+};
+
+/// レート制限の状態を保持するバックエンド
+///
+/// NGINXのグルーコードはこのトレイトを通してのみ判定を行う。
+/// 新しいバックエンド（memcached、サイドカーなど）はこのトレイトを実装すれば追加できる
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// バックエンドの名前（ログ出力用）
+    fn name(&self) -> &'static str;
+
+    /// 上書き設定がない場合に適用されるレート・バースト
+    fn default_limits(&self) -> LimitOverride;
+
+    /// レート・バーストを指定してキーのレート制限をチェックする
+    async fn check_rate_limit_with(
+        &self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String>;
+
+    /// デフォルトのレート・バーストでキーのレート制限をチェックする
+    async fn check_rate_limit(&self, key: &str) -> Result<RateLimitDecision, String> {
+        let limits = self.default_limits();
+        self.check_rate_limit_with(key, limits.rate, limits.burst)
+            .await
+    }
+
+    /// 複数のカウンタのレート制限をまとめてチェックする
+    ///
+    /// ゾーンのキーとLocation全体のカウンタ（global_rate=）をキーごとの判定と一緒に数える。
+    /// 判定はチェックと同じ順に返す。拒否したチェックがあっても残りのカウンタは数える
+    async fn check_rate_limits(
+        &self,
+        checks: &[(CounterKey<'_>, u32, u32)],
+    ) -> Result<Vec<RateLimitDecision>, String>;
+
+    /// キーがBANされているか
+    async fn is_banned(&self, key: &str) -> Result<bool, String>;
+
+    /// 不審な応答が多いため上限が引き下げられているか（abuse_action=tighten）
+    async fn is_penalized(&self, key: &str) -> Result<bool, String>;
+
+    /// BANされているCIDRと残り秒数（無期限はNone）
+    async fn banned_cidrs(&self) -> Result<Vec<(String, Option<u64>)>, String>;
+
+    /// ロケーションに設定された実行時の上書き
+    async fn get_limit_override(&self, location: &str) -> Result<Option<LimitOverride>, String>;
+
+    /// 拒否したリクエストを数え、閾値に達したキーをBANする（ban_after=）
+    ///
+    /// BANした場合は (違反歴の回数, BANの期間) を返す
+    async fn record_rejection(
+        &self,
+        key: &str,
+        policy: &BanPolicy,
+    ) -> Result<Option<(u64, u64)>, String>;
+
+    /// キーごとに保存されたリミット（key_limits=on）
+    async fn get_key_limits(&self, key: &str) -> Result<Option<LimitOverride>, String>;
+
+    /// 暦の期間ごとのクォータを1件消費する（quota=）
+    async fn consume_quota(
+        &self,
+        key: &str,
+        quota: u64,
+        period: QuotaPeriod,
+    ) -> Result<QuotaDecision, String>;
+}
+
+#[async_trait]
+impl RateLimitBackend for RedisRateLimiter {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn default_limits(&self) -> LimitOverride {
+        RedisRateLimiter::default_limits(self)
+    }
+
+    async fn check_rate_limit_with(
+        &self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
+        RedisRateLimiter::check_rate_limit_with(self, key, rate, burst).await
+    }
+
+    async fn check_rate_limits(
+        &self,
+        checks: &[(CounterKey<'_>, u32, u32)],
+    ) -> Result<Vec<RateLimitDecision>, String> {
+        // 1回のパイプラインで実行する
+        RedisRateLimiter::check_rate_limits(self, checks).await
+    }
+
+    async fn is_banned(&self, key: &str) -> Result<bool, String> {
+        RedisRateLimiter::is_banned(self, key).await
+    }
+
+    async fn is_penalized(&self, key: &str) -> Result<bool, String> {
+        RedisRateLimiter::is_penalized(self, key).await
+    }
+
+    async fn banned_cidrs(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        RedisRateLimiter::banned_cidrs(self).await
+    }
+
+    async fn get_limit_override(&self, location: &str) -> Result<Option<LimitOverride>, String> {
+        RedisRateLimiter::get_limit_override(self, location).await
+    }
+
+    async fn record_rejection(
+        &self,
+        key: &str,
+        policy: &BanPolicy,
+    ) -> Result<Option<(u64, u64)>, String> {
+        RedisRateLimiter::record_rejection(self, key, policy).await
+    }
+
+    async fn get_key_limits(&self, key: &str) -> Result<Option<LimitOverride>, String> {
+        RedisRateLimiter::get_key_limits(self, key).await
+    }
+
+    async fn consume_quota(
+        &self,
+        key: &str,
+        quota: u64,
+        period: QuotaPeriod,
+    ) -> Result<QuotaDecision, String> {
+        RedisRateLimiter::consume_quota(self, key, quota, period).await
+    }
+}
@@ -0,0 +1,89 @@
+use log::{error, warn};
+
+use crate::backend::RateLimitBackend;
+
+/// 拒否が続いたキーを自動的にBANする条件（ban_after=）
+///
+/// ban_window 秒の時間窓で ban_after 回拒否されたキーをBANする。BANされるたびに違反歴を数え、
+/// 繰り返し違反するキーのBAN期間は ban_duration から倍々に延ばす（ban_max_duration まで）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BanPolicy {
+    /// BANするまでに許容する時間窓内の拒否数（0は無効）
+    pub after: u32,
+    /// 拒否数を数える時間窓（秒）
+    pub window: u64,
+    /// 1回目のBANの期間（秒）
+    pub duration: u64,
+    /// 延長したBANの期間の上限（秒）
+    pub max_duration: u64,
+}
+
+impl Default for BanPolicy {
+    fn default() -> Self {
+        Self {
+            after: 0,
+            window: 60,
+            duration: 300,
+            max_duration: 86400,
+        }
+    }
+}
+
+impl BanPolicy {
+    /// 自動BANが有効か
+    pub fn enabled(&self) -> bool {
+        self.after > 0
+    }
+
+    /// offense 回目（1始まり）のBANの期間
+    pub fn duration_for(&self, offense: u64) -> u64 {
+        let shift = offense.saturating_sub(1).min(63) as u32;
+        self.duration
+            .checked_mul(1 << shift)
+            .map_or(self.max_duration, |duration| {
+                duration.min(self.max_duration)
+            })
+            .max(1)
+    }
+
+    /// BANの後に違反歴を保持する期間
+    ///
+    /// BANが解けてから ban_max_duration 秒の間に再びBANされると、期間を延長する
+    pub fn offense_ttl(&self, duration: u64) -> u64 {
+        duration.saturating_add(self.max_duration)
+    }
+
+    /// 設定値を検証する
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        if self.window == 0 {
+            return Err("ban_window must be greater than 0".to_string());
+        }
+        if self.duration == 0 {
+            return Err("ban_duration must be greater than 0".to_string());
+        }
+        if self.max_duration < self.duration {
+            return Err(format!(
+                "ban_max_duration ({}) must not be less than ban_duration ({})",
+                self.max_duration, self.duration
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 拒否したリクエストを数え、閾値に達したキーをBANする
+///
+/// BANできなくても判定は変わらないため、エラーはログに残すだけにする
+pub async fn record_rejection(limiter: &dyn RateLimitBackend, key: &str, policy: &BanPolicy) {
+    match limiter.record_rejection(key, policy).await {
+        Ok(Some((offense, duration))) => warn!(
+            "{} was rejected {} times within {}s, banned for {}s (offense {})",
+            key, policy.after, policy.window, duration, offense
+        ),
+        Ok(None) => {}
+        Err(e) => error!("Failed to record rejection of {}: {}", key, e),
+    }
+}
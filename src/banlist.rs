@@ -0,0 +1,185 @@
+use lazy_static::lazy_static;
+use log::{error, warn};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::acl::Cidr;
+use crate::backend::RateLimitBackend;
+use crate::redis_client::RedisRateLimiter;
+
+/// RedisのCIDR BAN一覧をローカルに保持する時間
+const CIDR_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// BANの対象
+#[derive(Debug, Clone, PartialEq)]
+pub enum BanTarget {
+    /// レート制限キー（IPアドレスやAPIキー）
+    Key(String),
+    /// CIDR範囲（クライアントIPに対して適用）
+    Cidr(Cidr),
+}
+
+/// インポートするBANエントリ
+#[derive(Debug, Clone, PartialEq)]
+pub struct BanEntry {
+    pub target: BanTarget,
+    /// BAN期間（秒、0の場合は無期限）
+    pub duration: u64,
+}
+
+/// インポート結果
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ImportSummary {
+    pub keys: usize,
+    pub cidrs: usize,
+    pub errors: Vec<String>,
+}
+
+lazy_static! {
+    static ref CIDR_CACHE: Mutex<Option<(Instant, Vec<Cidr>)>> = Mutex::new(None);
+}
+
+/// 改行区切り/CSV形式のBANリストを解析する
+///
+/// 各行は `<キーまたはCIDR>[,<秒数>]`。空行と `#` で始まる行は無視する。
+/// 秒数を省略した行には `default_duration` が適用される。
+pub fn parse_ban_list(text: &str, default_duration: u64) -> (Vec<BanEntry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(',').map(|f| f.trim());
+        let target = fields.next().unwrap_or("");
+        if target.is_empty() {
+            errors.push(format!("line {}: empty entry", lineno + 1));
+            continue;
+        }
+
+        let duration = match fields.next() {
+            Some(d) if !d.is_empty() => match d.parse::<u64>() {
+                Ok(d) => d,
+                Err(_) => {
+                    errors.push(format!("line {}: invalid duration: {}", lineno + 1, d));
+                    continue;
+                }
+            },
+            _ => default_duration,
+        };
+
+        // "/" を含むエントリはCIDRとして扱う
+        let target = if target.contains('/') {
+            match Cidr::parse(target) {
+                Ok(cidr) => BanTarget::Cidr(cidr),
+                Err(e) => {
+                    errors.push(format!("line {}: {}", lineno + 1, e));
+                    continue;
+                }
+            }
+        } else {
+            BanTarget::Key(target.to_string())
+        };
+
+        entries.push(BanEntry { target, duration });
+    }
+
+    (entries, errors)
+}
+
+/// 解析済みのBANエントリをRedisに登録する
+pub async fn import(limiter: &RedisRateLimiter, entries: &[BanEntry]) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+
+    for entry in entries {
+        let result = match &entry.target {
+            BanTarget::Key(key) => limiter.ban(key, entry.duration).await.map(|_| {
+                summary.keys += 1;
+            }),
+            BanTarget::Cidr(cidr) => limiter
+                .ban_cidr(&cidr.to_string(), entry.duration)
+                .await
+                .map(|_| {
+                    summary.cidrs += 1;
+                }),
+        };
+        if let Err(e) = result {
+            summary.errors.push(e);
+        }
+    }
+
+    invalidate_cache();
+    summary
+}
+
+/// 現在のBAN一覧をインポートと同じCSV形式で出力する（残り秒数0は無期限）
+pub async fn export(limiter: &RedisRateLimiter) -> Result<String, String> {
+    let mut out = String::from("# target,remaining_seconds\n");
+
+    for (key, remaining) in limiter.list_bans().await? {
+        out.push_str(&format!("{},{}\n", key, remaining.unwrap_or(0)));
+    }
+    for (cidr, remaining) in limiter.banned_cidrs().await? {
+        out.push_str(&format!("{},{}\n", cidr, remaining.unwrap_or(0)));
+    }
+
+    Ok(out)
+}
+
+/// クライアントIPがBANされたCIDR範囲に含まれるか
+///
+/// CIDR一覧は数秒間キャッシュする。Redisから取得できない場合は直前の一覧を使う。
+pub async fn is_ip_banned(limiter: &dyn RateLimitBackend, ip: &IpAddr) -> bool {
+    let cached = CIDR_CACHE.lock().ok().and_then(|cache| {
+        cache
+            .as_ref()
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < CIDR_CACHE_TTL)
+            .map(|(_, cidrs)| cidrs.iter().any(|c| c.contains(ip)))
+    });
+    if let Some(banned) = cached {
+        return banned;
+    }
+
+    match limiter.banned_cidrs().await {
+        Ok(entries) => {
+            let cidrs: Vec<Cidr> = entries
+                .iter()
+                .filter_map(|(cidr, _)| match Cidr::parse(cidr) {
+                    Ok(cidr) => Some(cidr),
+                    Err(e) => {
+                        warn!("Ignoring invalid banned CIDR {}: {}", cidr, e);
+                        None
+                    }
+                })
+                .collect();
+            let banned = cidrs.iter().any(|c| c.contains(ip));
+            if let Ok(mut cache) = CIDR_CACHE.lock() {
+                *cache = Some((Instant::now(), cidrs));
+            }
+            banned
+        }
+        Err(e) => {
+            error!("Failed to refresh banned CIDRs: {}", e);
+            CIDR_CACHE
+                .lock()
+                .ok()
+                .and_then(|cache| {
+                    cache
+                        .as_ref()
+                        .map(|(_, cidrs)| cidrs.iter().any(|c| c.contains(ip)))
+                })
+                .unwrap_or(false)
+        }
+    }
+}
+
+/// CIDRキャッシュを破棄してこのワーカーに即時反映する
+pub fn invalidate_cache() {
+    if let Ok(mut cache) = CIDR_CACHE.lock() {
+        *cache = None;
+    }
+}
@@ -0,0 +1,246 @@
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::banlist;
+use crate::redis_client::RedisRateLimiter;
+
+/// ファイルへの保存間隔のデフォルト
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// BANをローカルファイルに保存する設定（"ratelimit_redis_ban_store" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct BanStoreSettings {
+    /// 保存先のファイル
+    pub path: PathBuf,
+    /// Redisと同期する間隔
+    pub interval: Duration,
+}
+
+impl BanStoreSettings {
+    /// "<パス> [interval=<秒>]" 形式の引数を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (path, rest) = match args.split_first() {
+            Some((path, rest)) if !path.contains('=') => (path, rest),
+            _ => {
+                return Err(
+                    "Syntax: ratelimit_redis_ban_store /path/to/bans.csv [interval=<s>]"
+                        .to_string(),
+                )
+            }
+        };
+
+        let mut interval = DEFAULT_INTERVAL;
+        for arg in rest {
+            match arg.strip_prefix("interval=") {
+                Some(value) => match value.parse::<u64>() {
+                    Ok(secs) if secs > 0 => interval = Duration::from_secs(secs),
+                    _ => return Err(format!("Invalid interval value: {}", value)),
+                },
+                None => {
+                    return Err(format!(
+                        "Unknown ratelimit_redis_ban_store parameter: {}",
+                        arg
+                    ))
+                }
+            }
+        }
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            interval,
+        })
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<BanStoreSettings>> = Mutex::new(None);
+}
+
+/// 設定を保持する（同期はワーカーで行う）
+pub fn configure(settings: BanStoreSettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+/// 現在の設定
+pub fn settings() -> Option<BanStoreSettings> {
+    SETTINGS.lock().ok().and_then(|slot| slot.clone())
+}
+
+/// 保存されたBAN1件
+#[derive(Debug, Clone, PartialEq)]
+struct StoredBan {
+    cidr: bool,
+    /// 解除時刻（UNIXエポックからの秒数、0は無期限）
+    expires_at: u64,
+    target: String,
+}
+
+/// 同期の結果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncResult {
+    /// RedisのBANをファイルに保存した
+    Saved(usize),
+    /// RedisにBANがなかったため、ファイルからBANを復元した
+    Restored(usize),
+    /// RedisにもファイルにもBANがなかった
+    Empty,
+}
+
+/// RedisのBANとファイルを同期する
+///
+/// RedisにBANが1件もなく、ファイルに有効なBANが残っている場合は、Redisの再起動や
+/// フェイルオーバーでBANが失われたものとみなしてファイルから復元する。
+/// それ以外の場合は現在のBANでファイルを置き換える
+pub async fn sync(limiter: &RedisRateLimiter, path: &Path) -> Result<SyncResult, String> {
+    let now = now_secs()?;
+    let keys = limiter.list_bans().await?;
+    let cidrs = limiter.banned_cidrs().await?;
+
+    if keys.is_empty() && cidrs.is_empty() {
+        let stored = match load(path, now) {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("{}", e);
+                Vec::new()
+            }
+        };
+        if stored.is_empty() {
+            return Ok(SyncResult::Empty);
+        }
+        return restore(limiter, &stored, now)
+            .await
+            .map(SyncResult::Restored);
+    }
+
+    let mut stored = Vec::with_capacity(keys.len() + cidrs.len());
+    for (target, remaining) in keys {
+        stored.push(StoredBan {
+            cidr: false,
+            expires_at: remaining.map_or(0, |remaining| now + remaining),
+            target,
+        });
+    }
+    for (target, remaining) in cidrs {
+        stored.push(StoredBan {
+            cidr: true,
+            expires_at: remaining.map_or(0, |remaining| now + remaining),
+            target,
+        });
+    }
+    save(path, &stored)?;
+    Ok(SyncResult::Saved(stored.len()))
+}
+
+// 保存されたBANをRedisに登録する（残り期間はファイルに記録した解除時刻から求める）
+async fn restore(
+    limiter: &RedisRateLimiter,
+    stored: &[StoredBan],
+    now: u64,
+) -> Result<usize, String> {
+    let remaining = |ban: &StoredBan| {
+        if ban.expires_at == 0 {
+            0
+        } else {
+            ban.expires_at - now
+        }
+    };
+
+    let mut restored = 0;
+    for ban in stored.iter().filter(|ban| !ban.cidr) {
+        match limiter.ban(&ban.target, remaining(ban)).await {
+            Ok(()) => restored += 1,
+            Err(e) => error!("Failed to restore ban of {}: {}", ban.target, e),
+        }
+    }
+    let cidrs: Vec<(String, u64)> = stored
+        .iter()
+        .filter(|ban| ban.cidr)
+        .map(|ban| (ban.target.clone(), remaining(ban)))
+        .collect();
+    if !cidrs.is_empty() {
+        restored += limiter.ban_cidrs_batch(&cidrs).await?;
+        banlist::invalidate_cache();
+    }
+
+    info!("Restored {} bans from the local ban store", restored);
+    Ok(restored)
+}
+
+// ファイルから有効なBANを読み込む（期限切れのエントリは除く）
+//
+// 各行は "key|cidr,<解除時刻>,<対象>"。対象にはカンマを含められるよう最後の列に置く
+fn load(path: &Path, now: u64) -> Result<Vec<StoredBan>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read ban store {:?}: {}", path, e)),
+    };
+
+    let mut stored = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, ',');
+        let ban = match (fields.next(), fields.next(), fields.next()) {
+            (Some(kind @ ("key" | "cidr")), Some(expires_at), Some(target))
+                if !target.is_empty() =>
+            {
+                expires_at.parse::<u64>().ok().map(|expires_at| StoredBan {
+                    cidr: kind == "cidr",
+                    expires_at,
+                    target: target.to_string(),
+                })
+            }
+            _ => None,
+        };
+        match ban {
+            Some(ban) if ban.expires_at == 0 || ban.expires_at > now => stored.push(ban),
+            Some(_) => {}
+            None => warn!("Ignoring invalid ban store line {}: {}", lineno + 1, line),
+        }
+    }
+    Ok(stored)
+}
+
+// ファイルを置き換える（一時ファイルに書き込んでからリネームし、読み込み途中の破損を防ぐ）
+fn save(path: &Path, stored: &[StoredBan]) -> Result<(), String> {
+    let mut out = String::from("# kind,expires_at,target\n");
+    for ban in stored {
+        out.push_str(&format!(
+            "{},{},{}\n",
+            if ban.cidr { "cidr" } else { "key" },
+            ban.expires_at,
+            ban.target
+        ));
+    }
+
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, out).map_err(|e| format!("Failed to write ban store {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("Failed to replace ban store {:?}: {}", path, e)
+    })
+}
+
+/// ファイルが interval 以内に更新されているか（他のワーカーが保存済みであれば書き込まない）
+pub fn is_fresh(path: &Path, interval: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map_or(false, |age| age < interval / 2)
+}
+
+fn now_secs() -> Result<u64, String> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .map_err(|_| "SystemTime before UNIX EPOCH!".to_string())
+}
@@ -0,0 +1,854 @@
+// ngx_ratelimit_redis の運用コマンドラインツール
+//
+// モジュール本体と同じ実装を lib フィーチャーのライブラリとして使用するため、
+// Redisキーの形式やLuaスクリプトはNGINX上で動作するモジュールと常に一致する。
+
+#[cfg(feature = "lua-emulator")]
+use ngx_ratelimit_redis::{check_with_executor, LuaExecutor};
+use ngx_ratelimit_redis::{
+    export_bans, import_bans, parse_ban_list, AuditEntry, CleanupOptions, Clock, ConfigFile,
+    ManualClock, RateLimitAlgorithm, RateLimitConfig, RateLimitDecision, RedisRateLimiter,
+    Replayer, SystemClock,
+};
+#[cfg(feature = "blocklist")]
+use ngx_ratelimit_redis::{sync_blocklist, BlocklistSource};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: ngx-ratelimit-ctl [options] <command> [args]
+
+Commands:
+  usage <key>              Show current usage for a key
+  reset <key>              Delete all counters and bans for a key
+  ban <key> [seconds]      Ban a key (0 or omitted = until unbanned)
+  unban <key>              Lift a ban
+  ttl <key> <target> <s>   Set the TTL of a key's ban/counters without deleting them
+                           (target: ban, counters, fixed, sliding, token, leaky, limitreq,
+                            gcra, slidinglog)
+  import-bans <file> [s]   Import a newline/CSV ban list ('-' reads stdin)
+  export-bans              Print the current bans as CSV
+  top [count]              List keys with the most requests (default: 10)
+  keys [cursor] [count]    List tracked keys page by page using SCAN
+  migrate                  Rename keys from older key-format versions
+  cleanup [max_age]        Delete orphaned/lingering keys (default max_age: 86400s)
+  validate <config.json>   Validate a configuration file
+  show-config <config.json> [location]
+                           Print the effective (default-merged) settings of every location
+  replay <config.json> <access.log> [count]
+                           Replay an access log ('-' reads stdin) against a configuration and
+                           show how many requests would have been limited (default: top 20 keys)
+  simulate <schedule> [start]
+                           Check the keys of a schedule ('-' reads stdin) at simulated times
+                           (lines: \"<ms after start> <key>\", start: UNIX time in seconds,
+                            default: the start of the current hour)
+  diff <active> <candidate>
+                           Show per-location limit changes between two configuration files
+  preload-scripts          Load missing Lua scripts and print their versions and SHA1
+  audit [count]            Show recent admin operations (default: 20)
+  sync-blocklist <source>  Ban the CIDRs of an external blocklist (requires the blocklist feature)
+                           (source: crowdsec:<lapi-url> or an http(s) URL of IPs/CIDRs)
+
+Options:
+  --redis-url <url>        Redis server URL (default: redis://127.0.0.1:6379)
+  --password <password>    Redis password
+  --database <n>           Redis database number
+  --config <config.json>   Take Redis and limit settings from a configuration file
+  --location <path>        Location in the configuration file (default: default settings)
+  --algorithm <name>       Algorithm used to interpret counters for 'usage' and checked by
+                           'simulate'
+  --emulate                Run 'simulate' on the embedded Lua emulator instead of Redis
+                           (requires the lua-emulator feature)
+  --dry-run                Report what 'cleanup' would delete without deleting
+  --batch-size <n>         Keys per SCAN/DEL batch for 'cleanup' (default: 100)
+  --pause-ms <ms>          Pause between 'cleanup' batches (default: 50)
+  --api-key <key>          CrowdSec bouncer API key for 'sync-blocklist'
+  --interval <s>           Repeat 'sync-blocklist' every s seconds (default: 0, run once)
+  --ttl <s>                Ban duration for blocklist entries without one
+                           (default: 3 x interval, or 86400 when run once)
+  --help                   Show this help";
+
+// コマンドライン引数
+struct Options {
+    redis_url: Option<String>,
+    password: Option<String>,
+    database: Option<i64>,
+    config_path: Option<String>,
+    location: Option<String>,
+    algorithm: Option<RateLimitAlgorithm>,
+    cleanup: CleanupOptions,
+    api_key: Option<String>,
+    interval: u64,
+    ttl: Option<u64>,
+    emulate: bool,
+    command: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        redis_url: None,
+        password: None,
+        database: None,
+        config_path: None,
+        location: None,
+        algorithm: None,
+        cleanup: CleanupOptions::default(),
+        api_key: None,
+        interval: 0,
+        ttl: None,
+        emulate: false,
+        command: Vec::new(),
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+
+        match arg.as_str() {
+            "--redis-url" => options.redis_url = Some(value("--redis-url")?),
+            "--password" => options.password = Some(value("--password")?),
+            "--database" => {
+                let db = value("--database")?;
+                options.database = Some(
+                    db.parse::<i64>()
+                        .map_err(|_| format!("Invalid database value: {}", db))?,
+                );
+            }
+            "--config" => options.config_path = Some(value("--config")?),
+            "--location" => options.location = Some(value("--location")?),
+            "--algorithm" => {
+                options.algorithm = Some(RateLimitAlgorithm::from_str(&value("--algorithm")?)?)
+            }
+            "--dry-run" => options.cleanup.dry_run = true,
+            "--batch-size" => {
+                let n = value("--batch-size")?;
+                options.cleanup.batch_size = n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid batch size: {}", n))?;
+            }
+            "--pause-ms" => {
+                let n = value("--pause-ms")?;
+                options.cleanup.pause_ms = n
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid pause: {}", n))?;
+            }
+            "--api-key" => options.api_key = Some(value("--api-key")?),
+            "--interval" => {
+                let n = value("--interval")?;
+                options.interval = n
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid interval: {}", n))?;
+            }
+            "--ttl" => {
+                let n = value("--ttl")?;
+                options.ttl = Some(
+                    n.parse::<u64>()
+                        .map_err(|_| format!("Invalid TTL: {}", n))?,
+                );
+            }
+            "--emulate" => options.emulate = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => options.command.push(arg.clone()),
+        }
+    }
+
+    if options.command.is_empty() {
+        return Err("No command specified".to_string());
+    }
+
+    Ok(options)
+}
+
+// オプションからリミッターの設定を組み立てる
+fn limiter_config(options: &Options) -> Result<RateLimitConfig, String> {
+    let mut config = RateLimitConfig::default();
+
+    if let Some(path) = &options.config_path {
+        let config_file = ConfigFile::from_file(path)?;
+        let settings = match &options.location {
+            Some(location) => config_file.get_settings(location),
+            None => config_file.default.clone(),
+        };
+        config.redis_url = settings.redis_url;
+        config.requests_per_second = settings.rate;
+        config.burst = settings.burst;
+        config.algorithm = ConfigFile::parse_algorithm(&settings.algorithm)?;
+        config.window_size = settings.window_size;
+        config.rate_period = settings.rate_period;
+        config.redis_options = settings.redis_options;
+    }
+
+    if let Some(url) = &options.redis_url {
+        config.redis_url = url.clone();
+    }
+    if let Some(password) = &options.password {
+        config.redis_options.password = Some(password.clone());
+    }
+    if let Some(database) = options.database {
+        config.redis_options.database = database;
+    }
+    if let Some(algorithm) = options.algorithm {
+        config.algorithm = algorithm;
+    }
+
+    // 対話的なツールなので接続リトライは行わない
+    config.redis_options.retry_count = 0;
+    Ok(config)
+}
+
+// 必須の位置引数を取り出す
+fn required_arg<'a>(command: &'a [String], index: usize, name: &str) -> Result<&'a str, String> {
+    command
+        .get(index)
+        .map(|s| s.as_str())
+        .ok_or_else(|| format!("Missing argument: <{}>", name))
+}
+
+fn validate_config(path: &str) -> Result<(), String> {
+    let config_file = ConfigFile::from_file(path)?;
+    match config_file.validate() {
+        Ok(()) => {
+            println!("{}: OK ({} locations)", path, config_file.locations.len());
+            Ok(())
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}: {}", path, e);
+            }
+            Err(format!("{} error(s) found", errors.len()))
+        }
+    }
+}
+
+fn show_config(path: &str, location: Option<&str>) -> Result<(), String> {
+    let config_file = ConfigFile::from_file(path)?;
+
+    // デフォルト設定に続けて、Locationをパスの順に出力する
+    let sections: Vec<&str> = match location {
+        Some(location) => vec![location],
+        None => std::iter::once("default")
+            .chain(
+                config_file
+                    .locations
+                    .keys()
+                    .map(|s| s.as_str())
+                    .collect::<std::collections::BTreeSet<_>>(),
+            )
+            .collect(),
+    };
+
+    for section in sections {
+        println!("{}", section);
+        for (field, value) in config_file.effective_fields(section) {
+            println!("  {:<32} {}", field, value);
+        }
+    }
+    Ok(())
+}
+
+// スケジュールを読み込む（各行は "<開始からのミリ秒> <キー>"、空行と#で始まる行は無視する）
+fn read_schedule(path: &str) -> Result<Vec<(u64, String)>, String> {
+    let content = if path == "-" {
+        std::io::read_to_string(std::io::stdin())
+            .map_err(|e| format!("Failed to read stdin: {}", e))?
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?
+    };
+
+    let mut schedule = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (offset, key) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("{}:{}: expected \"<ms> <key>\"", path, number + 1))?;
+        let offset = offset
+            .parse::<u64>()
+            .map_err(|_| format!("{}:{}: invalid offset: {}", path, number + 1, offset))?;
+        schedule.push((offset, key.trim().to_string()));
+    }
+    Ok(schedule)
+}
+
+// simulate の判定の実行先
+enum Simulator {
+    Redis(Box<RedisRateLimiter>),
+    // --emulate: 組み込みのLuaでスクリプトを実行する（Redisとの比較に使用する）
+    #[cfg(feature = "lua-emulator")]
+    Emulator {
+        executor: LuaExecutor,
+        config: RateLimitConfig,
+    },
+}
+
+impl Simulator {
+    #[cfg(feature = "lua-emulator")]
+    fn emulator(config: RateLimitConfig, clock: Arc<ManualClock>) -> Result<Self, String> {
+        Ok(Simulator::Emulator {
+            executor: LuaExecutor::new(clock)?,
+            config,
+        })
+    }
+
+    #[cfg(not(feature = "lua-emulator"))]
+    fn emulator(_config: RateLimitConfig, _clock: Arc<ManualClock>) -> Result<Self, String> {
+        Err("--emulate requires the lua-emulator feature".to_string())
+    }
+
+    // now はエミュレータに渡す（リミッターは同じ ManualClock から時刻を読む）
+    #[cfg_attr(not(feature = "lua-emulator"), allow(unused_variables))]
+    async fn check(&self, key: &str, now: Duration) -> Result<RateLimitDecision, String> {
+        match self {
+            Simulator::Redis(limiter) => limiter.check_rate_limit(key).await,
+            #[cfg(feature = "lua-emulator")]
+            Simulator::Emulator { executor, config } => {
+                check_with_executor(
+                    executor,
+                    config,
+                    key,
+                    config.requests_per_second,
+                    config.burst,
+                    now,
+                )
+                .await
+            }
+        }
+    }
+}
+
+// スケジュールの各行の時刻に時計を合わせて判定し、"<ミリ秒> <キー> <結果>" を行の順に出力する
+//
+// 時刻は ManualClock で進めるため、ウィンドウの境界やバケットの回復を待たずに確認できる。
+// 同じ時刻の連続した行は並行して判定する（同じキーへの同時のリクエストの検証に使用する）
+async fn simulate_schedule(
+    options: &Options,
+    schedule_path: &str,
+    start: Option<&str>,
+) -> Result<(), String> {
+    let start = match start {
+        Some(s) => s
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid start time: {}", s))?,
+        // 現在の1時間の始まり（3600秒を割り切る時間窓の境界に揃う）
+        None => SystemClock.now()?.as_secs() / 3600 * 3600,
+    };
+    let schedule = read_schedule(schedule_path)?;
+
+    let clock = Arc::new(ManualClock::new(Duration::from_secs(start)));
+    let config = limiter_config(options)?;
+    let simulator = Arc::new(if options.emulate {
+        Simulator::emulator(config, clock.clone())?
+    } else {
+        Simulator::Redis(Box::new(
+            RedisRateLimiter::new(config)
+                .await?
+                .with_clock(clock.clone()),
+        ))
+    });
+
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            for group in schedule.chunk_by(|a, b| a.0 == b.0) {
+                let now = Duration::from_secs(start) + Duration::from_millis(group[0].0);
+                clock.set(now);
+                let tasks: Vec<_> = group
+                    .iter()
+                    .map(|(_, key)| {
+                        let simulator = simulator.clone();
+                        let key = key.clone();
+                        tokio::task::spawn_local(async move { simulator.check(&key, now).await })
+                    })
+                    .collect();
+                for ((offset, key), task) in group.iter().zip(tasks) {
+                    let decision = task.await.map_err(|e| e.to_string())??;
+                    println!("{} {} {}", offset, key, format_decision(&decision));
+                }
+            }
+            Ok(())
+        })
+        .await
+}
+
+// 判定結果を "allowed|limited remaining=... count=... excess=... retry_after_ms=..." の形式にする
+fn format_decision(decision: &RateLimitDecision) -> String {
+    let optional = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    format!(
+        "{} remaining={} count={} excess={} retry_after_ms={}",
+        if decision.allowed {
+            "allowed"
+        } else {
+            "limited"
+        },
+        decision.remaining,
+        decision.count,
+        optional(decision.excess),
+        optional(decision.retry_after_ms)
+    )
+}
+
+async fn replay_access_log(
+    config_path: &str,
+    log_path: &str,
+    count: Option<&str>,
+) -> Result<(), String> {
+    let count = match count {
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid count: {}", n))?,
+        None => 20,
+    };
+    let config_file = ConfigFile::from_file(config_path)?;
+
+    let mut replayer = Replayer::new(&config_file);
+    if log_path == "-" {
+        replayer.replay_reader(std::io::stdin().lock()).await?;
+    } else {
+        let file = std::fs::File::open(log_path)
+            .map_err(|e| format!("Failed to open {}: {}", log_path, e))?;
+        replayer
+            .replay_reader(std::io::BufReader::new(file))
+            .await?;
+    }
+    let report = replayer.finish();
+
+    println!(
+        "{} requests replayed ({} unparsable lines skipped, {} in locations without rate limiting)",
+        report.lines, report.skipped, report.unlimited
+    );
+    println!(
+        "\n{:<32} {:>10} {:>10} {:>8}",
+        "location", "requests", "limited", "%"
+    );
+    for (location, stats) in &report.locations {
+        println!(
+            "{:<32} {:>10} {:>10} {:>7.2}%",
+            location,
+            stats.requests,
+            stats.limited,
+            stats.limited as f64 * 100.0 / stats.requests as f64
+        );
+    }
+
+    let top = report.top_keys(count);
+    if !top.is_empty() {
+        println!(
+            "\n{:<32} {:<40} {:>10} {:>10}",
+            "location", "key", "requests", "limited"
+        );
+        for (location, key, stats) in top {
+            println!(
+                "{:<32} {:<40} {:>10} {:>10}",
+                location, key, stats.requests, stats.limited
+            );
+        }
+    }
+    Ok(())
+}
+
+fn diff_configs(active_path: &str, candidate_path: &str) -> Result<(), String> {
+    let active = ConfigFile::from_file(active_path)?;
+    let candidate = ConfigFile::from_file(candidate_path)?;
+
+    if let Err(errors) = candidate.validate() {
+        for e in &errors {
+            eprintln!("{}: {}", candidate_path, e);
+        }
+    }
+
+    let changes = active.diff(&candidate);
+    if changes.is_empty() {
+        println!("No changes");
+        return Ok(());
+    }
+
+    let mut current_location = "";
+    for change in &changes {
+        if change.location != current_location {
+            println!("{}", change.location);
+            current_location = &change.location;
+        }
+        println!(
+            "  {:<32} {} -> {}",
+            change.field,
+            change.before.as_deref().unwrap_or("(none)"),
+            change.after.as_deref().unwrap_or("(none)")
+        );
+    }
+
+    let locations: std::collections::BTreeSet<&str> =
+        changes.iter().map(|c| c.location.as_str()).collect();
+    println!(
+        "{} change(s) affecting {} section(s)",
+        changes.len(),
+        locations.len()
+    );
+    Ok(())
+}
+
+// 監査ログに記録する状態変更コマンド
+const AUDITED_COMMANDS: &[&str] = &[
+    "reset",
+    "ban",
+    "unban",
+    "ttl",
+    "import-bans",
+    "migrate",
+    "cleanup",
+];
+
+// コマンドの実行結果を監査ログに記録する（記録に失敗してもコマンドは失敗させない）
+async fn record_audit(limiter: &RedisRateLimiter, options: &Options, result: &Result<(), String>) {
+    let actor = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    let mut params = options.command[1..].join(" ");
+    if options.cleanup.dry_run {
+        params.push_str(" --dry-run");
+    }
+    let outcome = match result {
+        Ok(()) => "ok".to_string(),
+        Err(e) => e.clone(),
+    };
+
+    let entry = AuditEntry::new(
+        &actor,
+        &format!("cli@{}", host),
+        &options.command[0],
+        params.trim(),
+        &outcome,
+    );
+    if let Err(e) = limiter.record_audit(&entry).await {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+async fn run(options: Options) -> Result<(), String> {
+    let command = options.command[0].as_str();
+
+    // Redisに接続しないコマンド
+    if command == "validate" {
+        return validate_config(required_arg(&options.command, 1, "config.json")?);
+    }
+    if command == "show-config" {
+        return show_config(
+            required_arg(&options.command, 1, "config.json")?,
+            options.command.get(2).map(|s| s.as_str()),
+        );
+    }
+    if command == "replay" {
+        return replay_access_log(
+            required_arg(&options.command, 1, "config.json")?,
+            required_arg(&options.command, 2, "access.log")?,
+            options.command.get(3).map(|s| s.as_str()),
+        )
+        .await;
+    }
+    if command == "simulate" {
+        return simulate_schedule(
+            &options,
+            required_arg(&options.command, 1, "schedule")?,
+            options.command.get(2).map(|s| s.as_str()),
+        )
+        .await;
+    }
+    if command == "diff" {
+        return diff_configs(
+            required_arg(&options.command, 1, "active")?,
+            required_arg(&options.command, 2, "candidate")?,
+        );
+    }
+
+    let limiter = RedisRateLimiter::new(limiter_config(&options)?).await?;
+    let result = execute(&limiter, &options).await;
+
+    if AUDITED_COMMANDS.contains(&command) {
+        record_audit(&limiter, &options, &result).await;
+    }
+
+    result
+}
+
+async fn execute(limiter: &RedisRateLimiter, options: &Options) -> Result<(), String> {
+    let command = options.command[0].as_str();
+
+    match command {
+        "usage" => {
+            let key = required_arg(&options.command, 1, "key")?;
+            let usage = limiter.get_usage(key).await?;
+            let json = serde_json::to_string_pretty(&usage).map_err(|e| e.to_string())?;
+            println!("{}", json);
+        }
+        "reset" => {
+            let key = required_arg(&options.command, 1, "key")?;
+            let deleted = limiter.reset_key(key).await?;
+            println!("Reset {} ({} keys deleted)", key, deleted);
+        }
+        "ban" => {
+            let key = required_arg(&options.command, 1, "key")?;
+            let duration = match options.command.get(2) {
+                Some(d) => d
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid duration: {}", d))?,
+                None => 0,
+            };
+            limiter.ban(key, duration).await?;
+            println!("Banned {}", key);
+        }
+        "unban" => {
+            let key = required_arg(&options.command, 1, "key")?;
+            if limiter.unban(key).await? {
+                println!("Unbanned {}", key);
+            } else {
+                println!("{} was not banned", key);
+            }
+        }
+        "ttl" => {
+            let key = required_arg(&options.command, 1, "key")?;
+            let target = required_arg(&options.command, 2, "target")?;
+            let ttl = required_arg(&options.command, 3, "seconds")?;
+            let ttl = ttl
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid TTL: {}", ttl))?;
+            let updated = limiter.set_key_ttl(key, target, ttl).await?;
+            println!("Updated TTL of {} {} key(s) for {}", updated, target, key);
+        }
+        "import-bans" => {
+            let path = required_arg(&options.command, 1, "file")?;
+            let default_duration = match options.command.get(2) {
+                Some(d) => d
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid duration: {}", d))?,
+                None => 0,
+            };
+            let text = if path == "-" {
+                let mut text = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+                    .map_err(|e| format!("Failed to read stdin: {}", e))?;
+                text
+            } else {
+                std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path, e))?
+            };
+
+            let (entries, parse_errors) = parse_ban_list(&text, default_duration);
+            for e in &parse_errors {
+                eprintln!("{}: {}", path, e);
+            }
+            let summary = import_bans(limiter, &entries).await;
+            for e in &summary.errors {
+                eprintln!("{}", e);
+            }
+            println!(
+                "Imported {} keys and {} CIDRs ({} errors)",
+                summary.keys,
+                summary.cidrs,
+                parse_errors.len() + summary.errors.len()
+            );
+        }
+        "export-bans" => {
+            print!("{}", export_bans(limiter).await?);
+        }
+        "top" => {
+            let count = match options.command.get(1) {
+                Some(n) => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid count: {}", n))?,
+                None => 10,
+            };
+            for (key, requests) in limiter.top_keys(count).await? {
+                println!("{:>10}  {}", requests, key);
+            }
+        }
+        "keys" => {
+            let cursor = match options.command.get(1) {
+                Some(c) => c
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid cursor: {}", c))?,
+                None => 0,
+            };
+            let count = match options.command.get(2) {
+                Some(n) => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid count: {}", n))?,
+                None => 100,
+            };
+            let (next, keys) = limiter.list_keys(cursor, count).await?;
+            for k in keys {
+                println!(
+                    "{:<8} {:>10} {:>8}  {}",
+                    k.kind,
+                    k.count
+                        .map_or_else(|| "-".to_string(), |c| format!("{:.1}", c)),
+                    k.ttl,
+                    k.key
+                );
+            }
+            println!("next cursor: {}", next);
+        }
+        "migrate" => {
+            let report = limiter
+                .migrate_keys(options.cleanup.batch_size, options.cleanup.dry_run)
+                .await?;
+            println!(
+                "Scanned {} keys, {} migrated, {} conflicts{}",
+                report.scanned,
+                report.migrated,
+                report.conflicts,
+                if options.cleanup.dry_run {
+                    " (dry run)"
+                } else {
+                    ""
+                }
+            );
+        }
+        "cleanup" => {
+            let mut cleanup = options.cleanup.clone();
+            if let Some(age) = options.command.get(1) {
+                cleanup.max_window_age = age
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid max_age: {}", age))?;
+            }
+            let report = limiter.cleanup_keys(&cleanup).await?;
+            for key in &report.samples {
+                println!("  {}", key);
+            }
+            println!(
+                "Scanned {} keys, {} orphaned, {} deleted{}",
+                report.scanned,
+                report.orphaned,
+                report.deleted,
+                if cleanup.dry_run { " (dry run)" } else { "" }
+            );
+        }
+        "preload-scripts" => {
+            for status in limiter.preload_scripts().await? {
+                println!(
+                    "{:<16} v{:<3} {} {}",
+                    status.name,
+                    status.version,
+                    status.sha,
+                    if status.reloaded { "(loaded)" } else { "" }
+                );
+            }
+        }
+        "audit" => {
+            let count = match options.command.get(1) {
+                Some(n) => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid count: {}", n))?,
+                None => 20,
+            };
+            for entry in limiter.recent_audit(count).await? {
+                println!(
+                    "{:>13}  {:<20} {:<24} {:<12} {} [{}]",
+                    entry.timestamp_ms,
+                    entry.actor,
+                    entry.source,
+                    entry.action,
+                    entry.params,
+                    entry.result
+                );
+            }
+        }
+        "sync-blocklist" => {
+            let source = required_arg(&options.command, 1, "source")?;
+            return sync_blocklist(limiter, options, source).await;
+        }
+        _ => return Err(format!("Unknown command: {}", command)),
+    }
+
+    Ok(())
+}
+
+// 外部ブロックリストをCIDR BANとして同期する（--interval 指定時は定期的に繰り返す）
+#[cfg(feature = "blocklist")]
+async fn sync_blocklist(
+    limiter: &RedisRateLimiter,
+    options: &Options,
+    source: &str,
+) -> Result<(), String> {
+    let source = BlocklistSource::parse(source, options.api_key.as_deref())?;
+    // 次回の同期に失敗しても、数回分はBANが失効しないようにする
+    let ttl = options.ttl.unwrap_or(if options.interval > 0 {
+        options.interval * 3
+    } else {
+        86400
+    });
+
+    loop {
+        match sync_blocklist(limiter, &source, ttl).await {
+            Ok(summary) => {
+                for e in &summary.errors {
+                    eprintln!("{}: {}", source, e);
+                }
+                println!(
+                    "Synced {} CIDRs from {} ({} errors)",
+                    summary.cidrs,
+                    source,
+                    summary.errors.len()
+                );
+            }
+            // 定期実行中は一時的な取得エラーで終了しない
+            Err(e) if options.interval > 0 => eprintln!("Error: {}", e),
+            Err(e) => return Err(e),
+        }
+
+        if options.interval == 0 {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(options.interval)).await;
+    }
+}
+
+#[cfg(not(feature = "blocklist"))]
+async fn sync_blocklist(
+    _limiter: &RedisRateLimiter,
+    _options: &Options,
+    _source: &str,
+) -> Result<(), String> {
+    Err("sync-blocklist requires the blocklist feature".to_string())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: Failed to create Tokio runtime: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(run(options)) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
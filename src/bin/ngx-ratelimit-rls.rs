@@ -0,0 +1,376 @@
+// Envoy の Rate Limit Service (RLS) 互換の gRPC サーバー
+//
+// NGINXモジュールと同じ実装を lib フィーチャーのライブラリとして使用し、ドメインを設定ファイルの
+// Location として扱うことで、メッシュ内のNGINX以外のサービスも同じカウンタと設定を利用できる。
+
+mod rls {
+    tonic::include_proto!("envoy.service.ratelimit.v3");
+}
+
+#[cfg(feature = "fault-injection")]
+use ngx_ratelimit_redis::FaultSettings;
+use ngx_ratelimit_redis::{
+    is_ip_banned, resolve_override, ConfigFile, CounterKey, LimitOverride, RateLimitConfig,
+    RateLimitDecision, RateLimitSettings, RedisRateLimiter,
+};
+use rls::rate_limit_descriptor::Entry;
+use rls::rate_limit_response::rate_limit::Unit;
+use rls::rate_limit_response::{Code, DescriptorStatus, RateLimit};
+use rls::rate_limit_service_server::{RateLimitService, RateLimitServiceServer};
+use rls::{RateLimitDescriptor, RateLimitRequest, RateLimitResponse};
+use std::net::{IpAddr, SocketAddr};
+use std::process;
+use tonic::{Request, Response, Status};
+
+const USAGE: &str = "Usage: ngx-ratelimit-rls [options]
+
+Serves Envoy's envoy.service.ratelimit.v3.RateLimitService over gRPC using the
+same Redis counters as the NGINX module. The request domain selects the location
+in the configuration file.
+
+Options:
+  --listen <addr>          Address to listen on (default: 0.0.0.0:8081)
+  --config <config.json>   Take Redis and limit settings from a configuration file
+  --redis-url <url>        Redis server URL (default: redis://127.0.0.1:6379)
+  --password <password>    Redis password
+  --database <n>           Redis database number
+  --fault <params>         Inject Redis failures, e.g. \"drop=10 latency=50\"
+                           (requires the fault-injection feature; not for production)
+  --help                   Show this help";
+
+// Envoy の remote_address アクションが生成する記述子のキー
+const ENVOY_REMOTE_ADDRESS: &str = "remote_address";
+
+// コマンドライン引数
+struct Options {
+    listen: SocketAddr,
+    config_path: Option<String>,
+    redis_url: Option<String>,
+    password: Option<String>,
+    database: Option<i64>,
+    fault: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        listen: SocketAddr::from(([0, 0, 0, 0], 8081)),
+        config_path: None,
+        redis_url: None,
+        password: None,
+        database: None,
+        fault: None,
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+
+        match arg.as_str() {
+            "--listen" => {
+                let addr = value("--listen")?;
+                options.listen = addr
+                    .parse::<SocketAddr>()
+                    .map_err(|_| format!("Invalid listen address: {}", addr))?;
+            }
+            "--config" => options.config_path = Some(value("--config")?),
+            "--redis-url" => options.redis_url = Some(value("--redis-url")?),
+            "--password" => options.password = Some(value("--password")?),
+            "--database" => {
+                let db = value("--database")?;
+                options.database = Some(
+                    db.parse::<i64>()
+                        .map_err(|_| format!("Invalid database value: {}", db))?,
+                );
+            }
+            "--fault" => options.fault = Some(value("--fault")?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+// デフォルト設定とオプションからリミッターの設定を組み立てる
+//
+// NGINXモジュールと同様に、リミッターはデフォルト設定のレート・バーストを基準とする
+fn limiter_config(options: &Options, config_file: &ConfigFile) -> Result<RateLimitConfig, String> {
+    let settings = &config_file.default;
+    let mut config = RateLimitConfig {
+        redis_url: settings.redis_url.clone(),
+        requests_per_second: settings.rate,
+        burst: settings.burst,
+        algorithm: ConfigFile::parse_algorithm(&settings.algorithm)?,
+        window_size: settings.window_size,
+        rate_period: settings.rate_period,
+        redis_options: settings.redis_options.clone(),
+    };
+
+    if let Some(url) = &options.redis_url {
+        config.redis_url = url.clone();
+    }
+    if let Some(password) = &options.password {
+        config.redis_options.password = Some(password.clone());
+    }
+    if let Some(database) = options.database {
+        config.redis_options.database = database;
+    }
+    Ok(config)
+}
+
+// "--fault" の値（ratelimit_redis_fault と同じ引数を空白で区切ったもの）で障害の注入を有効にする
+#[cfg(feature = "fault-injection")]
+fn configure_faults(params: &str) -> Result<(), String> {
+    let args: Vec<String> = params.split_whitespace().map(str::to_string).collect();
+    let settings = FaultSettings::parse(&args)?;
+    eprintln!(
+        "Warning: injecting Redis faults ({}); do not use this in production",
+        params
+    );
+    ngx_ratelimit_redis::configure_faults(settings);
+    Ok(())
+}
+
+#[cfg(not(feature = "fault-injection"))]
+fn configure_faults(_params: &str) -> Result<(), String> {
+    Err("--fault requires ngx-ratelimit-rls built with the fault-injection feature".to_string())
+}
+
+// 記述子1件分のチェック対象（Redisキーとレート・バースト）
+struct DescriptorCheck {
+    key: String,
+    rate: u32,
+    burst: u32,
+}
+
+// 記述子をNGINXモジュールと同じRedisキーに変換する
+//
+// - ロケーションの key（remote_addr、http_x_api_keyなど）と同名のエントリ1つだけの記述子は、
+//   モジュールの主キーと同じカウンタ（値そのもの）を使用する
+// - zone のキーと同名のエントリ1つだけの記述子は、そのゾーンのカウンタとリミットを使用する
+// - それ以外は "キー=値" をカンマで連結したキーに、ロケーションのリミットを適用する
+fn descriptor_check(
+    settings: &RateLimitSettings,
+    primary: LimitOverride,
+    descriptor: &RateLimitDescriptor,
+) -> Result<DescriptorCheck, String> {
+    if let [entry] = descriptor.entries.as_slice() {
+        let name = entry_key(entry);
+        if name == settings.key {
+            return Ok(DescriptorCheck {
+                key: entry.value.clone(),
+                rate: primary.rate,
+                burst: primary.burst,
+            });
+        }
+        if let Some(zone) = settings.zones.iter().find(|zone| zone.key == name) {
+            return Ok(DescriptorCheck {
+                key: format!("{}={}", zone.key, entry.value),
+                rate: zone.rate,
+                burst: zone.burst,
+            });
+        }
+    }
+
+    if descriptor.entries.is_empty() {
+        return Err("Descriptor has no entries".to_string());
+    }
+    let key = descriptor
+        .entries
+        .iter()
+        .map(|entry| format!("{}={}", entry_key(entry), entry.value))
+        .collect::<Vec<_>>()
+        .join(",");
+    Ok(DescriptorCheck {
+        key,
+        rate: primary.rate,
+        burst: primary.burst,
+    })
+}
+
+// Envoy の remote_address はモジュールの remote_addr と同じキーとして扱う
+fn entry_key(entry: &Entry) -> &str {
+    if entry.key == ENVOY_REMOTE_ADDRESS {
+        "remote_addr"
+    } else {
+        &entry.key
+    }
+}
+
+// 記述子に含まれるクライアントIP（CIDR単位のBANの判定に使用する）
+fn client_ip(request: &RateLimitRequest) -> Option<IpAddr> {
+    request
+        .descriptors
+        .iter()
+        .flat_map(|descriptor| descriptor.entries.iter())
+        .find(|entry| entry_key(entry) == "remote_addr")
+        .and_then(|entry| entry.value.parse::<IpAddr>().ok())
+}
+
+fn descriptor_status(
+    check: &DescriptorCheck,
+    decision: Option<&RateLimitDecision>,
+) -> DescriptorStatus {
+    let code = match decision {
+        Some(decision) if decision.allowed => Code::Ok,
+        _ => Code::OverLimit,
+    };
+    DescriptorStatus {
+        code: code as i32,
+        current_limit: Some(RateLimit {
+            name: check.key.clone(),
+            requests_per_unit: check.rate,
+            unit: Unit::Second as i32,
+        }),
+        limit_remaining: decision
+            .map_or(0, |decision| decision.remaining.min(u32::MAX as u64) as u32),
+        duration_until_reset: decision.map(|decision| prost_types::Duration {
+            seconds: decision.reset as i64,
+            nanos: 0,
+        }),
+    }
+}
+
+struct RlsService {
+    limiter: RedisRateLimiter,
+    config_file: ConfigFile,
+}
+
+impl RlsService {
+    async fn check(&self, request: &RateLimitRequest) -> Result<RateLimitResponse, String> {
+        let settings = self.config_file.get_settings(&request.domain);
+        // 管理APIで設定された実行時の上書きがあれば優先する
+        let primary = resolve_override(&self.limiter, &request.domain)
+            .await
+            .unwrap_or_else(|| self.limiter.default_limits());
+
+        let checks = request
+            .descriptors
+            .iter()
+            .map(|descriptor| descriptor_check(&settings, primary, descriptor))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // BANされたキー・クライアントIPはカウンタを更新せずに拒否する
+        let mut banned = false;
+        for check in &checks {
+            if self.limiter.is_banned(&check.key).await? {
+                banned = true;
+                break;
+            }
+        }
+        if !banned {
+            if let Some(ip) = client_ip(request) {
+                banned = is_ip_banned(&self.limiter, &ip).await;
+            }
+        }
+        if banned {
+            return Ok(RateLimitResponse {
+                overall_code: Code::OverLimit as i32,
+                statuses: checks
+                    .iter()
+                    .map(|check| descriptor_status(check, None))
+                    .collect(),
+            });
+        }
+
+        let decisions = self
+            .limiter
+            .check_rate_limits(
+                &checks
+                    .iter()
+                    .map(|check| (CounterKey::Client(&check.key), check.rate, check.burst))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+
+        let allowed = decisions.iter().all(|decision| decision.allowed);
+        Ok(RateLimitResponse {
+            overall_code: if allowed { Code::Ok } else { Code::OverLimit } as i32,
+            statuses: checks
+                .iter()
+                .zip(decisions.iter())
+                .map(|(check, decision)| descriptor_status(check, Some(decision)))
+                .collect(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl RateLimitService for RlsService {
+    async fn should_rate_limit(
+        &self,
+        request: Request<RateLimitRequest>,
+    ) -> Result<Response<RateLimitResponse>, Status> {
+        let request = request.into_inner();
+        if request.descriptors.is_empty() {
+            return Ok(Response::new(RateLimitResponse {
+                overall_code: Code::Ok as i32,
+                statuses: Vec::new(),
+            }));
+        }
+
+        // Redisのエラー時の扱いは Envoy 側の failure_mode_deny に任せる
+        match self.check(&request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(e) => {
+                eprintln!("Rate limit check failed for {}: {}", request.domain, e);
+                Err(Status::unavailable(e))
+            }
+        }
+    }
+}
+
+async fn run(options: Options) -> Result<(), String> {
+    if let Some(params) = &options.fault {
+        configure_faults(params)?;
+    }
+    let config_file = match &options.config_path {
+        Some(path) => ConfigFile::from_file(path)?,
+        None => ConfigFile::default(),
+    };
+    let limiter = RedisRateLimiter::new(limiter_config(&options, &config_file)?).await?;
+
+    println!("Listening on {}", options.listen);
+    tonic::transport::Server::builder()
+        .add_service(RateLimitServiceServer::new(RlsService {
+            limiter,
+            config_file,
+        }))
+        .serve(options.listen)
+        .await
+        .map_err(|e| format!("gRPC server failed: {}", e))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: Failed to create Tokio runtime: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(run(options)) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,218 @@
+use log::warn;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::acl::Cidr;
+use crate::banlist;
+use crate::redis_client::RedisRateLimiter;
+
+/// CrowdSec のソース指定のプレフィックス（"crowdsec:http://lapi:8080"）
+const CROWDSEC_PREFIX: &str = "crowdsec:";
+
+/// HTTPリクエストのタイムアウト
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 外部ブロックリストの取得元
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlocklistSource {
+    /// CrowdSec Local API（バウンサーのAPIキーで /v1/decisions を取得する）
+    CrowdSec { url: String, api_key: String },
+    /// 1行に1つのIPアドレスまたはCIDRを記載したテキスト
+    Plain { url: String },
+}
+
+impl BlocklistSource {
+    /// "crowdsec:<LAPIのURL>" または "http(s)://..." を解析する
+    pub fn parse(spec: &str, api_key: Option<&str>) -> Result<Self, String> {
+        if let Some(url) = spec.strip_prefix(CROWDSEC_PREFIX) {
+            let api_key = api_key
+                .ok_or_else(|| "CrowdSec sources require --api-key".to_string())?
+                .to_string();
+            return Ok(BlocklistSource::CrowdSec {
+                url: url.trim_end_matches('/').to_string(),
+                api_key,
+            });
+        }
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            return Ok(BlocklistSource::Plain {
+                url: spec.to_string(),
+            });
+        }
+        Err(format!(
+            "Invalid blocklist source (expected crowdsec:<url> or http(s)://...): {}",
+            spec
+        ))
+    }
+}
+
+impl std::fmt::Display for BlocklistSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlocklistSource::CrowdSec { url, .. } => write!(f, "{}{}", CROWDSEC_PREFIX, url),
+            BlocklistSource::Plain { url } => write!(f, "{}", url),
+        }
+    }
+}
+
+/// 同期結果
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct SyncSummary {
+    /// Redisに登録したCIDRの数
+    pub cidrs: usize,
+    /// 解析できなかったエントリ
+    pub errors: Vec<String>,
+}
+
+// CrowdSec LAPI の decision（使用する項目のみ）
+#[derive(Debug, Deserialize)]
+struct Decision {
+    duration: String,
+    scope: String,
+    value: String,
+}
+
+/// ブロックリストを取得し、CIDR BANとしてRedisに登録する
+///
+/// 取得元に期限がない場合は default_ttl 秒で失効する。取得元から消えたエントリは
+/// 再登録されないため、期限が来た時点でBANが解除される
+pub async fn sync(
+    limiter: &RedisRateLimiter,
+    source: &BlocklistSource,
+    default_ttl: u64,
+) -> Result<SyncSummary, String> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let (entries, errors) = match source {
+        BlocklistSource::CrowdSec { url, api_key } => {
+            let decisions: Option<Vec<Decision>> = client
+                .get(format!("{}/v1/decisions", url))
+                .query(&[("type", "ban")])
+                .header("X-Api-Key", api_key)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to fetch CrowdSec decisions: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse CrowdSec decisions: {}", e))?;
+            // 決定が1件もない場合、LAPIは null を返す
+            parse_decisions(&decisions.unwrap_or_default(), default_ttl)
+        }
+        BlocklistSource::Plain { url } => {
+            let text = client
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to fetch blocklist {}: {}", url, e))?
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read blocklist {}: {}", url, e))?;
+            parse_plain(&text, default_ttl)
+        }
+    };
+
+    let cidrs = limiter.ban_cidrs_batch(&entries).await?;
+    banlist::invalidate_cache();
+    Ok(SyncSummary { cidrs, errors })
+}
+
+// CrowdSec の decision を (CIDR, 秒数) に変換する（IP・Range 以外のスコープは対象外）
+fn parse_decisions(decisions: &[Decision], default_ttl: u64) -> (Vec<(String, u64)>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for decision in decisions {
+        if !decision.scope.eq_ignore_ascii_case("ip")
+            && !decision.scope.eq_ignore_ascii_case("range")
+        {
+            continue;
+        }
+        let cidr = match Cidr::parse(&decision.value) {
+            Ok(cidr) => cidr,
+            Err(e) => {
+                errors.push(format!("{}: {}", decision.value, e));
+                continue;
+            }
+        };
+        let ttl = parse_go_duration(&decision.duration).unwrap_or_else(|| {
+            warn!(
+                "Invalid duration {} for {}, using {}s",
+                decision.duration, decision.value, default_ttl
+            );
+            default_ttl
+        });
+        // 期限切れ直前の決定は登録しない
+        if ttl > 0 {
+            entries.push((cidr.to_string(), ttl));
+        }
+    }
+
+    (entries, errors)
+}
+
+// テキストのブロックリストを解析する
+//
+// 各行の先頭のIPアドレスまたはCIDRを使用し、空行と "#"・";" で始まるコメントは無視する
+// （"192.0.2.0/24 ; SBL123" のような行末のコメントも許容する）
+fn parse_plain(text: &str, default_ttl: u64) -> (Vec<(String, u64)>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for (lineno, line) in text.lines().enumerate() {
+        let entry = line
+            .split(|c: char| c == '#' || c == ';' || c.is_whitespace())
+            .find(|field| !field.is_empty());
+        let entry = match entry {
+            Some(entry) => entry,
+            None => continue,
+        };
+        match Cidr::parse(entry) {
+            Ok(cidr) => entries.push((cidr.to_string(), default_ttl)),
+            Err(e) => errors.push(format!("line {}: {}", lineno + 1, e)),
+        }
+    }
+
+    (entries, errors)
+}
+
+// Goの time.Duration の文字列表現（"3h59m58.5s" など）を秒数（切り上げ）に変換する
+fn parse_go_duration(s: &str) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    // 期限切れの決定は負の値になる
+    if s.starts_with('-') {
+        return Some(0);
+    }
+
+    let mut total = 0.0f64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let value = rest[..number_len].parse::<f64>().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            "ns" => 1e-9,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += value * scale;
+    }
+
+    Some(total.ceil() as u64)
+}
@@ -0,0 +1,118 @@
+use serde::Serialize;
+
+use crate::redis_client::RedisCompat;
+
+/// 接続先のRedisサーバーが対応している機能
+///
+/// 接続時に INFO server と COMMAND INFO で判定する。プロキシなどでこれらのコマンドが
+/// 使えない場合は、不明な項目をバージョンから推定する
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RedisCapabilities {
+    /// サーバーのバージョン（取得できない場合はNone）
+    pub version: Option<(u32, u32, u32)>,
+    /// Redis Functions（FUNCTION LOAD / FCALL、7.0以降）
+    pub functions: bool,
+    /// Luaスクリプト（EVALSHA）
+    pub scripting: bool,
+    /// RESP3（HELLO、6.0以降）
+    pub resp3: bool,
+    /// サーバーがクラスタモードで動作しているか
+    pub cluster: bool,
+}
+
+/// レート制限の判定をRedisで実行する方法
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionMode {
+    /// Redis Functions（FCALL）。関数はサーバーに永続化され、再起動やフェイルオーバーでも消えない
+    Functions,
+    /// Luaスクリプト（EVALSHA）。スクリプトキャッシュにない場合は本文を送って再実行する
+    Scripts,
+    /// 単一キーのコマンドのみ（判定はモジュール側で計算する）
+    Commands,
+}
+
+impl std::fmt::Display for ExecutionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionMode::Functions => write!(f, "functions"),
+            ExecutionMode::Scripts => write!(f, "scripts"),
+            ExecutionMode::Commands => write!(f, "commands"),
+        }
+    }
+}
+
+impl RedisCapabilities {
+    /// INFO server の応答からバージョンとクラスタモードを読み取り、機能をバージョンから推定する
+    pub fn from_info(info: &str) -> Self {
+        let mut capabilities = Self {
+            // バージョンが分からない場合も、EVALSHAは使えるものとして扱う
+            scripting: true,
+            ..Self::default()
+        };
+        for line in info.lines() {
+            let (name, value) = match line.trim_end().split_once(':') {
+                Some(field) => field,
+                None => continue,
+            };
+            match name {
+                "redis_version" => capabilities.version = parse_version(value),
+                "redis_mode" => capabilities.cluster = value == "cluster",
+                _ => {}
+            }
+        }
+        if let Some(version) = capabilities.version {
+            capabilities.functions = version >= (7, 0, 0);
+            capabilities.resp3 = version >= (6, 0, 0);
+            capabilities.scripting = version >= (2, 6, 0);
+        }
+        capabilities
+    }
+
+    /// COMMAND INFO evalsha fcall hello の結果（各コマンドが存在するか）で推定を置き換える
+    ///
+    /// rename-command や ACL でコマンドが無効にされている場合もここで判定できる
+    pub fn apply_commands(&mut self, evalsha: bool, fcall: bool, hello: bool) {
+        self.scripting = evalsha;
+        self.functions = fcall;
+        self.resp3 = hello;
+    }
+
+    /// 設定とサーバーの機能から判定の実行方法を選ぶ
+    ///
+    /// compat=proxy は常に単一キーのコマンド、compat=scripts はEVALSHAを優先する。
+    /// クラスタでは FUNCTION LOAD が1つのノードにしか届かないため、Functions を使わない
+    pub fn select_mode(&self, compat: RedisCompat, cluster_mode: bool) -> ExecutionMode {
+        match compat {
+            RedisCompat::Proxy => ExecutionMode::Commands,
+            _ if !self.scripting => ExecutionMode::Commands,
+            RedisCompat::None if self.functions && !self.cluster && !cluster_mode => {
+                ExecutionMode::Functions
+            }
+            _ => ExecutionMode::Scripts,
+        }
+    }
+
+    /// ログ用の "7.2.4" 形式のバージョン
+    pub fn version_string(&self) -> String {
+        match self.version {
+            Some((major, minor, patch)) => format!("{}.{}.{}", major, minor, patch),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+// "7.2.4" 形式のバージョンを解析する（"7.2" や "7.2.4-rc1" も受け付ける）
+fn parse_version(value: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = value.trim().split('.').map(|part| {
+        part.chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
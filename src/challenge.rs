@@ -0,0 +1,116 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// チャレンジを通過したクライアントに発行する Cookie の名前
+pub const PASS_COOKIE: &str = "ratelimit_pass";
+
+/// リダイレクト先に元のURIを渡すクエリパラメータ
+pub const RETURN_PARAM: &str = "return";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 通過Cookieの値を生成する
+///
+/// 値は "<有効期限（UNIXエポックからの秒数）>.<HMAC-SHA256の16進表現>" の形式で、
+/// "<レート制限キー>|<有効期限>" に対して署名する。キーを含めることで、
+/// 別のクライアント（別のIPアドレスなど）に Cookie を流用されても通過させない
+///
+/// モジュールは検証のみを行い、発行はチャレンジページ側（"lib" フィーチャー）で行う
+#[cfg_attr(not(feature = "lib"), allow(dead_code))]
+pub fn sign_pass(secret: &str, key: &str, expires_at: u64) -> String {
+    let signature = mac(secret, key, expires_at).finalize().into_bytes();
+    format!("{}.{}", expires_at, to_hex(&signature))
+}
+
+/// 通過Cookieの値を検証する（署名が一致し、有効期限内であればtrue）
+pub fn verify_pass(secret: &str, key: &str, value: &str) -> bool {
+    let (expires_at, signature) = match value.split_once('.') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let expires_at = match expires_at.parse::<u64>() {
+        Ok(expires_at) => expires_at,
+        Err(_) => return false,
+    };
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_) => return false,
+    };
+    if expires_at <= now {
+        return false;
+    }
+    let signature = match from_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    // 比較は verify_slice で定数時間に行う
+    mac(secret, key, expires_at)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+/// Cookie ヘッダーから通過Cookieの値を取り出す
+pub fn find_pass_cookie(header: &str) -> Option<&str> {
+    header.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        if name == PASS_COOKIE {
+            Some(value.trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// チャレンジページのURLに元のURIを付加したリダイレクト先を返す
+pub fn redirect_url(challenge_url: &str, uri: &str) -> String {
+    let separator = if challenge_url.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    format!(
+        "{}{}{}={}",
+        challenge_url,
+        separator,
+        RETURN_PARAM,
+        percent_encode(uri)
+    )
+}
+
+fn mac(secret: &str, key: &str, expires_at: u64) -> HmacSha256 {
+    // HMACは任意の長さの鍵を受け付けるため失敗しない
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}|{}", key, expires_at).as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+// クエリパラメータの値としてエンコードする（RFC 3986 の非予約文字以外）
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
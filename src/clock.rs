@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// レート制限の判定に使用する現在時刻の取得元
+///
+/// ウィンドウの境界やバケットの補充はこの時刻で計算する。
+/// テストでは ManualClock に差し替えて、待たずに時刻を進められる
+pub trait Clock: Send + Sync {
+    /// UNIXエポックからの経過時間
+    fn now(&self) -> Result<Duration, String>;
+}
+
+/// システム時刻（既定）
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<Duration, String> {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "SystemTime before UNIX EPOCH!".to_string())
+    }
+}
+
+/// 明示的に設定・前進させる時計（テスト用）
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    micros: AtomicU64,
+}
+
+impl ManualClock {
+    /// now（UNIXエポックからの経過時間）を指す時計を作成する
+    pub fn new(now: Duration) -> Self {
+        Self {
+            micros: AtomicU64::new(now.as_micros() as u64),
+        }
+    }
+
+    /// 時刻を設定する
+    pub fn set(&self, now: Duration) {
+        self.micros.store(now.as_micros() as u64, Ordering::SeqCst);
+    }
+
+    /// 時刻を進める
+    pub fn advance(&self, by: Duration) {
+        self.micros
+            .fetch_add(by.as_micros() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Result<Duration, String> {
+        Ok(Duration::from_micros(self.micros.load(Ordering::SeqCst)))
+    }
+}
+
+/// 1970-01-01 からの日数（グレゴリオ暦）
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// 1970-01-01 からの日数を (年, 月, 日) に変換する（days_from_civil の逆）
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -1,11 +1,16 @@
 use log::{error, info};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use redis::IntoConnectionInfo;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
-use crate::redis_client::{RateLimitAlgorithm, RedisConnectionOptions};
+use crate::acl::CidrSet;
+use crate::ban::BanPolicy;
+use crate::openapi;
+use crate::redis_client::{QuotaPeriod, RateLimitAlgorithm, RedisCompat, RedisConnectionOptions};
 
 /// レートリミットの設定を保持する構造体
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,11 +19,13 @@ pub struct RateLimitSettings {
     #[serde(default = "default_redis_url")]
     pub redis_url: String,
 
-    /// レート制限に使用するキー（remote_addr、http_x_api_keyなど）
-    #[serde(default = "default_key")]
+    /// レート制限に使用するキー（remote_addr、http_x_api_key、"$binary_remote_addr$uri" などのNGINX変数）
+    ///
+    /// カンマ区切り、または要素の配列で複数の要素を組み合わせたキーにできる
+    #[serde(default = "default_key", deserialize_with = "deserialize_key")]
     pub key: String,
 
-    /// 1秒あたりの最大リクエスト数
+    /// 1秒（rate_period 秒）あたりの最大リクエスト数（0はすべてのリクエストを拒否する）
     #[serde(default = "default_rate")]
     pub rate: u32,
 
@@ -34,6 +41,182 @@ pub struct RateLimitSettings {
     #[serde(default = "default_window_size")]
     pub window_size: u32,
 
+    /// rate のリクエスト数を許可する秒数（10で「10秒に rate 件」、時間窓のアルゴリズム以外）
+    #[serde(default = "default_rate_period")]
+    pub rate_period: u32,
+
+    /// ホットキーの判定を先読みする際に許容する鮮度（ミリ秒、0で無効）
+    #[serde(default = "default_prefetch_ms")]
+    pub prefetch_ms: u64,
+
+    /// 上限超過が確認されたキーをRedisに問い合わせずに拒否する時間（ミリ秒、0で無効）
+    #[serde(default = "default_overlimit_cache_ms")]
+    pub overlimit_cache_ms: u64,
+
+    /// 上限超過のキーを制限が戻るまで（overlimit_cache_ms を上限として）拒否し続けるか
+    #[serde(default = "default_overlimit_cache_until_reset")]
+    pub overlimit_cache_until_reset: bool,
+
+    /// ワーカーごとに同時に実行するRedisチェックの上限（超過分は障害時の動作を適用、0で無制限）
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: u32,
+
+    /// Redisチェックに許容する所要時間（ミリ秒、予測がこれを超える場合は問い合わせを省略、0で無効）
+    #[serde(default = "default_latency_budget_ms")]
+    pub latency_budget_ms: u64,
+
+    /// Redisで判定できない場合の動作（"open": 許可、"closed": 拒否、"local": ワーカー内のカウンタで判定）
+    #[serde(default = "default_failure_mode")]
+    pub failure_mode: String,
+
+    /// 追加で適用するゾーン（全てのゾーンで許可された場合のみリクエストを許可する）
+    #[serde(default)]
+    pub zones: Vec<ZoneSettings>,
+
+    /// gRPCのメソッド（:path）ごとのレート制限
+    #[serde(default)]
+    pub grpc_methods: Vec<GrpcMethodSettings>,
+
+    /// レート制限を適用するHTTPメソッド（空の場合は全てのメソッド）
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    /// HTTPメソッドごとのレート制限
+    #[serde(default)]
+    pub method_limits: Vec<MethodLimitSettings>,
+
+    /// HTTPメソッドとパスごとのレート制限（openapi で指定した仕様から生成される）
+    #[serde(default)]
+    pub routes: Vec<RouteSettings>,
+
+    /// トークンイントロスペクションで得たプランごとのレート制限（key=oauth_subject の場合）
+    #[serde(default)]
+    pub plans: Vec<PlanSettings>,
+
+    /// Redisに保存されたキーごとのリミット（ratelimit:v3:limits:{<キー>} の rate と burst）を使うか
+    #[serde(default = "default_key_limits")]
+    pub key_limits: bool,
+
+    /// 設定ファイルの tiers からティアを選択するリクエストヘッダー（空の場合はティアを使用しない）
+    #[serde(default)]
+    pub tier_header: String,
+
+    /// Location全体で共有する1秒あたりの最大リクエスト数（0は無制限）
+    #[serde(default)]
+    pub global_rate: u32,
+
+    /// Location全体で一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub global_burst: u32,
+
+    /// 暦の期間（per）ごとに許可するリクエスト数（0はクォータなし）
+    #[serde(default)]
+    pub quota: u64,
+
+    /// クォータを数える期間（day: UTCの1日、month: UTCの1か月）
+    #[serde(default = "default_per")]
+    pub per: String,
+
+    /// レート制限を適用しないクライアントのアドレス範囲（CIDRまたは単一アドレス）
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+
+    /// Redisに問い合わせずに拒否するクライアントのアドレス範囲（CIDRまたは単一アドレス）
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+
+    /// Redisチェックの実行方法（async、thread_pool、thread_pool:<プール名>）
+    #[serde(default = "default_offload")]
+    pub offload: String,
+
+    /// 状態を保持するバックエンド（redis、memory）
+    #[serde(default = "default_backend")]
+    pub backend: String,
+
+    /// 動作モード（filter: アクセスフェーズで制限、auth: 判定結果を204/429で返す）
+    #[serde(default = "default_mode")]
+    pub mode: String,
+
+    /// 完了したリクエストの応答ステータスと送信バイト数をRedisに集計するか
+    #[serde(default = "default_accounting")]
+    pub accounting: bool,
+
+    /// 不審な応答（401/403/404/429）の割合（%）がこの値を超えたキーに対処する（0は無効）
+    #[serde(default = "default_abuse_ratio")]
+    pub abuse_ratio: u32,
+
+    /// 不審な応答の割合を判定するのに必要な時間窓内の応答数
+    #[serde(default = "default_abuse_min_requests")]
+    pub abuse_min_requests: u32,
+
+    /// 不審な応答の割合が閾値を超えたキーへの対処（ban: BAN、tighten: 上限を引き下げる）
+    #[serde(default = "default_abuse_action")]
+    pub abuse_action: String,
+
+    /// abuse_action によるBAN・制限の期間（秒）
+    #[serde(default = "default_abuse_duration")]
+    pub abuse_duration: u64,
+
+    /// ban_window 秒の間にこの回数拒否されたキーをBANする（0は無効）
+    #[serde(default)]
+    pub ban_after: u32,
+
+    /// 自動BANまでの拒否数を数える時間窓（秒）
+    #[serde(default = "default_ban_window")]
+    pub ban_window: u64,
+
+    /// 1回目の自動BANの期間（秒、繰り返すたびに倍にする）
+    #[serde(default = "default_ban_duration")]
+    pub ban_duration: u64,
+
+    /// 繰り返し延長した自動BANの期間の上限（秒）
+    #[serde(default = "default_ban_max_duration")]
+    pub ban_max_duration: u64,
+
+    /// BANされたキーに返すステータスコード（0の場合は status と同じ）
+    #[serde(default)]
+    pub ban_status: u16,
+
+    /// algorithm=limit_req でバースト内のリクエストを遅延させない（limit_req の nodelay）
+    #[serde(default = "default_nodelay")]
+    pub nodelay: bool,
+
+    /// algorithm=limit_req・throttle で遅延させずに許可するバースト内のリクエスト数（limit_req の delay=）
+    #[serde(default = "default_delay")]
+    pub delay: u32,
+
+    /// algorithm=gcra でバースト内のリクエストを拒否せず、定常レートに合う時刻まで遅延させる
+    #[serde(default = "default_throttle")]
+    pub throttle: bool,
+
+    /// 上限を超えたリクエストに返すステータスコード（400〜599、例えば 429 や 503）
+    #[serde(default = "default_status")]
+    pub status: u16,
+
+    /// レート制限ヘッダーの形式（"legacy"、"ietf" または "both"）
+    #[serde(default = "default_header_format")]
+    pub header_format: String,
+
+    /// 上限を超えたリクエストに返す本文のファイル（空の場合は組み込みのJSON）
+    #[serde(default)]
+    pub reject_body_file: String,
+
+    /// 上限を超えたリクエストに返す本文の Content-Type
+    #[serde(default = "default_reject_content_type")]
+    pub reject_content_type: String,
+
+    /// 上限超過時の動作（"reject" または "challenge"）
+    #[serde(default = "default_on_limit")]
+    pub on_limit: String,
+
+    /// on_limit=challenge でリダイレクトするチャレンジページのURL
+    #[serde(default = "default_challenge_url")]
+    pub challenge_url: String,
+
+    /// 通過Cookieの署名を検証する共有シークレット
+    #[serde(default = "default_challenge_secret")]
+    pub challenge_secret: String,
+
     /// モジュールの有効/無効
     #[serde(default = "default_enabled")]
     pub enabled: bool,
@@ -52,31 +235,1046 @@ impl Default for RateLimitSettings {
             burst: default_burst(),
             algorithm: default_algorithm(),
             window_size: default_window_size(),
+            rate_period: default_rate_period(),
+            prefetch_ms: default_prefetch_ms(),
+            overlimit_cache_ms: default_overlimit_cache_ms(),
+            overlimit_cache_until_reset: default_overlimit_cache_until_reset(),
+            max_in_flight: default_max_in_flight(),
+            latency_budget_ms: default_latency_budget_ms(),
+            failure_mode: default_failure_mode(),
+            zones: Vec::new(),
+            grpc_methods: Vec::new(),
+            methods: Vec::new(),
+            method_limits: Vec::new(),
+            plans: Vec::new(),
+            key_limits: default_key_limits(),
+            tier_header: String::new(),
+            global_rate: 0,
+            global_burst: 0,
+            quota: 0,
+            per: default_per(),
+            whitelist: Vec::new(),
+            blacklist: Vec::new(),
+            routes: Vec::new(),
+            offload: default_offload(),
+            backend: default_backend(),
+            mode: default_mode(),
+            accounting: default_accounting(),
+            abuse_ratio: default_abuse_ratio(),
+            abuse_min_requests: default_abuse_min_requests(),
+            abuse_action: default_abuse_action(),
+            abuse_duration: default_abuse_duration(),
+            ban_after: 0,
+            ban_window: default_ban_window(),
+            ban_duration: default_ban_duration(),
+            ban_max_duration: default_ban_max_duration(),
+            ban_status: 0,
+            nodelay: default_nodelay(),
+            delay: default_delay(),
+            throttle: default_throttle(),
+            status: default_status(),
+            header_format: default_header_format(),
+            reject_body_file: String::new(),
+            reject_content_type: default_reject_content_type(),
+            on_limit: default_on_limit(),
+            challenge_url: default_challenge_url(),
+            challenge_secret: default_challenge_secret(),
             enabled: default_enabled(),
             redis_options: RedisConnectionOptions::default(),
         }
     }
 }
 
+impl RateLimitSettings {
+    /// 自動BANの条件
+    pub fn ban_policy(&self) -> BanPolicy {
+        BanPolicy {
+            after: self.ban_after,
+            window: self.ban_window,
+            duration: self.ban_duration,
+            max_duration: self.ban_max_duration,
+        }
+    }
+}
+
+// Some の項目だけを dest に上書きする
+macro_rules! apply_overrides {
+    ($src:expr, $dest:expr, [$($field:ident),* $(,)?]) => {
+        $(
+            if let Some(value) = &$src.$field {
+                $dest.$field = value.clone();
+            }
+        )*
+    };
+}
+
+/// Locationごとの設定（指定した項目だけがデフォルト設定を上書きする）
+///
+/// 指定されなかった項目は None となり、デフォルト設定の値を継承する。
+/// 値がデフォルト値と同じでも、指定されていれば上書きする
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocationSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis_url: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_key"
+    )]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_period: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefetch_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlimit_cache_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlimit_cache_until_reset: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_budget_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_mode: Option<String>,
+    /// 指定した場合はデフォルト設定の一覧を置き換える（[] で空にできる）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zones: Option<Vec<ZoneSettings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_methods: Option<Vec<GrpcMethodSettings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub methods: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method_limits: Option<Vec<MethodLimitSettings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<RouteSettings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plans: Option<Vec<PlanSettings>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_limits: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier_header: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_rate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_burst: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whitelist: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blacklist: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounting: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_ratio: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_min_requests: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_after: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_window: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_max_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodelay: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_body_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub challenge_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub challenge_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "LocationRedisOptions::is_empty")]
+    pub redis_options: LocationRedisOptions,
+}
+
+impl LocationSettings {
+    /// 指定された項目をsettingsに上書きする
+    pub fn apply(&self, settings: &mut RateLimitSettings) {
+        apply_overrides!(
+            self,
+            settings,
+            [
+                redis_url,
+                key,
+                rate,
+                burst,
+                algorithm,
+                window_size,
+                rate_period,
+                prefetch_ms,
+                overlimit_cache_ms,
+                overlimit_cache_until_reset,
+                max_in_flight,
+                latency_budget_ms,
+                failure_mode,
+                zones,
+                grpc_methods,
+                methods,
+                method_limits,
+                routes,
+                plans,
+                key_limits,
+                tier_header,
+                global_rate,
+                global_burst,
+                quota,
+                per,
+                whitelist,
+                blacklist,
+                offload,
+                backend,
+                mode,
+                accounting,
+                abuse_ratio,
+                abuse_min_requests,
+                abuse_action,
+                abuse_duration,
+                ban_after,
+                ban_window,
+                ban_duration,
+                ban_max_duration,
+                ban_status,
+                nodelay,
+                delay,
+                throttle,
+                status,
+                header_format,
+                reject_body_file,
+                reject_content_type,
+                on_limit,
+                challenge_url,
+                challenge_secret,
+                enabled,
+            ]
+        );
+        self.redis_options.apply(&mut settings.redis_options);
+    }
+}
+
+/// LocationごとのRedis接続オプション（指定した項目だけがデフォルト設定を上書きする）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocationRedisOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_mode: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ca: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_insecure: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaker_failures: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaker_cooldown: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaker_probes: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compat: Option<RedisCompat>,
+}
+
+impl LocationRedisOptions {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 指定された項目をoptionsに上書きする（パスワードは設定済みのものを取り消せない）
+    pub fn apply(&self, options: &mut RedisConnectionOptions) {
+        if let Some(password) = &self.password {
+            options.password = Some(password.clone());
+        }
+        apply_overrides!(
+            self,
+            options,
+            [
+                connect_timeout,
+                command_timeout,
+                retry_count,
+                retry_delay,
+                database,
+                pool_size,
+                cluster_mode,
+                tls_enabled,
+                tls_ca,
+                tls_cert,
+                tls_key,
+                tls_insecure,
+                keepalive,
+                breaker_failures,
+                breaker_cooldown,
+                breaker_probes,
+                compat,
+            ]
+        );
+    }
+}
+
+/// Redisチェックの実行方法
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Offload {
+    /// モジュールのTokioランタイムで非同期に実行する
+    #[default]
+    Async,
+    /// NGINXのスレッドプール（aio threads）上でRedisの応答を待つ
+    ThreadPool(String),
+}
+
+impl Offload {
+    /// "async"、"thread_pool"、"thread_pool:<プール名>" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "async" => Ok(Offload::Async),
+            "thread_pool" => Ok(Offload::ThreadPool("default".to_string())),
+            _ => match value.strip_prefix("thread_pool:") {
+                Some(pool) if !pool.is_empty() => Ok(Offload::ThreadPool(pool.to_string())),
+                _ => Err(format!(
+                    "Invalid offload value (expected async or thread_pool[:name]): {}",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+/// レート制限の状態を保持するバックエンド
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    /// Redis（全ワーカー・全サーバーで状態を共有する）
+    #[default]
+    Redis,
+    /// ワーカープロセス内のメモリ（開発・テスト用、Redisを必要としない）
+    Memory,
+}
+
+impl Backend {
+    /// "redis"、"memory" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "redis" => Ok(Backend::Redis),
+            "memory" => Ok(Backend::Memory),
+            _ => Err(format!(
+                "Invalid backend value (expected redis or memory): {}",
+                value
+            )),
+        }
+    }
+}
+
+/// モジュールの動作モード
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Mode {
+    /// アクセスフェーズで上限を超えたリクエストを拒否し、許可したリクエストは処理を続ける
+    #[default]
+    Filter,
+    /// 判定結果だけを返す（許可は204、拒否は429）。auth_request のサブリクエスト先として使用する
+    Auth,
+    /// mirror でコピーされたリクエストを候補の上限で評価する（本番とは別のキーで数え、拒否しない）
+    Mirror,
+}
+
+impl Mode {
+    /// "filter"、"auth"、"mirror" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "filter" => Ok(Mode::Filter),
+            "auth" => Ok(Mode::Auth),
+            "mirror" => Ok(Mode::Mirror),
+            _ => Err(format!(
+                "Invalid mode value (expected filter, auth or mirror): {}",
+                value
+            )),
+        }
+    }
+}
+
+/// Redisで判定できない場合（接続エラー、滞留、レイテンシ予算の超過）の動作
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FailureMode {
+    /// リクエストを許可する
+    #[default]
+    Open,
+    /// リクエストを拒否する
+    Closed,
+    /// ワーカーごとのメモリ上のカウンタで判定する（上限はワーカーごとに適用される）
+    Local,
+}
+
+impl FailureMode {
+    /// "open"、"closed"、"local" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "open" => Ok(FailureMode::Open),
+            "closed" => Ok(FailureMode::Closed),
+            "local" => Ok(FailureMode::Local),
+            _ => Err(format!(
+                "Invalid failure_mode value (expected open, closed or local): {}",
+                value
+            )),
+        }
+    }
+}
+
+/// 上限を超えたリクエストへの応答
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnLimit {
+    /// エラーで拒否する
+    #[default]
+    Reject,
+    /// ブラウザからのリクエストはチャレンジページにリダイレクトし、通過Cookieを持つクライアントは許可する
+    Challenge,
+}
+
+impl OnLimit {
+    /// "reject"、"challenge" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(OnLimit::Reject),
+            "challenge" => Ok(OnLimit::Challenge),
+            _ => Err(format!(
+                "Invalid on_limit value (expected reject or challenge): {}",
+                value
+            )),
+        }
+    }
+}
+
+/// 上限を超えたリクエストに返すステータスコード
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RejectStatus {
+    /// 403 Forbidden
+    #[default]
+    Forbidden,
+    /// 429 Too Many Requests
+    TooManyRequests,
+    /// 503 Service Unavailable（limit_req_status の既定値）
+    ServiceUnavailable,
+    /// その他の 4xx・5xx のコード
+    Custom(u16),
+}
+
+impl RejectStatus {
+    /// 400〜599 のステータスコードを解析する
+    ///
+    /// 拒否した応答を成功やリダイレクトとして扱わせないため、それ以外のコードは受け付けない
+    pub fn from_code(code: u16) -> Result<Self, String> {
+        match code {
+            403 => Ok(RejectStatus::Forbidden),
+            429 => Ok(RejectStatus::TooManyRequests),
+            503 => Ok(RejectStatus::ServiceUnavailable),
+            400..=599 => Ok(RejectStatus::Custom(code)),
+            _ => Err(format!("Invalid status value (expected 400-599): {}", code)),
+        }
+    }
+
+    /// "429" のようなステータスコードを解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        value
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid status value (expected 400-599): {}", value))
+            .and_then(Self::from_code)
+    }
+}
+
+/// レート制限ヘッダーの形式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HeaderFormat {
+    /// X-RateLimit-Limit、X-RateLimit-Remaining、X-RateLimit-Reset
+    #[default]
+    Legacy,
+    /// draft-ietf-httpapi-ratelimit-headers の RateLimit-Limit、RateLimit-Remaining、
+    /// RateLimit-Reset、RateLimit-Policy
+    Ietf,
+    /// 両方の形式
+    Both,
+}
+
+impl HeaderFormat {
+    /// "legacy"、"ietf"、"both" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "legacy" => Ok(HeaderFormat::Legacy),
+            "ietf" => Ok(HeaderFormat::Ietf),
+            "both" => Ok(HeaderFormat::Both),
+            _ => Err(format!(
+                "Invalid header_format value (expected legacy, ietf or both): {}",
+                value
+            )),
+        }
+    }
+
+    /// X-RateLimit-* を付けるか
+    pub fn legacy(&self) -> bool {
+        matches!(self, HeaderFormat::Legacy | HeaderFormat::Both)
+    }
+
+    /// RateLimit-* を付けるか
+    pub fn ietf(&self) -> bool {
+        matches!(self, HeaderFormat::Ietf | HeaderFormat::Both)
+    }
+}
+
+/// 不審な応答の割合が閾値を超えたキーへの対処
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AbuseAction {
+    /// キーをBANする
+    #[default]
+    Ban,
+    /// キーのレート・バーストを引き下げる
+    Tighten,
+}
+
+impl AbuseAction {
+    /// "ban"、"tighten" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "ban" => Ok(AbuseAction::Ban),
+            "tighten" => Ok(AbuseAction::Tighten),
+            _ => Err(format!(
+                "Invalid abuse_action value (expected ban or tighten): {}",
+                value
+            )),
+        }
+    }
+}
+
+/// NGINX変数を含むキー指定（"$binary_remote_addr$uri" など）の要素
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPart {
+    /// そのまま使用する文字列
+    Literal(String),
+    /// NGINX変数の名前（"$" を除き小文字化したもの）
+    Variable(String),
+}
+
+/// NGINX変数を含むキー指定を要素に分解する（"$" を含まない場合は None）
+///
+/// 変数は "$name" または、直後に英数字が続く場合のため "${name}" で書く
+pub fn parse_key_expression(spec: &str) -> Result<Option<Vec<KeyPart>>, String> {
+    if !spec.contains('$') {
+        return Ok(None);
+    }
+
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut parts = Vec::new();
+    let mut rest = spec;
+    while let Some(pos) = rest.find('$') {
+        if pos > 0 {
+            parts.push(KeyPart::Literal(rest[..pos].to_string()));
+        }
+        rest = &rest[pos + 1..];
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => return Err(format!("Unterminated variable in key: {}", spec)),
+            },
+            None => {
+                let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return Err(format!("Invalid variable name in key: {}", spec));
+        }
+        parts.push(KeyPart::Variable(name.to_ascii_lowercase()));
+        rest = after;
+    }
+    if !rest.is_empty() {
+        parts.push(KeyPart::Literal(rest.to_string()));
+    }
+    Ok(Some(parts))
+}
+
+/// キー指定を検証する
+///
+/// カンマで区切った各要素が remote_addr、jwt_claim:<クレーム名>、http_*、cookie_*、uri、
+/// NGINX変数を含む式、固定文字列のいずれかであること。oauth_subject は判定時にキー全体をサブジェクトに置き換えるため、
+/// 他の要素と組み合わせられない
+pub fn validate_key(spec: &str) -> Result<(), String> {
+    let components: Vec<&str> = spec.split(',').collect();
+    for component in &components {
+        if component.is_empty() {
+            return Err(format!("Empty component in key: {}", spec));
+        }
+        if *component == "oauth_subject" && components.len() > 1 {
+            return Err(format!(
+                "oauth_subject cannot be combined with other key components: {}",
+                spec
+            ));
+        }
+        if *component == "cookie_" {
+            return Err(format!(
+                "cookie_ requires a cookie name (cookie_<name>): {}",
+                spec
+            ));
+        }
+        if let Some(claim) = component.strip_prefix("jwt_claim") {
+            if claim.strip_prefix(':').is_none_or(str::is_empty) {
+                return Err(format!(
+                    "jwt_claim requires a claim name (jwt_claim:<name>): {}",
+                    spec
+                ));
+            }
+        }
+        parse_key_expression(component)?;
+    }
+    Ok(())
+}
+
+// 設定ファイルのキー指定（文字列、または組み合わせる要素の配列）
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeySpec {
+    Joined(String),
+    Components(Vec<String>),
+}
+
+impl From<KeySpec> for String {
+    fn from(spec: KeySpec) -> Self {
+        match spec {
+            KeySpec::Joined(key) => key,
+            KeySpec::Components(components) => components.join(","),
+        }
+    }
+}
+
+// 要素の配列で書かれたキー指定をカンマ区切りの文字列として読み込む
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    KeySpec::deserialize(deserializer).map(String::from)
+}
+
+fn deserialize_optional_key<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Option::<KeySpec>::deserialize(deserializer).map(|spec| spec.map(String::from))
+}
+
+/// 1リクエストに追加で適用するレート制限ゾーン
+///
+/// ゾーンごとに異なるキー・レート・バーストでカウントされ、アルゴリズムと時間窓はLocationの設定に従う
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZoneSettings {
+    /// レート制限に使用するキー（remote_addr、http_x_api_keyなど）
+    #[serde(deserialize_with = "deserialize_key")]
+    pub key: String,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+}
+
+impl ZoneSettings {
+    /// "key:rate[:burst]" 形式のゾーン指定を解析する
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut fields = spec.split(':');
+        let key = fields.next().unwrap_or("");
+        if key.is_empty() {
+            return Err(format!(
+                "Invalid zone (expected key:rate[:burst]): {}",
+                spec
+            ));
+        }
+        validate_key(key)?;
+        let rate = fields
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| format!("Invalid zone rate: {}", spec))?;
+        let burst = match fields.next() {
+            Some(v) => v
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid zone burst: {}", spec))?,
+            None => 0,
+        };
+        if fields.next().is_some() {
+            return Err(format!(
+                "Invalid zone (expected key:rate[:burst]): {}",
+                spec
+            ));
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            rate,
+            burst,
+        })
+    }
+}
+
+impl std::fmt::Display for ZoneSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.key, self.rate, self.burst)
+    }
+}
+
+/// プランごとのレート制限
+///
+/// トークンイントロスペクションの応答に含まれるプラン名に一致した場合、
+/// Locationのrate/burstの代わりに適用する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanSettings {
+    /// プラン名（イントロスペクション応答の plan_claim の値）
+    pub name: String,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+}
+
+impl PlanSettings {
+    /// "name:rate[:burst]" 形式のプラン指定を解析する
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let zone = ZoneSettings::parse(spec)
+            .map_err(|_| format!("Invalid plan (expected name:rate[:burst]): {}", spec))?;
+        Ok(Self {
+            name: zone.key,
+            rate: zone.rate,
+            burst: zone.burst,
+        })
+    }
+
+    /// プラン名に一致する設定を探す
+    pub fn find<'a>(plans: &'a [PlanSettings], name: &str) -> Option<&'a Self> {
+        plans.iter().find(|plan| plan.name == name)
+    }
+}
+
+impl std::fmt::Display for PlanSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.name, self.rate, self.burst)
+    }
+}
+
+/// ティアごとのレート制限
+///
+/// Locationの tier_header で指定したヘッダーの値がティア名に一致した場合、
+/// Locationのrate/burstの代わりに適用する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TierSettings {
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+    /// 判定に使用するアルゴリズム（省略した場合はLocationのアルゴリズム）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+}
+
+impl std::fmt::Display for TierSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.rate, self.burst)?;
+        if let Some(algorithm) = &self.algorithm {
+            write!(f, ":{}", algorithm)?;
+        }
+        Ok(())
+    }
+}
+
+/// tier_header に指定されたヘッダー名を検証する（空の場合はティアを使用しない）
+pub fn validate_tier_header(header: &str) -> Result<(), String> {
+    if header
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!("Invalid tier_header: {}", header))
+    }
+}
+
+/// gRPCのメソッドごとのレート制限
+///
+/// method は "/パッケージ.サービス/メソッド" 形式の :path、またはサービス内の全メソッドに
+/// 一致する "/パッケージ.サービス/*"。ワイルドカードのカウンタはサービス全体で共有される
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GrpcMethodSettings {
+    /// 対象のメソッド（/pkg.Service/Method または /pkg.Service/*）
+    pub method: String,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+}
+
+impl GrpcMethodSettings {
+    /// "/pkg.Service/Method:rate[:burst]" 形式のメソッド指定を解析する
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Invalid grpc method (expected /pkg.Service/Method:rate[:burst]): {}",
+                spec
+            )
+        };
+        let mut fields = spec.split(':');
+        let method = fields.next().unwrap_or("");
+        match method.strip_prefix('/').and_then(|m| m.split_once('/')) {
+            Some((service, name)) if !service.is_empty() && !name.is_empty() => {
+                if name.contains('/') {
+                    return Err(invalid());
+                }
+            }
+            _ => return Err(invalid()),
+        }
+        let rate = fields
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| format!("Invalid grpc method rate: {}", spec))?;
+        let burst = match fields.next() {
+            Some(v) => v
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid grpc method burst: {}", spec))?,
+            None => 0,
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            rate,
+            burst,
+        })
+    }
+
+    /// リクエストの :path がこのメソッド指定に一致するか
+    pub fn matches(&self, path: &str) -> bool {
+        match self.method.strip_suffix('*') {
+            Some(service) => path.starts_with(service) && !path[service.len()..].contains('/'),
+            None => path == self.method,
+        }
+    }
+
+    /// :path に適用するメソッド指定（完全一致をサービスのワイルドカードより優先する）
+    pub fn find<'a>(methods: &'a [GrpcMethodSettings], path: &str) -> Option<&'a Self> {
+        methods
+            .iter()
+            .find(|method| method.method == path)
+            .or_else(|| methods.iter().find(|method| method.matches(path)))
+    }
+}
+
+impl std::fmt::Display for GrpcMethodSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.method, self.rate, self.burst)
+    }
+}
+
+/// HTTPメソッドの名前（GET、POST などの大文字のトークン）として正しいかを確認する
+pub fn validate_http_method(method: &str) -> Result<(), String> {
+    if method.is_empty() || !method.bytes().all(|c| c.is_ascii_uppercase()) {
+        return Err(format!("Invalid HTTP method: {}", method));
+    }
+    Ok(())
+}
+
+/// HTTPメソッドごとのレート制限
+///
+/// 一致したリクエストはLocationのカウンタに加えて、メソッドごとのカウンタでも数える
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MethodLimitSettings {
+    /// 対象のHTTPメソッド（POST、DELETE など）
+    pub method: String,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+}
+
+impl MethodLimitSettings {
+    /// "METHOD:rate[:burst]" 形式のメソッド指定を解析する
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Invalid method limit (expected METHOD:rate[:burst]): {}",
+                spec
+            )
+        };
+        let mut fields = spec.split(':');
+        let method = fields.next().unwrap_or("");
+        validate_http_method(method).map_err(|_| invalid())?;
+        let rate = fields
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| format!("Invalid method limit rate: {}", spec))?;
+        let burst = match fields.next() {
+            Some(v) => v
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid method limit burst: {}", spec))?,
+            None => 0,
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            rate,
+            burst,
+        })
+    }
+
+    /// リクエストのメソッドに適用するメソッド指定
+    pub fn find<'a>(limits: &'a [MethodLimitSettings], method: &str) -> Option<&'a Self> {
+        limits.iter().find(|limit| limit.method == method)
+    }
+}
+
+impl std::fmt::Display for MethodLimitSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.method, self.rate, self.burst)
+    }
+}
+
+/// HTTPメソッドとパスごとのレート制限
+///
+/// path はOpenAPIのパステンプレートで、"{id}" のようなパラメータは任意の1セグメントに一致する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteSettings {
+    /// HTTPメソッド（大文字）
+    pub method: String,
+    /// パステンプレート（/users/{id} など）
+    pub path: String,
+    /// 1秒あたりの最大リクエスト数
+    pub rate: u32,
+    /// 一時的に許容される超過リクエスト数
+    #[serde(default)]
+    pub burst: u32,
+}
+
+impl RouteSettings {
+    /// リクエストのメソッドとパスがこのルートに一致するか
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        if !self.method.eq_ignore_ascii_case(method) {
+            return false;
+        }
+        let mut template = self.path.split('/');
+        let mut segments = path.split('/');
+        loop {
+            match (template.next(), segments.next()) {
+                (Some(t), Some(s)) => {
+                    let is_param = t.starts_with('{') && t.ends_with('}');
+                    if (is_param && s.is_empty()) || (!is_param && t != s) {
+                        return false;
+                    }
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    /// リクエストに適用するルート（パラメータを含まないルートを優先する）
+    pub fn find<'a>(routes: &'a [RouteSettings], method: &str, path: &str) -> Option<&'a Self> {
+        routes
+            .iter()
+            .filter(|route| route.matches(method, path))
+            .min_by_key(|route| route.path.matches('{').count())
+    }
+}
+
+impl std::fmt::Display for RouteSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}:{}:{}",
+            self.method, self.path, self.rate, self.burst
+        )
+    }
+}
+
+/// 2つの設定ファイル間で変化する1項目
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettingChange {
+    /// "default" またはLocationのパス
+    pub location: String,
+    pub field: String,
+    /// 変更前の値（Locationが追加される場合はNone）
+    pub before: Option<String>,
+    /// 変更後の値（Locationが削除される場合はNone）
+    pub after: Option<String>,
+}
+
 /// LocationごとのRateLimitSettingsマップ
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConfigFile {
     /// デフォルト設定（全てのLocationで共有される）
     #[serde(default)]
     pub default: RateLimitSettings,
 
-    /// Locationごとの設定（デフォルト設定をオーバーライドする）
+    /// Locationごとの設定（指定した項目だけがデフォルト設定をオーバーライドする）
     #[serde(default)]
-    pub locations: HashMap<String, RateLimitSettings>,
-}
+    pub locations: HashMap<String, LocationSettings>,
 
-impl Default for ConfigFile {
-    fn default() -> Self {
-        Self {
-            default: RateLimitSettings::default(),
-            locations: HashMap::new(),
-        }
-    }
+    /// x-ratelimit 拡張からルートごとのレート制限を生成するOpenAPI仕様のパス
+    ///
+    /// 相対パスは設定ファイルのディレクトリから解決し、生成したルートはデフォルト設定に追加する
+    #[serde(default)]
+    pub openapi: Option<String>,
+
+    /// ティア名ごとのレート制限（Locationの tier_header で指定したヘッダーの値で選択する）
+    #[serde(default)]
+    pub tiers: HashMap<String, TierSettings>,
 }
 
 impl ConfigFile {
@@ -99,62 +1297,327 @@ impl ConfigFile {
             return Err(format!("Failed to read config file: {}", e));
         }
 
-        match serde_json::from_str(&contents) {
-            Ok(config) => Ok(config),
+        Self::from_json(&contents, file_path.parent())
+    }
+
+    /// JSON文字列から設定を読み込む
+    ///
+    /// openapi の相対パスは base_dir から解決する（Noneの場合はカレントディレクトリ）
+    pub fn from_json(contents: &str, base_dir: Option<&Path>) -> Result<Self, String> {
+        let mut config: ConfigFile = match serde_json::from_str(contents) {
+            Ok(config) => config,
             Err(e) => {
                 error!("Failed to parse config file: {}", e);
-                Err(format!("Failed to parse config file: {}", e))
+                return Err(format!("Failed to parse config file: {}", e));
             }
+        };
+
+        if let Some(spec) = &config.openapi {
+            let spec_path = match base_dir {
+                Some(dir) => dir.join(spec),
+                None => Path::new(spec).to_path_buf(),
+            };
+            let routes = openapi::load_routes(&spec_path).map_err(|e| {
+                error!("{}", e);
+                e
+            })?;
+            info!(
+                "Loaded {} rate limited routes from {:?}",
+                routes.len(),
+                spec_path
+            );
+            config.default.routes.extend(routes);
         }
+
+        Ok(config)
+    }
+
+    /// 設定内容を識別する短いハッシュ（同じ内容であれば読み込み元によらず同じ値になる）
+    pub fn version(&self) -> String {
+        // serde_json::Value のオブジェクトはキーの順に並ぶため、Locationの順序に左右されない
+        let canonical = serde_json::to_value(self)
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+        let digest = Sha256::digest(canonical.as_bytes());
+        digest[..6].iter().map(|b| format!("{:02x}", b)).collect()
     }
 
     /// 特定のLocationの設定を取得する。Locationが設定されていない場合はデフォルト設定を返す
+    ///
+    /// Locationで指定された項目だけがデフォルト設定を上書きする（デフォルト値と同じ値も含む）
     pub fn get_settings(&self, location: &str) -> RateLimitSettings {
+        let mut settings = self.default.clone();
         if let Some(location_settings) = self.locations.get(location) {
-            // ロケーション固有の設定がある場合、デフォルト値から足りない項目を継承
-            let mut merged_settings = self.default.clone();
+            location_settings.apply(&mut settings);
+        }
+        settings
+    }
 
-            // デフォルト値が上書きされている項目のみを更新
-            if location_settings.redis_url != default_redis_url() {
-                merged_settings.redis_url = location_settings.redis_url.clone();
-            }
+    /// Locationに実際に適用される設定を項目ごとに返す（パスワードなどの値は伏せる）
+    ///
+    /// ngx-ratelimit-ctl show-config とゴールデンファイルのテストで使用する
+    #[cfg_attr(not(feature = "lib"), allow(dead_code))]
+    pub fn effective_fields(&self, location: &str) -> Vec<(&'static str, String)> {
+        settings_fields(&self.get_settings(location))
+    }
 
-            if location_settings.key != default_key() {
-                merged_settings.key = location_settings.key.clone();
-            }
+    /// 設定内容を検証し、問題があればエラーメッセージの一覧を返す
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
 
-            if location_settings.rate != default_rate() {
-                merged_settings.rate = location_settings.rate;
-            }
+        let mut sections: Vec<(String, RateLimitSettings)> =
+            vec![("default".to_string(), self.default.clone())];
+        for location in self.locations.keys() {
+            sections.push((location.clone(), self.get_settings(location)));
+        }
 
-            if location_settings.burst != default_burst() {
-                merged_settings.burst = location_settings.burst;
+        for (name, settings) in sections {
+            match Self::parse_algorithm(&settings.algorithm) {
+                Ok(algorithm) => {
+                    if let Err(e) = validate_limits(
+                        algorithm,
+                        settings.rate,
+                        settings.burst,
+                        settings.window_size,
+                        settings.rate_period,
+                    ) {
+                        errors.push(format!("{}: {}", name, e));
+                    }
+                    if settings.global_rate > 0 {
+                        if let Err(e) = validate_limits(
+                            algorithm,
+                            settings.global_rate,
+                            settings.global_burst,
+                            settings.window_size,
+                            settings.rate_period,
+                        ) {
+                            errors.push(format!("{}: global_rate: {}", name, e));
+                        }
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {}", name, e)),
             }
-
-            if location_settings.algorithm != default_algorithm() {
-                merged_settings.algorithm = location_settings.algorithm.clone();
+            if settings.key.is_empty() {
+                errors.push(format!("{}: key must not be empty", name));
+            } else if let Err(e) = validate_key(&settings.key) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = Offload::parse(&settings.offload) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = Backend::parse(&settings.backend) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = Mode::parse(&settings.mode) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = FailureMode::parse(&settings.failure_mode) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = AbuseAction::parse(&settings.abuse_action) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            match OnLimit::parse(&settings.on_limit) {
+                Ok(OnLimit::Challenge) => {
+                    if settings.challenge_url.is_empty() || settings.challenge_secret.is_empty() {
+                        errors.push(format!(
+                            "{}: on_limit=challenge requires challenge_url and challenge_secret",
+                            name
+                        ));
+                    }
+                }
+                Ok(OnLimit::Reject) => {}
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+            if let Err(e) = RejectStatus::from_code(settings.status) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if settings.ban_status != 0 {
+                if let Err(e) = RejectStatus::from_code(settings.ban_status) {
+                    errors.push(format!("{}: ban_status: {}", name, e));
+                }
+            }
+            if let Err(e) = settings.ban_policy().validate() {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = HeaderFormat::parse(&settings.header_format) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if !settings.reject_body_file.is_empty() {
+                if let Err(e) = load_reject_body(&settings.reject_body_file) {
+                    errors.push(format!("{}: {}", name, e));
+                }
+            }
+            if settings.reject_content_type.is_empty() {
+                errors.push(format!("{}: reject_content_type must not be empty", name));
+            }
+            if settings.global_burst > 0 && settings.global_rate == 0 {
+                errors.push(format!("{}: global_burst requires global_rate", name));
+            }
+            if settings.overlimit_cache_until_reset && settings.overlimit_cache_ms == 0 {
+                errors.push(format!(
+                    "{}: overlimit_cache_until_reset requires overlimit_cache_ms",
+                    name
+                ));
+            }
+            let algorithm = Self::parse_algorithm(&settings.algorithm).ok();
+            if settings.throttle && algorithm != Some(RateLimitAlgorithm::Gcra) {
+                errors.push(format!("{}: throttle requires algorithm gcra", name));
+            }
+            if (settings.nodelay || settings.delay > 0)
+                && algorithm != Some(RateLimitAlgorithm::LimitReq)
+                && !settings.throttle
+            {
+                errors.push(format!(
+                    "{}: nodelay and delay require algorithm limit_req or throttle",
+                    name
+                ));
+            }
+            if settings.abuse_ratio > 100 {
+                errors.push(format!(
+                    "{}: abuse_ratio must be a percentage (0-100)",
+                    name
+                ));
+            }
+            for zone in &settings.zones {
+                if zone.key.is_empty() || zone.rate == 0 {
+                    errors.push(format!("{}: invalid zone {}", name, zone));
+                } else if let Err(e) = validate_key(&zone.key) {
+                    errors.push(format!("{}: {}", name, e));
+                }
+            }
+            for method in &settings.grpc_methods {
+                if let Err(e) = GrpcMethodSettings::parse(&method.to_string()) {
+                    errors.push(format!("{}: {}", name, e));
+                } else if method.rate == 0 {
+                    errors.push(format!("{}: invalid grpc method {}", name, method));
+                }
+            }
+            for method in &settings.methods {
+                if let Err(e) = validate_http_method(method) {
+                    errors.push(format!("{}: {}", name, e));
+                }
             }
+            for limit in &settings.method_limits {
+                if let Err(e) = MethodLimitSettings::parse(&limit.to_string()) {
+                    errors.push(format!("{}: {}", name, e));
+                } else if limit.rate == 0 {
+                    errors.push(format!("{}: invalid method limit {}", name, limit));
+                }
+            }
+            for route in &settings.routes {
+                if !route.path.starts_with('/') || route.rate == 0 {
+                    errors.push(format!("{}: invalid route {}", name, route));
+                }
+            }
+            for plan in &settings.plans {
+                if plan.name.is_empty() || plan.rate == 0 {
+                    errors.push(format!("{}: invalid plan {}", name, plan));
+                }
+            }
+            if let Err(e) = validate_tier_header(&settings.tier_header) {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = settings.per.parse::<QuotaPeriod>() {
+                errors.push(format!("{}: {}", name, e));
+            }
+            if let Err(e) = CidrSet::parse_all(&settings.whitelist) {
+                errors.push(format!("{}: whitelist: {}", name, e));
+            }
+            if let Err(e) = CidrSet::parse_all(&settings.blacklist) {
+                errors.push(format!("{}: blacklist: {}", name, e));
+            }
+            if let Err(e) = settings.redis_url.as_str().into_connection_info() {
+                errors.push(format!("{}: invalid redis_url: {}", name, e));
+            }
+            if let Err(e) = settings.redis_options.validate_tls(&settings.redis_url) {
+                errors.push(format!("{}: redis_options: {}", name, e));
+            }
+            if let Err(e) = settings.redis_options.validate_breaker() {
+                errors.push(format!("{}: redis_options: {}", name, e));
+            }
+        }
 
-            if location_settings.window_size != default_window_size() {
-                merged_settings.window_size = location_settings.window_size;
+        // ティアはデフォルト設定の window_size・rate_period で判定する
+        for (name, tier) in &self.tiers {
+            let algorithm = match &tier.algorithm {
+                Some(algorithm) => Self::parse_algorithm(algorithm),
+                None => Self::parse_algorithm(&self.default.algorithm),
+            };
+            match algorithm {
+                Ok(algorithm) => {
+                    if let Err(e) = validate_limits(
+                        algorithm,
+                        tier.rate,
+                        tier.burst,
+                        self.default.window_size,
+                        self.default.rate_period,
+                    ) {
+                        errors.push(format!("tiers.{}: {}", name, e));
+                    }
+                }
+                Err(e) => errors.push(format!("tiers.{}: {}", name, e)),
             }
+        }
 
-            // 有効/無効フラグは明示的に設定されている場合のみ上書き
-            if location_settings.enabled != self.default.enabled {
-                merged_settings.enabled = location_settings.enabled;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// 候補の設定ファイルと比較し、Locationごとに実際に適用される値の変化を返す
+    ///
+    /// Locationの設定はデフォルト設定とマージした結果で比較するため、
+    /// デフォルト設定の変更が影響する全てのLocationも報告される
+    pub fn diff(&self, candidate: &ConfigFile) -> Vec<SettingChange> {
+        let mut changes = Vec::new();
+
+        diff_settings("default", &self.default, &candidate.default, &mut changes);
+
+        let locations: BTreeSet<&String> = self
+            .locations
+            .keys()
+            .chain(candidate.locations.keys())
+            .collect();
+
+        for location in locations {
+            let before = self.locations.contains_key(location);
+            let after = candidate.locations.contains_key(location);
+            if before != after {
+                changes.push(SettingChange {
+                    location: location.clone(),
+                    field: "location".to_string(),
+                    before: before.then(|| "defined".to_string()),
+                    after: after.then(|| "defined".to_string()),
+                });
             }
 
-            // Redis接続オプションをマージ（設定されている項目のみを上書き）
-            // 注: デフォルト値と異なる項目のみをマージ
-            merge_redis_options(
-                &mut merged_settings.redis_options,
-                &location_settings.redis_options,
+            diff_settings(
+                location,
+                &self.get_settings(location),
+                &candidate.get_settings(location),
+                &mut changes,
             );
+        }
 
-            merged_settings
-        } else {
-            self.default.clone()
+        // ティアは "tiers" の項目として報告する
+        let tiers: BTreeSet<&String> = self.tiers.keys().chain(candidate.tiers.keys()).collect();
+        for tier in tiers {
+            let before = self.tiers.get(tier).map(|t| t.to_string());
+            let after = candidate.tiers.get(tier).map(|t| t.to_string());
+            if before != after {
+                changes.push(SettingChange {
+                    location: "tiers".to_string(),
+                    field: tier.clone(),
+                    before,
+                    after,
+                });
+            }
         }
+
+        changes
     }
 
     /// 設定からRateLimitAlgorithmを解析する
@@ -163,57 +1626,225 @@ impl ConfigFile {
     }
 }
 
-/// Redis接続オプションをマージする（srcにある非デフォルト値のみをdestに適用）
-fn merge_redis_options(dest: &mut RedisConnectionOptions, src: &RedisConnectionOptions) {
-    // デフォルト値と異なる接続タイムアウトのみを適用
-    if src.connect_timeout != RedisConnectionOptions::default().connect_timeout {
-        dest.connect_timeout = src.connect_timeout;
-    }
-
-    // デフォルト値と異なるコマンドタイムアウトのみを適用
-    if src.command_timeout != RedisConnectionOptions::default().command_timeout {
-        dest.command_timeout = src.command_timeout;
-    }
-
-    // デフォルト値と異なるリトライ回数のみを適用
-    if src.retry_count != RedisConnectionOptions::default().retry_count {
-        dest.retry_count = src.retry_count;
-    }
-
-    // デフォルト値と異なるリトライ間隔のみを適用
-    if src.retry_delay != RedisConnectionOptions::default().retry_delay {
-        dest.retry_delay = src.retry_delay;
-    }
+// 実際に適用される設定値を項目ごとに比較する
+fn diff_settings(
+    location: &str,
+    before: &RateLimitSettings,
+    after: &RateLimitSettings,
+    changes: &mut Vec<SettingChange>,
+) {
+    let before_fields = settings_fields(before);
+    let after_fields = settings_fields(after);
 
-    // パスワードが設定されている場合のみ適用
-    if src.password.is_some() {
-        dest.password = src.password.clone();
+    for ((field, old), (_, new)) in before_fields.into_iter().zip(after_fields) {
+        if old != new {
+            changes.push(SettingChange {
+                location: location.to_string(),
+                field: field.to_string(),
+                before: Some(old),
+                after: Some(new),
+            });
+        }
     }
+}
 
-    // デフォルト値と異なるデータベース番号のみを適用
-    if src.database != RedisConnectionOptions::default().database {
-        dest.database = src.database;
+/// rate・burst・window_size・rate_period の組み合わせを検証する
+///
+/// 設定ファイルとディレクティブの両方で使用する。rate=0 は「すべて拒否」、burst=0 は「バーストなし」として
+/// 受け付け、意味を持たない組み合わせだけをエラーにする
+pub fn validate_limits(
+    algorithm: RateLimitAlgorithm,
+    rate: u32,
+    burst: u32,
+    window_size: u32,
+    rate_period: u32,
+) -> Result<(), String> {
+    if window_size == 0 {
+        return Err("window_size must be greater than 0".to_string());
     }
-
-    // デフォルト値と異なる接続プールサイズのみを適用
-    if src.pool_size != RedisConnectionOptions::default().pool_size {
-        dest.pool_size = src.pool_size;
+    if rate_period == 0 {
+        return Err("rate_period must be greater than 0".to_string());
     }
-
-    // クラスタモードの設定
-    if src.cluster_mode != RedisConnectionOptions::default().cluster_mode {
-        dest.cluster_mode = src.cluster_mode;
+    // rate=0 はRedisに問い合わせずに拒否するため、burst は使われない
+    if rate == 0 && burst > 0 {
+        return Err("rate=0 denies every request, so burst must be 0".to_string());
     }
-
-    // TLS設定
-    if src.tls_enabled != RedisConnectionOptions::default().tls_enabled {
-        dest.tls_enabled = src.tls_enabled;
+    match algorithm {
+        // 時間窓のアルゴリズムは window_size ごとに rate + burst 件を許可するため、期間は window_size で指定する
+        RateLimitAlgorithm::FixedWindow
+        | RateLimitAlgorithm::SlidingWindow
+        | RateLimitAlgorithm::SlidingLog
+            if rate_period > 1 =>
+        {
+            Err(format!(
+                "rate_period does not apply to {} (it allows rate + burst requests per window_size)",
+                algorithm
+            ))
+        }
+        RateLimitAlgorithm::LimitReq if rate > 0 && (rate as u64) * 1000 < rate_period as u64 => {
+            Err("limit_req cannot represent rates below 1 request per 1000 seconds".to_string())
+        }
+        _ => Ok(()),
     }
+}
 
-    // キープアライブ設定
-    if src.keepalive != RedisConnectionOptions::default().keepalive {
-        dest.keepalive = src.keepalive;
-    }
+// 比較対象の項目を表示用の文字列として並べる（パスワードは値を伏せる）
+fn settings_fields(settings: &RateLimitSettings) -> Vec<(&'static str, String)> {
+    let options = &settings.redis_options;
+    vec![
+        ("enabled", settings.enabled.to_string()),
+        ("algorithm", settings.algorithm.clone()),
+        ("rate", settings.rate.to_string()),
+        ("burst", settings.burst.to_string()),
+        ("window_size", settings.window_size.to_string()),
+        ("rate_period", settings.rate_period.to_string()),
+        ("prefetch_ms", settings.prefetch_ms.to_string()),
+        (
+            "overlimit_cache_ms",
+            settings.overlimit_cache_ms.to_string(),
+        ),
+        (
+            "overlimit_cache_until_reset",
+            settings.overlimit_cache_until_reset.to_string(),
+        ),
+        ("max_in_flight", settings.max_in_flight.to_string()),
+        ("latency_budget_ms", settings.latency_budget_ms.to_string()),
+        ("failure_mode", settings.failure_mode.clone()),
+        ("offload", settings.offload.clone()),
+        ("backend", settings.backend.clone()),
+        ("mode", settings.mode.clone()),
+        ("accounting", settings.accounting.to_string()),
+        ("abuse_ratio", settings.abuse_ratio.to_string()),
+        (
+            "abuse_min_requests",
+            settings.abuse_min_requests.to_string(),
+        ),
+        ("abuse_action", settings.abuse_action.clone()),
+        ("abuse_duration", settings.abuse_duration.to_string()),
+        ("ban_after", settings.ban_after.to_string()),
+        ("ban_window", settings.ban_window.to_string()),
+        ("ban_duration", settings.ban_duration.to_string()),
+        ("ban_max_duration", settings.ban_max_duration.to_string()),
+        ("ban_status", settings.ban_status.to_string()),
+        ("nodelay", settings.nodelay.to_string()),
+        ("delay", settings.delay.to_string()),
+        ("throttle", settings.throttle.to_string()),
+        ("status", settings.status.to_string()),
+        ("header_format", settings.header_format.clone()),
+        ("reject_body_file", settings.reject_body_file.clone()),
+        ("reject_content_type", settings.reject_content_type.clone()),
+        ("on_limit", settings.on_limit.clone()),
+        ("challenge_url", settings.challenge_url.clone()),
+        (
+            "challenge_secret",
+            if settings.challenge_secret.is_empty() {
+                String::new()
+            } else {
+                "(set)".to_string()
+            },
+        ),
+        ("key", settings.key.clone()),
+        (
+            "zones",
+            settings
+                .zones
+                .iter()
+                .map(|zone| zone.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        (
+            "grpc_methods",
+            settings
+                .grpc_methods
+                .iter()
+                .map(|method| method.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        ("methods", settings.methods.join(",")),
+        (
+            "method_limits",
+            settings
+                .method_limits
+                .iter()
+                .map(|limit| limit.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        (
+            "routes",
+            settings
+                .routes
+                .iter()
+                .map(|route| route.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        (
+            "plans",
+            settings
+                .plans
+                .iter()
+                .map(|plan| plan.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        ("key_limits", settings.key_limits.to_string()),
+        ("tier_header", settings.tier_header.clone()),
+        ("global_rate", settings.global_rate.to_string()),
+        ("global_burst", settings.global_burst.to_string()),
+        ("quota", settings.quota.to_string()),
+        ("per", settings.per.clone()),
+        ("whitelist", settings.whitelist.join(",")),
+        ("blacklist", settings.blacklist.join(",")),
+        ("redis_url", settings.redis_url.clone()),
+        (
+            "redis_options.connect_timeout",
+            options.connect_timeout.to_string(),
+        ),
+        (
+            "redis_options.command_timeout",
+            options.command_timeout.to_string(),
+        ),
+        ("redis_options.retry_count", options.retry_count.to_string()),
+        ("redis_options.retry_delay", options.retry_delay.to_string()),
+        (
+            "redis_options.password",
+            match &options.password {
+                Some(p) => format!("(set, {} chars)", p.len()),
+                None => "(none)".to_string(),
+            },
+        ),
+        ("redis_options.database", options.database.to_string()),
+        ("redis_options.pool_size", options.pool_size.to_string()),
+        (
+            "redis_options.cluster_mode",
+            options.cluster_mode.to_string(),
+        ),
+        ("redis_options.tls_enabled", options.tls_enabled.to_string()),
+        ("redis_options.tls_ca", options.tls_ca.clone()),
+        ("redis_options.tls_cert", options.tls_cert.clone()),
+        ("redis_options.tls_key", options.tls_key.clone()),
+        (
+            "redis_options.tls_insecure",
+            options.tls_insecure.to_string(),
+        ),
+        ("redis_options.keepalive", options.keepalive.to_string()),
+        (
+            "redis_options.breaker_failures",
+            options.breaker_failures.to_string(),
+        ),
+        (
+            "redis_options.breaker_cooldown",
+            options.breaker_cooldown.to_string(),
+        ),
+        (
+            "redis_options.breaker_probes",
+            options.breaker_probes.to_string(),
+        ),
+        ("redis_options.compat", options.compat.to_string()),
+    ]
 }
 
 // デフォルト値関数
@@ -241,6 +1872,139 @@ fn default_window_size() -> u32 {
     60
 }
 
+fn default_rate_period() -> u32 {
+    1
+}
+
+fn default_prefetch_ms() -> u64 {
+    0
+}
+
+fn default_overlimit_cache_ms() -> u64 {
+    0
+}
+
+fn default_overlimit_cache_until_reset() -> bool {
+    false
+}
+
+fn default_max_in_flight() -> u32 {
+    0
+}
+
+fn default_failure_mode() -> String {
+    "open".to_string()
+}
+
+fn default_latency_budget_ms() -> u64 {
+    0
+}
+
+fn default_offload() -> String {
+    "async".to_string()
+}
+
+fn default_backend() -> String {
+    "redis".to_string()
+}
+
+fn default_mode() -> String {
+    "filter".to_string()
+}
+
+fn default_accounting() -> bool {
+    false
+}
+
+fn default_abuse_ratio() -> u32 {
+    0
+}
+
+fn default_abuse_min_requests() -> u32 {
+    20
+}
+
+fn default_abuse_action() -> String {
+    "ban".to_string()
+}
+
+fn default_abuse_duration() -> u64 {
+    600
+}
+
+fn default_ban_window() -> u64 {
+    60
+}
+
+fn default_ban_duration() -> u64 {
+    300
+}
+
+fn default_ban_max_duration() -> u64 {
+    86400
+}
+
+fn default_nodelay() -> bool {
+    false
+}
+
+fn default_delay() -> u32 {
+    0
+}
+
+fn default_throttle() -> bool {
+    false
+}
+
+fn default_key_limits() -> bool {
+    false
+}
+
+fn default_per() -> String {
+    "day".to_string()
+}
+
+fn default_status() -> u16 {
+    403
+}
+
+fn default_header_format() -> String {
+    "legacy".to_string()
+}
+
+fn default_reject_content_type() -> String {
+    "application/json".to_string()
+}
+
+/// 拒否した応答の本文として返せるファイルの最大サイズ
+pub const MAX_REJECT_BODY_SIZE: u64 = 64 * 1024;
+
+/// 上限を超えたリクエストに返す本文をファイルから読み込む
+pub fn load_reject_body(path: &str) -> Result<Vec<u8>, String> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read reject_body_file {}: {}", path, e))?
+        .len();
+    if size > MAX_REJECT_BODY_SIZE {
+        return Err(format!(
+            "reject_body_file {} is larger than {} bytes",
+            path, MAX_REJECT_BODY_SIZE
+        ));
+    }
+    std::fs::read(path).map_err(|e| format!("Failed to read reject_body_file {}: {}", path, e))
+}
+
+fn default_on_limit() -> String {
+    "reject".to_string()
+}
+
+fn default_challenge_url() -> String {
+    String::new()
+}
+
+fn default_challenge_secret() -> String {
+    String::new()
+}
+
 fn default_enabled() -> bool {
     false
 }
@@ -0,0 +1,404 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::ConfigFile;
+
+/// Consulのブロッキングクエリの最大待機時間
+const CONSUL_WAIT: &str = "5m";
+
+/// 取得・監視に失敗した後、再試行するまでの間隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 設定読み込み時の取得のタイムアウト
+const INITIAL_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ConfigFileのJSONを保持する外部のKVストア
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    /// Consul KV（"consul://host:8500/<キー>"）
+    Consul { endpoint: String, key: String },
+    /// etcd v3（"etcd://host:2379/<キー>"、gRPC-gatewayのJSON APIを使用する）
+    Etcd { endpoint: String, key: String },
+}
+
+/// 設定ソースの設定（"ratelimit_redis_config_source" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigSourceSettings {
+    pub source: ConfigSource,
+    /// ConsulのACLトークン、またはetcdの認証トークン
+    pub token: Option<String>,
+}
+
+impl ConfigSourceSettings {
+    /// "consul://host:port/key" または "etcd://host:port/key" と [token=...] を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let syntax = || {
+            "Syntax: ratelimit_redis_config_source consul://<host:port>/<key>|etcd://<host:port>/<key> [token=<token>]"
+                .to_string()
+        };
+        let (spec, rest) = args.split_first().ok_or_else(syntax)?;
+
+        let (scheme, rest_of_spec) = spec.split_once("://").ok_or_else(syntax)?;
+        let (host, key) = rest_of_spec.split_once('/').ok_or_else(syntax)?;
+        if host.is_empty() || key.is_empty() {
+            return Err(syntax());
+        }
+        let endpoint = format!("http://{}", host);
+        let key = key.to_string();
+        let source = match scheme {
+            "consul" => ConfigSource::Consul { endpoint, key },
+            "etcd" => ConfigSource::Etcd { endpoint, key },
+            _ => return Err(syntax()),
+        };
+
+        let mut token = None;
+        for arg in rest {
+            match arg.strip_prefix("token=") {
+                Some(value) => token = Some(value.to_string()),
+                None => {
+                    return Err(format!(
+                        "Unknown ratelimit_redis_config_source parameter: {}",
+                        arg
+                    ))
+                }
+            }
+        }
+
+        Ok(Self { source, token })
+    }
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigSource::Consul { endpoint, key } => write!(f, "consul {}/{}", endpoint, key),
+            ConfigSource::Etcd { endpoint, key } => write!(f, "etcd {}/{}", endpoint, key),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<ConfigSourceSettings>> = Mutex::new(None);
+}
+
+/// 設定を保持する（監視はワーカーで行う）
+pub fn configure(settings: ConfigSourceSettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+/// 現在の設定
+pub fn settings() -> Option<ConfigSourceSettings> {
+    SETTINGS.lock().ok().and_then(|slot| slot.clone())
+}
+
+/// 現在の値を1回だけ取得する（設定の読み込み時に使用する）
+pub async fn fetch(settings: &ConfigSourceSettings) -> Result<ConfigFile, String> {
+    let client = client(Some(INITIAL_FETCH_TIMEOUT))?;
+    let contents = match &settings.source {
+        ConfigSource::Consul { endpoint, key } => {
+            consul_get(&client, endpoint, key, settings.token.as_deref(), None)
+                .await?
+                .map(|(_, contents)| contents)
+        }
+        ConfigSource::Etcd { endpoint, key } => {
+            etcd_range(&client, endpoint, key, settings.token.as_deref())
+                .await?
+                .and_then(|(_, contents)| contents)
+        }
+    };
+    match contents {
+        Some(contents) => parse(&contents),
+        None => Err(format!("{} does not exist", settings.source)),
+    }
+}
+
+/// 値の変更を監視し、有効な設定を受け取るたびに apply を呼ぶ（戻らない）
+///
+/// Consulはブロッキングクエリ（X-Consul-Index）、etcdは watch API で変更を待つ。
+/// 接続が切れた場合や不正な設定を受け取った場合は、現在の設定を維持して監視を続ける
+pub async fn watch(settings: ConfigSourceSettings, apply: impl Fn(ConfigFile)) {
+    // 長時間のブロッキングクエリ・ストリームを切らないよう、タイムアウトは設定しない
+    let client = match client(None) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
+
+    match &settings.source {
+        ConfigSource::Consul { endpoint, key } => {
+            watch_consul(&client, endpoint, key, settings.token.as_deref(), &apply).await
+        }
+        ConfigSource::Etcd { endpoint, key } => {
+            watch_etcd(&client, endpoint, key, settings.token.as_deref(), &apply).await
+        }
+    }
+}
+
+fn client(timeout: Option<Duration>) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+// 受け取ったJSONを検証する（問題があれば適用しない）
+fn parse(contents: &str) -> Result<ConfigFile, String> {
+    let config = ConfigFile::from_json(contents, None)?;
+    config
+        .validate()
+        .map_err(|errors| format!("Invalid configuration: {}", errors.join("; ")))?;
+    Ok(config)
+}
+
+// 受け取った値を検証して適用する
+fn apply_contents(source: &str, contents: &str, apply: &impl Fn(ConfigFile)) {
+    match parse(contents) {
+        Ok(config) => {
+            info!("Applying rate limit configuration from {}", source);
+            apply(config);
+        }
+        Err(e) => {
+            warn!("Ignoring configuration from {}: {}", source, e);
+            #[cfg(feature = "sentry")]
+            crate::sentry_report::config_error(source, &e);
+        }
+    }
+}
+
+// GET /v1/kv/<key>?raw を実行し、(X-Consul-Index, 値) を返す（キーがなければNone）
+async fn consul_get(
+    client: &reqwest::Client,
+    endpoint: &str,
+    key: &str,
+    token: Option<&str>,
+    index: Option<u64>,
+) -> Result<Option<(u64, String)>, String> {
+    let mut request = client
+        .get(format!("{}/v1/kv/{}", endpoint, key))
+        .query(&[("raw", "")]);
+    if let Some(index) = index {
+        request = request.query(&[("index", index.to_string().as_str()), ("wait", CONSUL_WAIT)]);
+    }
+    if let Some(token) = token {
+        request = request.header("X-Consul-Token", token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query Consul: {}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = response
+        .error_for_status()
+        .map_err(|e| format!("Failed to query Consul: {}", e))?;
+    let index = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0);
+    let contents = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read Consul response: {}", e))?;
+    Ok(Some((index, contents)))
+}
+
+async fn watch_consul(
+    client: &reqwest::Client,
+    endpoint: &str,
+    key: &str,
+    token: Option<&str>,
+    apply: &impl Fn(ConfigFile),
+) {
+    let source = format!("consul {}/{}", endpoint, key);
+    let mut index = 0;
+    loop {
+        match consul_get(client, endpoint, key, token, Some(index)).await {
+            Ok(Some((new_index, contents))) => {
+                // インデックスが戻った場合（Consulのスナップショット復元など）は最初から監視し直す
+                if new_index < index {
+                    index = 0;
+                    continue;
+                }
+                if new_index != index {
+                    apply_contents(&source, &contents, apply);
+                    index = new_index;
+                }
+            }
+            Ok(None) => {
+                debug!(
+                    "{} does not exist, keeping the current configuration",
+                    source
+                );
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+            Err(e) => {
+                warn!("{}", e);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+// POST /v3/kv/range を実行し、(リビジョン, 値) を返す
+async fn etcd_range(
+    client: &reqwest::Client,
+    endpoint: &str,
+    key: &str,
+    token: Option<&str>,
+) -> Result<Option<(u64, Option<String>)>, String> {
+    let mut request = client
+        .post(format!("{}/v3/kv/range", endpoint))
+        .json(&json!({ "key": BASE64.encode(key) }));
+    if let Some(token) = token {
+        request = request.header("Authorization", token);
+    }
+
+    let response: Value = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to query etcd: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse etcd response: {}", e))?;
+
+    // etcdのJSON APIは64ビット整数を文字列で返す
+    let revision = match response
+        .pointer("/header/revision")
+        .and_then(Value::as_str)
+        .and_then(|revision| revision.parse::<u64>().ok())
+    {
+        Some(revision) => revision,
+        None => return Ok(None),
+    };
+    let contents = response
+        .pointer("/kvs/0/value")
+        .and_then(Value::as_str)
+        .map(decode_value)
+        .transpose()?;
+    Ok(Some((revision, contents)))
+}
+
+async fn watch_etcd(
+    client: &reqwest::Client,
+    endpoint: &str,
+    key: &str,
+    token: Option<&str>,
+    apply: &impl Fn(ConfigFile),
+) {
+    let source = format!("etcd {}/{}", endpoint, key);
+    loop {
+        // 現在の値を適用してから、その次のリビジョンからの変更を監視する
+        let revision = match etcd_range(client, endpoint, key, token).await {
+            Ok(Some((revision, contents))) => {
+                match contents {
+                    Some(contents) => apply_contents(&source, &contents, apply),
+                    None => debug!(
+                        "{} does not exist, keeping the current configuration",
+                        source
+                    ),
+                }
+                revision
+            }
+            Ok(None) => 0,
+            Err(e) => {
+                warn!("{}", e);
+                tokio::time::sleep(RETRY_INTERVAL).await;
+                continue;
+            }
+        };
+
+        if let Err(e) = etcd_watch(client, endpoint, key, token, revision + 1, &source, apply).await
+        {
+            warn!("{}", e);
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+// POST /v3/watch のストリームを読み、PUTイベントの値を適用する（ストリームが終わると戻る）
+async fn etcd_watch(
+    client: &reqwest::Client,
+    endpoint: &str,
+    key: &str,
+    token: Option<&str>,
+    start_revision: u64,
+    source: &str,
+    apply: &impl Fn(ConfigFile),
+) -> Result<(), String> {
+    let body = json!({
+        "create_request": {
+            "key": BASE64.encode(key),
+            "start_revision": start_revision.to_string(),
+        }
+    });
+    let mut request = client.post(format!("{}/v3/watch", endpoint)).json(&body);
+    if let Some(token) = token {
+        request = request.header("Authorization", token);
+    }
+
+    let mut response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to watch etcd: {}", e))?;
+
+    // ストリームは1行に1つのJSONオブジェクトを返す
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("etcd watch stream failed: {}", e))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let message: Value = match serde_json::from_slice(&line) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            let events = message
+                .pointer("/result/events")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            // 同じ応答に複数の変更が含まれる場合は最後の値だけを適用する
+            let latest = events
+                .iter()
+                .filter(|event| event.get("type").and_then(Value::as_str) != Some("DELETE"))
+                .filter_map(|event| event.pointer("/kv/value").and_then(Value::as_str))
+                .last();
+            if let Some(value) = latest {
+                match decode_value(value) {
+                    Ok(contents) => apply_contents(source, &contents, apply),
+                    Err(e) => warn!("{}", e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn decode_value(value: &str) -> Result<String, String> {
+    BASE64
+        .decode(value)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| "Invalid value in etcd response".to_string())
+}
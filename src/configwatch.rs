@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::config::ConfigFile;
+
+/// ConfigMapのボリュームで、現在のデータディレクトリを指すシンボリックリンク
+const CONFIGMAP_DATA_LINK: &str = "..data";
+
+/// 設定ファイルの監視（"ratelimit_redis_config ... watch=<秒>"）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigWatchSettings {
+    /// 設定ファイルのパス（シンボリックリンクのまま保持する）
+    pub path: PathBuf,
+    /// 変更を確認する間隔
+    pub interval: Duration,
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<ConfigWatchSettings>> = Mutex::new(None);
+}
+
+/// 設定を保持する（監視はワーカーで行う）
+pub fn configure(settings: ConfigWatchSettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+/// 現在の設定
+pub fn settings() -> Option<ConfigWatchSettings> {
+    SETTINGS.lock().ok().and_then(|slot| slot.clone())
+}
+
+// 設定ファイルの変更を検出するための状態
+//
+// KubernetesのConfigMapは "..data" のシンボリックリンクを新しいディレクトリに張り替えて更新するため、
+// 設定ファイル（"..data/<名前>" へのリンク）自体の mtime は変わらない。
+// リンクを解決した実体のパスと親ディレクトリの "..data" の指す先も比較する
+#[derive(Debug, Clone, PartialEq)]
+struct Fingerprint {
+    target: Option<PathBuf>,
+    data_link: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let data_link = path
+            .parent()
+            .and_then(|dir| fs::read_link(dir.join(CONFIGMAP_DATA_LINK)).ok());
+        Some(Self {
+            target: fs::canonicalize(path).ok(),
+            data_link,
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// 設定ファイルを interval ごとに確認し、変更された有効な設定で apply を呼ぶ（戻らない）
+///
+/// 読み込みや検証に失敗した設定は適用せず、現在の設定を維持する
+pub async fn watch(settings: ConfigWatchSettings, apply: impl Fn(ConfigFile)) {
+    let mut last = Fingerprint::of(&settings.path);
+    loop {
+        tokio::time::sleep(settings.interval).await;
+
+        // 張り替えの途中でファイルが見つからない場合は次の確認まで待つ
+        let current = match Fingerprint::of(&settings.path) {
+            Some(current) => current,
+            None => continue,
+        };
+        if last.as_ref() == Some(&current) {
+            continue;
+        }
+        last = Some(current);
+
+        match load(&settings.path) {
+            Ok(config) => {
+                info!(
+                    "Reloading rate limit configuration from {:?}",
+                    settings.path
+                );
+                apply(config);
+            }
+            Err(e) => {
+                warn!(
+                    "Ignoring changed configuration in {:?}: {}",
+                    settings.path, e
+                );
+                #[cfg(feature = "sentry")]
+                crate::sentry_report::config_error(&settings.path.to_string_lossy(), &e);
+            }
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<ConfigFile, String> {
+    let config = ConfigFile::from_file(path)?;
+    config
+        .validate()
+        .map_err(|errors| format!("Invalid configuration: {}", errors.join("; ")))?;
+    Ok(config)
+}
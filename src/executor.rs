@@ -0,0 +1,313 @@
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::redis_client::{
+    bucket_capacity, gcra_interval_us, limit_req_rate, rate_per_second, sliding_log_id,
+    window_limit, CounterKey, RateLimitAlgorithm, RateLimitConfig, RateLimitDecision,
+    FIXED_WINDOW_PREFIX, GCRA_PREFIX, LEAKY_BUCKET_PREFIX, LIMIT_REQ_PREFIX, SLIDING_LOG_PREFIX,
+    SLIDING_WINDOW_PREFIX, TOKEN_BUCKET_PREFIX,
+};
+use crate::scripts::{self, ScriptAsset};
+
+/// スクリプトに渡す引数の値
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptArg {
+    Int(u64),
+    Float(f64),
+}
+
+impl std::fmt::Display for ScriptArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptArg::Int(value) => write!(f, "{}", value),
+            ScriptArg::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl redis::ToRedisArgs for ScriptArg {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + redis::RedisWrite,
+    {
+        match self {
+            ScriptArg::Int(value) => value.write_redis_args(out),
+            ScriptArg::Float(value) => value.write_redis_args(out),
+        }
+    }
+}
+
+/// スクリプトのキー（KEYS）と引数（ARGV）の書き込み先
+///
+/// Redisへの送信時はコマンドに直接書き込み、キーごとにStringを確保しない
+pub trait ScriptArgs {
+    fn key(&mut self, key: &str);
+    fn arg(&mut self, arg: ScriptArg);
+}
+
+impl ScriptArgs for redis::ScriptInvocation<'_> {
+    fn key(&mut self, key: &str) {
+        redis::ScriptInvocation::key(self, key);
+    }
+
+    fn arg(&mut self, arg: ScriptArg) {
+        redis::ScriptInvocation::arg(self, arg);
+    }
+}
+
+// EVALSHA <sha> <キーの数> の後に続けて書き込む
+impl ScriptArgs for redis::Pipeline {
+    fn key(&mut self, key: &str) {
+        redis::Pipeline::arg(self, key);
+    }
+
+    fn arg(&mut self, arg: ScriptArg) {
+        redis::Pipeline::arg(self, arg);
+    }
+}
+
+/// アルゴリズムのスクリプト1回分の呼び出し
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptCall {
+    pub script: ScriptAsset,
+    pub keys: Vec<String>,
+    pub args: Vec<ScriptArg>,
+}
+
+impl ScriptCall {
+    /// アルゴリズムの判定1回分の呼び出しを組み立てる
+    pub fn for_check(
+        config: &RateLimitConfig,
+        key: &str,
+        rate: u32,
+        burst: u32,
+        now: Duration,
+    ) -> Result<Self, String> {
+        let key = CounterKey::Client(key);
+        let mut call = Self {
+            script: script_for(config.algorithm)?,
+            keys: Vec::new(),
+            args: Vec::new(),
+        };
+        encode_check(config, key, rate, burst, now, &mut call)?;
+        Ok(call)
+    }
+}
+
+impl ScriptArgs for ScriptCall {
+    fn key(&mut self, key: &str) {
+        self.keys.push(key.to_string());
+    }
+
+    fn arg(&mut self, arg: ScriptArg) {
+        self.args.push(arg);
+    }
+}
+
+/// アルゴリズムのスクリプト
+pub fn script_for(algorithm: RateLimitAlgorithm) -> Result<ScriptAsset, String> {
+    match algorithm {
+        #[cfg(feature = "algo-fixed-window")]
+        RateLimitAlgorithm::FixedWindow => Ok(scripts::FIXED_WINDOW),
+        #[cfg(feature = "algo-sliding-window")]
+        RateLimitAlgorithm::SlidingWindow => Ok(scripts::SLIDING_WINDOW),
+        #[cfg(feature = "algo-token-bucket")]
+        RateLimitAlgorithm::TokenBucket => Ok(scripts::TOKEN_BUCKET),
+        #[cfg(feature = "algo-leaky-bucket")]
+        RateLimitAlgorithm::LeakyBucket => Ok(scripts::LEAKY_BUCKET),
+        #[cfg(feature = "algo-limit-req")]
+        RateLimitAlgorithm::LimitReq => Ok(scripts::LIMIT_REQ),
+        #[cfg(feature = "algo-gcra")]
+        RateLimitAlgorithm::Gcra => Ok(scripts::GCRA),
+        #[cfg(feature = "algo-sliding-log")]
+        RateLimitAlgorithm::SlidingLog => Ok(scripts::SLIDING_LOG),
+        #[allow(unreachable_patterns)]
+        algorithm => Err(format!(
+            "Rate limit algorithm {} is not available in this build",
+            algorithm
+        )),
+    }
+}
+
+/// アルゴリズムのスクリプトが使用するキーの数（EVALSHAの numkeys）
+pub fn key_count(algorithm: RateLimitAlgorithm) -> usize {
+    match algorithm {
+        RateLimitAlgorithm::SlidingWindow => 2,
+        _ => 1,
+    }
+}
+
+/// 判定1回分のキーと引数を書き込む
+///
+/// リミッターの単発の判定・パイプライン・ScriptExecutor のすべてがこの関数で引数を組み立てるため、
+/// スクリプトとの引数の対応はここだけで定義する。アルゴリズム・時間窓・rate_period は config から、
+/// rate・burst は引数から取る（ゾーンや実行時の上書きは config と異なる値で判定する）。
+/// rate=0 はスクリプトで扱えないため、呼び出し側でRedisに送らずに拒否すること
+pub fn encode_check(
+    config: &RateLimitConfig,
+    key: CounterKey<'_>,
+    rate: u32,
+    burst: u32,
+    now: Duration,
+    out: &mut impl ScriptArgs,
+) -> Result<(), String> {
+    let window = config.window_size as u64;
+    let secs = now.as_secs();
+
+    match config.algorithm {
+        #[cfg(feature = "algo-fixed-window")]
+        RateLimitAlgorithm::FixedWindow => {
+            key.with_redis_key(FIXED_WINDOW_PREFIX, Some(secs / window * window), |k| {
+                out.key(k)
+            });
+            out.arg(ScriptArg::Int(window_limit(rate, burst)));
+            out.arg(ScriptArg::Int(window));
+        }
+        #[cfg(feature = "algo-sliding-window")]
+        RateLimitAlgorithm::SlidingWindow => {
+            let current_window = secs / window * window;
+            for window_start in [current_window, current_window - window] {
+                key.with_redis_key(SLIDING_WINDOW_PREFIX, Some(window_start), |k| out.key(k));
+            }
+            out.arg(ScriptArg::Int(secs));
+            out.arg(ScriptArg::Int(window));
+            out.arg(ScriptArg::Int(rate as u64));
+            out.arg(ScriptArg::Int(burst as u64));
+        }
+        #[cfg(feature = "algo-token-bucket")]
+        RateLimitAlgorithm::TokenBucket => {
+            key.with_redis_key(TOKEN_BUCKET_PREFIX, None, |k| out.key(k));
+            out.arg(ScriptArg::Int(secs));
+            // トークン1つが補充される時間（秒）
+            out.arg(ScriptArg::Float(
+                1.0 / rate_per_second(rate, config.rate_period),
+            ));
+            out.arg(ScriptArg::Int(bucket_capacity(burst) as u64));
+            out.arg(ScriptArg::Int(window));
+        }
+        #[cfg(feature = "algo-leaky-bucket")]
+        RateLimitAlgorithm::LeakyBucket => {
+            key.with_redis_key(LEAKY_BUCKET_PREFIX, None, |k| out.key(k));
+            out.arg(ScriptArg::Float(
+                secs as f64 + now.subsec_micros() as f64 / 1_000_000.0,
+            ));
+            out.arg(ScriptArg::Float(rate_per_second(rate, config.rate_period)));
+            // バケットサイズ
+            out.arg(ScriptArg::Float(bucket_capacity(burst)));
+            out.arg(ScriptArg::Int(window));
+        }
+        #[cfg(feature = "algo-limit-req")]
+        RateLimitAlgorithm::LimitReq => {
+            key.with_redis_key(LIMIT_REQ_PREFIX, None, |k| out.key(k));
+            // limit_req と同じく、時刻はミリ秒、レートと超過量は1/1000単位の整数で渡す
+            out.arg(ScriptArg::Int(now.as_millis() as u64));
+            out.arg(ScriptArg::Int(limit_req_rate(rate, config.rate_period)));
+            out.arg(ScriptArg::Int(burst as u64 * 1000));
+        }
+        #[cfg(feature = "algo-gcra")]
+        RateLimitAlgorithm::Gcra => {
+            key.with_redis_key(GCRA_PREFIX, None, |k| out.key(k));
+            // 時刻と1リクエストあたりの間隔はマイクロ秒の整数で渡す
+            out.arg(ScriptArg::Int(now.as_micros() as u64));
+            out.arg(ScriptArg::Int(gcra_interval_us(rate, config.rate_period)));
+            out.arg(ScriptArg::Int(bucket_capacity(burst) as u64));
+        }
+        #[cfg(feature = "algo-sliding-log")]
+        RateLimitAlgorithm::SlidingLog => {
+            key.with_redis_key(SLIDING_LOG_PREFIX, None, |k| out.key(k));
+            // 時刻と時間窓はミリ秒で渡す
+            out.arg(ScriptArg::Int(now.as_millis() as u64));
+            out.arg(ScriptArg::Int(window * 1000));
+            out.arg(ScriptArg::Int(window_limit(rate, burst)));
+            out.arg(ScriptArg::Int(sliding_log_id()));
+        }
+        #[allow(unreachable_patterns)]
+        algorithm => {
+            return Err(format!(
+                "Rate limit algorithm {} is not available in this build",
+                algorithm
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// アルゴリズムのスクリプトを実行する層
+///
+/// Redisに接続せずにアルゴリズムを検証するため、組み込みのLuaインタプリタ（LuaExecutor）や
+/// 呼び出しを記録するモック（RecordingExecutor）に差し替えられる
+#[async_trait]
+pub trait ScriptExecutor: Send + Sync {
+    /// スクリプトを実行し、{許可, 残り, リセットまでの秒数, カウント} を返す
+    async fn eval(&self, call: &ScriptCall) -> Result<Vec<i64>, String>;
+}
+
+/// ScriptExecutor でアルゴリズムの判定を1回行う
+///
+/// now を指定できるため、ウィンドウの境界やバケットの回復を待たずに検証できる。
+/// アルゴリズム・時間窓・rate_period は config の値を使う（Redisの接続設定は参照しない）
+pub async fn check_with_executor(
+    executor: &dyn ScriptExecutor,
+    config: &RateLimitConfig,
+    key: &str,
+    rate: u32,
+    burst: u32,
+    now: Duration,
+) -> Result<RateLimitDecision, String> {
+    // RedisRateLimiter と同じく、rate=0 はスクリプトを実行せずに拒否する
+    if rate == 0 {
+        return Ok(RateLimitDecision::deny_all(config.window_size));
+    }
+    let call = ScriptCall::for_check(config, key, rate, burst, now)?;
+    let reply = executor.eval(&call).await?;
+    RateLimitDecision::from_reply(config.algorithm, &reply)
+}
+
+/// 呼び出しを記録し、登録した応答を順に返すモック
+///
+/// 応答が登録されていない場合は許可 {1, 0, 0, 0} を返す
+#[derive(Default)]
+pub struct RecordingExecutor {
+    calls: Mutex<Vec<ScriptCall>>,
+    replies: Mutex<VecDeque<Result<Vec<i64>, String>>>,
+}
+
+impl RecordingExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 次の呼び出しで返す応答を追加する
+    pub fn push_reply(&self, reply: Result<Vec<i64>, String>) {
+        self.replies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push_back(reply);
+    }
+
+    /// これまでの呼び出し
+    pub fn calls(&self) -> Vec<ScriptCall> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl ScriptExecutor for RecordingExecutor {
+    async fn eval(&self, call: &ScriptCall) -> Result<Vec<i64>, String> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(call.clone());
+        self.replies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front()
+            .unwrap_or_else(|| Ok(vec![1, 0, 0, 0]))
+    }
+}
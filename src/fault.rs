@@ -0,0 +1,166 @@
+use lazy_static::lazy_static;
+use log::warn;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Redisの障害を模擬する設定（"ratelimit_redis_fault" ディレクティブ）
+///
+/// 割合はいずれもレート制限のコマンド1回に対するパーセント（0〜100）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultSettings {
+    /// 送信せずに接続エラーとして失敗させる割合
+    pub drop: u32,
+    /// 送信の前に追加する遅延
+    pub latency: Duration,
+    /// 遅延を追加する割合
+    pub latency_rate: u32,
+    /// NOSCRIPT エラーを返す割合
+    pub noscript: u32,
+    /// MOVED エラーを返す割合
+    pub moved: u32,
+}
+
+impl FaultSettings {
+    /// "[drop=N] [latency=MS] [latency_rate=N] [noscript=N] [moved=N]" 形式の引数を解析する
+    ///
+    /// latency_rate を省略した場合はすべてのコマンドに遅延を追加する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut latency_rate = None;
+
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("drop=") {
+                settings.drop = parse_percent("drop", value)?;
+            } else if let Some(value) = arg.strip_prefix("latency=") {
+                settings.latency = match value.parse::<u64>() {
+                    Ok(ms) => Duration::from_millis(ms),
+                    Err(_) => return Err(format!("Invalid latency value: {}", value)),
+                };
+            } else if let Some(value) = arg.strip_prefix("latency_rate=") {
+                latency_rate = Some(parse_percent("latency_rate", value)?);
+            } else if let Some(value) = arg.strip_prefix("noscript=") {
+                settings.noscript = parse_percent("noscript", value)?;
+            } else if let Some(value) = arg.strip_prefix("moved=") {
+                settings.moved = parse_percent("moved", value)?;
+            } else {
+                return Err(format!("Unknown ratelimit_redis_fault parameter: {}", arg));
+            }
+        }
+        settings.latency_rate = latency_rate.unwrap_or(100);
+
+        if !settings.is_active() {
+            return Err(
+                "Syntax: ratelimit_redis_fault [drop=N] [latency=MS] [latency_rate=N] [noscript=N] [moved=N]"
+                    .to_string(),
+            );
+        }
+        Ok(settings)
+    }
+
+    /// いずれかの障害が発生しうるか
+    pub fn is_active(&self) -> bool {
+        self.drop > 0
+            || (!self.latency.is_zero() && self.latency_rate > 0)
+            || self.noscript > 0
+            || self.moved > 0
+    }
+}
+
+fn parse_percent(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(percent) if percent <= 100 => Ok(percent),
+        _ => Err(format!(
+            "Invalid {} value: {} (expected 0-100)",
+            name, value
+        )),
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<FaultSettings>> = Mutex::new(None);
+}
+
+/// 障害の注入を有効にする
+pub fn configure(settings: FaultSettings) {
+    warn!(
+        "Redis fault injection is enabled (drop={}% latency={}ms at {}% noscript={}% moved={}%); do not use this in production",
+        settings.drop,
+        settings.latency.as_millis(),
+        settings.latency_rate,
+        settings.noscript,
+        settings.moved
+    );
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+thread_local! {
+    // 障害を発生させるかを決める乱数の状態（xorshift64）
+    static RNG: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    // スレッドごとに異なる値にする（0 は xorshift の不動点のため避ける）
+    let local = 0u8;
+    (nanos ^ (&local as *const u8 as u64).rotate_left(32)) | 1
+}
+
+// percent % の確率で true を返す
+fn roll(percent: u32) -> bool {
+    if percent == 0 {
+        return false;
+    }
+    RNG.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x % 100 < percent as u64
+    })
+}
+
+/// 設定に従って遅延・エラーを加えてからRedisのコマンドを実行する
+///
+/// 遅延はコマンドの前に入るため、呼び出し側のコマンドタイムアウトの対象になる。
+/// 接続の切断・NOSCRIPT・MOVED はコマンドを送信せずに返すため、カウンタは更新されない
+pub async fn inject<T>(command: impl Future<Output = RedisResult<T>>) -> RedisResult<T> {
+    let settings = match SETTINGS.lock().ok().and_then(|slot| slot.clone()) {
+        Some(settings) => settings,
+        None => return command.await,
+    };
+
+    if !settings.latency.is_zero() && roll(settings.latency_rate) {
+        tokio::time::sleep(settings.latency).await;
+    }
+    if roll(settings.drop) {
+        return Err(RedisError::from((
+            ErrorKind::IoError,
+            "Injected fault",
+            "connection dropped".to_string(),
+        )));
+    }
+    if roll(settings.noscript) {
+        return Err(RedisError::from((
+            ErrorKind::NoScriptError,
+            "Injected fault",
+            "NOSCRIPT No matching script".to_string(),
+        )));
+    }
+    if roll(settings.moved) {
+        return Err(RedisError::from((
+            ErrorKind::Moved,
+            "Injected fault",
+            "MOVED 0 127.0.0.1:6379".to_string(),
+        )));
+    }
+    command.await
+}
@@ -0,0 +1,205 @@
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::redis_client::RedisRateLimiter;
+
+/// イントロスペクションのリクエストのタイムアウト
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// トークンイントロスペクション（RFC 7662）の設定（"ratelimit_redis_introspection" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntrospectionSettings {
+    /// イントロスペクションエンドポイントのURL
+    pub url: String,
+    /// エンドポイントのBasic認証に使用するクライアントID
+    pub client_id: Option<String>,
+    /// エンドポイントのBasic認証に使用するクライアントシークレット
+    pub client_secret: Option<String>,
+    /// プラン名を含む応答のクレーム
+    pub plan_claim: String,
+    /// 結果をRedisにキャッシュする秒数（トークンの exp が先に来る場合はそれまで）
+    pub cache: u64,
+}
+
+impl IntrospectionSettings {
+    /// "url=<URL> [client_id=.. client_secret=..] [plan_claim=plan] [cache=300]" 形式の引数を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut url = None;
+        let mut client_id = None;
+        let mut client_secret = None;
+        let mut plan_claim = "plan".to_string();
+        let mut cache = 300;
+
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("url=") {
+                url = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("client_id=") {
+                client_id = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("client_secret=") {
+                client_secret = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("plan_claim=") {
+                plan_claim = value.to_string();
+            } else if let Some(value) = arg.strip_prefix("cache=") {
+                cache = match value.parse::<u64>() {
+                    Ok(cache) if cache > 0 => cache,
+                    _ => return Err(format!("Invalid cache value: {}", value)),
+                };
+            } else {
+                return Err(format!(
+                    "Unknown ratelimit_redis_introspection parameter: {}",
+                    arg
+                ));
+            }
+        }
+
+        let url = match url {
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => url,
+            _ => {
+                return Err(
+                    "Syntax: ratelimit_redis_introspection url=<http(s)://...> [client_id=<id> client_secret=<secret>] [plan_claim=<name>] [cache=<s>]"
+                        .to_string(),
+                )
+            }
+        };
+        if client_id.is_some() != client_secret.is_some() {
+            return Err("client_id and client_secret must be set together".to_string());
+        }
+
+        Ok(Self {
+            url,
+            client_id,
+            client_secret,
+            plan_claim,
+            cache,
+        })
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<IntrospectionSettings>> = Mutex::new(None);
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+}
+
+/// 設定を保持する
+pub fn configure(settings: IntrospectionSettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+/// トークンの持ち主
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Principal {
+    /// サブジェクト（sub クレーム）
+    pub subject: String,
+    /// プラン名（plan_claim のクレーム）
+    pub plan: Option<String>,
+}
+
+/// トークンをサブジェクトとプランに解決する（無効なトークンはNone）
+///
+/// 結果はトークンのSHA-256をキーとしてRedisにキャッシュし、トークンそのものは保存しない。
+/// 無効なトークンも問い合わせを繰り返さないよう null としてキャッシュする
+pub async fn resolve(limiter: &RedisRateLimiter, token: &str) -> Result<Option<Principal>, String> {
+    let settings = SETTINGS
+        .lock()
+        .ok()
+        .and_then(|slot| slot.clone())
+        .ok_or_else(|| "ratelimit_redis_introspection is not configured".to_string())?;
+
+    let token_hash = token_hash(token);
+    match limiter.get_introspection(&token_hash).await {
+        Ok(Some(cached)) => match serde_json::from_str::<Option<Principal>>(&cached) {
+            Ok(principal) => return Ok(principal),
+            Err(e) => warn!("Ignoring invalid introspection cache entry: {}", e),
+        },
+        Ok(None) => {}
+        // キャッシュを読めない場合もエンドポイントには問い合わせる
+        Err(e) => warn!("{}", e),
+    }
+
+    let (principal, ttl) = introspect(&settings, token).await?;
+    match serde_json::to_string(&principal) {
+        Ok(value) => {
+            if let Err(e) = limiter.cache_introspection(&token_hash, &value, ttl).await {
+                warn!("{}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize introspection result: {}", e),
+    }
+    Ok(principal)
+}
+
+/// トークンを解決できない場合のキー（トークンそのものはRedisのキーに含めない）
+pub fn fallback_key(token: &str) -> String {
+    format!("token:{}", token_hash(token))
+}
+
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// エンドポイントに問い合わせ、結果とキャッシュする秒数を返す
+async fn introspect(
+    settings: &IntrospectionSettings,
+    token: &str,
+) -> Result<(Option<Principal>, u64), String> {
+    let mut request = CLIENT
+        .post(&settings.url)
+        .form(&[("token", token), ("token_type_hint", "access_token")]);
+    if let (Some(id), Some(secret)) = (&settings.client_id, &settings.client_secret) {
+        request = request.basic_auth(id, Some(secret));
+    }
+
+    let response: Value = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Token introspection failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse introspection response: {}", e))?;
+
+    let active = response
+        .get("active")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    // サブジェクトのないトークンは主体を特定できないため、無効なトークンと同様に扱う
+    let subject = match response.get("sub").and_then(Value::as_str) {
+        Some(subject) if active && !subject.is_empty() => subject,
+        _ => {
+            debug!("Introspection reported an inactive token");
+            return Ok((None, settings.cache));
+        }
+    };
+
+    // 失効したトークンのサブジェクトを使い続けないよう、キャッシュは exp までとする
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let ttl = match response.get("exp").and_then(Value::as_u64) {
+        Some(exp) => settings.cache.min(exp.saturating_sub(now)),
+        None => settings.cache,
+    };
+
+    let plan = response
+        .get(&settings.plan_claim)
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok((
+        Some(Principal {
+            subject: subject.to_string(),
+            plan,
+        }),
+        ttl,
+    ))
+}
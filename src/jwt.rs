@@ -0,0 +1,173 @@
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use serde_json::Value;
+use sha2::Sha256;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// JWTの署名検証の設定（"ratelimit_redis_jwt" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct JwtSettings {
+    /// HS256 の署名鍵
+    pub secret: Vec<u8>,
+    /// exp・nbf の判定で許容する時計のずれ（秒）
+    pub leeway: u64,
+}
+
+impl JwtSettings {
+    /// "secret=<鍵> または secret_file=<パス> [leeway=60]" 形式の引数を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut secret = None;
+        let mut leeway = 60;
+
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("secret=") {
+                secret = Some(value.as_bytes().to_vec());
+            } else if let Some(path) = arg.strip_prefix("secret_file=") {
+                // ファイル末尾の改行は鍵に含めない
+                let content = std::fs::read(path)
+                    .map_err(|e| format!("Failed to read JWT secret file {}: {}", path, e))?;
+                let end = content
+                    .iter()
+                    .rposition(|b| !b.is_ascii_whitespace())
+                    .map_or(0, |pos| pos + 1);
+                secret = Some(content[..end].to_vec());
+            } else if let Some(value) = arg.strip_prefix("leeway=") {
+                leeway = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid leeway value: {}", value))?;
+            } else {
+                return Err(format!("Unknown ratelimit_redis_jwt parameter: {}", arg));
+            }
+        }
+
+        match secret {
+            Some(secret) if !secret.is_empty() => Ok(Self { secret, leeway }),
+            _ => Err(
+                "Syntax: ratelimit_redis_jwt secret=<key> | secret_file=<path> [leeway=<s>]"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: RwLock<Option<JwtSettings>> = RwLock::new(None);
+}
+
+/// 設定を保持する
+pub fn configure(settings: JwtSettings) {
+    if let Ok(mut slot) = SETTINGS.write() {
+        *slot = Some(settings);
+    }
+}
+
+/// JWTからクレームの値を取り出す（文字列と数値のクレームに対応する）
+///
+/// ratelimit_redis_jwt で鍵を設定した場合は HS256 の署名と exp・nbf を検証し、検証できない
+/// トークンからはキーを取得しない。設定していない場合は署名を検証せずにペイロードを読む
+pub fn claim(token: &str, name: &str) -> Result<String, String> {
+    let mut segments = token.split('.');
+    let (header, payload, signature) = match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some(header), Some(payload), Some(signature), None) => (header, payload, signature),
+        _ => return Err("Malformed JWT".to_string()),
+    };
+    let claims = decode_json(payload)?;
+
+    let settings = SETTINGS.read().ok();
+    if let Some(settings) = settings.as_ref().and_then(|slot| slot.as_ref()) {
+        verify(settings, header, payload, signature, &claims)?;
+    }
+
+    match claims.get(name) {
+        Some(Value::String(value)) if !value.is_empty() => Ok(value.clone()),
+        Some(Value::Number(value)) => Ok(value.to_string()),
+        _ => Err(format!("JWT claim not found: {}", name)),
+    }
+}
+
+// 署名と有効期間を検証する
+fn verify(
+    settings: &JwtSettings,
+    header: &str,
+    payload: &str,
+    signature: &str,
+    claims: &Value,
+) -> Result<(), String> {
+    // alg=none や公開鍵を共通鍵として使わせる攻撃を防ぐため、HS256 以外は受け付けない
+    if decode_json(header)?.get("alg").and_then(Value::as_str) != Some("HS256") {
+        return Err("Unsupported JWT algorithm (expected HS256)".to_string());
+    }
+    let signature =
+        base64url_decode(signature).ok_or_else(|| "Malformed JWT signature".to_string())?;
+    // HMACは任意の長さの鍵を受け付けるため失敗しない
+    let mut mac =
+        HmacSha256::new_from_slice(&settings.secret).expect("HMAC accepts keys of any length");
+    mac.update(header.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    // 比較は verify_slice で定数時間に行う
+    mac.verify_slice(&signature)
+        .map_err(|_| "Invalid JWT signature".to_string())?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if let Some(exp) = claims.get("exp").and_then(Value::as_u64) {
+        if now > exp.saturating_add(settings.leeway) {
+            return Err("JWT has expired".to_string());
+        }
+    }
+    if let Some(nbf) = claims.get("nbf").and_then(Value::as_u64) {
+        if now.saturating_add(settings.leeway) < nbf {
+            return Err("JWT is not valid yet".to_string());
+        }
+    }
+    Ok(())
+}
+
+// base64url でエンコードされたJSONオブジェクトをデコードする
+fn decode_json(segment: &str) -> Result<Value, String> {
+    base64url_decode(segment)
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .filter(Value::is_object)
+        .ok_or_else(|| "Malformed JWT".to_string())
+}
+
+// base64url（RFC 7515、パディングは省略可）をデコードする
+fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    // 1文字だけ余る長さは6ビットしか表せないため不正
+    if s.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
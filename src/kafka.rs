@@ -0,0 +1,146 @@
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+use crate::observer::{self, DecisionEvent, DecisionObserver};
+
+/// 送信待ちのイベントを保持する最大数（超えた分は破棄する）
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// プロデューサーの送信キューが空くのを待つ時間
+const ENQUEUE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Kafkaへのイベント送信の設定（"ratelimit_redis_kafka" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaSettings {
+    /// ブートストラップサーバー（カンマ区切り）
+    pub brokers: String,
+    /// 送信先のトピック
+    pub topic: String,
+    /// 許可された判定を何件に1件送信するか（0は送信しない）。拒否とBANは常に送信する
+    pub sample: u64,
+}
+
+impl KafkaSettings {
+    /// "brokers=host:9092 topic=name [sample=N]" 形式の引数を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut brokers = None;
+        let mut topic = None;
+        let mut sample = 100;
+
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("brokers=") {
+                brokers = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("topic=") {
+                topic = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("sample=") {
+                sample = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid sample value: {}", value))?;
+            } else {
+                return Err(format!("Unknown ratelimit_redis_kafka parameter: {}", arg));
+            }
+        }
+
+        match (brokers, topic) {
+            (Some(brokers), Some(topic)) if !brokers.is_empty() && !topic.is_empty() => Ok(Self {
+                brokers,
+                topic,
+                sample,
+            }),
+            _ => Err(
+                "Syntax: ratelimit_redis_kafka brokers=<host:port,...> topic=<name> [sample=N]"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<KafkaSettings>> = Mutex::new(None);
+}
+
+/// 設定を保持する（プロデューサーはフォーク後のワーカーで作成する）
+pub fn configure(settings: KafkaSettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+}
+
+/// ワーカーのランタイム上でプロデューサーを起動し、判定イベントのオブザーバーとして登録する
+pub fn start(runtime: &Runtime) -> Result<(), String> {
+    let settings = match SETTINGS.lock().ok().and_then(|slot| slot.clone()) {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", &settings.brokers)
+        .set("message.timeout.ms", "5000")
+        .create()
+        .map_err(|e| format!("Failed to create Kafka producer: {}", e))?;
+
+    let (sender, mut receiver) = mpsc::channel::<DecisionEvent>(QUEUE_CAPACITY);
+    let topic = settings.topic.clone();
+    runtime.spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            let payload = match serde_json::to_vec(&event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    error!("Failed to serialize decision event: {}", e);
+                    continue;
+                }
+            };
+            let record = FutureRecord::to(&topic).key(&event.key).payload(&payload);
+            if let Err((e, _)) = producer.send(record, ENQUEUE_TIMEOUT).await {
+                warn!("Failed to publish decision event to {}: {}", topic, e);
+            }
+        }
+    });
+
+    info!(
+        "Publishing rate limit decisions to Kafka topic {} (1 in {} allowed)",
+        settings.topic, settings.sample
+    );
+    observer::register_observer(Arc::new(KafkaObserver {
+        sender,
+        sample: settings.sample,
+        allowed_seen: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+    }));
+    Ok(())
+}
+
+// 判定イベントをキューに積むオブザーバー（送信はランタイム上のタスクで行う）
+struct KafkaObserver {
+    sender: mpsc::Sender<DecisionEvent>,
+    sample: u64,
+    allowed_seen: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl DecisionObserver for KafkaObserver {
+    fn on_decision(&self, event: &DecisionEvent) {
+        // 許可された判定のみ間引く
+        if event.allowed && !event.banned {
+            if self.sample == 0 {
+                return;
+            }
+            if self.allowed_seen.fetch_add(1, Ordering::Relaxed) % self.sample != 0 {
+                return;
+            }
+        }
+
+        // リクエスト処理を待たせないよう、キューが一杯の場合は破棄する
+        if self.sender.try_send(event.clone()).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            debug!("Kafka event queue is full, {} events dropped", dropped);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use log::debug;
+use nginx_rs::bindings::*;
+use std::fmt::Write;
+
+use crate::config::{parse_key_expression, KeyPart};
+use crate::jwt;
+
+/// 設定読み込み時に解決したレート制限キーの取得方法
+///
+/// リクエストごとにキー指定の文字列を解析したり、ヘッダー名を確保したりしないよう、
+/// ヘッダー名は小文字化とハッシュ計算を済ませた状態で保持する
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum KeySource {
+    /// クライアントのIPアドレス（remote_addr）
+    #[default]
+    RemoteAddr,
+    /// リクエストヘッダーの値（http_<ヘッダー名>）
+    Header {
+        /// 小文字化したヘッダー名（アンダースコアはハイフンに変換済み）
+        name: String,
+        /// NGINXのヘッダーハッシュと同じ方式で計算したハッシュ
+        hash: usize,
+    },
+    /// Cookie の値（cookie_<Cookie名>）
+    ///
+    /// NGINXの $cookie_<Cookie名> 変数で取得するため、HTTP/2 で分割された Cookie ヘッダーも対象になる
+    Cookie {
+        /// 小文字化した変数名（cookie_<Cookie名>）
+        name: String,
+        /// 変数名のハッシュ
+        hash: usize,
+    },
+    /// Authorization ヘッダーのBearerトークン（oauth_subject）
+    ///
+    /// 取得したトークンは判定の前にイントロスペクションでサブジェクトに置き換える
+    BearerToken,
+    /// Authorization ヘッダーのBearerトークン（JWT）のクレーム（jwt_claim:<クレーム名>）
+    JwtClaim(String),
+    /// リクエストのURI（uri）
+    Uri,
+    /// 複数の要素を組み合わせたキー（remote_addr,http_x_api_key,uri など）
+    ///
+    /// 各要素の値を ":" で連結する。取得できない要素が1つでもあればキーを取得できない
+    Composite(Vec<KeySource>),
+    /// NGINX変数を含む式（$binary_remote_addr$uri など）
+    ///
+    /// リクエストごとに変数を評価して連結する。値が見つからない変数は空文字列として扱う
+    Expression(Vec<ExpressionPart>),
+    /// 固定のキー
+    Literal(String),
+}
+
+/// 式の要素（変数名はハッシュ計算を済ませた状態で保持する）
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionPart {
+    Literal(String),
+    Variable { name: String, hash: usize },
+}
+
+impl KeySource {
+    /// キー指定（remote_addr、oauth_subject、jwt_claim:*、http_*、cookie_*、uri、NGINX変数を含む式、
+    /// 固定文字列、またはそれらのカンマ区切り）を解決する
+    pub fn compile(spec: &str) -> Self {
+        if spec.contains(',') {
+            return KeySource::Composite(spec.split(',').map(Self::compile).collect());
+        }
+        // 構文は設定の読み込み時に検証済み
+        if let Ok(Some(parts)) = parse_key_expression(spec) {
+            return KeySource::Expression(
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        KeyPart::Literal(text) => ExpressionPart::Literal(text),
+                        KeyPart::Variable(name) => {
+                            let hash = header_hash(&name);
+                            ExpressionPart::Variable { name, hash }
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        if spec == "remote_addr" {
+            return KeySource::RemoteAddr;
+        }
+        if spec == "oauth_subject" {
+            return KeySource::BearerToken;
+        }
+        if let Some(claim) = spec.strip_prefix("jwt_claim:") {
+            return KeySource::JwtClaim(claim.to_string());
+        }
+        if spec == "uri" {
+            return KeySource::Uri;
+        }
+        if spec.starts_with("cookie_") {
+            let name = spec.to_ascii_lowercase();
+            let hash = header_hash(&name);
+            return KeySource::Cookie { name, hash };
+        }
+        match spec.strip_prefix("http_") {
+            Some(header) => {
+                // NGINXの $http_* 変数と同様に、アンダースコアはハイフンとして扱う
+                let name = header.to_ascii_lowercase().replace('_', "-");
+                let hash = header_hash(&name);
+                KeySource::Header { name, hash }
+            }
+            None => KeySource::Literal(spec.to_string()),
+        }
+    }
+
+    /// リクエストからキーを取得する
+    pub fn extract(&self, r: &Request) -> Result<String, String> {
+        match self {
+            KeySource::RemoteAddr => r
+                .connection()
+                .remote_addr()
+                .map(|addr| addr.to_string())
+                .ok_or_else(|| "Could not get remote address".to_string()),
+            KeySource::Header { name, hash } => r
+                .headers_in()
+                .find_hashed(*hash, name)
+                .map(|value| value.to_string())
+                .ok_or_else(|| format!("Header not found: {}", name)),
+            KeySource::Cookie { name, hash } => {
+                let mut key = String::new();
+                if let Some(value) = r.variable_hashed(*hash, name) {
+                    push_variable_value(&mut key, value.as_bytes());
+                }
+                // 空の値は見つからないものとして扱う
+                if key.is_empty() {
+                    return Err(format!("Cookie not found: {}", name));
+                }
+                Ok(key)
+            }
+            KeySource::BearerToken => bearer_token(r),
+            // 検証できないトークンで制限を免れないよう、クレームを取得できない場合はクライアントのアドレスで数える
+            KeySource::JwtClaim(claim) => {
+                match bearer_token(r).and_then(|token| jwt::claim(&token, claim)) {
+                    Ok(value) => Ok(value),
+                    Err(e) => {
+                        debug!("{}, limiting by client address", e);
+                        KeySource::RemoteAddr.extract(r)
+                    }
+                }
+            }
+            KeySource::Uri => Ok(r.uri().to_string()),
+            KeySource::Composite(components) => {
+                let values = components
+                    .iter()
+                    .map(|component| component.extract(r))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(values.join(":"))
+            }
+            KeySource::Expression(parts) => {
+                let mut key = String::new();
+                for part in parts {
+                    match part {
+                        ExpressionPart::Literal(text) => key.push_str(text),
+                        ExpressionPart::Variable { name, hash } => {
+                            if let Some(value) = r.variable_hashed(*hash, name) {
+                                push_variable_value(&mut key, value.as_bytes());
+                            }
+                        }
+                    }
+                }
+                if key.is_empty() {
+                    return Err("Key expression evaluated to an empty value".to_string());
+                }
+                Ok(key)
+            }
+            KeySource::Literal(key) => Ok(key.clone()),
+        }
+    }
+}
+
+// Authorization ヘッダーからBearerトークンを取り出す
+fn bearer_token(r: &Request) -> Result<String, String> {
+    r.headers_in()
+        .find_hashed(header_hash("authorization"), "authorization")
+        .and_then(|value| {
+            let value = value.to_string();
+            let (scheme, token) = value.split_once(' ')?;
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+                Some(token.trim().to_string())
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| "Bearer token not found".to_string())
+}
+
+// 変数の値をキーに追加する
+//
+// $binary_remote_addr のようにUTF-8の文字列でない値（制御文字を含む値を含む）は16進数で表す
+fn push_variable_value(key: &mut String, value: &[u8]) {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(char::is_control) => key.push_str(text),
+        _ => {
+            for byte in value {
+                let _ = write!(key, "{:02x}", byte);
+            }
+        }
+    }
+}
+
+/// ngx_hash_key_lc と同じハッシュ（小文字化済みの名前に対して計算する）
+pub fn header_hash(name: &str) -> usize {
+    name.bytes().fold(0usize, |hash, c| {
+        hash.wrapping_mul(31).wrapping_add(c as usize)
+    })
+}
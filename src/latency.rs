@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 予測が予算を超えている間も、この間隔で1件はRedisに問い合わせて予測を更新する
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+// このワーカーでのRedisチェック所要時間の指数移動平均（マイクロ秒、0は未計測）
+static EWMA_US: AtomicU64 = AtomicU64::new(0);
+
+// 最後に予測の更新のためチェックを通した時刻（UNIXエポックからのミリ秒）
+static LAST_PROBE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Redisチェック1回の所要時間を記録する
+///
+/// 直近の値に1/8の重みを置く（TCPのRTT推定と同じ係数）
+pub(crate) fn record(elapsed: Duration) {
+    let sample = (elapsed.as_micros() as u64).max(1);
+    let _ = EWMA_US.fetch_update(Ordering::AcqRel, Ordering::Acquire, |ewma| {
+        Some(if ewma == 0 {
+            sample
+        } else {
+            ewma - ewma / 8 + sample / 8
+        })
+    });
+}
+
+/// 予測所要時間が予算を超えているため、Redisへの問い合わせを省略すべきかを返す
+pub(crate) fn should_skip(budget: Duration) -> bool {
+    let ewma = EWMA_US.load(Ordering::Acquire);
+    if ewma == 0 || ewma <= budget.as_micros() as u64 {
+        return false;
+    }
+
+    // 予測を回復させるため、一定間隔で1件だけ問い合わせを通す
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let last = LAST_PROBE_MS.load(Ordering::Acquire);
+    if now.saturating_sub(last) >= PROBE_INTERVAL.as_millis() as u64
+        && LAST_PROBE_MS
+            .compare_exchange(last, now, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    {
+        return false;
+    }
+    true
+}
+
+/// 現在の予測所要時間
+pub(crate) fn estimate() -> Duration {
+    Duration::from_micros(EWMA_US.load(Ordering::Acquire))
+}
@@ -0,0 +1,57 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::redis_client::RateLimitDecision;
+
+/// バケットの数がこれを超えたら、満杯まで回復したバケットを削除する
+const PRUNE_THRESHOLD: usize = 100_000;
+
+// キーごとのトークンバケット（残りのトークン数と最後に更新した時刻（秒））
+//
+// ワーカーのプロセスごとに独立し、他のワーカーやRedisの状態とは共有しない
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<String, (f64, f64)>> = Mutex::new(HashMap::new());
+}
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// ワーカー内のトークンバケットでキーを判定する
+///
+/// バケットは満杯の状態から始まり、1秒あたり rate_per_second 個のトークンが capacity まで回復する
+pub(crate) fn check(key: &str, rate_per_second: f64, capacity: f64) -> RateLimitDecision {
+    let now = now_secs();
+    let mut buckets = BUCKETS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if buckets.len() >= PRUNE_THRESHOLD {
+        buckets
+            .retain(|_, (tokens, updated)| *tokens + (now - *updated) * rate_per_second < capacity);
+    }
+
+    let (tokens, updated) = buckets.get(key).copied().unwrap_or((capacity, now));
+    let tokens = (tokens + (now - updated).max(0.0) * rate_per_second).min(capacity);
+    let allowed = tokens >= 1.0;
+    let tokens = if allowed { tokens - 1.0 } else { tokens };
+    buckets.insert(key.to_string(), (tokens, now));
+
+    let retry_after_ms = if allowed {
+        0
+    } else {
+        ((1.0 - tokens) / rate_per_second * 1000.0).ceil() as u64
+    };
+    RateLimitDecision {
+        allowed,
+        remaining: tokens.floor() as u64,
+        reset: ((capacity - tokens) / rate_per_second).ceil() as u64,
+        count: (capacity - tokens).ceil() as u64,
+        excess: None,
+        retry_after_ms: Some(retry_after_ms),
+    }
+}
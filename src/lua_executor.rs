@@ -0,0 +1,427 @@
+use async_trait::async_trait;
+use mlua::{Lua, Value, Variadic};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::Clock;
+use crate::executor::{ScriptCall, ScriptExecutor};
+
+// エミュレータが保持するRedisの値
+enum Data {
+    String(String),
+    Hash(HashMap<String, String>),
+    // ソート済みセット（スコア、メンバーの順に並べる）
+    SortedSet(Vec<(f64, String)>),
+}
+
+struct Entry {
+    data: Data,
+    // 有効期限（UNIXエポックからの秒数）
+    expires_at: Option<u64>,
+}
+
+// スクリプトから redis.call で操作するインメモリのキー空間
+#[derive(Default)]
+struct Store {
+    entries: HashMap<String, Entry>,
+}
+
+impl Store {
+    // 有効期限の切れたキーは存在しないものとして扱う
+    fn live(&mut self, key: &str, now: u64) -> Option<&mut Entry> {
+        if matches!(self.entries.get(key), Some(entry) if entry.expires_at.map_or(false, |at| at <= now))
+        {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key)
+    }
+}
+
+/// アルゴリズムのLuaスクリプトを組み込みのLua 5.1で実行するエミュレータ
+///
+/// スクリプトが使用するコマンド（INCR、EXPIRE、PEXPIRE、TTL、GET、EXISTS、HSET、HGET、
+/// ZADD、ZCARD、ZRANGE、ZREMRANGEBYSCORE）だけをメモリ上で実装する。キーの有効期限は渡された時計で判定するため、
+/// `check_with_executor` にも同じ時計の時刻を渡すこと
+pub struct LuaExecutor {
+    lua: Mutex<Lua>,
+    store: Arc<Mutex<Store>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LuaExecutor {
+    /// clock で有効期限を判定するエミュレータを作成する
+    pub fn new(clock: Arc<dyn Clock>) -> Result<Self, String> {
+        let store = Arc::new(Mutex::new(Store::default()));
+        let lua = Lua::new();
+
+        // スクリプトからは redis.call としてコマンドを呼び出す
+        let (commands, command_clock) = (store.clone(), clock.clone());
+        let register = || -> mlua::Result<()> {
+            let call = lua.create_function(move |lua, args: Variadic<Value>| {
+                let args = args
+                    .iter()
+                    .map(command_arg)
+                    .collect::<mlua::Result<Vec<String>>>()?;
+                let now = command_clock
+                    .now()
+                    .map_err(mlua::Error::RuntimeError)?
+                    .as_secs();
+                let mut store = commands.lock().unwrap_or_else(|e| e.into_inner());
+                run_command(lua, &mut store, now, &args)
+            })?;
+            let redis = lua.create_table()?;
+            redis.set("call", call)?;
+            lua.globals().set("redis", redis)
+        };
+        register().map_err(|e| format!("Failed to register redis.call: {}", e))?;
+
+        Ok(Self {
+            lua: Mutex::new(lua),
+            store,
+            clock,
+        })
+    }
+
+    /// キーの残り秒数（TTLコマンドと同じく、存在しない場合は-2、期限なしは-1）
+    pub fn ttl(&self, key: &str) -> Result<i64, String> {
+        let now = self.clock.now()?.as_secs();
+        let mut store = self.store.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(match store.live(key, now) {
+            Some(entry) => entry
+                .expires_at
+                .map_or(-1, |at| at.saturating_sub(now) as i64),
+            None => -2,
+        })
+    }
+}
+
+#[async_trait]
+impl ScriptExecutor for LuaExecutor {
+    async fn eval(&self, call: &ScriptCall) -> Result<Vec<i64>, String> {
+        let lua = self.lua.lock().unwrap_or_else(|e| e.into_inner());
+        let globals = lua.globals();
+        let keys = lua
+            .create_sequence_from(call.keys.iter().cloned())
+            .and_then(|keys| globals.set("KEYS", keys));
+        let args = lua
+            .create_sequence_from(call.args.iter().map(|arg| arg.to_string()))
+            .and_then(|args| globals.set("ARGV", args));
+        keys.and(args)
+            .map_err(|e| format!("Failed to set script arguments: {}", e))?;
+
+        let reply: Value = lua
+            .load(call.script.source)
+            .set_name(call.script.name)
+            .eval()
+            .map_err(|e| format!("Failed to execute {} script: {}", call.script.id(), e))?;
+
+        // Redisと同じく、数値は小数点以下を切り捨てて整数の応答にする
+        match reply {
+            Value::Table(table) => table
+                .sequence_values::<Value>()
+                .map(|value| match value {
+                    Ok(Value::Integer(n)) => Ok(n as i64),
+                    Ok(Value::Number(n)) => Ok(n as i64),
+                    Ok(Value::Boolean(b)) => Ok(b as i64),
+                    Ok(other) => Err(format!(
+                        "Unexpected value in {} script reply: {:?}",
+                        call.script.id(),
+                        other
+                    )),
+                    Err(e) => Err(format!("Failed to read script reply: {}", e)),
+                })
+                .collect(),
+            other => Err(format!(
+                "Unexpected {} script reply: {:?}",
+                call.script.id(),
+                other
+            )),
+        }
+    }
+}
+
+// redis.call の引数を文字列にする（Redisと同様に数値は文字列として渡る）
+fn command_arg(value: &Value) -> mlua::Result<String> {
+    match value {
+        Value::String(s) => Ok(s.to_str()?.to_string()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => Ok((*n as i64).to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(mlua::Error::RuntimeError(format!(
+            "Unsupported redis.call argument: {:?}",
+            other
+        ))),
+    }
+}
+
+fn arg<'a>(args: &'a [String], index: usize) -> mlua::Result<&'a str> {
+    args.get(index).map(String::as_str).ok_or_else(|| {
+        mlua::Error::RuntimeError(format!(
+            "ERR wrong number of arguments for '{}' command",
+            args.first().map_or("", String::as_str)
+        ))
+    })
+}
+
+// ZREMRANGEBYSCORE の範囲（"-inf"、"+inf"、"(" で始まる場合は境界を含まない）
+fn score_bound(value: &str) -> mlua::Result<(f64, bool)> {
+    let (value, exclusive) = match value.strip_prefix('(') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let score = match value {
+        "-inf" => f64::NEG_INFINITY,
+        "+inf" | "inf" => f64::INFINITY,
+        _ => value
+            .parse::<f64>()
+            .map_err(|_| mlua::Error::RuntimeError("ERR min or max is not a float".to_string()))?,
+    };
+    Ok((score, exclusive))
+}
+
+// ZRANGE WITHSCORES のスコア（Redisと同じく整数値は小数点なしで返す）
+fn format_score(score: f64) -> String {
+    if score.fract() == 0.0 && score.abs() < 1e17 {
+        (score as i64).to_string()
+    } else {
+        score.to_string()
+    }
+}
+
+fn sorted_set<'a>(
+    store: &'a mut Store,
+    key: &str,
+    now: u64,
+) -> mlua::Result<Option<&'a mut Vec<(f64, String)>>> {
+    match store.live(key, now) {
+        Some(Entry {
+            data: Data::SortedSet(set),
+            ..
+        }) => Ok(Some(set)),
+        Some(_) => Err(wrong_type()),
+        None => Ok(None),
+    }
+}
+
+fn wrong_type() -> mlua::Error {
+    mlua::Error::RuntimeError(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    )
+}
+
+// 1件のコマンドを実行する（nil は Redis と同じく false としてスクリプトに返す）
+fn run_command<'lua>(
+    lua: &'lua Lua,
+    store: &mut Store,
+    now: u64,
+    args: &[String],
+) -> mlua::Result<Value<'lua>> {
+    let command = arg(args, 0)?.to_ascii_uppercase();
+    let key = arg(args, 1)?;
+
+    match command.as_str() {
+        "INCR" => {
+            let current = match store.live(key, now) {
+                Some(Entry {
+                    data: Data::String(value),
+                    ..
+                }) => value.parse::<i64>().map_err(|_| {
+                    mlua::Error::RuntimeError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    )
+                })?,
+                Some(_) => return Err(wrong_type()),
+                None => 0,
+            };
+            let next = current + 1;
+            match store.live(key, now) {
+                Some(entry) => entry.data = Data::String(next.to_string()),
+                None => {
+                    store.entries.insert(
+                        key.to_string(),
+                        Entry {
+                            data: Data::String(next.to_string()),
+                            expires_at: None,
+                        },
+                    );
+                }
+            }
+            Ok(Value::Integer(next as _))
+        }
+        "EXPIRE" => {
+            let secs = arg(args, 2)?
+                .parse::<u64>()
+                .map_err(|_| mlua::Error::RuntimeError("ERR invalid expire time".to_string()))?;
+            match store.live(key, now) {
+                Some(entry) => {
+                    entry.expires_at = Some(now + secs);
+                    Ok(Value::Integer(1))
+                }
+                None => Ok(Value::Integer(0)),
+            }
+        }
+        "PEXPIRE" => {
+            let ms = arg(args, 2)?
+                .parse::<u64>()
+                .map_err(|_| mlua::Error::RuntimeError("ERR invalid expire time".to_string()))?;
+            // 有効期限は秒単位で管理しているため切り上げる
+            match store.live(key, now) {
+                Some(entry) => {
+                    entry.expires_at = Some(now + (ms + 999) / 1000);
+                    Ok(Value::Integer(1))
+                }
+                None => Ok(Value::Integer(0)),
+            }
+        }
+        "TTL" => Ok(Value::Integer(match store.live(key, now) {
+            Some(entry) => entry
+                .expires_at
+                .map_or(-1, |at| at.saturating_sub(now) as i64) as _,
+            None => -2,
+        })),
+        "EXISTS" => Ok(Value::Integer(store.live(key, now).is_some() as _)),
+        "GET" => match store.live(key, now) {
+            Some(Entry {
+                data: Data::String(value),
+                ..
+            }) => Ok(Value::String(lua.create_string(value.as_str())?)),
+            Some(_) => Err(wrong_type()),
+            None => Ok(Value::Boolean(false)),
+        },
+        "HSET" => {
+            if args.len() < 4 || args.len() % 2 != 0 {
+                return Err(mlua::Error::RuntimeError(
+                    "ERR wrong number of arguments for 'hset' command".to_string(),
+                ));
+            }
+            if store.live(key, now).is_none() {
+                store.entries.insert(
+                    key.to_string(),
+                    Entry {
+                        data: Data::Hash(HashMap::new()),
+                        expires_at: None,
+                    },
+                );
+            }
+            let hash = match store.live(key, now) {
+                Some(Entry {
+                    data: Data::Hash(hash),
+                    ..
+                }) => hash,
+                _ => return Err(wrong_type()),
+            };
+            let added = args[2..]
+                .chunks(2)
+                .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                .count();
+            Ok(Value::Integer(added as _))
+        }
+        "HGET" => {
+            let field = arg(args, 2)?;
+            match store.live(key, now) {
+                Some(Entry {
+                    data: Data::Hash(hash),
+                    ..
+                }) => match hash.get(field) {
+                    Some(value) => Ok(Value::String(lua.create_string(value.as_str())?)),
+                    None => Ok(Value::Boolean(false)),
+                },
+                Some(_) => Err(wrong_type()),
+                None => Ok(Value::Boolean(false)),
+            }
+        }
+        "ZADD" => {
+            let score = arg(args, 2)?.parse::<f64>().map_err(|_| {
+                mlua::Error::RuntimeError("ERR value is not a valid float".to_string())
+            })?;
+            let member = arg(args, 3)?.to_string();
+            if store.live(key, now).is_none() {
+                store.entries.insert(
+                    key.to_string(),
+                    Entry {
+                        data: Data::SortedSet(Vec::new()),
+                        expires_at: None,
+                    },
+                );
+            }
+            let set = sorted_set(store, key, now)?.ok_or_else(wrong_type)?;
+            let existing = set.iter().position(|(_, m)| *m == member);
+            if let Some(index) = existing {
+                set.remove(index);
+            }
+            let index = set.partition_point(|(s, m)| (*s, m.as_str()) < (score, member.as_str()));
+            set.insert(index, (score, member));
+            Ok(Value::Integer(existing.is_none() as _))
+        }
+        "ZCARD" => Ok(Value::Integer(
+            sorted_set(store, key, now)?.map_or(0, |set| set.len()) as _,
+        )),
+        "ZRANGE" => {
+            let index = |i: usize| {
+                arg(args, i)?.parse::<i64>().map_err(|_| {
+                    mlua::Error::RuntimeError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    )
+                })
+            };
+            let (start, stop) = (index(2)?, index(3)?);
+            let with_scores = args
+                .get(4)
+                .map_or(false, |a| a.eq_ignore_ascii_case("WITHSCORES"));
+            let set = sorted_set(store, key, now)?.map_or(&[][..], |set| &set[..]);
+            let len = set.len() as i64;
+            let start = if start < 0 {
+                (len + start).max(0)
+            } else {
+                start
+            };
+            let stop = if stop < 0 {
+                len + stop
+            } else {
+                stop.min(len - 1)
+            };
+            let mut reply = Vec::new();
+            if start <= stop {
+                for (score, member) in &set[start as usize..=stop as usize] {
+                    reply.push(member.clone());
+                    if with_scores {
+                        reply.push(format_score(*score));
+                    }
+                }
+            }
+            Ok(Value::Table(lua.create_sequence_from(reply)?))
+        }
+        "ZREMRANGEBYSCORE" => {
+            let (min, min_exclusive) = score_bound(arg(args, 2)?)?;
+            let (max, max_exclusive) = score_bound(arg(args, 3)?)?;
+            let in_range = |score: f64| {
+                (if min_exclusive {
+                    score > min
+                } else {
+                    score >= min
+                }) && (if max_exclusive {
+                    score < max
+                } else {
+                    score <= max
+                })
+            };
+            let removed = match sorted_set(store, key, now)? {
+                Some(set) => {
+                    let before = set.len();
+                    set.retain(|(score, _)| !in_range(*score));
+                    before - set.len()
+                }
+                None => 0,
+            };
+            // Redisと同じく、空になったソート済みセットは削除する
+            if matches!(sorted_set(store, key, now)?, Some(set) if set.is_empty()) {
+                store.entries.remove(key);
+            }
+            Ok(Value::Integer(removed as _))
+        }
+        _ => Err(mlua::Error::RuntimeError(format!(
+            "Command not supported by the Lua emulator: {}",
+            command
+        ))),
+    }
+}
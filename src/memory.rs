@@ -0,0 +1,629 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+#[cfg(feature = "algo-sliding-log")]
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::backend::RateLimitBackend;
+use crate::ban::BanPolicy;
+use crate::clock::{Clock, SystemClock};
+#[cfg(any(
+    feature = "algo-token-bucket",
+    feature = "algo-leaky-bucket",
+    feature = "algo-gcra"
+))]
+use crate::redis_client::bucket_capacity;
+#[cfg(any(feature = "algo-token-bucket", feature = "algo-leaky-bucket"))]
+use crate::redis_client::rate_per_second;
+#[cfg(any(
+    feature = "algo-fixed-window",
+    feature = "algo-sliding-window",
+    feature = "algo-sliding-log"
+))]
+use crate::redis_client::window_limit;
+#[cfg(feature = "algo-fixed-window")]
+use crate::redis_client::FIXED_WINDOW_PREFIX;
+#[cfg(feature = "algo-leaky-bucket")]
+use crate::redis_client::LEAKY_BUCKET_PREFIX;
+#[cfg(feature = "algo-sliding-window")]
+use crate::redis_client::SLIDING_WINDOW_PREFIX;
+#[cfg(feature = "algo-token-bucket")]
+use crate::redis_client::TOKEN_BUCKET_PREFIX;
+#[cfg(feature = "algo-gcra")]
+use crate::redis_client::{gcra_decision, gcra_interval_us, gcra_ttl_ms, GCRA_PREFIX};
+#[cfg(feature = "algo-limit-req")]
+use crate::redis_client::{limit_req_decision, limit_req_rate, limit_req_ttl_ms, LIMIT_REQ_PREFIX};
+use crate::redis_client::{
+    quota_key, CounterKey, LimitOverride, QuotaDecision, QuotaPeriod, RateLimitAlgorithm,
+    RateLimitConfig, RateLimitDecision,
+};
+#[cfg(feature = "algo-sliding-log")]
+use crate::redis_client::{sliding_log_decision, SLIDING_LOG_PREFIX};
+
+/// 期限切れのエントリを掃除するエントリ数の目安
+const PRUNE_THRESHOLD: usize = 100_000;
+
+// キー1つ分の状態（expiresはUNIXエポックからの秒数）
+#[derive(Debug, Clone)]
+enum Entry {
+    // 固定・スライディングウィンドウのカウンタ
+    Counter {
+        count: u64,
+        expires: f64,
+    },
+    // トークンバケットのトークン数、リーキーバケットの水位、limit_req の超過量、GCRA の TAT
+    Bucket {
+        value: f64,
+        last: f64,
+        expires: f64,
+    },
+    // スライディングウィンドウログで許可したリクエストの時刻（ミリ秒、古い順）
+    #[cfg(feature = "algo-sliding-log")]
+    Log {
+        times: VecDeque<u64>,
+        expires: f64,
+    },
+}
+
+impl Entry {
+    fn expires(&self) -> f64 {
+        match self {
+            Entry::Counter { expires, .. } | Entry::Bucket { expires, .. } => *expires,
+            #[cfg(feature = "algo-sliding-log")]
+            Entry::Log { expires, .. } => *expires,
+        }
+    }
+}
+
+/// プロセス内のメモリで状態を保持するバックエンド
+///
+/// 各アルゴリズムはLuaスクリプトと同じ計算を行う。状態はプロセス（NGINXではワーカー）ごとに
+/// 独立しており、BANや実行時の上書き設定には対応しない
+pub struct MemoryBackend {
+    config: RateLimitConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryBackend {
+    pub fn new(config: RateLimitConfig) -> Result<Self, String> {
+        if !config.algorithm.is_compiled_in() {
+            return Err(format!(
+                "Rate limit algorithm {} is not available in this build",
+                config.algorithm
+            ));
+        }
+        Ok(Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// 判定に使用する時計を差し替える（テストで時刻を制御する場合など）
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // 有効期限内のエントリを取得する
+    fn live(entries: &HashMap<String, Entry>, key: &str, now: f64) -> Option<Entry> {
+        entries
+            .get(key)
+            .cloned()
+            .filter(|entry| entry.expires() > now)
+    }
+
+    #[cfg(feature = "algo-fixed-window")]
+    fn fixed_window(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        limit: u64,
+        secs: u64,
+    ) -> RateLimitDecision {
+        let window_size = self.config.window_size as u64;
+        let now = secs as f64;
+        let counter_key = key.redis_key(
+            FIXED_WINDOW_PREFIX,
+            Some((secs / window_size) * window_size),
+        );
+
+        let (count, expires) = match Self::live(entries, &counter_key, now) {
+            Some(Entry::Counter { count, expires }) => (count + 1, expires),
+            _ => (1, now + window_size as f64),
+        };
+        entries.insert(counter_key, Entry::Counter { count, expires });
+
+        RateLimitDecision {
+            allowed: count <= limit,
+            remaining: limit.saturating_sub(count),
+            reset: (expires - now).max(0.0) as u64,
+            count,
+            excess: None,
+            retry_after_ms: None,
+        }
+    }
+
+    #[cfg(feature = "algo-sliding-window")]
+    fn sliding_window(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        secs: u64,
+    ) -> RateLimitDecision {
+        let window_size = self.config.window_size as u64;
+        let now = secs as f64;
+        let current_window = secs / window_size * window_size;
+        let previous_window = current_window - window_size;
+        let elapsed_ratio = (secs - current_window) as f64 / window_size as f64;
+
+        let current_key = key.redis_key(SLIDING_WINDOW_PREFIX, Some(current_window));
+        let current_count = match Self::live(entries, &current_key, now) {
+            Some(Entry::Counter { count, expires }) => {
+                entries.insert(
+                    current_key,
+                    Entry::Counter {
+                        count: count + 1,
+                        expires,
+                    },
+                );
+                count + 1
+            }
+            _ => {
+                entries.insert(
+                    current_key,
+                    Entry::Counter {
+                        count: 1,
+                        expires: now + (window_size * 2) as f64,
+                    },
+                );
+                1
+            }
+        };
+        let previous_count = match Self::live(
+            entries,
+            &key.redis_key(SLIDING_WINDOW_PREFIX, Some(previous_window)),
+            now,
+        ) {
+            Some(Entry::Counter { count, .. }) => count,
+            _ => 0,
+        };
+
+        let weighted_count = current_count as f64 + previous_count as f64 * (1.0 - elapsed_ratio);
+        let limit = window_limit(rate, burst) as f64;
+        RateLimitDecision {
+            allowed: weighted_count <= limit,
+            remaining: (limit - weighted_count).floor().max(0.0) as u64,
+            reset: current_window + window_size - secs,
+            count: weighted_count.floor() as u64,
+            excess: None,
+            retry_after_ms: None,
+        }
+    }
+
+    #[cfg(feature = "algo-token-bucket")]
+    fn token_bucket(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        secs: u64,
+    ) -> RateLimitDecision {
+        let now = secs as f64;
+        let refill_time = 1.0 / rate_per_second(rate, self.config.rate_period);
+        let burst = bucket_capacity(burst);
+        let bucket_key = key.redis_key(TOKEN_BUCKET_PREFIX, None);
+
+        let (tokens, last, expires) = match Self::live(entries, &bucket_key, now) {
+            Some(Entry::Bucket {
+                value,
+                last,
+                expires,
+            }) => (value, last, expires),
+            _ => {
+                // 新規キー: バケットを最大容量で初期化
+                entries.insert(
+                    bucket_key,
+                    Entry::Bucket {
+                        value: burst,
+                        last: now,
+                        expires: now + (self.config.window_size * 2) as f64,
+                    },
+                );
+                return RateLimitDecision {
+                    allowed: true,
+                    remaining: burst as u64,
+                    reset: 0,
+                    count: 0,
+                    excess: None,
+                    retry_after_ms: None,
+                };
+            }
+        };
+
+        let mut new_tokens = burst.min(tokens + (now - last) / refill_time);
+        let allowed = new_tokens >= 1.0;
+        if allowed {
+            new_tokens -= 1.0;
+        }
+        // トークンが不足している場合は補充時間だけ更新する
+        let value = if allowed { new_tokens } else { tokens };
+        entries.insert(
+            bucket_key,
+            Entry::Bucket {
+                value,
+                last: now,
+                expires,
+            },
+        );
+
+        RateLimitDecision {
+            allowed,
+            remaining: new_tokens.floor() as u64,
+            reset: ((burst - new_tokens) * refill_time).ceil() as u64,
+            count: (burst - new_tokens).ceil() as u64,
+            excess: None,
+            retry_after_ms: None,
+        }
+    }
+
+    #[cfg(feature = "algo-leaky-bucket")]
+    fn leaky_bucket(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        now: f64,
+    ) -> RateLimitDecision {
+        let rate = rate_per_second(rate, self.config.rate_period);
+        let bucket_size = bucket_capacity(burst);
+        let bucket_key = key.redis_key(LEAKY_BUCKET_PREFIX, None);
+
+        let (level, last, expires) = match Self::live(entries, &bucket_key, now) {
+            Some(Entry::Bucket {
+                value,
+                last,
+                expires,
+            }) => (value, last, expires),
+            _ => {
+                // 新規キー: レベルを1で初期化
+                entries.insert(
+                    bucket_key,
+                    Entry::Bucket {
+                        value: 1.0,
+                        last: now,
+                        expires: now + (self.config.window_size * 2) as f64,
+                    },
+                );
+                return RateLimitDecision {
+                    allowed: true,
+                    remaining: (bucket_size - 1.0).floor().max(0.0) as u64,
+                    reset: (1.0 / rate).ceil() as u64,
+                    count: 1,
+                    excess: None,
+                    retry_after_ms: None,
+                };
+            }
+        };
+
+        let new_level = (level - rate * (now - last)).max(0.0) + 1.0;
+        if new_level <= bucket_size {
+            entries.insert(
+                bucket_key,
+                Entry::Bucket {
+                    value: new_level,
+                    last: now,
+                    expires,
+                },
+            );
+            RateLimitDecision {
+                allowed: true,
+                remaining: (bucket_size - new_level).floor().max(0.0) as u64,
+                reset: (new_level / rate).ceil() as u64,
+                count: new_level.ceil() as u64,
+                excess: None,
+                retry_after_ms: None,
+            }
+        } else {
+            // オーバーフロー: リクエストを拒否（タイムスタンプだけ更新）
+            entries.insert(
+                bucket_key,
+                Entry::Bucket {
+                    value: level,
+                    last: now,
+                    expires,
+                },
+            );
+            RateLimitDecision {
+                allowed: false,
+                remaining: 0,
+                reset: (level / rate).ceil() as u64,
+                count: level.ceil() as u64,
+                excess: None,
+                retry_after_ms: None,
+            }
+        }
+    }
+
+    #[cfg(feature = "algo-limit-req")]
+    fn limit_req(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        now_ms: u64,
+    ) -> RateLimitDecision {
+        let rate = limit_req_rate(rate, self.config.rate_period);
+        let state_key = key.redis_key(LIMIT_REQ_PREFIX, None);
+        let now = now_ms as f64 / 1000.0;
+
+        let state = match Self::live(entries, &state_key, now) {
+            Some(Entry::Bucket { value, last, .. }) => Some((value as u64, last as u64)),
+            _ => None,
+        };
+        let (decision, state) = limit_req_decision(state, now_ms, rate, burst as u64 * 1000);
+        if let Some((excess, last)) = state {
+            entries.insert(
+                state_key,
+                Entry::Bucket {
+                    value: excess as f64,
+                    last: last as f64,
+                    expires: now + limit_req_ttl_ms(excess, rate) as f64 / 1000.0,
+                },
+            );
+        }
+        decision
+    }
+
+    #[cfg(feature = "algo-gcra")]
+    fn gcra(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        now_us: u64,
+    ) -> RateLimitDecision {
+        let state_key = key.redis_key(GCRA_PREFIX, None);
+        let now = now_us as f64 / 1_000_000.0;
+
+        let tat = match Self::live(entries, &state_key, now) {
+            Some(Entry::Bucket { value, .. }) => Some(value as u64),
+            _ => None,
+        };
+        let (decision, tat) = gcra_decision(
+            tat,
+            now_us,
+            gcra_interval_us(rate, self.config.rate_period),
+            bucket_capacity(burst) as u64,
+        );
+        if let Some(tat) = tat {
+            entries.insert(
+                state_key,
+                Entry::Bucket {
+                    value: tat as f64,
+                    last: now,
+                    expires: now + gcra_ttl_ms(tat, now_us) as f64 / 1000.0,
+                },
+            );
+        }
+        decision
+    }
+
+    #[cfg(feature = "algo-sliding-log")]
+    fn sliding_log(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: CounterKey<'_>,
+        limit: u64,
+        now_ms: u64,
+    ) -> RateLimitDecision {
+        let window_ms = self.config.window_size as u64 * 1000;
+        let log_key = key.redis_key(SLIDING_LOG_PREFIX, None);
+        let now = now_ms as f64 / 1000.0;
+
+        // 記録はエントリから取り出して更新する（リクエストごとに複製しない）
+        let mut times = match entries.remove(&log_key) {
+            Some(Entry::Log { times, expires }) if expires > now => times,
+            _ => VecDeque::new(),
+        };
+        // 窓から外れた記録を削除する
+        let cutoff = now_ms.saturating_sub(window_ms);
+        while times.front().is_some_and(|time| *time <= cutoff) {
+            times.pop_front();
+        }
+
+        let log = (
+            times.len() as u64,
+            times.front().copied(),
+            times.back().copied(),
+        );
+        let decision = sliding_log_decision(log, now_ms, window_ms, limit);
+        if decision.allowed {
+            times.push_back(now_ms);
+        }
+        if let Some(newest) = times.back() {
+            let expires = (newest + window_ms) as f64 / 1000.0;
+            entries.insert(log_key, Entry::Log { times, expires });
+        }
+        decision
+    }
+
+    // カウンタ1つのレート制限をチェックする
+    #[cfg_attr(
+        not(all(
+            feature = "algo-fixed-window",
+            feature = "algo-sliding-window",
+            feature = "algo-token-bucket",
+            feature = "algo-leaky-bucket",
+            feature = "algo-limit-req",
+            feature = "algo-gcra",
+            feature = "algo-sliding-log"
+        )),
+        allow(unused_variables)
+    )]
+    fn check_counter(
+        &self,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
+        let now = self.clock.now()?;
+        let secs = now.as_secs();
+
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| "In-memory rate limit state is poisoned".to_string())?;
+        if entries.len() >= PRUNE_THRESHOLD {
+            let now = now.as_secs_f64();
+            entries.retain(|_, entry| entry.expires() > now);
+        }
+
+        // rate=0 はどのリクエストも許可しない（状態は作らない）
+        if rate == 0 {
+            return Ok(RateLimitDecision::deny_all(self.config.window_size));
+        }
+
+        match self.config.algorithm {
+            #[cfg(feature = "algo-fixed-window")]
+            RateLimitAlgorithm::FixedWindow => {
+                Ok(self.fixed_window(&mut entries, key, window_limit(rate, burst), secs))
+            }
+            #[cfg(feature = "algo-sliding-window")]
+            RateLimitAlgorithm::SlidingWindow => {
+                Ok(self.sliding_window(&mut entries, key, rate, burst, secs))
+            }
+            #[cfg(feature = "algo-token-bucket")]
+            RateLimitAlgorithm::TokenBucket => {
+                Ok(self.token_bucket(&mut entries, key, rate, burst, secs))
+            }
+            #[cfg(feature = "algo-leaky-bucket")]
+            RateLimitAlgorithm::LeakyBucket => {
+                Ok(self.leaky_bucket(&mut entries, key, rate, burst, now.as_secs_f64()))
+            }
+            #[cfg(feature = "algo-limit-req")]
+            RateLimitAlgorithm::LimitReq => {
+                Ok(self.limit_req(&mut entries, key, rate, burst, now.as_millis() as u64))
+            }
+            #[cfg(feature = "algo-gcra")]
+            RateLimitAlgorithm::Gcra => {
+                Ok(self.gcra(&mut entries, key, rate, burst, now.as_micros() as u64))
+            }
+            #[cfg(feature = "algo-sliding-log")]
+            RateLimitAlgorithm::SlidingLog => Ok(self.sliding_log(
+                &mut entries,
+                key,
+                window_limit(rate, burst),
+                now.as_millis() as u64,
+            )),
+            #[allow(unreachable_patterns)]
+            algorithm => Err(format!(
+                "Rate limit algorithm {} is not available in this build",
+                algorithm
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn default_limits(&self) -> LimitOverride {
+        LimitOverride {
+            rate: self.config.requests_per_second,
+            burst: self.config.burst,
+        }
+    }
+
+    async fn check_rate_limit_with(
+        &self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
+        self.check_counter(CounterKey::Client(key), rate, burst)
+    }
+
+    // プロセス内の状態のため往復はなく、1件ずつ順に数える
+    async fn check_rate_limits(
+        &self,
+        checks: &[(CounterKey<'_>, u32, u32)],
+    ) -> Result<Vec<RateLimitDecision>, String> {
+        checks
+            .iter()
+            .map(|(key, rate, burst)| self.check_counter(*key, *rate, *burst))
+            .collect()
+    }
+
+    async fn is_banned(&self, _key: &str) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    async fn is_penalized(&self, _key: &str) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    async fn banned_cidrs(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn get_limit_override(&self, _location: &str) -> Result<Option<LimitOverride>, String> {
+        Ok(None)
+    }
+
+    // BANは保持できないため、拒否を数えない
+    async fn record_rejection(
+        &self,
+        _key: &str,
+        _policy: &BanPolicy,
+    ) -> Result<Option<(u64, u64)>, String> {
+        Ok(None)
+    }
+
+    async fn get_key_limits(&self, _key: &str) -> Result<Option<LimitOverride>, String> {
+        Ok(None)
+    }
+
+    async fn consume_quota(
+        &self,
+        key: &str,
+        quota: u64,
+        period: QuotaPeriod,
+    ) -> Result<QuotaDecision, String> {
+        let secs = self.clock.now()?.as_secs();
+        let (start, end) = period.window(secs);
+        let counter_key = quota_key(key, start);
+
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| "In-memory rate limit state is poisoned".to_string())?;
+        let count = match Self::live(&entries, &counter_key, secs as f64) {
+            Some(Entry::Counter { count, .. }) => count + 1,
+            _ => 1,
+        };
+        entries.insert(
+            counter_key,
+            Entry::Counter {
+                count,
+                expires: end as f64,
+            },
+        );
+
+        Ok(QuotaDecision {
+            allowed: count <= quota,
+            remaining: quota.saturating_sub(count),
+            reset: end - secs,
+        })
+    }
+}
@@ -0,0 +1,3557 @@
+// NGINXモジュール本体（ディレクティブ、設定のスナップショット、リクエストハンドラー）
+//
+// "nginx" フィーチャーが有効な場合のみビルドされる
+
+use arc_swap::ArcSwapOption;
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use nginx_rs::bindings::*;
+use nginx_rs::ffi::*;
+use nginx_rs::http;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::net::IpAddr;
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+use crate::acl::{Cidr, CidrSet};
+#[cfg(feature = "admin")]
+use crate::admin;
+use crate::backend::RateLimitBackend;
+use crate::ban::{self, BanPolicy};
+use crate::config::{
+    load_reject_body, validate_http_method, validate_key, validate_limits, validate_tier_header,
+    AbuseAction, Backend, ConfigFile, FailureMode, GrpcMethodSettings, HeaderFormat,
+    MethodLimitSettings, Mode, Offload, OnLimit, PlanSettings, RateLimitSettings, RejectStatus,
+    RouteSettings, TierSettings, ZoneSettings,
+};
+#[cfg(feature = "config-source")]
+use crate::configsource;
+#[cfg(feature = "fault-injection")]
+use crate::fault;
+#[cfg(feature = "introspection")]
+use crate::introspection;
+use crate::jwt;
+#[cfg(feature = "kafka")]
+use crate::kafka;
+use crate::keys::{self, KeySource};
+use crate::memory::MemoryBackend;
+#[cfg(feature = "metrics")]
+use crate::observer::{self, DecisionEvent};
+use crate::redis_client::{
+    bucket_capacity, limit_req_rate, rate_per_second, window_limit, CounterKey, LimitOverride,
+    QuotaDecision, QuotaPeriod, RateLimitAlgorithm, RateLimitConfig, RateLimitDecision,
+    RedisCompat, RedisConnectionOptions, RedisRateLimiter,
+};
+#[cfg(feature = "sentry")]
+use crate::sentry_report;
+use crate::{
+    acl, banlist, banstore, challenge, configwatch, latency, local_fallback, overlimit, overrides,
+    prefetch,
+};
+
+// モジュールの設定構造体
+#[derive(Debug, Clone)]
+struct RateLimitRedisConfig {
+    redis_url: String,
+    rate_limit_key: String, // IPアドレスやAPIキーなどのレート制限キーを特定するための設定
+    requests_per_second: u32,
+    burst: u32,
+    enabled: bool,
+    algorithm: RateLimitAlgorithm,
+    window_size: u32,
+    rate_period: u32,
+    prefetch_ms: u64,
+    overlimit_cache_ms: u64,
+    overlimit_cache_until_reset: bool, // 上限超過のキーを制限が戻るまで拒否し続けるか
+    max_in_flight: u32,
+    latency_budget_ms: u64,
+    failure_mode: FailureMode,
+    zones: Vec<ZoneSettings>,
+    grpc_methods: Vec<GrpcMethodSettings>,
+    methods: Vec<String>, // 空でなければ、一致したメソッドのリクエストだけを制限する
+    method_limits: Vec<MethodLimitSettings>,
+    routes: Vec<RouteSettings>,
+    plans: Vec<PlanSettings>,
+    key_limits: bool,    // Redisに保存されたキーごとのリミットを使うか
+    tier_header: String, // 設定ファイルの tiers からティアを選択するヘッダー
+    global_rate: u32,    // Location全体で共有するレート（0は無制限）
+    global_burst: u32,
+    quota: u64, // 暦の期間ごとに許可するリクエスト数（0はクォータなし）
+    quota_period: QuotaPeriod,
+    whitelist: CidrSet, // 制限しないクライアントのアドレス範囲
+    blacklist: CidrSet, // Redisに問い合わせずに拒否するクライアントのアドレス範囲
+    offload: Offload,
+    backend: Backend,
+    mode: Mode,
+    accounting: bool,
+    abuse_ratio: u32,
+    abuse_min_requests: u32,
+    abuse_action: AbuseAction,
+    abuse_duration: u64,
+    ban_policy: BanPolicy,
+    ban_status: Option<RejectStatus>, // BANされたキーに返すステータス（Noneは reject_status と同じ）
+    nodelay: bool,
+    delay: u32,
+    throttle: bool,
+    reject_status: RejectStatus,
+    header_format: HeaderFormat,
+    reject_body_file: String,
+    reject_body: Option<Arc<Vec<u8>>>, // reject_body_file を設定時に読み込んだもの
+    reject_content_type: String,
+    on_limit: OnLimit,
+    challenge_url: String,
+    challenge_secret: String,
+    key_source: KeySource,          // rate_limit_key を設定時に解決したもの
+    zone_sources: Vec<KeySource>,   // zones の各キーを設定時に解決したもの
+    tier_source: Option<KeySource>, // tier_header を設定時に解決したもの
+    config_file_path: Option<String>,
+    redis_options: RedisConnectionOptions,
+}
+
+impl Default for RateLimitRedisConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            rate_limit_key: "remote_addr".to_string(),
+            requests_per_second: 10,
+            burst: 5,
+            enabled: false,
+            algorithm: RateLimitAlgorithm::SlidingWindow,
+            window_size: 60,
+            rate_period: 1,
+            prefetch_ms: 0,
+            overlimit_cache_ms: 0,
+            overlimit_cache_until_reset: false,
+            max_in_flight: 0,
+            latency_budget_ms: 0,
+            failure_mode: FailureMode::Open,
+            zones: Vec::new(),
+            grpc_methods: Vec::new(),
+            methods: Vec::new(),
+            method_limits: Vec::new(),
+            routes: Vec::new(),
+            plans: Vec::new(),
+            key_limits: false,
+            tier_header: String::new(),
+            global_rate: 0,
+            global_burst: 0,
+            quota: 0,
+            quota_period: QuotaPeriod::Day,
+            whitelist: CidrSet::default(),
+            blacklist: CidrSet::default(),
+            offload: Offload::Async,
+            backend: Backend::Redis,
+            mode: Mode::Filter,
+            accounting: false,
+            abuse_ratio: 0,
+            abuse_min_requests: 20,
+            abuse_action: AbuseAction::Ban,
+            abuse_duration: 600,
+            ban_policy: BanPolicy::default(),
+            ban_status: None,
+            nodelay: false,
+            delay: 0,
+            throttle: false,
+            reject_status: RejectStatus::Forbidden,
+            header_format: HeaderFormat::Legacy,
+            reject_body_file: String::new(),
+            reject_body: None,
+            reject_content_type: "application/json".to_string(),
+            on_limit: OnLimit::Reject,
+            challenge_url: String::new(),
+            challenge_secret: String::new(),
+            key_source: KeySource::RemoteAddr,
+            zone_sources: Vec::new(),
+            tier_source: None,
+            config_file_path: None,
+            redis_options: RedisConnectionOptions::default(),
+        }
+    }
+}
+
+impl RateLimitRedisConfig {
+    // キー指定を解決する（リクエストごとに文字列を解析しないよう、スナップショットの作成時に行う）
+    fn compile_keys(mut self) -> Self {
+        self.key_source = KeySource::compile(&self.rate_limit_key);
+        self.zone_sources = self
+            .zones
+            .iter()
+            .map(|zone| KeySource::compile(&zone.key))
+            .collect();
+        self.tier_source = Some(&self.tier_header)
+            .filter(|header| !header.is_empty())
+            .map(|header| KeySource::compile(&format!("http_{}", header)));
+        self
+    }
+
+    // methods= で制限するメソッドを絞り込んでいる場合に、リクエストのメソッドが含まれるか
+    fn applies_to_method(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m == method)
+    }
+
+    // 主キー以外のカウンタ（追加のゾーン・gRPCメソッド・HTTPメソッド・ルート・Location全体・クォータ・拒否数）を使用するか、
+    // 主キーを判定時に解決する（key=oauth_subject）か
+    fn has_extra_checks(&self) -> bool {
+        !self.zones.is_empty()
+            || !self.grpc_methods.is_empty()
+            || !self.method_limits.is_empty()
+            || !self.routes.is_empty()
+            || self.key_source == KeySource::BearerToken
+            || self.tier_source.is_some()
+            || self.global_rate > 0
+            || self.quota > 0
+            || self.ban_policy.enabled()
+    }
+}
+
+/// 設定読み込み時のRedis接続確認の動作（"ratelimit_redis_check" ディレクティブ）
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum StartupCheck {
+    /// 接続できない場合はNGINXの起動を中止する
+    On,
+    /// 設定時には接続せず、最初のリクエストで接続する
+    Off,
+    /// 警告を出力して起動を続け、リクエスト時に再接続を試みる
+    #[default]
+    Warn,
+}
+
+/// 遅延接続に失敗した後、再試行するまでの間隔
+const DEFERRED_CONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+// プロセスごとのTokioランタイム（runtime() で取得する）
+//
+// ランタイムのスレッドはプロセスに属し、設定のサイクルとは寿命が異なるためmain confには置かない
+lazy_static! {
+    static ref PROCESS_RUNTIME: std::sync::Mutex<Option<(u32, Arc<Runtime>)>> =
+        std::sync::Mutex::new(None);
+}
+
+// 呼び出し元のスレッドが最後に取得したランタイム（リクエストごとに PROCESS_RUNTIME をロックしない）
+thread_local! {
+    static THREAD_RUNTIME: std::cell::RefCell<Option<(u32, Arc<Runtime>)>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+// http main confに保持するモジュールの設定
+//
+// NGINXは設定の読み込み（リロード）ごとに新しいmain confを作成するため、
+// 前回の設定で定義されたLocationやリミッターが新しい設定に持ち越されない
+#[derive(Clone, Default)]
+struct MainConf {
+    // "ratelimit_redis_config" などで読み込んだ設定ファイル
+    config_file: Option<ConfigFile>,
+    // ディレクティブで設定されたLocationごとの設定
+    locations: HashMap<String, RateLimitRedisConfig>,
+    // 管理APIを有効にしたLocationごとの設定
+    #[cfg(feature = "admin")]
+    admin_locations: HashMap<String, admin::AdminConfig>,
+    startup_check: StartupCheck,
+    // strict=on: 初期化の失敗があればNGINXの起動を中止する
+    strict: bool,
+    // 設定の読み込み中に発生し、記録だけして起動を続けた初期化の失敗
+    init_failures: Vec<String>,
+    // デフォルトのリミッター（設定ファイル、または最初に有効にしたLocationの設定で作成する）。
+    // 管理APIやBANの保存はこのリミッターを使用する
+    limiter: LimiterSlot,
+    // ディレクティブで有効にしたLocationごとのリミッター
+    location_limiters: HashMap<String, LimiterSlot>,
+    // 設定ファイルの tiers でアルゴリズムを指定したティアごとのリミッター
+    tier_limiters: HashMap<String, LimiterSlot>,
+}
+
+// リミッター1つ分の状態
+//
+// rate・burst 以外の設定（接続先・アルゴリズムなど）が同じLocationは、接続済みのリミッターを共有する
+#[derive(Clone, Default)]
+struct LimiterSlot {
+    limiter_config: RateLimitConfig,
+    // 設定の読み込み時に接続したリミッター
+    limiter: Option<Arc<RedisRateLimiter>>,
+    // Redis以外のバックエンド（backend=memory）
+    backend: Option<Arc<dyn RateLimitBackend>>,
+    // 接続を後回しにした場合に最後に接続を試みた時刻（まだ試みていなければ Some(None)）
+    pending: Option<Option<Instant>>,
+}
+
+impl LimiterSlot {
+    fn is_empty(&self) -> bool {
+        self.limiter.is_none() && self.backend.is_none() && self.pending.is_none()
+    }
+}
+
+// main confを取得する（未作成の場合は空の設定を返す）
+fn main_conf(cf: &mut HttpConfRef) -> MainConf {
+    cf.get_main_conf::<MainConf>(&ngx_ratelimit_redis_module)
+        .unwrap_or_default()
+}
+
+// main confを保存する（スナップショットはすべての設定を読み込んだ後に module_init で公開する）
+fn save_main_conf(cf: &mut HttpConfRef, conf: MainConf) {
+    cf.set_main_conf(&ngx_ratelimit_redis_module, &conf);
+}
+
+// リクエスト処理で参照する設定のスナップショット
+//
+// 設定の読み込み時にmain confから全Locationの設定を解決して構築し、公開後は変更しない。
+// リクエストはArcを共有するだけで、設定の文字列や接続オプションを複製しない
+struct ConfigSnapshot {
+    // ディレクティブ・設定ファイルで明示されたLocationごとの設定
+    locations: HashMap<String, Arc<RateLimitRedisConfig>>,
+    // 設定ファイルに記載のないLocationに適用するデフォルト設定
+    default: Option<Arc<RateLimitRedisConfig>>,
+    #[cfg(feature = "admin")]
+    admin_locations: HashMap<String, admin::AdminConfig>,
+    // デフォルトのリミッターと、ディレクティブで有効にしたLocationごとのリミッター
+    limiter: PublishedLimiter,
+    location_limiters: HashMap<String, PublishedLimiter>,
+    tier_limiters: HashMap<String, PublishedLimiter>,
+    // 作成元のmain conf（設定の再読み込み時にスナップショットを作り直すために保持する）
+    conf: MainConf,
+}
+
+impl ConfigSnapshot {
+    fn resolve(&self, location: &str) -> Option<&Arc<RateLimitRedisConfig>> {
+        self.locations.get(location).or(self.default.as_ref())
+    }
+
+    // Locationの判定に使用するリミッター（Location専用のものがなければデフォルト）
+    fn limiter_for(&self, location: &str) -> &PublishedLimiter {
+        self.location_limiters
+            .get(location)
+            .unwrap_or(&self.limiter)
+    }
+
+    // ヘッダーで選択されたティアの設定と、アルゴリズムを指定したティアのリミッター
+    fn tier(&self, name: &str) -> Option<(&TierSettings, Option<&PublishedLimiter>)> {
+        let tier = self.conf.config_file.as_ref()?.tiers.get(name)?;
+        Some((tier, self.tier_limiters.get(name)))
+    }
+}
+
+// スナップショットで公開するリミッター
+struct PublishedLimiter {
+    limiter_config: RateLimitConfig,
+    // 起動時または遅延接続に成功した時点で一度だけ設定される。
+    // 設定後は変更しないため、リクエストはロックせずに読み出す
+    limiter: std::sync::OnceLock<Arc<RedisRateLimiter>>,
+    // Redis以外のバックエンド（設定時に作成され、以後変更されない）
+    backend: Option<Arc<dyn RateLimitBackend>>,
+    // 接続を後回しにした場合に最後に接続を試みた時刻
+    pending: std::sync::Mutex<Option<Option<Instant>>>,
+}
+
+impl PublishedLimiter {
+    fn new(slot: &LimiterSlot) -> Self {
+        let limiter = std::sync::OnceLock::new();
+        if let Some(connected) = &slot.limiter {
+            let _ = limiter.set(connected.clone());
+        }
+        Self {
+            limiter_config: slot.limiter_config.clone(),
+            limiter,
+            backend: slot.backend.clone(),
+            pending: std::sync::Mutex::new(slot.pending),
+        }
+    }
+
+    // 現在の状態（遅延接続の結果を含む）をmain confに戻す
+    fn slot(&self) -> LimiterSlot {
+        LimiterSlot {
+            limiter_config: self.limiter_config.clone(),
+            limiter: self.limiter.get().cloned(),
+            backend: self.backend.clone(),
+            pending: *self
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+
+    // 判定に使用するバックエンド
+    //
+    // 判定はRateLimitBackendを通してのみ行い、バックエンドの種類に依存しない
+    fn backend(&self) -> Option<Arc<dyn RateLimitBackend>> {
+        if let Some(backend) = &self.backend {
+            return Some(backend.clone());
+        }
+        self.limiter
+            .get()
+            .map(|limiter| limiter.clone() as Arc<dyn RateLimitBackend>)
+    }
+}
+
+// 公開中のスナップショット（ロックせずに読み出す）
+//
+// 置き換えた古いスナップショットとそのリミッターは、参照している処理中のリクエストが終わった時点で解放される
+static CONFIG_SNAPSHOT: ArcSwapOption<ConfigSnapshot> = ArcSwapOption::const_empty();
+
+// 現在のスナップショットを返す
+fn config_snapshot() -> Option<Arc<ConfigSnapshot>> {
+    CONFIG_SNAPSHOT.load_full()
+}
+
+// main confからスナップショットを作り直して公開する（設定の読み込みの完了時と再読み込み時）
+fn publish_config_snapshot(conf: &MainConf) {
+    let mut locations = HashMap::new();
+    let mut default = None;
+    if let Some(config_file) = &conf.config_file {
+        for location in config_file.locations.keys() {
+            locations.insert(
+                location.clone(),
+                Arc::new(apply_config_from_file(config_file, location).compile_keys()),
+            );
+        }
+        default = Some(Arc::new(
+            apply_settings_to_config(config_file.default.clone()).compile_keys(),
+        ));
+    }
+    // ディレクティブで指定された設定を優先する
+    for (location, config) in conf.locations.iter() {
+        locations.insert(location.clone(), Arc::new(config.clone().compile_keys()));
+    }
+
+    // エラー報告に、どの設定で発生したかを付与する
+    #[cfg(feature = "sentry")]
+    sentry_report::set_config_version(
+        &conf
+            .config_file
+            .as_ref()
+            .map_or_else(|| "directives".to_string(), ConfigFile::version),
+    );
+
+    CONFIG_SNAPSHOT.store(Some(Arc::new(ConfigSnapshot {
+        locations,
+        default,
+        #[cfg(feature = "admin")]
+        admin_locations: conf.admin_locations.clone(),
+        limiter: PublishedLimiter::new(&conf.limiter),
+        location_limiters: conf
+            .location_limiters
+            .iter()
+            .map(|(location, slot)| (location.clone(), PublishedLimiter::new(slot)))
+            .collect(),
+        tier_limiters: conf
+            .tier_limiters
+            .iter()
+            .map(|(tier, slot)| (tier.clone(), PublishedLimiter::new(slot)))
+            .collect(),
+        conf: conf.clone(),
+    })));
+}
+
+// 再読み込みした設定（設定ファイルの監視・設定ソース）でスナップショットを作り直す
+//
+// ディレクティブで設定されたLocationと接続済みのリミッターは現在のものを引き継ぐ。
+// デフォルトのリミッターの接続先・アルゴリズムなど（rate・burst 以外）が変わった場合は、
+// 新しい設定のリミッターをバックグラウンドで接続し、接続できた時点で置き換える
+fn replace_config_file(config_file: ConfigFile) {
+    let current = match config_snapshot() {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+    let mut conf = snapshot_conf(&current);
+    let limiter_config = default_limiter_config(&config_file);
+    let mut reconnect = None;
+    if config_file.default.enabled {
+        if conf.limiter.is_empty() {
+            // 起動時に設定を取得できなかった場合は、最初のリクエストでRedisに接続する
+            conf.limiter = LimiterSlot {
+                limiter_config,
+                pending: Some(None),
+                ..LimiterSlot::default()
+            };
+        } else if conf.limiter.backend.is_none()
+            && !conf
+                .limiter
+                .limiter_config
+                .shares_limiter_with(&limiter_config)
+        {
+            reconnect = Some(limiter_config);
+        }
+    }
+    conf.tier_limiters = reload_tier_limiters(&conf.tier_limiters, &config_file);
+    conf.config_file = Some(config_file);
+    publish_config_snapshot(&conf);
+
+    if let Some(limiter_config) = reconnect {
+        info!("Default limiter settings changed, connecting a new limiter");
+        runtime().spawn(reconnect_default_limiter(limiter_config));
+    }
+}
+
+// スナップショットの作成元のmain confに、遅延接続の結果を含む現在のリミッターを戻したもの
+fn snapshot_conf(snapshot: &ConfigSnapshot) -> MainConf {
+    let mut conf = snapshot.conf.clone();
+    conf.limiter = snapshot.limiter.slot();
+    conf.location_limiters = snapshot
+        .location_limiters
+        .iter()
+        .map(|(location, limiter)| (location.clone(), limiter.slot()))
+        .collect();
+    conf.tier_limiters = snapshot
+        .tier_limiters
+        .iter()
+        .map(|(tier, limiter)| (tier.clone(), limiter.slot()))
+        .collect();
+    conf
+}
+
+// 設定の変わったデフォルトのリミッターを新しい設定で接続し、現在のリミッターと置き換える
+//
+// 接続するまでは現在のリミッターで判定を続け、接続できなければ現在のリミッターを使い続ける
+async fn reconnect_default_limiter(limiter_config: RateLimitConfig) {
+    let limiter = match RedisRateLimiter::new(limiter_config.clone()).await {
+        Ok(limiter) => Arc::new(limiter),
+        Err(e) => {
+            error!(
+                "Failed to connect with the changed limiter settings, keeping the current limiter: {}",
+                e
+            );
+            return;
+        }
+    };
+    let current = match config_snapshot() {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+    // 接続している間に設定が再び変わった場合は、その設定の接続に任せる
+    let latest = current
+        .conf
+        .config_file
+        .as_ref()
+        .map(default_limiter_config);
+    if !latest.map_or(false, |latest| latest.shares_limiter_with(&limiter_config)) {
+        return;
+    }
+
+    let mut conf = snapshot_conf(&current);
+    conf.limiter = LimiterSlot {
+        limiter_config,
+        limiter: Some(limiter.clone()),
+        ..LimiterSlot::default()
+    };
+    publish_config_snapshot(&conf);
+    info!("Replaced the default limiter after a settings change");
+    warm_up_connections(limiter).await;
+}
+
+// 現在の設定のデフォルトのRedisリミッターを返す
+//
+// RedisRateLimiterは&selfのメソッドのみを持ち、複数のチェックから同時に使用できる
+pub(crate) fn current_limiter() -> Option<Arc<RedisRateLimiter>> {
+    config_snapshot()?.limiter.limiter.get().cloned()
+}
+
+// Locationの判定に使用するRedisリミッターを返す
+fn location_limiter(location: &str) -> Option<Arc<RedisRateLimiter>> {
+    config_snapshot()?
+        .limiter_for(location)
+        .limiter
+        .get()
+        .cloned()
+}
+
+// Locationの判定に使用するバックエンドを返す
+fn location_backend(location: &str) -> Option<Arc<dyn RateLimitBackend>> {
+    config_snapshot()?.limiter_for(location).backend()
+}
+
+// 現在の設定で接続済みのRedisリミッター（共有しているものは1つにまとめる）
+fn connected_limiters() -> Vec<Arc<RedisRateLimiter>> {
+    let snapshot = match config_snapshot() {
+        Some(snapshot) => snapshot,
+        None => return Vec::new(),
+    };
+    let mut limiters: Vec<Arc<RedisRateLimiter>> = Vec::new();
+    for published in std::iter::once(&snapshot.limiter)
+        .chain(snapshot.location_limiters.values())
+        .chain(snapshot.tier_limiters.values())
+    {
+        if let Some(limiter) = published.limiter.get() {
+            if !limiters.iter().any(|known| Arc::ptr_eq(known, limiter)) {
+                limiters.push(limiter.clone());
+            }
+        }
+    }
+    limiters
+}
+
+// 管理APIを有効にしたLocationの設定を返す
+#[cfg(feature = "admin")]
+pub(crate) fn admin_config(location: &str) -> Option<admin::AdminConfig> {
+    config_snapshot()?.admin_locations.get(location).cloned()
+}
+
+// 遅延接続したリミッターを現在の設定に登録する
+//
+// 再試行が重なって複数の接続に成功した場合は、最初に登録したものを使い続ける
+fn install_limiter(
+    published: &PublishedLimiter,
+    limiter: RedisRateLimiter,
+) -> Arc<RedisRateLimiter> {
+    published.limiter.get_or_init(|| Arc::new(limiter)).clone()
+}
+
+// 現在のプロセスのTokioランタイムを返す
+//
+// NGINXはマスタープロセスで設定を読み込んだ後にワーカーをforkするため、
+// マスターで作成したランタイムのスレッドはワーカーには存在しない。
+// プロセスIDが変わっていれば、そのプロセス専用のランタイムを作り直す
+pub(crate) fn runtime() -> Arc<Runtime> {
+    let pid = std::process::id();
+    let cached = THREAD_RUNTIME.with(|cached| match &*cached.borrow() {
+        Some((owner, runtime)) if *owner == pid => Some(runtime.clone()),
+        _ => None,
+    });
+    if let Some(runtime) = cached {
+        return runtime;
+    }
+
+    let runtime = process_runtime(pid);
+    THREAD_RUNTIME.with(|cached| *cached.borrow_mut() = Some((pid, runtime.clone())));
+    runtime
+}
+
+// PROCESS_RUNTIME から pid のプロセスのランタイムを取得し、なければ作成する
+//
+// 引き継いだランタイムは手放した参照が残るため、スレッドのキャッシュを置き換えても解放されない
+fn process_runtime(pid: u32) -> Arc<Runtime> {
+    let mut slot = PROCESS_RUNTIME
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if let Some((owner, runtime)) = &*slot {
+        if *owner == pid {
+            return runtime.clone();
+        }
+    }
+
+    // 親プロセスから引き継いだランタイムはスレッドが存在しないためdropせずに手放す
+    if let Some((_, inherited)) = slot.take() {
+        std::mem::forget(inherited);
+    }
+
+    let runtime = build_process_runtime().expect("Failed to create Tokio runtime");
+    debug!("Created Tokio runtime for process {}", pid);
+    *slot = Some((pid, runtime.clone()));
+    runtime
+}
+
+// シングルスレッドのランタイムと、それを駆動する専用スレッドを1本作成する
+//
+// spawnされたタスクとRedis接続のI/Oはこのスレッドで処理され、
+// NGINXのスレッドから呼ぶ block_on は結果を待つ間だけ呼び出し元で動作する
+fn build_process_runtime() -> Result<Arc<Runtime>, String> {
+    let runtime = Arc::new(
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create Tokio runtime: {}", e))?,
+    );
+
+    let driver = runtime.clone();
+    std::thread::Builder::new()
+        .name("ratelimit-redis".to_string())
+        .spawn(move || driver.block_on(std::future::pending::<()>()))
+        .map_err(|e| format!("Failed to start Tokio runtime thread: {}", e))?;
+
+    Ok(runtime)
+}
+
+// モジュールのコンテキスト管理
+#[derive(Clone)]
+struct ModuleContext {
+    config: Arc<RateLimitRedisConfig>,
+    // リクエスト処理中の非同期チェック（リクエストのコンテキストでのみ使用）
+    pending: Option<Arc<PendingCheck>>,
+    // C API（ngx_ratelimit_redis_check）で最後に行った判定
+    c_decision: Option<NgxRateLimitRedisDecision>,
+    // このモジュールが拒否したリクエスト（ログフェーズで上流の応答と区別する）
+    limited: bool,
+    // $ratelimit_redis_* 変数で参照する判定結果
+    variables: Option<DecisionVariables>,
+}
+
+// $ratelimit_redis_* 変数の値（finish_check で記録する）
+#[derive(Debug, Clone, Copy)]
+struct DecisionVariables {
+    limit: u32,
+    // 判定していない場合（フォールバック・BAN）はNone
+    remaining: Option<u64>,
+    status: &'static str,
+}
+
+impl DecisionVariables {
+    // limit_req の $limit_req_status に倣い、判定結果を大文字の名前で表す
+    fn from_outcome(outcome: &CheckOutcome, rate: u32) -> Self {
+        let status = if outcome.banned {
+            "BANNED"
+        } else if !outcome.allowed {
+            "REJECTED"
+        } else if outcome.delayed {
+            "DELAYED"
+        } else if outcome.fallback {
+            "FALLBACK"
+        } else {
+            "PASSED"
+        };
+        Self {
+            limit: rate,
+            remaining: outcome.decision.map(|decision| decision.remaining),
+            status,
+        }
+    }
+}
+
+// 非同期で実行したレート制限チェックの結果
+#[derive(Debug)]
+pub(crate) struct CheckOutcome {
+    location: String,
+    key: String,
+    allowed: bool,
+    banned: bool,
+    limits: Option<LimitOverride>,
+    // レート制限スクリプトの判定（BAN・フォールバック時はNone）
+    decision: Option<RateLimitDecision>,
+    // quota= のクォータの判定（レート制限で拒否した場合はNone）
+    quota: Option<QuotaDecision>,
+    fallback: bool,
+    // Redisへの問い合わせ自体を省略した判定か（滞留・レイテンシ予算の超過）
+    skipped: bool,
+    // algorithm=limit_req の遅延を終えた判定か
+    delayed: bool,
+}
+
+impl CheckOutcome {
+    // Redisで判定できない場合の結果（リクエストを許可し、finish_check で failure_mode を適用する）
+    fn fallback(location: String, key: String) -> Self {
+        Self {
+            location,
+            key,
+            allowed: true,
+            banned: false,
+            limits: None,
+            decision: None,
+            quota: None,
+            fallback: true,
+            skipped: false,
+            delayed: false,
+        }
+    }
+
+    // Redisに問い合わせずに障害時の動作を適用した結果
+    fn skipped(location: String, key: String) -> Self {
+        Self {
+            skipped: true,
+            ..Self::fallback(location, key)
+        }
+    }
+
+    // BAN・拒否リストによりカウンタを更新せずに拒否した結果
+    fn banned(location: String, key: String) -> Self {
+        Self {
+            allowed: false,
+            banned: true,
+            fallback: false,
+            ..Self::fallback(location, key)
+        }
+    }
+}
+
+// ランタイム上のチェックとNGINXのリクエスト処理の間で結果を受け渡す
+#[derive(Debug, Default)]
+struct PendingCheck {
+    outcome: std::sync::Mutex<Option<CheckOutcome>>,
+}
+
+impl PendingCheck {
+    fn complete(&self, outcome: CheckOutcome) {
+        if let Ok(mut slot) = self.outcome.lock() {
+            *slot = Some(outcome);
+        }
+    }
+
+    fn take(&self) -> Option<CheckOutcome> {
+        self.outcome.lock().ok().and_then(|mut slot| slot.take())
+    }
+}
+
+// このワーカーで実行中のRedisチェックの数
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// 実行中のRedisチェック1件分の枠（破棄されると解放される）
+struct InFlightGuard;
+
+impl InFlightGuard {
+    // 実行中のチェックが上限に達していなければ枠を確保する（0は無制限）
+    fn acquire(limit: u32) -> Option<Self> {
+        let depth = IN_FLIGHT.fetch_add(1, Ordering::AcqRel);
+        if limit > 0 && depth >= limit as usize {
+            IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(Self)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// モジュール定義
+nginx_module!(ngx_ratelimit_redis_module);
+
+// モジュールの初期化関数
+#[nginx_handler]
+async fn module_init(cf: &mut MainConf) -> Result<(), String> {
+    info!("Initializing Redis Rate Limiter module");
+    publish_config_snapshot(cf);
+    check_strict(cf)
+}
+
+// strict=on の場合、設定の読み込み中に記録した初期化の失敗があれば起動を中止する
+//
+// ディレクティブの順序に左右されないよう、すべての設定を読み込んだ後に判定する
+fn check_strict(conf: &MainConf) -> Result<(), String> {
+    if !conf.strict {
+        return Ok(());
+    }
+
+    let mut failures = conf.init_failures.clone();
+    // 接続を後回しにすると、Redisに接続できるかを起動時に確認できない
+    let pending = std::iter::once(&conf.limiter)
+        .chain(conf.location_limiters.values())
+        .chain(conf.tier_limiters.values())
+        .any(|slot| slot.pending.is_some());
+    if conf.startup_check == StartupCheck::Off && pending {
+        failures.push("strict=on cannot be combined with ratelimit_redis_check off".to_string());
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for failure in &failures {
+        error!("Initialization failed (strict=on): {}", failure);
+    }
+    Err(format!(
+        "Aborting startup because of {} initialization failure(s) (strict=on): {}",
+        failures.len(),
+        failures.join("; ")
+    ))
+}
+
+// strict=on が設定されているか（ワーカーの初期化で参照する）
+fn is_strict() -> bool {
+    config_snapshot().map_or(false, |snapshot| snapshot.conf.strict)
+}
+
+// ワーカープロセスの初期化関数
+#[nginx_handler]
+async fn worker_init() -> Result<(), String> {
+    // 最初のリクエストを待たずにこのワーカー専用のランタイムを用意する
+    let runtime = runtime();
+
+    // Sentryの送信スレッドはフォークで失われるため、ワーカーごとに作り直す
+    #[cfg(feature = "sentry")]
+    sentry_report::init();
+    info!(
+        "Redis Rate Limiter runtime ready in worker {}",
+        std::process::id()
+    );
+
+    // 接続はフォーク後のワーカーで確立する（ワーカーの起動はブロックしない）
+    for limiter in connected_limiters() {
+        runtime.spawn(warm_up_connections(limiter));
+    }
+
+    // BANのローカル保存が有効な場合は、起動時の復元と定期的な保存を行う
+    if let Some(settings) = banstore::settings() {
+        runtime.spawn(sync_ban_store(settings));
+    }
+
+    // 設定ファイル・設定ソースの監視はワーカーごとに行い、各ワーカーのスナップショットを更新する
+    if let Some(settings) = configwatch::settings() {
+        runtime.spawn(configwatch::watch(settings, replace_config_file));
+    }
+    #[cfg(feature = "config-source")]
+    if let Some(settings) = configsource::settings() {
+        runtime.spawn(configsource::watch(settings, replace_config_file));
+    }
+
+    // Kafkaのプロデューサーはスレッドを持つため、ワーカーごとに作成する
+    #[cfg(feature = "kafka")]
+    if let Err(e) = kafka::start(&runtime) {
+        error!("{}", e);
+        if is_strict() {
+            return Err(format!("{} (strict=on)", e));
+        }
+    }
+    Ok(())
+}
+
+// RedisのBANとローカルファイルを定期的に同期する
+//
+// 最初の同期はワーカーの起動直後に行い、Redisが空であればファイルからBANを復元する
+async fn sync_ban_store(settings: banstore::BanStoreSettings) {
+    loop {
+        if let Some(limiter) = current_limiter() {
+            if !banstore::is_fresh(&settings.path, settings.interval) {
+                match banstore::sync(&limiter, &settings.path).await {
+                    Ok(banstore::SyncResult::Restored(count)) => warn!(
+                        "Redis had no bans, restored {} from {:?}",
+                        count, settings.path
+                    ),
+                    Ok(_) => {}
+                    Err(e) => error!("Ban store sync failed: {}", e),
+                }
+            }
+        }
+        tokio::time::sleep(settings.interval).await;
+    }
+}
+
+// 設定されたプールサイズ分の接続を事前に確立する
+async fn warm_up_connections(limiter: Arc<RedisRateLimiter>) {
+    match limiter.warm_up().await {
+        Ok(count) => info!(
+            "Warmed up {} Redis connections in worker {}",
+            count,
+            std::process::id()
+        ),
+        Err(e) => warn!("Redis connection warm-up failed: {}", e),
+    }
+}
+
+// モジュールの終了関数
+#[nginx_handler]
+async fn module_exit() -> Result<(), String> {
+    info!("Shutting down Redis Rate Limiter module");
+    Ok(())
+}
+
+// HTTP部分の初期化
+#[nginx_handler]
+async fn http_init(cmcf: &mut HttpMainConf) -> Result<(), String> {
+    let handler_loc = HttpLocationHandler::new(ratelimit_handler);
+    let _ = cmcf.register_loc_handler("ratelimit_redis", handler_loc);
+
+    // 応答の集計（accounting=on）はログフェーズで行う
+    let log_handler = HttpLocationHandler::new(ratelimit_log_handler);
+    let _ = cmcf.register_log_handler("ratelimit_redis", log_handler);
+
+    // 判定結果を access_log や proxy_set_header から参照できるよう変数として公開する
+    for (name, getter) in [
+        (
+            "ratelimit_redis_remaining",
+            remaining_variable as fn(&Request) -> Option<String>,
+        ),
+        ("ratelimit_redis_limit", limit_variable),
+        ("ratelimit_redis_status", status_variable),
+    ] {
+        cmcf.register_variable(name, HttpVariableHandler::new(getter))?;
+    }
+
+    // 上限超過キーのテーブルはワーカーと共有するためフォーク前に確保する
+    overlimit::init();
+
+    #[cfg(feature = "admin")]
+    {
+        let admin_loc = HttpLocationHandler::new(admin::ratelimit_admin_handler);
+        let _ = cmcf.register_loc_handler("ratelimit_redis_admin", admin_loc);
+    }
+
+    Ok(())
+}
+
+// 設定ファイルの読み込み
+async fn load_config_file(path: &str) -> Result<ConfigFile, String> {
+    match ConfigFile::from_file(path) {
+        Ok(config) => {
+            info!("Successfully loaded configuration from {}", path);
+            Ok(config)
+        }
+        Err(e) => {
+            error!("Failed to load configuration file: {}", e);
+            Err(e)
+        }
+    }
+}
+
+// 起動時に読み込んだ設定ファイルを検証する
+//
+// 不正な値は既定値に置き換えて起動を続けるため、初期化の失敗として記録する
+fn validate_config_file(conf: &mut MainConf, path: &str, config_file: &ConfigFile) {
+    if let Err(errors) = config_file.validate() {
+        for e in errors {
+            warn!("Invalid setting in {}, using the default: {}", path, e);
+            // 複数のLocationから同じ設定ファイルを読み込んだ場合は1件にまとめる
+            let failure = format!("{}: {}", path, e);
+            if !conf.init_failures.contains(&failure) {
+                conf.init_failures.push(failure);
+            }
+        }
+    }
+}
+
+// 設定ファイルから特定のLocationの設定を取得して適用
+fn apply_config_from_file(config_file: &ConfigFile, location: &str) -> RateLimitRedisConfig {
+    let settings = config_file.get_settings(location);
+    apply_settings_to_config(settings)
+}
+
+// RateLimitSettingsからRateLimitRedisConfigを生成
+fn apply_settings_to_config(settings: RateLimitSettings) -> RateLimitRedisConfig {
+    let algorithm = ConfigFile::parse_algorithm(&settings.algorithm)
+        .unwrap_or(RateLimitAlgorithm::SlidingWindow);
+
+    RateLimitRedisConfig {
+        redis_url: settings.redis_url,
+        rate_limit_key: settings.key,
+        requests_per_second: settings.rate,
+        burst: settings.burst,
+        enabled: settings.enabled,
+        algorithm,
+        window_size: settings.window_size,
+        rate_period: settings.rate_period,
+        prefetch_ms: settings.prefetch_ms,
+        overlimit_cache_ms: settings.overlimit_cache_ms,
+        overlimit_cache_until_reset: settings.overlimit_cache_until_reset,
+        max_in_flight: settings.max_in_flight,
+        latency_budget_ms: settings.latency_budget_ms,
+        failure_mode: FailureMode::parse(&settings.failure_mode).unwrap_or_default(),
+        zones: settings.zones,
+        grpc_methods: settings.grpc_methods,
+        methods: settings.methods,
+        method_limits: settings.method_limits,
+        routes: settings.routes,
+        plans: settings.plans,
+        key_limits: settings.key_limits,
+        tier_header: settings.tier_header,
+        global_rate: settings.global_rate,
+        global_burst: settings.global_burst,
+        quota: settings.quota,
+        quota_period: settings.per.parse::<QuotaPeriod>().unwrap_or_default(),
+        whitelist: CidrSet::parse_all(&settings.whitelist).unwrap_or_default(),
+        blacklist: CidrSet::parse_all(&settings.blacklist).unwrap_or_default(),
+        offload: Offload::parse(&settings.offload).unwrap_or_default(),
+        backend: Backend::parse(&settings.backend).unwrap_or_default(),
+        mode: Mode::parse(&settings.mode).unwrap_or_default(),
+        accounting: settings.accounting,
+        abuse_ratio: settings.abuse_ratio,
+        abuse_min_requests: settings.abuse_min_requests,
+        abuse_action: AbuseAction::parse(&settings.abuse_action).unwrap_or_default(),
+        abuse_duration: settings.abuse_duration,
+        ban_policy: settings.ban_policy(),
+        ban_status: Some(settings.ban_status)
+            .filter(|code| *code != 0)
+            .and_then(|code| RejectStatus::from_code(code).ok()),
+        nodelay: settings.nodelay,
+        delay: settings.delay,
+        throttle: settings.throttle,
+        reject_status: RejectStatus::from_code(settings.status).unwrap_or_default(),
+        header_format: HeaderFormat::parse(&settings.header_format).unwrap_or_default(),
+        // 読み込めない場合は検証でエラーになるため、ここでは組み込みの本文に戻す
+        reject_body: if settings.reject_body_file.is_empty() {
+            None
+        } else {
+            load_reject_body(&settings.reject_body_file)
+                .map_err(|e| error!("{}", e))
+                .ok()
+                .map(Arc::new)
+        },
+        reject_body_file: settings.reject_body_file,
+        reject_content_type: settings.reject_content_type,
+        on_limit: OnLimit::parse(&settings.on_limit).unwrap_or_default(),
+        challenge_url: settings.challenge_url,
+        challenge_secret: settings.challenge_secret,
+        key_source: KeySource::default(),
+        zone_sources: Vec::new(),
+        tier_source: None,
+        config_file_path: None,
+        redis_options: settings.redis_options,
+    }
+}
+
+// Redisリミッターを初期化する
+//
+// 接続に失敗した場合の扱いは "ratelimit_redis_check" の設定に従う。
+// 接続を後回しにした場合は、リクエスト処理時に connect_pending_limiter で接続する。
+// rate・burst 以外が同じ設定で作成済みのリミッターがあれば、新たに接続せずに共有する
+fn initialize_limiter(
+    conf: &mut MainConf,
+    limiter_config: RateLimitConfig,
+    backend: Backend,
+) -> Result<(LimiterSlot, bool), String> {
+    let shared = std::iter::once(&conf.limiter)
+        .chain(conf.location_limiters.values())
+        .chain(conf.tier_limiters.values())
+        .filter(|slot| slot.limiter_config.shares_limiter_with(&limiter_config))
+        .find_map(|slot| match backend {
+            Backend::Memory => slot.backend.clone().map(|backend| LimiterSlot {
+                backend: Some(backend),
+                ..LimiterSlot::default()
+            }),
+            Backend::Redis => slot.limiter.clone().map(|limiter| LimiterSlot {
+                limiter: Some(limiter),
+                ..LimiterSlot::default()
+            }),
+        });
+    if let Some(shared) = shared {
+        return Ok((
+            LimiterSlot {
+                limiter_config,
+                ..shared
+            },
+            false,
+        ));
+    }
+
+    // メモリバックエンドはRedisに接続しない（状態はフォーク後のワーカーごとに独立する）
+    if backend == Backend::Memory {
+        info!(
+            "Using in-memory rate limit backend with algorithm {} (state is per worker)",
+            limiter_config.algorithm
+        );
+        let backend = MemoryBackend::new(limiter_config.clone())?;
+        return Ok((
+            LimiterSlot {
+                limiter_config,
+                backend: Some(Arc::new(backend)),
+                ..LimiterSlot::default()
+            },
+            false,
+        ));
+    }
+
+    let check = conf.startup_check;
+
+    if check == StartupCheck::Off {
+        info!("Deferring Redis connection until the first request (ratelimit_redis_check off)");
+        return Ok((
+            LimiterSlot {
+                limiter_config,
+                pending: Some(None),
+                ..LimiterSlot::default()
+            },
+            false,
+        ));
+    }
+
+    match runtime().block_on(RedisRateLimiter::new(limiter_config.clone())) {
+        Ok(limiter) => Ok((
+            LimiterSlot {
+                limiter_config,
+                limiter: Some(Arc::new(limiter)),
+                ..LimiterSlot::default()
+            },
+            true,
+        )),
+        Err(e) if check == StartupCheck::On => {
+            error!("Failed to initialize Redis connection: {}", e);
+            Err(format!(
+                "Failed to initialize Redis connection (ratelimit_redis_check on): {}",
+                e
+            ))
+        }
+        Err(e) => {
+            warn!(
+                "Failed to initialize Redis connection, will retry on incoming requests: {}",
+                e
+            );
+            conf.init_failures
+                .push(format!("Failed to initialize Redis connection: {}", e));
+            Ok((
+                LimiterSlot {
+                    limiter_config,
+                    pending: Some(Some(Instant::now())),
+                    ..LimiterSlot::default()
+                },
+                false,
+            ))
+        }
+    }
+}
+
+// 後回しにしたリミッターのRedis接続を試みる（失敗した場合は一定間隔で再試行する）
+async fn connect_pending_limiter(published: &PublishedLimiter) -> Option<Arc<RedisRateLimiter>> {
+    {
+        let mut pending = published
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match &mut *pending {
+            Some(Some(attempted)) if attempted.elapsed() < DEFERRED_CONNECT_INTERVAL => {
+                return None
+            }
+            Some(attempted) => *attempted = Some(Instant::now()),
+            None => return None,
+        }
+    }
+
+    match RedisRateLimiter::new(published.limiter_config.clone()).await {
+        Ok(limiter) => {
+            info!("Redis Rate Limiter initialized on demand");
+            *published
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+            let limiter = install_limiter(published, limiter);
+            runtime().spawn(warm_up_connections(limiter.clone()));
+            Some(limiter)
+        }
+        Err(e) => {
+            error!("Deferred Redis connection failed: {}", e);
+            None
+        }
+    }
+}
+
+// "ratelimit_redis_check" ディレクティブの設定ハンドラ
+#[nginx_handler]
+async fn ratelimit_redis_check_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args = cmd.args();
+    if args.len() != 1 {
+        return Err("Syntax: ratelimit_redis_check on|off|warn".to_string());
+    }
+
+    let check = match args[0].as_str() {
+        "on" => StartupCheck::On,
+        "off" => StartupCheck::Off,
+        "warn" => StartupCheck::Warn,
+        other => {
+            return Err(format!(
+                "ratelimit_redis_check should be 'on', 'off' or 'warn': {}",
+                other
+            ))
+        }
+    };
+
+    let mut conf = main_conf(cf);
+    conf.startup_check = check;
+    save_main_conf(cf, conf);
+
+    Ok(())
+}
+
+// "ratelimit_redis_ban_store" ディレクティブの設定ハンドラ
+#[nginx_handler]
+async fn ratelimit_redis_ban_store_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = banstore::BanStoreSettings::parse(&args)?;
+    info!(
+        "Mirroring bans to {:?} every {}s",
+        settings.path,
+        settings.interval.as_secs()
+    );
+    banstore::configure(settings);
+    Ok(())
+}
+
+// "ratelimit_redis_config" ディレクティブの設定ハンドラ
+#[nginx_handler]
+async fn ratelimit_redis_config_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args = cmd.args();
+    let syntax = || "Syntax: ratelimit_redis_config /path/to/config.json [watch=<s>]".to_string();
+    if args.is_empty() || args.len() > 2 {
+        return Err(syntax());
+    }
+
+    let config_path = args[0].as_str().to_string();
+    info!("Loading rate limit configuration from {}", config_path);
+
+    // watch=<秒> の場合はワーカーで設定ファイルの変更を監視する
+    if let Some(arg) = args.get(1) {
+        let value = arg.as_str().strip_prefix("watch=").ok_or_else(syntax)?;
+        let interval = match value.parse::<u64>() {
+            Ok(secs) if secs > 0 => Duration::from_secs(secs),
+            _ => return Err(format!("Invalid watch value: {}", value)),
+        };
+        configwatch::configure(configwatch::ConfigWatchSettings {
+            path: config_path.clone().into(),
+            interval,
+        });
+    }
+
+    // 設定ファイルを読み込む
+    let config_file = match runtime().block_on(load_config_file(&config_path)) {
+        Ok(config) => config,
+        Err(e) => {
+            #[cfg(feature = "sentry")]
+            sentry_report::config_error(&config_path, &e);
+            return Err(format!("Failed to load config file: {}", e));
+        }
+    };
+
+    let mut conf = main_conf(cf);
+    validate_config_file(&mut conf, &config_path, &config_file);
+    save_main_conf(cf, conf);
+
+    install_config_file(cf, config_file)
+}
+
+// "ratelimit_redis_config_source" ディレクティブの設定ハンドラ
+//
+// 起動時に現在の値を取得して適用し、以後の変更はワーカーで監視する。
+// 取得できない場合は、設定ソースが復旧するまで他の設定で起動を続ける
+#[cfg(feature = "config-source")]
+#[nginx_handler]
+async fn ratelimit_redis_config_source_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = configsource::ConfigSourceSettings::parse(&args)?;
+    info!("Loading rate limit configuration from {}", settings.source);
+
+    match runtime().block_on(configsource::fetch(&settings)) {
+        Ok(config_file) => install_config_file(cf, config_file)?,
+        Err(e) => {
+            warn!(
+                "Failed to load configuration from {}, waiting for it to become available: {}",
+                settings.source, e
+            );
+            #[cfg(feature = "sentry")]
+            sentry_report::config_error(&settings.source.to_string(), &e);
+            let mut conf = main_conf(cf);
+            conf.init_failures.push(format!(
+                "Failed to load configuration from {}: {}",
+                settings.source, e
+            ));
+            save_main_conf(cf, conf);
+        }
+    }
+    configsource::configure(settings);
+    Ok(())
+}
+
+// 読み込んだ設定ファイルのデフォルト設定でリミッターを初期化し、main confに保存する
+fn install_config_file(cf: &mut HttpConfRef, config_file: ConfigFile) -> Result<(), String> {
+    // デフォルト設定からRedisを初期化
+    let mut conf = main_conf(cf);
+    if config_file.default.enabled {
+        let limiter_config = default_limiter_config(&config_file);
+        let backend = Backend::parse(&config_file.default.backend)?;
+        let (slot, initialized) = initialize_limiter(&mut conf, limiter_config, backend)?;
+        conf.limiter = slot;
+        if initialized {
+            info!("Redis Rate Limiter initialized from config file");
+        }
+    }
+
+    // アルゴリズムを指定したティアは、ティアごとのリミッターで判定する
+    let backend = Backend::parse(&config_file.default.backend)?;
+    for (name, tier) in &config_file.tiers {
+        if let Some(limiter_config) = tier_limiter_config(&config_file, tier) {
+            let (slot, _) = initialize_limiter(&mut conf, limiter_config, backend)?;
+            conf.tier_limiters.insert(name.clone(), slot);
+        }
+    }
+
+    // main confに保存
+    conf.config_file = Some(config_file);
+    save_main_conf(cf, conf);
+
+    Ok(())
+}
+
+// 設定ファイルのデフォルト設定からリミッターの設定を作成する
+fn default_limiter_config(config_file: &ConfigFile) -> RateLimitConfig {
+    RateLimitConfig {
+        redis_url: config_file.default.redis_url.clone(),
+        requests_per_second: config_file.default.rate,
+        burst: config_file.default.burst,
+        algorithm: ConfigFile::parse_algorithm(&config_file.default.algorithm)
+            .unwrap_or(RateLimitAlgorithm::SlidingWindow),
+        window_size: config_file.default.window_size,
+        rate_period: config_file.default.rate_period,
+        redis_options: config_file.default.redis_options.clone(),
+    }
+}
+
+// アルゴリズムを指定したティアのリミッターの設定（接続先などはデフォルト設定に従う）
+fn tier_limiter_config(config_file: &ConfigFile, tier: &TierSettings) -> Option<RateLimitConfig> {
+    let algorithm = ConfigFile::parse_algorithm(tier.algorithm.as_ref()?).ok()?;
+    Some(RateLimitConfig {
+        requests_per_second: tier.rate,
+        burst: tier.burst,
+        algorithm,
+        ..default_limiter_config(config_file)
+    })
+}
+
+// 再読み込みした設定のティアのリミッター
+//
+// 接続先・アルゴリズムなどが変わらないティアは接続済みのリミッターを引き継ぎ、
+// 追加・変更されたティアは最初のリクエストで接続する
+fn reload_tier_limiters(
+    current: &HashMap<String, LimiterSlot>,
+    config_file: &ConfigFile,
+) -> HashMap<String, LimiterSlot> {
+    let memory = Backend::parse(&config_file.default.backend) == Ok(Backend::Memory);
+    config_file
+        .tiers
+        .iter()
+        .filter_map(|(name, tier)| {
+            let limiter_config = tier_limiter_config(config_file, tier)?;
+            let slot = match current.get(name) {
+                Some(slot) if slot.limiter_config.shares_limiter_with(&limiter_config) => {
+                    LimiterSlot {
+                        limiter_config,
+                        ..slot.clone()
+                    }
+                }
+                _ if memory => LimiterSlot {
+                    backend: MemoryBackend::new(limiter_config.clone())
+                        .ok()
+                        .map(|backend| Arc::new(backend) as Arc<dyn RateLimitBackend>),
+                    limiter_config,
+                    ..LimiterSlot::default()
+                },
+                _ => LimiterSlot {
+                    limiter_config,
+                    pending: Some(None),
+                    ..LimiterSlot::default()
+                },
+            };
+            Some((name.clone(), slot))
+        })
+        .collect()
+}
+
+// Redis接続オプションを解析する
+fn parse_redis_option(arg: &str, config: &mut RateLimitRedisConfig) -> Result<(), String> {
+    if arg.starts_with("redis_connect_timeout=") {
+        let timeout_str = arg.trim_start_matches("redis_connect_timeout=");
+        if let Ok(timeout) = timeout_str.parse::<u64>() {
+            config.redis_options.connect_timeout = timeout;
+        } else {
+            return Err(format!(
+                "Invalid redis_connect_timeout value: {}",
+                timeout_str
+            ));
+        }
+    } else if arg.starts_with("redis_command_timeout=") {
+        let timeout_str = arg.trim_start_matches("redis_command_timeout=");
+        if let Ok(timeout) = timeout_str.parse::<u64>() {
+            config.redis_options.command_timeout = timeout;
+        } else {
+            return Err(format!(
+                "Invalid redis_command_timeout value: {}",
+                timeout_str
+            ));
+        }
+    } else if arg.starts_with("redis_retry_count=") {
+        let retry_str = arg.trim_start_matches("redis_retry_count=");
+        if let Ok(retry) = retry_str.parse::<u32>() {
+            config.redis_options.retry_count = retry;
+        } else {
+            return Err(format!("Invalid redis_retry_count value: {}", retry_str));
+        }
+    } else if arg.starts_with("redis_retry_delay=") {
+        let delay_str = arg.trim_start_matches("redis_retry_delay=");
+        if let Ok(delay) = delay_str.parse::<u64>() {
+            config.redis_options.retry_delay = delay;
+        } else {
+            return Err(format!("Invalid redis_retry_delay value: {}", delay_str));
+        }
+    } else if arg.starts_with("redis_password=") {
+        let password = arg.trim_start_matches("redis_password=").to_string();
+        if !password.is_empty() {
+            config.redis_options.password = Some(password);
+        }
+    } else if arg.starts_with("redis_database=") {
+        let db_str = arg.trim_start_matches("redis_database=");
+        if let Ok(db) = db_str.parse::<i64>() {
+            config.redis_options.database = db;
+        } else {
+            return Err(format!("Invalid redis_database value: {}", db_str));
+        }
+    } else if arg.starts_with("redis_pool_size=") {
+        let pool_str = arg.trim_start_matches("redis_pool_size=");
+        if let Ok(pool) = pool_str.parse::<u32>() {
+            config.redis_options.pool_size = pool;
+        } else {
+            return Err(format!("Invalid redis_pool_size value: {}", pool_str));
+        }
+    } else if arg.starts_with("redis_cluster_mode=") {
+        let mode_str = arg.trim_start_matches("redis_cluster_mode=");
+        if mode_str == "on" {
+            config.redis_options.cluster_mode = true;
+        } else if mode_str == "off" {
+            config.redis_options.cluster_mode = false;
+        } else {
+            return Err(format!("Invalid redis_cluster_mode value: {}", mode_str));
+        }
+    } else if arg.starts_with("redis_tls=") {
+        let tls_str = arg.trim_start_matches("redis_tls=");
+        if tls_str == "on" {
+            config.redis_options.tls_enabled = true;
+        } else if tls_str == "off" {
+            config.redis_options.tls_enabled = false;
+        } else {
+            return Err(format!("Invalid redis_tls value: {}", tls_str));
+        }
+    } else if arg.starts_with("redis_tls_ca=") {
+        config.redis_options.tls_ca = arg.trim_start_matches("redis_tls_ca=").to_string();
+    } else if arg.starts_with("redis_tls_cert=") {
+        config.redis_options.tls_cert = arg.trim_start_matches("redis_tls_cert=").to_string();
+    } else if arg.starts_with("redis_tls_key=") {
+        config.redis_options.tls_key = arg.trim_start_matches("redis_tls_key=").to_string();
+    } else if arg.starts_with("redis_tls_insecure=") {
+        let insecure_str = arg.trim_start_matches("redis_tls_insecure=");
+        if insecure_str == "on" {
+            config.redis_options.tls_insecure = true;
+        } else if insecure_str == "off" {
+            config.redis_options.tls_insecure = false;
+        } else {
+            return Err(format!(
+                "Invalid redis_tls_insecure value: {}",
+                insecure_str
+            ));
+        }
+    } else if arg.starts_with("redis_keepalive=") {
+        let keepalive_str = arg.trim_start_matches("redis_keepalive=");
+        if let Ok(keepalive) = keepalive_str.parse::<u64>() {
+            config.redis_options.keepalive = keepalive;
+        } else {
+            return Err(format!("Invalid redis_keepalive value: {}", keepalive_str));
+        }
+    } else if arg.starts_with("redis_breaker_failures=") {
+        let failures_str = arg.trim_start_matches("redis_breaker_failures=");
+        if let Ok(failures) = failures_str.parse::<u32>() {
+            config.redis_options.breaker_failures = failures;
+        } else {
+            return Err(format!(
+                "Invalid redis_breaker_failures value: {}",
+                failures_str
+            ));
+        }
+    } else if arg.starts_with("redis_breaker_cooldown=") {
+        let cooldown_str = arg.trim_start_matches("redis_breaker_cooldown=");
+        if let Ok(cooldown) = cooldown_str.parse::<u64>() {
+            config.redis_options.breaker_cooldown = cooldown;
+        } else {
+            return Err(format!(
+                "Invalid redis_breaker_cooldown value: {}",
+                cooldown_str
+            ));
+        }
+    } else if arg.starts_with("redis_breaker_probes=") {
+        let probes_str = arg.trim_start_matches("redis_breaker_probes=");
+        if let Ok(probes) = probes_str.parse::<u32>() {
+            config.redis_options.breaker_probes = probes;
+        } else {
+            return Err(format!(
+                "Invalid redis_breaker_probes value: {}",
+                probes_str
+            ));
+        }
+    } else {
+        return Err(format!("Unknown Redis connection option: {}", arg));
+    }
+
+    Ok(())
+}
+
+// "ratelimit_redis" ディレクティブの設定ハンドラ
+#[nginx_handler]
+async fn ratelimit_redis_command(cf: &mut HttpConfRef, cmd: &CommandArgs) -> Result<(), String> {
+    let ctx = cf
+        .get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+        .unwrap_or_else(|| {
+            let ctx = ModuleContext {
+                config: Arc::new(RateLimitRedisConfig::default()),
+                pending: None,
+                c_decision: None,
+                limited: false,
+                variables: None,
+            };
+            cf.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+            ctx
+        });
+
+    let mut config = (*ctx.config).clone();
+
+    // コマンド引数の解析
+    let args = cmd.args();
+    if args.len() < 1 {
+        return Err("Invalid number of arguments for ratelimit_redis directive".to_string());
+    }
+
+    // 有効/無効の設定
+    let enabled = match args[0].as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err("ratelimit_redis should be 'on' or 'off'".to_string()),
+    };
+
+    config.enabled = enabled;
+    let mut strict = false;
+
+    // オプションのパラメータ解析
+    for i in 1..args.len() {
+        let arg = args[i].as_str();
+
+        if arg.starts_with("redis_url=") {
+            config.redis_url = arg.trim_start_matches("redis_url=").to_string();
+        } else if arg.starts_with("key=") {
+            let key = arg.trim_start_matches("key=");
+            validate_key(key)?;
+            config.rate_limit_key = key.to_string();
+        } else if arg.starts_with("rate=") {
+            let rate_str = arg.trim_start_matches("rate=");
+            if let Ok(rate) = rate_str.parse::<u32>() {
+                config.requests_per_second = rate;
+            } else {
+                return Err(format!("Invalid rate value: {}", rate_str));
+            }
+        } else if arg.starts_with("burst=") {
+            let burst_str = arg.trim_start_matches("burst=");
+            if let Ok(burst) = burst_str.parse::<u32>() {
+                config.burst = burst;
+            } else {
+                return Err(format!("Invalid burst value: {}", burst_str));
+            }
+        } else if arg.starts_with("algorithm=") {
+            let algorithm_str = arg.trim_start_matches("algorithm=");
+            match RateLimitAlgorithm::from_str(algorithm_str) {
+                Ok(algorithm) => config.algorithm = algorithm,
+                Err(err) => return Err(err),
+            }
+        } else if arg.starts_with("window_size=") {
+            let window_str = arg.trim_start_matches("window_size=");
+            if let Ok(window) = window_str.parse::<u32>() {
+                config.window_size = window;
+            } else {
+                return Err(format!("Invalid window_size value: {}", window_str));
+            }
+        } else if arg.starts_with("rate_period=") {
+            let value = arg.trim_start_matches("rate_period=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.rate_period = v;
+            } else {
+                return Err(format!("Invalid rate_period value: {}", value));
+            }
+        } else if arg.starts_with("prefetch_ms=") {
+            let value = arg.trim_start_matches("prefetch_ms=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.prefetch_ms = v;
+            } else {
+                return Err(format!("Invalid prefetch_ms value: {}", value));
+            }
+        } else if arg.starts_with("overlimit_cache_ms=") {
+            let value = arg.trim_start_matches("overlimit_cache_ms=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.overlimit_cache_ms = v;
+            } else {
+                return Err(format!("Invalid overlimit_cache_ms value: {}", value));
+            }
+        } else if arg.starts_with("overlimit_cache_until_reset=") {
+            match arg.trim_start_matches("overlimit_cache_until_reset=") {
+                "on" => config.overlimit_cache_until_reset = true,
+                "off" => config.overlimit_cache_until_reset = false,
+                value => {
+                    return Err(format!(
+                        "Invalid overlimit_cache_until_reset value: {}",
+                        value
+                    ))
+                }
+            }
+        } else if arg.starts_with("max_in_flight=") {
+            let value = arg.trim_start_matches("max_in_flight=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.max_in_flight = v;
+            } else {
+                return Err(format!("Invalid max_in_flight value: {}", value));
+            }
+        } else if arg.starts_with("latency_budget_ms=") {
+            let value = arg.trim_start_matches("latency_budget_ms=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.latency_budget_ms = v;
+            } else {
+                return Err(format!("Invalid latency_budget_ms value: {}", value));
+            }
+        } else if arg.starts_with("failure_mode=") {
+            config.failure_mode = FailureMode::parse(arg.trim_start_matches("failure_mode="))?;
+        } else if arg.starts_with("offload=") {
+            config.offload = Offload::parse(arg.trim_start_matches("offload="))?;
+        } else if arg.starts_with("compat=") {
+            config.redis_options.compat = RedisCompat::parse(arg.trim_start_matches("compat="))?;
+        } else if arg.starts_with("backend=") {
+            config.backend = Backend::parse(arg.trim_start_matches("backend="))?;
+        } else if arg.starts_with("zone=") {
+            let zone = ZoneSettings::parse(arg.trim_start_matches("zone="))?;
+            config.zones.push(zone);
+        } else if arg.starts_with("grpc_method=") {
+            let method = GrpcMethodSettings::parse(arg.trim_start_matches("grpc_method="))?;
+            config.grpc_methods.push(method);
+        } else if arg.starts_with("methods=") {
+            let methods = arg.trim_start_matches("methods=");
+            config.methods = Vec::new();
+            for method in methods.split(',') {
+                validate_http_method(method)?;
+                config.methods.push(method.to_string());
+            }
+        } else if arg.starts_with("method_limit=") {
+            let limit = MethodLimitSettings::parse(arg.trim_start_matches("method_limit="))?;
+            config.method_limits.push(limit);
+        } else if arg.starts_with("plan=") {
+            let plan = PlanSettings::parse(arg.trim_start_matches("plan="))?;
+            config.plans.push(plan);
+        } else if arg.starts_with("key_limits=") {
+            match arg.trim_start_matches("key_limits=") {
+                "on" => config.key_limits = true,
+                "off" => config.key_limits = false,
+                value => return Err(format!("Invalid key_limits value: {}", value)),
+            }
+        } else if arg.starts_with("tier_header=") {
+            let header = arg.trim_start_matches("tier_header=");
+            validate_tier_header(header)?;
+            config.tier_header = header.to_string();
+        } else if arg.starts_with("global_rate=") {
+            let value = arg.trim_start_matches("global_rate=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.global_rate = v;
+            } else {
+                return Err(format!("Invalid global_rate value: {}", value));
+            }
+        } else if arg.starts_with("global_burst=") {
+            let value = arg.trim_start_matches("global_burst=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.global_burst = v;
+            } else {
+                return Err(format!("Invalid global_burst value: {}", value));
+            }
+        } else if arg.starts_with("quota=") {
+            let value = arg.trim_start_matches("quota=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.quota = v;
+            } else {
+                return Err(format!("Invalid quota value: {}", value));
+            }
+        } else if arg.starts_with("per=") {
+            config.quota_period = arg.trim_start_matches("per=").parse::<QuotaPeriod>()?;
+        } else if arg.starts_with("whitelist=") {
+            for cidr in arg.trim_start_matches("whitelist=").split(',') {
+                config.whitelist.insert(Cidr::parse(cidr)?);
+            }
+        } else if arg.starts_with("blacklist=") {
+            for cidr in arg.trim_start_matches("blacklist=").split(',') {
+                config.blacklist.insert(Cidr::parse(cidr)?);
+            }
+        } else if arg.starts_with("mode=") {
+            config.mode = Mode::parse(arg.trim_start_matches("mode="))?;
+        } else if arg.starts_with("accounting=") {
+            match arg.trim_start_matches("accounting=") {
+                "on" => config.accounting = true,
+                "off" => config.accounting = false,
+                value => return Err(format!("Invalid accounting value: {}", value)),
+            }
+        } else if arg.starts_with("abuse_ratio=") {
+            let value = arg.trim_start_matches("abuse_ratio=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.abuse_ratio = v;
+            } else {
+                return Err(format!("Invalid abuse_ratio value: {}", value));
+            }
+        } else if arg.starts_with("abuse_min_requests=") {
+            let value = arg.trim_start_matches("abuse_min_requests=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.abuse_min_requests = v;
+            } else {
+                return Err(format!("Invalid abuse_min_requests value: {}", value));
+            }
+        } else if arg.starts_with("abuse_action=") {
+            config.abuse_action = AbuseAction::parse(arg.trim_start_matches("abuse_action="))?;
+        } else if arg.starts_with("abuse_duration=") {
+            let value = arg.trim_start_matches("abuse_duration=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.abuse_duration = v;
+            } else {
+                return Err(format!("Invalid abuse_duration value: {}", value));
+            }
+        } else if arg.starts_with("ban_after=") {
+            let value = arg.trim_start_matches("ban_after=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.ban_policy.after = v;
+            } else {
+                return Err(format!("Invalid ban_after value: {}", value));
+            }
+        } else if arg.starts_with("ban_window=") {
+            let value = arg.trim_start_matches("ban_window=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.ban_policy.window = v;
+            } else {
+                return Err(format!("Invalid ban_window value: {}", value));
+            }
+        } else if arg.starts_with("ban_duration=") {
+            let value = arg.trim_start_matches("ban_duration=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.ban_policy.duration = v;
+            } else {
+                return Err(format!("Invalid ban_duration value: {}", value));
+            }
+        } else if arg.starts_with("ban_max_duration=") {
+            let value = arg.trim_start_matches("ban_max_duration=");
+            if let Ok(v) = value.parse::<u64>() {
+                config.ban_policy.max_duration = v;
+            } else {
+                return Err(format!("Invalid ban_max_duration value: {}", value));
+            }
+        } else if arg.starts_with("ban_status=") {
+            config.ban_status = Some(RejectStatus::parse(arg.trim_start_matches("ban_status="))?);
+        } else if arg == "nodelay" {
+            config.nodelay = true;
+        } else if arg.starts_with("delay=") {
+            let value = arg.trim_start_matches("delay=");
+            if let Ok(v) = value.parse::<u32>() {
+                config.delay = v;
+            } else {
+                return Err(format!("Invalid delay value: {}", value));
+            }
+        } else if arg.starts_with("throttle=") {
+            match arg.trim_start_matches("throttle=") {
+                "on" => config.throttle = true,
+                "off" => config.throttle = false,
+                value => return Err(format!("Invalid throttle value: {}", value)),
+            }
+        } else if arg.starts_with("status=") {
+            config.reject_status = RejectStatus::parse(arg.trim_start_matches("status="))?;
+        } else if arg.starts_with("header_format=") {
+            config.header_format = HeaderFormat::parse(arg.trim_start_matches("header_format="))?;
+        } else if arg.starts_with("reject_body_file=") {
+            let path = arg.trim_start_matches("reject_body_file=");
+            config.reject_body = Some(Arc::new(load_reject_body(path)?));
+            config.reject_body_file = path.to_string();
+        } else if arg.starts_with("reject_content_type=") {
+            let content_type = arg.trim_start_matches("reject_content_type=");
+            if content_type.is_empty() {
+                return Err("reject_content_type must not be empty".to_string());
+            }
+            config.reject_content_type = content_type.to_string();
+        } else if arg.starts_with("on_limit=") {
+            config.on_limit = OnLimit::parse(arg.trim_start_matches("on_limit="))?;
+        } else if arg.starts_with("challenge_url=") {
+            config.challenge_url = arg.trim_start_matches("challenge_url=").to_string();
+        } else if arg.starts_with("challenge_secret=") {
+            config.challenge_secret = arg.trim_start_matches("challenge_secret=").to_string();
+        } else if arg.starts_with("strict=") {
+            // 設定全体に適用する（いずれかのディレクティブで on にすれば有効になる）
+            match arg.trim_start_matches("strict=") {
+                "on" => strict = true,
+                "off" => {}
+                value => return Err(format!("Invalid strict value: {}", value)),
+            }
+        } else if arg.starts_with("config_file=") {
+            let file_path = arg.trim_start_matches("config_file=").to_string();
+            config.config_file_path = Some(file_path);
+        } else if arg.starts_with("redis_") {
+            // Redis接続オプションを解析
+            parse_redis_option(arg, &mut config)?;
+        } else {
+            return Err(format!("Unknown parameter: {}", arg));
+        }
+    }
+
+    if strict {
+        let mut conf = main_conf(cf);
+        conf.strict = true;
+        save_main_conf(cf, conf);
+    }
+
+    // config_file指定がある場合は設定ファイルを読み込む
+    if let Some(file_path) = &config.config_file_path {
+        let config_file = match runtime().block_on(load_config_file(file_path)) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                #[cfg(feature = "sentry")]
+                sentry_report::config_error(file_path, &e);
+                return Err(format!("Failed to load config file: {}", e));
+            }
+        };
+
+        // 現在のロケーションの設定を適用
+        let location = cf.loc_conf_get_path().to_string();
+        let location_config = apply_config_from_file(&config_file, &location);
+
+        // 設定をマージ
+        config.redis_url = location_config.redis_url;
+        config.rate_limit_key = location_config.rate_limit_key;
+        config.requests_per_second = location_config.requests_per_second;
+        config.burst = location_config.burst;
+        config.algorithm = location_config.algorithm;
+        config.window_size = location_config.window_size;
+        config.rate_period = location_config.rate_period;
+        config.prefetch_ms = location_config.prefetch_ms;
+        config.overlimit_cache_ms = location_config.overlimit_cache_ms;
+        config.overlimit_cache_until_reset = location_config.overlimit_cache_until_reset;
+        config.max_in_flight = location_config.max_in_flight;
+        config.latency_budget_ms = location_config.latency_budget_ms;
+        config.failure_mode = location_config.failure_mode;
+        config.zones = location_config.zones;
+        config.grpc_methods = location_config.grpc_methods;
+        config.methods = location_config.methods;
+        config.method_limits = location_config.method_limits;
+        config.routes = location_config.routes;
+        config.plans = location_config.plans;
+        config.key_limits = location_config.key_limits;
+        config.tier_header = location_config.tier_header;
+        config.global_rate = location_config.global_rate;
+        config.global_burst = location_config.global_burst;
+        config.quota = location_config.quota;
+        config.quota_period = location_config.quota_period;
+        config.whitelist = location_config.whitelist;
+        config.blacklist = location_config.blacklist;
+        config.offload = location_config.offload;
+        config.backend = location_config.backend;
+        config.mode = location_config.mode;
+        config.accounting = location_config.accounting;
+        config.abuse_ratio = location_config.abuse_ratio;
+        config.abuse_min_requests = location_config.abuse_min_requests;
+        config.abuse_action = location_config.abuse_action;
+        config.abuse_duration = location_config.abuse_duration;
+        config.ban_policy = location_config.ban_policy;
+        config.ban_status = location_config.ban_status;
+        config.nodelay = location_config.nodelay;
+        config.delay = location_config.delay;
+        config.throttle = location_config.throttle;
+        config.reject_status = location_config.reject_status;
+        config.header_format = location_config.header_format;
+        config.reject_body_file = location_config.reject_body_file;
+        config.reject_body = location_config.reject_body;
+        config.reject_content_type = location_config.reject_content_type;
+        config.on_limit = location_config.on_limit;
+        config.challenge_url = location_config.challenge_url;
+        config.challenge_secret = location_config.challenge_secret;
+        config.redis_options = location_config.redis_options;
+
+        // enabledはコマンドラインの設定を優先
+        if enabled {
+            config.enabled = location_config.enabled;
+        }
+
+        // 設定ファイルとロケーション固有の設定をmain confに保存
+        let mut conf = main_conf(cf);
+        validate_config_file(&mut conf, file_path, &config_file);
+        conf.config_file = Some(config_file);
+        conf.locations.insert(location, config.clone());
+        save_main_conf(cf, conf);
+    }
+
+    #[cfg(not(feature = "introspection"))]
+    if config.rate_limit_key == "oauth_subject" {
+        return Err("key=oauth_subject requires the introspection feature".to_string());
+    }
+
+    // チャレンジページと署名の鍵がない場合はリダイレクトも通過Cookieの検証もできない
+    if config.on_limit == OnLimit::Challenge
+        && (config.challenge_url.is_empty() || config.challenge_secret.is_empty())
+    {
+        return Err("on_limit=challenge requires challenge_url and challenge_secret".to_string());
+    }
+
+    validate_limits(
+        config.algorithm,
+        config.requests_per_second,
+        config.burst,
+        config.window_size,
+        config.rate_period,
+    )?;
+    // global_rate= はLocationのアルゴリズムと時間窓で数える
+    if config.global_rate > 0 {
+        validate_limits(
+            config.algorithm,
+            config.global_rate,
+            config.global_burst,
+            config.window_size,
+            config.rate_period,
+        )
+        .map_err(|e| format!("global_rate: {}", e))?;
+    } else if config.global_burst > 0 {
+        return Err("global_burst= requires global_rate=".to_string());
+    }
+
+    // 遅延は limit_req互換アルゴリズム・GCRAの超過量から計算するため、他のアルゴリズムでは指定できない
+    if config.throttle && config.algorithm != RateLimitAlgorithm::Gcra {
+        return Err("throttle=on requires algorithm=gcra".to_string());
+    }
+    if (config.nodelay || config.delay > 0)
+        && config.algorithm != RateLimitAlgorithm::LimitReq
+        && !config.throttle
+    {
+        return Err("nodelay and delay= require algorithm=limit_req or throttle=on".to_string());
+    }
+    // overlimit_cache_ms は記録する時間の上限になるため、指定がなければ記録できない
+    if config.overlimit_cache_until_reset && config.overlimit_cache_ms == 0 {
+        return Err("overlimit_cache_until_reset=on requires overlimit_cache_ms=".to_string());
+    }
+    // 拒否数を数える時間窓とBANの期間がなければ自動BANできない
+    config.ban_policy.validate()?;
+    config.redis_options.validate_tls(&config.redis_url)?;
+    config.redis_options.validate_breaker()?;
+
+    // コンテキストの更新
+    let new_ctx = ModuleContext {
+        config: Arc::new(config.clone()),
+        pending: None,
+        c_decision: None,
+        limited: false,
+        variables: None,
+    };
+    cf.set_module_ctx(&ngx_ratelimit_redis_module, &new_ctx);
+
+    // Redis接続の初期化
+    if config.enabled {
+        let limiter_config = RateLimitConfig {
+            redis_url: config.redis_url.clone(),
+            requests_per_second: config.requests_per_second,
+            burst: config.burst,
+            algorithm: config.algorithm,
+            window_size: config.window_size,
+            rate_period: config.rate_period,
+            redis_options: config.redis_options.clone(),
+        };
+
+        // Locationごとにリミッターを持ち、接続先やアルゴリズムの異なるLocationが互いに上書きしないようにする
+        let location = cf.loc_conf_get_path().to_string();
+        let mut conf = main_conf(cf);
+        let (slot, initialized) = initialize_limiter(&mut conf, limiter_config, config.backend)?;
+        if conf.limiter.is_empty() {
+            conf.limiter = slot.clone();
+        }
+        conf.location_limiters.insert(location, slot);
+        save_main_conf(cf, conf);
+
+        if initialized {
+            info!(
+                "Redis Rate Limiter initialized with algorithm: {}",
+                config.algorithm
+            );
+            info!("Redis connection options: connect_timeout={}ms, command_timeout={}ms, retry_count={}, database={}",
+                config.redis_options.connect_timeout,
+                config.redis_options.command_timeout,
+                config.redis_options.retry_count,
+                config.redis_options.database);
+        }
+    }
+
+    Ok(())
+}
+
+// "ratelimit_redis_admin" ディレクティブの設定ハンドラ
+#[cfg(feature = "admin")]
+#[nginx_handler]
+async fn ratelimit_redis_admin_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let admin_config = admin::parse_admin_args(&args)?;
+
+    let location = cf.loc_conf_get_path().to_string();
+    info!(
+        "Rate limit admin API {} at {}",
+        if admin_config.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        location
+    );
+
+    let mut conf = main_conf(cf);
+    conf.admin_locations.insert(location, admin_config);
+    save_main_conf(cf, conf);
+
+    Ok(())
+}
+
+// "ratelimit_redis_kafka" ディレクティブの設定ハンドラ
+#[cfg(feature = "kafka")]
+#[nginx_handler]
+async fn ratelimit_redis_kafka_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = kafka::KafkaSettings::parse(&args)?;
+    info!(
+        "Rate limit decisions will be published to Kafka topic {} via {}",
+        settings.topic, settings.brokers
+    );
+    kafka::configure(settings);
+    Ok(())
+}
+
+// "ratelimit_redis_fault" ディレクティブの設定ハンドラ
+#[cfg(feature = "fault-injection")]
+#[nginx_handler]
+async fn ratelimit_redis_fault_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = fault::FaultSettings::parse(&args)?;
+    fault::configure(settings);
+    Ok(())
+}
+
+// "ratelimit_redis_jwt" ディレクティブの設定ハンドラ
+#[nginx_handler]
+async fn ratelimit_redis_jwt_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = jwt::JwtSettings::parse(&args)?;
+    info!(
+        "JWT signatures will be verified with HS256 (leeway {}s)",
+        settings.leeway
+    );
+    jwt::configure(settings);
+    Ok(())
+}
+
+// "ratelimit_redis_introspection" ディレクティブの設定ハンドラ
+#[cfg(feature = "introspection")]
+#[nginx_handler]
+async fn ratelimit_redis_introspection_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = introspection::IntrospectionSettings::parse(&args)?;
+    info!(
+        "Bearer tokens will be resolved via {} (cached for up to {}s)",
+        settings.url, settings.cache
+    );
+    introspection::configure(settings);
+    Ok(())
+}
+
+// "ratelimit_redis_sentry" ディレクティブの設定ハンドラ
+//
+// 以降の設定の読み込みエラーも報告できるよう、設定時にこのプロセスでもクライアントを初期化する
+#[cfg(feature = "sentry")]
+#[nginx_handler]
+async fn ratelimit_redis_sentry_command(
+    cf: &mut HttpConfRef,
+    cmd: &CommandArgs,
+) -> Result<(), String> {
+    let args: Vec<String> = cmd.args().iter().map(|a| a.as_str().to_string()).collect();
+    let settings = sentry_report::SentrySettings::parse(&args)?;
+    sentry_report::configure(settings);
+    Ok(())
+}
+
+// リクエストハンドラ
+#[nginx_handler]
+async fn ratelimit_handler(r: &mut Request) -> Status {
+    // 非同期チェックの完了後に再実行された場合は結果を適用する
+    if let Some(ctx) = r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+        if let Some(pending) = &ctx.pending {
+            return match pending.take() {
+                Some(outcome) => {
+                    remember_over_limit(&ctx.config, &outcome);
+                    finish_check(r, &ctx.config, outcome)
+                }
+                None => Status::Again,
+            };
+        }
+    }
+
+    // 現在のリクエストのロケーションパスを取得
+    let location_path = r.get_location_path().to_string();
+
+    // 設定読み込み時に解決済みのスナップショットから設定を取得する（ロック・複製しない）
+    let config =
+        match config_snapshot().and_then(|snapshot| snapshot.resolve(&location_path).cloned()) {
+            Some(cfg) => cfg,
+            None => {
+                // Context から設定を取得
+                r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+                    .map(|ctx| ctx.config.clone())
+                    .unwrap_or_default()
+            }
+        };
+
+    if !config.enabled || !config.applies_to_method(&r.method().to_string()) {
+        return Status::Declined;
+    }
+
+    // 許可リストのクライアントは制限せず、拒否リストのクライアントはRedisに問い合わせずに拒否する
+    if !config.whitelist.is_empty() || !config.blacklist.is_empty() {
+        if let Some(addr) = r
+            .connection()
+            .remote_addr()
+            .and_then(|addr| acl::parse_ip(&addr.to_string()))
+        {
+            if config.whitelist.contains(&addr) {
+                return Status::Declined;
+            }
+            // mode=mirror は拒否しないため、拒否リストも適用しない
+            if config.mode != Mode::Mirror && config.blacklist.contains(&addr) {
+                let outcome = CheckOutcome::banned(location_path, addr.to_string());
+                return finish_check(r, &config, outcome);
+            }
+        }
+    }
+
+    // レート制限キー（例：IPアドレス）の取得
+    let key = match config.key_source.extract(r) {
+        Ok(key) => key,
+        Err(e) => {
+            error!("{}", e);
+            return Status::Declined;
+        }
+    };
+
+    // 追加のゾーンはゾーンごとに別のカウンタを使用する（キーを取得できないゾーンは適用しない）
+    let mut zones: Vec<ZoneCheck> = config
+        .zones
+        .iter()
+        .zip(config.zone_sources.iter())
+        .filter_map(|(zone, source)| match source.extract(r) {
+            Ok(value) => Some(ZoneCheck {
+                key: format!("{}={}", zone.key, value),
+                rate: zone.rate,
+                burst: zone.burst,
+            }),
+            Err(e) => {
+                debug!("Skipping zone {}: {}", zone, e);
+                None
+            }
+        })
+        .collect();
+
+    // gRPCはすべての呼び出しが同じロケーションに届くため、:path のメソッドごとのカウンタを追加する
+    if !config.grpc_methods.is_empty() {
+        let path = r.uri().to_string();
+        if let Some(method) = GrpcMethodSettings::find(&config.grpc_methods, &path) {
+            zones.push(ZoneCheck {
+                key: format!("grpc:{}={}", method.method, key),
+                rate: method.rate,
+                burst: method.burst,
+            });
+        }
+    }
+
+    // HTTPメソッドごとのレート制限がある場合は、メソッドごとのカウンタを追加する
+    if !config.method_limits.is_empty() {
+        let method = r.method().to_string();
+        if let Some(limit) = MethodLimitSettings::find(&config.method_limits, &method) {
+            zones.push(ZoneCheck {
+                key: format!("method:{}={}", limit.method, key),
+                rate: limit.rate,
+                burst: limit.burst,
+            });
+        }
+    }
+
+    // OpenAPIから生成したルート表に一致する場合は、ルートごとのカウンタを追加する
+    if !config.routes.is_empty() {
+        let method = r.method().to_string();
+        let path = r.uri().to_string();
+        if let Some(route) = RouteSettings::find(&config.routes, &method, &path) {
+            zones.push(ZoneCheck {
+                key: format!("route:{} {}={}", route.method, route.path, key),
+                rate: route.rate,
+                burst: route.burst,
+            });
+        }
+    }
+
+    // tier_header の値で設定ファイルの tiers からティアを選択する（ヘッダーがなければLocationの設定）
+    let tier = config
+        .tier_source
+        .as_ref()
+        .and_then(|source| source.extract(r).ok());
+
+    // CIDR単位のBANはクライアントIPに対して適用する
+    let client_ip = r
+        .connection()
+        .remote_addr()
+        .and_then(|addr| acl::parse_ip(&addr.to_string()));
+
+    // 上限超過が確認済みのキーはRedisに問い合わせずに拒否する
+    // （記録が切れるまでの時間を Retry-After として返す）
+    let cached_over_limit = if config.overlimit_cache_ms > 0 {
+        overlimit::over_limit_for(&location_path, &key)
+    } else {
+        None
+    };
+    if let Some(remaining) = cached_over_limit {
+        let retry_after_ms = remaining.as_millis() as u64;
+        let outcome = CheckOutcome {
+            location: location_path,
+            key,
+            allowed: false,
+            banned: false,
+            limits: None,
+            decision: Some(RateLimitDecision {
+                allowed: false,
+                remaining: 0,
+                reset: (retry_after_ms + 999) / 1000,
+                count: 0,
+                excess: None,
+                retry_after_ms: Some(retry_after_ms),
+            }),
+            quota: None,
+            fallback: false,
+            skipped: false,
+            delayed: false,
+        };
+        return finish_check(r, &config, outcome);
+    }
+
+    // ホットキーの先読みが有効な場合、鮮度内の判定があればRedisを待たずに応答する
+    let staleness = Duration::from_millis(config.prefetch_ms);
+    let mut prefetch = false;
+    if config.prefetch_ms > 0 && !config.has_extra_checks() {
+        let cached = prefetch::take(&location_path, &key, client_ip, staleness);
+        prefetch = prefetch::begin(&location_path, &key, client_ip, staleness);
+        if let Some(outcome) = cached {
+            if prefetch {
+                match InFlightGuard::acquire(config.max_in_flight) {
+                    Some(guard) => {
+                        let (location, key) = (location_path.clone(), key.clone());
+                        runtime().spawn(async move {
+                            prefetch_check(location, key, client_ip).await;
+                            drop(guard);
+                        });
+                    }
+                    None => prefetch::store(&location_path, &key, client_ip, None),
+                }
+            }
+            remember_over_limit(&config, &outcome);
+            return finish_check(r, &config, outcome);
+        }
+    }
+
+    // 予測所要時間がレイテンシ予算を超える場合は、Redisに問い合わせずに障害時の動作を適用する
+    if config.latency_budget_ms > 0
+        && latency::should_skip(Duration::from_millis(config.latency_budget_ms))
+    {
+        debug!(
+            "Predicted Redis latency {:?} exceeds budget of {}ms, skipping check for {}",
+            latency::estimate(),
+            config.latency_budget_ms,
+            key
+        );
+        if prefetch {
+            prefetch::store(&location_path, &key, client_ip, None);
+        }
+        return finish_check(r, &config, CheckOutcome::skipped(location_path, key));
+    }
+
+    // サーキットブレーカーが開いている間は、接続を待たずに障害時の動作を適用する
+    // （半開きの状態では、回復を確認するため一部の問い合わせだけを通す）
+    if location_limiter(&location_path).map_or(false, |limiter| !limiter.breaker().allow()) {
+        debug!("Circuit breaker is open, skipping check for {}", key);
+        if prefetch {
+            prefetch::store(&location_path, &key, client_ip, None);
+        }
+        return finish_check(r, &config, CheckOutcome::skipped(location_path, key));
+    }
+
+    // Redisの応答が遅れてチェックが滞留している場合は、キューに積まずに障害時の動作を適用する
+    let guard = match InFlightGuard::acquire(config.max_in_flight) {
+        Some(guard) => guard,
+        None => {
+            debug!(
+                "Redis check queue is full ({} in flight), skipping check for {}",
+                config.max_in_flight, key
+            );
+            if prefetch {
+                prefetch::store(&location_path, &key, client_ip, None);
+            }
+            return finish_check(r, &config, CheckOutcome::skipped(location_path, key));
+        }
+    };
+
+    // Redisへの問い合わせはワーカーのイベントループの外で行い、ループをブロックしない。
+    // 完了するとリクエストが再実行され、先頭で結果が適用される
+    let pending = Arc::new(PendingCheck::default());
+    let ctx = ModuleContext {
+        config: config.clone(),
+        pending: Some(pending.clone()),
+        // 他のモジュールがC APIで判定した結果は引き続き参照できるようにする
+        c_decision: r
+            .get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+            .and_then(|ctx| ctx.c_decision),
+        limited: false,
+        variables: None,
+    };
+    r.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+
+    let check = {
+        let (location_path, key) = (location_path.clone(), key.clone());
+        #[cfg(feature = "introspection")]
+        let config = config.clone();
+        async move {
+            // key=oauth_subject ではBearerトークンをサブジェクトに置き換える
+            #[cfg(feature = "introspection")]
+            let (key, plan) = if config.key_source == KeySource::BearerToken {
+                resolve_principal(&config, key, client_ip).await
+            } else {
+                (key, None)
+            };
+            #[cfg(not(feature = "introspection"))]
+            let plan = None;
+
+            let started = Instant::now();
+            let outcome = check_request(
+                location_path.clone(),
+                key.clone(),
+                client_ip,
+                zones,
+                plan,
+                tier,
+            )
+            .await;
+            latency::record(started.elapsed());
+            pending.complete(outcome);
+            (location_path, key)
+        }
+    };
+
+    match &config.offload {
+        Offload::Async => {
+            let waker = r.waker();
+            runtime().spawn(async move {
+                let (location_path, key) = check.await;
+                waker.wake();
+
+                // ホットキーであれば次のリクエストに向けて判定を先読みする
+                if prefetch {
+                    prefetch_check(location_path, key, client_ip).await;
+                }
+                drop(guard);
+            });
+        }
+        Offload::ThreadPool(pool) => {
+            // スレッドプール上でRedisの応答を待つ。タスクが終わるとNGINXがリクエストを再開する
+            let task = move || {
+                let (location_path, key) = runtime().block_on(check);
+                if prefetch {
+                    runtime().spawn(async move {
+                        prefetch_check(location_path, key, client_ip).await;
+                        drop(guard);
+                    });
+                }
+            };
+            if let Err(e) = r.post_thread_task(pool, task) {
+                error!(
+                    "Failed to post rate limit check to thread pool {}: {}",
+                    pool, e
+                );
+                if prefetch {
+                    prefetch::store(&location_path, &key, client_ip, None);
+                }
+                return finish_check(r, &config, CheckOutcome::fallback(location_path, key));
+            }
+        }
+    }
+
+    Status::Again
+}
+
+// ログフェーズのハンドラ（accounting=on の場合に応答ステータスと送信バイト数を記録する）
+//
+// 記録は判定とは独立しており、応答を遅らせないようRedisへの書き込みは待たない
+#[nginx_handler]
+async fn ratelimit_log_handler(r: &mut Request) -> Status {
+    let location_path = r.get_location_path().to_string();
+    let config =
+        match config_snapshot().and_then(|snapshot| snapshot.resolve(&location_path).cloned()) {
+            Some(cfg) => cfg,
+            None => match r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+                Some(ctx) => ctx.config.clone(),
+                None => return Status::Ok,
+            },
+        };
+    let abuse = config.abuse_ratio > 0;
+    // ミラーされたリクエストの応答は実際の応答ではないため記録しない
+    if !config.enabled || config.mode == Mode::Mirror || !(config.accounting || abuse) {
+        return Status::Ok;
+    }
+    if !config.applies_to_method(&r.method().to_string()) {
+        return Status::Ok;
+    }
+
+    // 集計はRedisにのみ保存する（backend=memory では記録しない）
+    let limiter = match location_limiter(&location_path) {
+        Some(limiter) => limiter,
+        None => return Status::Ok,
+    };
+    let key = match config.key_source.extract(r) {
+        Ok(key) => key,
+        Err(e) => {
+            debug!("Skipping response accounting: {}", e);
+            return Status::Ok;
+        }
+    };
+    #[cfg(feature = "introspection")]
+    let client_ip = r
+        .connection()
+        .remote_addr()
+        .and_then(|addr| acl::parse_ip(&addr.to_string()));
+    let status = r.status();
+    let bytes = r.bytes_sent();
+    // このモジュール自身が拒否した応答は不審な応答として数えない
+    let limited = r
+        .get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+        .map_or(false, |ctx| ctx.limited);
+
+    runtime().spawn(async move {
+        // トークンをRedisのキーに含めないよう、判定と同じサブジェクトで記録する
+        #[cfg(feature = "introspection")]
+        let key = if config.key_source == KeySource::BearerToken {
+            resolve_principal(&config, key, client_ip).await.0
+        } else {
+            key
+        };
+
+        if config.accounting {
+            if let Err(e) = limiter.record_response(&key, status, bytes).await {
+                warn!("Failed to record response for {}: {}", key, e);
+            }
+        }
+        if abuse && !limited {
+            score_response(&limiter, &config, &key, status).await;
+        }
+    });
+    Status::Ok
+}
+
+// 認証情報の総当たりや列挙で増える応答ステータス
+const SUSPICIOUS_STATUSES: [u16; 4] = [401, 403, 404, 429];
+
+// abuse_action=tighten で引き下げた上限の割合（レート・バーストをこの値で割る）
+const TIGHTEN_DIVISOR: u32 = 4;
+
+// 応答を不審な応答の割合に加算し、閾値を超えたキーをBANまたは制限する
+async fn score_response(
+    limiter: &RedisRateLimiter,
+    config: &RateLimitRedisConfig,
+    key: &str,
+    status: u16,
+) {
+    let suspicious = SUSPICIOUS_STATUSES.contains(&status);
+    let (total, count) = match limiter.record_abuse_sample(key, suspicious).await {
+        Ok(sample) => sample,
+        Err(e) => {
+            warn!("Failed to record abuse sample for {}: {}", key, e);
+            return;
+        }
+    };
+    // 閾値を超えている間は不審な応答のたびに期間を延長する
+    if !suspicious
+        || total < config.abuse_min_requests as u64
+        || count * 100 <= total * config.abuse_ratio as u64
+    {
+        return;
+    }
+
+    warn!(
+        "{} of {} responses for {} were suspicious, applying abuse_action",
+        count, total, key
+    );
+    let result = match config.abuse_action {
+        AbuseAction::Ban => limiter.ban(key, config.abuse_duration).await,
+        AbuseAction::Tighten => limiter.penalize(key, config.abuse_duration).await,
+    };
+    if let Err(e) = result {
+        error!("Failed to apply abuse_action to {}: {}", key, e);
+    }
+}
+
+// key=oauth_subject: Bearerトークンをイントロスペクションでサブジェクトに解決し、プランの上限を返す
+//
+// 無効なトークンは、トークンを作り直して制限を逃れられないようクライアントIPで制限する。
+// エンドポイントに問い合わせられない場合はトークンのハッシュで制限する
+#[cfg(feature = "introspection")]
+async fn resolve_principal(
+    config: &RateLimitRedisConfig,
+    token: String,
+    client_ip: Option<IpAddr>,
+) -> (String, Option<LimitOverride>) {
+    let limiter = match current_limiter() {
+        Some(limiter) => limiter,
+        None => return (introspection::fallback_key(&token), None),
+    };
+    match introspection::resolve(&limiter, &token).await {
+        Ok(Some(principal)) => {
+            let plan = principal
+                .plan
+                .as_deref()
+                .and_then(|name| PlanSettings::find(&config.plans, name))
+                .map(|plan| LimitOverride {
+                    rate: plan.rate,
+                    burst: plan.burst,
+                });
+            (principal.subject, plan)
+        }
+        Ok(None) => match client_ip {
+            Some(ip) => (ip.to_string(), None),
+            None => (introspection::fallback_key(&token), None),
+        },
+        Err(e) => {
+            warn!("{}", e);
+            (introspection::fallback_key(&token), None)
+        }
+    }
+}
+
+// 次のリクエストの判定を先読みして保存する（Redisに到達できなかった判定は保存しない）
+async fn prefetch_check(location: String, key: String, client_ip: Option<IpAddr>) {
+    let outcome = check_request(
+        location.clone(),
+        key.clone(),
+        client_ip,
+        Vec::new(),
+        None,
+        None,
+    )
+    .await;
+    let outcome = if outcome.fallback {
+        None
+    } else {
+        Some(outcome)
+    };
+    prefetch::store(&location, &key, client_ip, outcome);
+}
+
+// mode=mirror のカウンタのキーの接頭辞（本番のカウンタ・BANと重ならないようにする）
+const MIRROR_KEY_PREFIX: &str = "mirror:";
+
+// global_rate= のLocation全体のカウンタの名前（CounterKey::Global としてクライアントのキーとは別の種類のキーに置く）
+fn global_key(location: &str, mirror: bool) -> String {
+    if mirror {
+        format!("{}{}", MIRROR_KEY_PREFIX, location)
+    } else {
+        location.to_string()
+    }
+}
+
+// 追加のゾーン1つ分のチェック
+struct ZoneCheck {
+    key: String,
+    rate: u32,
+    burst: u32,
+}
+
+// Redisを使用したレート制限チェック（BANされたキーはカウンタを更新せずに拒否）
+//
+// 追加のゾーンやLocation全体の上限（global_rate=）がある場合は、それらのスクリプトを1回のパイプラインで実行する
+async fn check_request(
+    location: String,
+    key: String,
+    client_ip: Option<IpAddr>,
+    zones: Vec<ZoneCheck>,
+    plan: Option<LimitOverride>,
+    tier: Option<String>,
+) -> CheckOutcome {
+    let current = config_snapshot();
+    let snapshot = current.as_deref();
+    let config = snapshot.and_then(|snapshot| snapshot.resolve(&location));
+    // 設定ファイルにないティア名は無視する
+    let tier = tier.and_then(|name| snapshot.and_then(|snapshot| snapshot.tier(&name)));
+    // mode=mirror: 本番のカウンタを更新しないよう別の名前空間のキーで数え、BANは適用しない
+    let mirror = config.map_or(false, |config| config.mode == Mode::Mirror);
+    let (counter_key, zones) = if mirror {
+        let zones = zones
+            .into_iter()
+            .map(|zone| ZoneCheck {
+                key: format!("{}{}", MIRROR_KEY_PREFIX, zone.key),
+                ..zone
+            })
+            .collect();
+        (format!("{}{}", MIRROR_KEY_PREFIX, key), zones)
+    } else {
+        (key.clone(), zones)
+    };
+
+    let result: Result<_, String> = async {
+        // アルゴリズムを指定したティアはティアのリミッター、それ以外はLocationのリミッターで判定する
+        let published = match tier.and_then(|(_, limiter)| limiter) {
+            Some(published) => Some(published),
+            None => snapshot.map(|snapshot| snapshot.limiter_for(&location)),
+        };
+        let limiter = match published {
+            Some(published) => match published.backend() {
+                Some(limiter) => Some(limiter),
+                None => connect_pending_limiter(published)
+                    .await
+                    .map(|limiter| limiter as Arc<dyn RateLimitBackend>),
+            },
+            None => None,
+        };
+        if let Some(limiter) = &limiter {
+            if !mirror && limiter.is_banned(&key).await? {
+                return Ok((false, true, None, None, None));
+            }
+            if let Some(ip) = client_ip.as_ref().filter(|_| !mirror) {
+                if banlist::is_ip_banned(limiter.as_ref(), ip).await {
+                    return Ok((false, true, None, None, None));
+                }
+            }
+            // Locationのrate/burstがリミッターの既定値と異なる場合（設定ソースからの更新など）はそれを使う
+            let configured = config
+                .map(|config| LimitOverride {
+                    rate: config.requests_per_second,
+                    burst: config.burst,
+                })
+                .filter(|configured| *configured != limiter.default_limits());
+            // キーごとに保存されたリミットは、トークンのプランとLocationの設定より優先する
+            let key_limits = match config {
+                Some(config) if config.key_limits => {
+                    overrides::resolve_key(limiter.as_ref(), &key).await
+                }
+                _ => None,
+            };
+            let tier_limits = tier.map(|(tier, _)| LimitOverride {
+                rate: tier.rate,
+                burst: tier.burst,
+            });
+            // 管理APIで設定された実行時の上書きがあれば、さらにそれを優先する
+            let mut limits = overrides::resolve(limiter.as_ref(), &location)
+                .await
+                .or(key_limits)
+                .or(tier_limits)
+                .or(plan)
+                .or(configured);
+            // 不審な応答が多いキーは上限を引き下げる（abuse_action=tighten）
+            let tighten = config.map_or(false, |config| {
+                config.abuse_ratio > 0 && config.abuse_action == AbuseAction::Tighten
+            });
+            if tighten && limiter.is_penalized(&counter_key).await? {
+                let base = limits.unwrap_or_else(|| limiter.default_limits());
+                limits = Some(LimitOverride {
+                    // rate=0（すべて拒否）は引き下げても0のままにする
+                    rate: (base.rate / TIGHTEN_DIVISOR).max(base.rate.min(1)),
+                    burst: base.burst / TIGHTEN_DIVISOR,
+                });
+            }
+            // Location全体のカウンタ（global_rate=）はキーごとの判定と同じ往復で数える
+            let global = config
+                .filter(|config| config.global_rate > 0)
+                .map(|config| {
+                    (
+                        global_key(&location, mirror),
+                        config.global_rate,
+                        config.global_burst,
+                    )
+                });
+            let (decision, global) = if !zones.is_empty() || global.is_some() {
+                let primary = limits.unwrap_or_else(|| limiter.default_limits());
+                let mut checks = vec![(
+                    CounterKey::Client(&counter_key),
+                    primary.rate,
+                    primary.burst,
+                )];
+                checks.extend(
+                    zones
+                        .iter()
+                        .map(|zone| (CounterKey::Client(&zone.key), zone.rate, zone.burst)),
+                );
+                if let Some((location, rate, burst)) = &global {
+                    checks.push((CounterKey::Global(location), *rate, *burst));
+                }
+                let mut decisions = limiter.check_rate_limits(&checks).await?;
+                let global = match global {
+                    Some(_) => decisions.pop(),
+                    None => None,
+                };
+                // 拒否したゾーンがあればその判定を報告する
+                let decision = decisions
+                    .iter()
+                    .find(|decision| !decision.allowed)
+                    .or(decisions.first())
+                    .copied();
+                (decision, global)
+            } else {
+                let decision = match limits {
+                    Some(limits) => {
+                        limiter
+                            .check_rate_limit_with(&counter_key, limits.rate, limits.burst)
+                            .await?
+                    }
+                    None => limiter.check_rate_limit(&counter_key).await?,
+                };
+                (Some(decision), None)
+            };
+            let allowed = decision.map_or(true, |decision| decision.allowed);
+            // Location全体の上限はキーごとの制限で許可したリクエストにだけ適用する。
+            // Location全体の上限による拒否はクライアントの違反ではないため、BANの対象にしない
+            let global_rejected = allowed && global.map_or(false, |global| !global.allowed);
+            // Location全体の上限で拒否した場合はその判定を報告する
+            let decision = if global_rejected { global } else { decision };
+            let key_rejected = !allowed;
+            let allowed = allowed && !global_rejected;
+            // レート制限で許可したリクエストだけがクォータを消費する
+            let quota = match config {
+                Some(config) if allowed && config.quota > 0 => Some(
+                    limiter
+                        .consume_quota(&counter_key, config.quota, config.quota_period)
+                        .await?,
+                ),
+                _ => None,
+            };
+            let quota_rejected = quota.map_or(false, |quota| !quota.allowed);
+            let allowed = allowed && !quota_rejected;
+            // 拒否が続いたキーをBANする（ban_after=）。BANは次のリクエストから適用する
+            if (key_rejected || quota_rejected) && !mirror {
+                if let Some(policy) = config
+                    .map(|config| config.ban_policy)
+                    .filter(BanPolicy::enabled)
+                {
+                    ban::record_rejection(limiter.as_ref(), &key, &policy).await;
+                }
+            }
+            Ok((allowed, false, limits, decision, quota))
+        } else {
+            error!("Redis Rate Limiter not initialized");
+            Ok((true, false, None, None, None)) // 初期化されていない場合は許可
+        }
+    }
+    .await;
+
+    match result {
+        Ok((allowed, banned, limits, decision, quota)) => {
+            #[cfg(feature = "sentry")]
+            sentry_report::redis_success();
+            CheckOutcome {
+                location,
+                key,
+                allowed,
+                banned,
+                limits,
+                decision,
+                quota,
+                fallback: false,
+                skipped: false,
+                delayed: false,
+            }
+        }
+        Err(e) => {
+            error!("Rate limit check failed: {}", e);
+            #[cfg(feature = "sentry")]
+            sentry_report::redis_failure(&location, &e);
+            CheckOutcome::fallback(location, key) // エラー時は failure_mode に従う（フォールバック）
+        }
+    }
+}
+
+// Redisで判定できなかったリクエストに failure_mode を適用する
+//
+// failure_mode=local はワーカーごとのトークンバケット（local_fallback）で判定する。
+// バケットはRedisの状態と共有されないため、上限はワーカーごとに適用される
+fn apply_failure_mode(config: &RateLimitRedisConfig, outcome: CheckOutcome) -> CheckOutcome {
+    match config.failure_mode {
+        FailureMode::Open => outcome,
+        FailureMode::Closed => CheckOutcome {
+            allowed: false,
+            ..outcome
+        },
+        FailureMode::Local => {
+            let (rate, burst) = (config.requests_per_second, config.burst);
+            let decision = if rate == 0 {
+                RateLimitDecision::deny_all(config.window_size)
+            } else {
+                let (rate_per_second, capacity) = local_fallback_limits(config, rate, burst);
+                local_fallback::check(&outcome.key, rate_per_second, capacity)
+            };
+            CheckOutcome {
+                allowed: decision.allowed,
+                decision: Some(decision),
+                ..outcome
+            }
+        }
+    }
+}
+
+// failure_mode=local のトークンバケットの1秒あたりの回復量と容量
+//
+// 時間窓のアルゴリズムは window_size ごとに rate + burst 件を均等に回復させ、
+// それ以外は rate_period ごとに rate 件、容量は burst とする
+fn local_fallback_limits(config: &RateLimitRedisConfig, rate: u32, burst: u32) -> (f64, f64) {
+    match config.algorithm {
+        RateLimitAlgorithm::FixedWindow
+        | RateLimitAlgorithm::SlidingWindow
+        | RateLimitAlgorithm::SlidingLog => {
+            let limit = window_limit(rate, burst) as f64;
+            (limit / config.window_size.max(1) as f64, limit)
+        }
+        _ => (
+            rate_per_second(rate, config.rate_period),
+            bucket_capacity(burst),
+        ),
+    }
+}
+
+// Redisで上限超過と判定されたキーを共有テーブルに記録する（BANとフォールバックは対象外）
+//
+// 追加のゾーンがある場合は、どのキーが上限を超えたかを区別できないため記録しない。
+// overlimit_cache_until_reset=on では、Redisが返した再試行できるまでの時間だけ記録する
+// （overlimit_cache_ms を上限とする）
+fn remember_over_limit(config: &RateLimitRedisConfig, outcome: &CheckOutcome) {
+    if config.overlimit_cache_ms == 0
+        || config.has_extra_checks()
+        || outcome.allowed
+        || outcome.banned
+        || outcome.fallback
+    {
+        return;
+    }
+    let mut ttl = Duration::from_millis(config.overlimit_cache_ms);
+    if config.overlimit_cache_until_reset {
+        if let Some(decision) = outcome.decision {
+            let until_reset = decision.retry_after_ms.unwrap_or(decision.reset * 1000);
+            ttl = ttl.min(Duration::from_millis(until_reset));
+        }
+    }
+    if !ttl.is_zero() {
+        overlimit::mark(&outcome.location, &outcome.key, ttl);
+    }
+}
+
+// 非同期チェックの結果をリクエストに適用する
+fn finish_check(
+    r: &mut Request,
+    config: &RateLimitRedisConfig,
+    mut outcome: CheckOutcome,
+) -> Status {
+    // Redisで判定できなかった場合は failure_mode に従う（mode=mirror は本番の応答に影響しないため除く）
+    if outcome.fallback && config.mode != Mode::Mirror {
+        outcome = apply_failure_mode(config, outcome);
+    }
+
+    // on_limit=challenge: チャレンジを通過したクライアントは上限を超えても許可する（BANは除く）
+    if !outcome.allowed
+        && !outcome.banned
+        && config.on_limit == OnLimit::Challenge
+        && has_challenge_pass(r, config, &outcome.key)
+    {
+        debug!("Allowing {} with a challenge pass", outcome.key);
+        outcome.allowed = true;
+    }
+
+    // オーバーライドが適用された場合はその上限を報告する
+    let (rate, burst) = match outcome.limits {
+        Some(limits) => (limits.rate, limits.burst),
+        None => (config.requests_per_second, config.burst),
+    };
+
+    // 登録されたオブザーバーに判定結果を通知
+    // 遅延させたリクエストは遅延の前に通知済み
+    #[cfg(feature = "metrics")]
+    if !outcome.delayed && observer::has_observers() {
+        let mut event = DecisionEvent::new(
+            &outcome.location,
+            &outcome.key,
+            config.algorithm,
+            outcome.allowed,
+            rate,
+            burst,
+            outcome.fallback,
+            outcome.skipped,
+        );
+        event.banned = outcome.banned;
+        event.mirror = config.mode == Mode::Mirror;
+        observer::notify(&event);
+    }
+
+    record_variables(r, config, DecisionVariables::from_outcome(&outcome, rate));
+
+    match config.mode {
+        Mode::Auth => return finish_auth(r, config, &outcome, rate, burst),
+        Mode::Mirror => return finish_mirror(r, &outcome),
+        Mode::Filter => {}
+    }
+
+    if !outcome.allowed {
+        mark_limited(r, config);
+
+        // ブラウザからのリクエストはエラーの代わりにチャレンジページへリダイレクトする
+        if config.on_limit == OnLimit::Challenge && !outcome.banned && is_browser_request(r) {
+            let location = challenge::redirect_url(&config.challenge_url, &r.uri().to_string());
+            r.set_status(Status::MovedTemporarily);
+            r.headers_out().set("Location", &location);
+            r.headers_out().set("Cache-Control", "no-store");
+            r.write_body(b"");
+            return Status::Done;
+        }
+
+        // BAN・拒否リストは ban_status があればそれを返す
+        let status = match config.ban_status {
+            Some(status) if outcome.banned => status,
+            _ => config.reject_status,
+        };
+        r.set_status(match status {
+            RejectStatus::Forbidden => Status::Forbidden,
+            RejectStatus::TooManyRequests => Status::TooManyRequests,
+            RejectStatus::ServiceUnavailable => Status::ServiceUnavailable,
+            RejectStatus::Custom(code) => Status::from(code),
+        });
+        set_limit_headers(
+            r,
+            config,
+            (rate, burst),
+            Some(outcome.decision.map_or(0, |decision| decision.remaining)),
+            outcome.decision.map(|decision| decision.reset),
+        );
+        if let Some(decision) = outcome.decision {
+            set_retry_after(r, &decision);
+        }
+        if let Some(quota) = outcome.quota {
+            set_quota_headers(r, config, &quota);
+        }
+        r.headers_out()
+            .set("X-RateLimit-Algorithm", &config.algorithm.to_string());
+        r.headers_out()
+            .set("Content-Type", &config.reject_content_type);
+
+        if outcome.banned {
+            r.headers_out().set("X-RateLimit-Banned", "true");
+        }
+        match &config.reject_body {
+            Some(body) => r.write_body(body),
+            None if outcome.banned => r.write_body(br#"{"error": "banned"}"#),
+            None if outcome.quota.map_or(false, |quota| !quota.allowed) => {
+                r.write_body(br#"{"error": "quota exceeded"}"#)
+            }
+            None => r.write_body(br#"{"error": "rate limit exceeded"}"#),
+        }
+
+        return Status::Done;
+    }
+
+    if !outcome.delayed {
+        if let Some(delay) = limit_req_delay(config, &outcome, rate) {
+            debug!("Delaying {} by {:?}", outcome.key, delay);
+            delay_request(r, config, outcome, delay);
+            return Status::Again;
+        }
+    }
+
+    // 判定していないリクエスト（フォールバックやスキップ）にはヘッダーを付けない
+    if let Some(decision) = outcome.decision {
+        set_limit_headers(
+            r,
+            config,
+            (rate, burst),
+            Some(decision.remaining),
+            Some(decision.reset),
+        );
+    }
+    if let Some(quota) = outcome.quota {
+        set_quota_headers(r, config, &quota);
+    }
+    Status::Declined
+}
+
+// algorithm=limit_req、または throttle=on の algorithm=gcra で許可したリクエストを遅延させる時間
+//
+// limit_req と同じく、超過量のうち delay を超えた分をレートで割った時間だけ遅延させる。
+// GCRA の超過量は TAT - 現在時刻で、遅延の後のリクエストは定常レートの間隔で送られる
+fn limit_req_delay(
+    config: &RateLimitRedisConfig,
+    outcome: &CheckOutcome,
+    rate: u32,
+) -> Option<Duration> {
+    let delays = match config.algorithm {
+        RateLimitAlgorithm::LimitReq => true,
+        RateLimitAlgorithm::Gcra => config.throttle,
+        _ => false,
+    };
+    if !delays || config.nodelay {
+        return None;
+    }
+    // 超過量・delay・レートはリクエストの1/1000単位
+    let excess = outcome.decision?.excess?;
+    let delay = config.delay as u64 * 1000;
+    if excess <= delay {
+        return None;
+    }
+    let rate = limit_req_rate(rate, config.rate_period);
+    Some(Duration::from_millis((excess - delay) * 1000 / rate)).filter(|d| !d.is_zero())
+}
+
+// 遅延の後にリクエストを再実行する（待機はランタイム上で行い、イベントループをブロックしない）
+fn delay_request(
+    r: &mut Request,
+    config: &RateLimitRedisConfig,
+    outcome: CheckOutcome,
+    delay: Duration,
+) {
+    let pending = Arc::new(PendingCheck::default());
+    let ctx = match r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+        Some(ctx) => ModuleContext {
+            pending: Some(pending.clone()),
+            ..ctx.clone()
+        },
+        None => ModuleContext {
+            config: Arc::new(config.clone()),
+            pending: Some(pending.clone()),
+            c_decision: None,
+            limited: false,
+            variables: None,
+        },
+    };
+    r.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+
+    let waker = r.waker();
+    runtime().spawn(async move {
+        tokio::time::sleep(delay).await;
+        pending.complete(CheckOutcome {
+            delayed: true,
+            ..outcome
+        });
+        waker.wake();
+    });
+}
+
+// 拒否の応答に Retry-After を付ける
+//
+// 再試行できるまでの時間を返すアルゴリズム（GCRA、スライディングウィンドウログ）はその時間を
+// 秒単位に切り上げ、それ以外は制限が完全に戻るまでの秒数を使う
+fn set_retry_after(r: &mut Request, decision: &RateLimitDecision) {
+    let seconds = match decision.retry_after_ms {
+        Some(ms) => (ms + 999) / 1000,
+        None => decision.reset,
+    };
+    if seconds > 0 {
+        r.headers_out().set("Retry-After", &seconds.to_string());
+    }
+}
+
+// quota= のクォータの上限・残り・次の期間までの秒数を付ける
+//
+// クォータを使い切って拒否した場合は、次の期間が始まるまでの秒数を Retry-After にする
+fn set_quota_headers(r: &mut Request, config: &RateLimitRedisConfig, quota: &QuotaDecision) {
+    r.headers_out()
+        .set("X-Quota-Limit", &config.quota.to_string());
+    r.headers_out()
+        .set("X-Quota-Remaining", &quota.remaining.to_string());
+    r.headers_out()
+        .set("X-Quota-Reset", &quota.reset.to_string());
+    if !quota.allowed {
+        r.headers_out().set("Retry-After", &quota.reset.to_string());
+    }
+}
+
+// 上限・残りのリクエスト数・制限が戻るまでの秒数を header_format の形式で付ける
+fn set_limit_headers(
+    r: &mut Request,
+    config: &RateLimitRedisConfig,
+    (rate, burst): (u32, u32),
+    remaining: Option<u64>,
+    reset: Option<u64>,
+) {
+    if config.header_format.legacy() {
+        r.headers_out().set("X-RateLimit-Limit", &rate.to_string());
+        if let Some(remaining) = remaining {
+            r.headers_out()
+                .set("X-RateLimit-Remaining", &remaining.to_string());
+        }
+        if let Some(reset) = reset {
+            r.headers_out().set("X-RateLimit-Reset", &reset.to_string());
+        }
+    }
+    if config.header_format.ietf() {
+        r.headers_out().set("RateLimit-Limit", &rate.to_string());
+        if let Some(remaining) = remaining {
+            r.headers_out()
+                .set("RateLimit-Remaining", &remaining.to_string());
+        }
+        if let Some(reset) = reset {
+            r.headers_out().set("RateLimit-Reset", &reset.to_string());
+        }
+        r.headers_out()
+            .set("RateLimit-Policy", &ratelimit_policy(config, rate, burst));
+    }
+}
+
+// RateLimit-Policy の値（"<上限>;w=<秒数>"）
+//
+// 時間窓のアルゴリズムは window_size ごとの rate + burst 件、それ以外は rate_period ごとの
+// rate 件を上限とし、バーストを burst パラメータで示す
+fn ratelimit_policy(config: &RateLimitRedisConfig, rate: u32, burst: u32) -> String {
+    match config.algorithm {
+        RateLimitAlgorithm::FixedWindow
+        | RateLimitAlgorithm::SlidingWindow
+        | RateLimitAlgorithm::SlidingLog => {
+            format!("{};w={}", window_limit(rate, burst), config.window_size)
+        }
+        _ if burst > 0 => format!("{};w={};burst={}", rate, config.rate_period, burst),
+        _ => format!("{};w={}", rate, config.rate_period),
+    }
+}
+
+// 有効な通過Cookieを持っているか（署名はレート制限キーに対して検証する）
+fn has_challenge_pass(r: &Request, config: &RateLimitRedisConfig, key: &str) -> bool {
+    r.headers_in()
+        .find_hashed(keys::header_hash("cookie"), "cookie")
+        .and_then(|header| {
+            challenge::find_pass_cookie(&header.to_string())
+                .map(|value| challenge::verify_pass(&config.challenge_secret, key, value))
+        })
+        .unwrap_or(false)
+}
+
+// ブラウザによるページの取得か（APIクライアントにはリダイレクトせず従来どおり拒否する）
+fn is_browser_request(r: &Request) -> bool {
+    let method = r.method().to_string();
+    if method != "GET" && method != "HEAD" {
+        return false;
+    }
+    r.headers_in()
+        .find_hashed(keys::header_hash("accept"), "accept")
+        .map_or(false, |accept| accept.to_string().contains("text/html"))
+}
+
+// このモジュールが拒否したことをリクエストのコンテキストに記録する
+fn mark_limited(r: &mut Request, config: &RateLimitRedisConfig) {
+    let ctx = match r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+        Some(ctx) => ModuleContext {
+            limited: true,
+            ..ctx.clone()
+        },
+        None => ModuleContext {
+            config: Arc::new(config.clone()),
+            pending: None,
+            c_decision: None,
+            limited: true,
+            variables: None,
+        },
+    };
+    r.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+}
+
+// 判定結果を $ratelimit_redis_* 変数の値としてリクエストのコンテキストに記録する
+fn record_variables(r: &mut Request, config: &RateLimitRedisConfig, variables: DecisionVariables) {
+    let ctx = match r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+        Some(ctx) => ModuleContext {
+            variables: Some(variables),
+            ..ctx.clone()
+        },
+        None => ModuleContext {
+            config: Arc::new(config.clone()),
+            pending: None,
+            c_decision: None,
+            limited: false,
+            variables: Some(variables),
+        },
+    };
+    r.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+}
+
+// 記録された判定結果（このモジュールが判定していないリクエストではNone）
+fn decision_variables(r: &Request) -> Option<DecisionVariables> {
+    r.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+        .and_then(|ctx| ctx.variables)
+}
+
+// $ratelimit_redis_remaining: 残りのリクエスト数（判定していない場合は空）
+fn remaining_variable(r: &Request) -> Option<String> {
+    decision_variables(r)
+        .and_then(|variables| variables.remaining)
+        .map(|remaining| remaining.to_string())
+}
+
+// $ratelimit_redis_limit: 適用した上限（X-RateLimit-Limit と同じ値）
+fn limit_variable(r: &Request) -> Option<String> {
+    decision_variables(r).map(|variables| variables.limit.to_string())
+}
+
+// $ratelimit_redis_status: PASSED、DELAYED、REJECTED、BANNED、FALLBACK のいずれか
+fn status_variable(r: &Request) -> Option<String> {
+    decision_variables(r).map(|variables| variables.status.to_string())
+}
+
+// mode=auth: 判定結果をステータスとヘッダーだけで返す
+//
+// auth_request のサブリクエスト先として使用し、親リクエストは auth_request_set で
+// $sent_http_x_ratelimit_* を参照できる
+fn finish_auth(
+    r: &mut Request,
+    config: &RateLimitRedisConfig,
+    outcome: &CheckOutcome,
+    rate: u32,
+    burst: u32,
+) -> Status {
+    set_limit_headers(
+        r,
+        config,
+        (rate, burst),
+        outcome.decision.map(|decision| decision.remaining),
+        outcome.decision.map(|decision| decision.reset),
+    );
+    if let Some(decision) = outcome.decision {
+        if !outcome.allowed {
+            set_retry_after(r, &decision);
+        }
+    }
+    if let Some(quota) = outcome.quota {
+        set_quota_headers(r, config, &quota);
+    }
+    r.headers_out()
+        .set("X-RateLimit-Algorithm", &config.algorithm.to_string());
+    if outcome.banned {
+        r.headers_out().set("X-RateLimit-Banned", "true");
+    }
+
+    if outcome.allowed {
+        r.set_status(Status::NoContent);
+    } else {
+        r.set_status(Status::TooManyRequests);
+    }
+    r.write_body(b"");
+    Status::Done
+}
+
+// mode=mirror: 候補の上限での判定を記録し、常に204を返す
+//
+// mirror のサブリクエストの応答はNGINXが破棄するため、判定はログとオブザーバーで確認する
+fn finish_mirror(r: &mut Request, outcome: &CheckOutcome) -> Status {
+    if !outcome.allowed {
+        info!(
+            "Mirrored request for {} in {} would have been limited",
+            outcome.key, outcome.location
+        );
+    }
+    r.set_status(Status::NoContent);
+    r.write_body(b"");
+    Status::Done
+}
+
+/// C API: リクエストが許可された
+pub const NGX_RATELIMIT_REDIS_ALLOWED: c_int = 0;
+/// C API: 上限を超えている、またはBANされている
+pub const NGX_RATELIMIT_REDIS_LIMITED: c_int = 1;
+/// C API: 引数が不正、または判定結果がない
+pub const NGX_RATELIMIT_REDIS_ERROR: c_int = -1;
+
+/// C APIで返す判定結果（include/ngx_ratelimit_redis.h の ngx_ratelimit_redis_decision_t）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NgxRateLimitRedisDecision {
+    /// 許可された場合は1
+    pub allowed: c_int,
+    /// BANにより拒否された場合は1
+    pub banned: c_int,
+    /// Redisで判定できず許可した場合は1
+    pub fallback: c_int,
+    /// 適用されたレート（1秒あたりのリクエスト数）
+    pub limit: u32,
+    /// 残りのリクエスト数
+    pub remaining: u64,
+    /// 制限が完全に戻るまでの秒数
+    pub reset: u64,
+}
+
+impl NgxRateLimitRedisDecision {
+    fn from_outcome(outcome: &CheckOutcome) -> Self {
+        let limit = outcome
+            .limits
+            .or_else(|| location_backend(&outcome.location).map(|limiter| limiter.default_limits()))
+            .map_or(0, |limits| limits.rate);
+        Self {
+            allowed: outcome.allowed as c_int,
+            banned: outcome.banned as c_int,
+            fallback: outcome.fallback as c_int,
+            limit,
+            remaining: outcome.decision.map_or(0, |decision| decision.remaining),
+            reset: outcome.decision.map_or(0, |decision| decision.reset),
+        }
+    }
+}
+
+// C APIの文字列引数を取得する（NULLはNone）
+unsafe fn c_str_arg(arg: *const c_char) -> Result<Option<String>, ()> {
+    if arg.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map(|s| Some(s.to_string()))
+        .map_err(|_| ())
+}
+
+/// 他のNGINXモジュール（C）からキーのレート制限を判定する
+///
+/// zone には設定ファイル・ディレクティブのロケーション名を指定し、そのロケーションの
+/// 実行時の上書き設定が適用される（NULLの場合はデフォルトのリミット）。
+/// 判定結果はリクエストのコンテキストに保存され、`ngx_ratelimit_redis_last_decision` で取得できる。
+/// Redisの応答を待つ間、ワーカーのイベントループは停止する
+///
+/// # Safety
+///
+/// `r` は処理中のリクエスト、`key` と `zone` はNUL終端の文字列（zoneはNULL可）であること
+#[no_mangle]
+pub unsafe extern "C" fn ngx_ratelimit_redis_check(
+    r: *mut ngx_http_request_t,
+    key: *const c_char,
+    zone: *const c_char,
+) -> c_int {
+    if r.is_null() {
+        return NGX_RATELIMIT_REDIS_ERROR;
+    }
+    let (key, zone) = match (c_str_arg(key), c_str_arg(zone)) {
+        (Ok(Some(key)), Ok(zone)) => (key, zone.unwrap_or_default()),
+        _ => {
+            error!("ngx_ratelimit_redis_check: key must be a non-NULL UTF-8 string");
+            return NGX_RATELIMIT_REDIS_ERROR;
+        }
+    };
+
+    let outcome = runtime().block_on(check_request(
+        zone.clone(),
+        key,
+        None,
+        Vec::new(),
+        None,
+        None,
+    ));
+    let decision = NgxRateLimitRedisDecision::from_outcome(&outcome);
+    debug!(
+        "C API check for {} in {}: allowed={}",
+        outcome.key, zone, outcome.allowed
+    );
+
+    let request = Request::from_ngx_http_request(r);
+    let ctx = match request.get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module) {
+        Some(ctx) => ModuleContext {
+            c_decision: Some(decision),
+            ..ctx.clone()
+        },
+        None => ModuleContext {
+            config: config_snapshot()
+                .and_then(|snapshot| snapshot.resolve(&zone))
+                .cloned()
+                .unwrap_or_default(),
+            pending: None,
+            c_decision: Some(decision),
+            limited: false,
+            variables: None,
+        },
+    };
+    request.set_module_ctx(&ngx_ratelimit_redis_module, &ctx);
+
+    if outcome.allowed {
+        NGX_RATELIMIT_REDIS_ALLOWED
+    } else {
+        NGX_RATELIMIT_REDIS_LIMITED
+    }
+}
+
+/// リクエストのコンテキストに保存された、C APIでの最後の判定結果を取得する
+///
+/// # Safety
+///
+/// `r` は処理中のリクエスト、`decision` は書き込み可能な構造体を指していること
+#[no_mangle]
+pub unsafe extern "C" fn ngx_ratelimit_redis_last_decision(
+    r: *mut ngx_http_request_t,
+    decision: *mut NgxRateLimitRedisDecision,
+) -> c_int {
+    if r.is_null() || decision.is_null() {
+        return NGX_RATELIMIT_REDIS_ERROR;
+    }
+    let request = Request::from_ngx_http_request(r);
+    match request
+        .get_module_ctx::<ModuleContext>(&ngx_ratelimit_redis_module)
+        .and_then(|ctx| ctx.c_decision)
+    {
+        Some(last) => {
+            *decision = last;
+            if last.allowed != 0 {
+                NGX_RATELIMIT_REDIS_ALLOWED
+            } else {
+                NGX_RATELIMIT_REDIS_LIMITED
+            }
+        }
+        None => NGX_RATELIMIT_REDIS_ERROR,
+    }
+}
+
+// モジュールコマンドの登録
+#[nginx_handler]
+async fn http_preinit(cmcf: &mut HttpMainConf) -> Result<(), String> {
+    let ratelimit_cmd = HttpCommand::new(ratelimit_redis_command);
+    cmcf.register_command("ratelimit_redis", ratelimit_cmd)?;
+
+    let config_cmd = HttpCommand::new(ratelimit_redis_config_command);
+    cmcf.register_command("ratelimit_redis_config", config_cmd)?;
+
+    let check_cmd = HttpCommand::new(ratelimit_redis_check_command);
+    cmcf.register_command("ratelimit_redis_check", check_cmd)?;
+
+    let ban_store_cmd = HttpCommand::new(ratelimit_redis_ban_store_command);
+    cmcf.register_command("ratelimit_redis_ban_store", ban_store_cmd)?;
+
+    let jwt_cmd = HttpCommand::new(ratelimit_redis_jwt_command);
+    cmcf.register_command("ratelimit_redis_jwt", jwt_cmd)?;
+
+    #[cfg(feature = "admin")]
+    {
+        let admin_cmd = HttpCommand::new(ratelimit_redis_admin_command);
+        cmcf.register_command("ratelimit_redis_admin", admin_cmd)?;
+    }
+
+    #[cfg(feature = "kafka")]
+    {
+        let kafka_cmd = HttpCommand::new(ratelimit_redis_kafka_command);
+        cmcf.register_command("ratelimit_redis_kafka", kafka_cmd)?;
+    }
+
+    #[cfg(feature = "sentry")]
+    {
+        let sentry_cmd = HttpCommand::new(ratelimit_redis_sentry_command);
+        cmcf.register_command("ratelimit_redis_sentry", sentry_cmd)?;
+    }
+
+    #[cfg(feature = "config-source")]
+    {
+        let source_cmd = HttpCommand::new(ratelimit_redis_config_source_command);
+        cmcf.register_command("ratelimit_redis_config_source", source_cmd)?;
+    }
+
+    #[cfg(feature = "introspection")]
+    {
+        let introspection_cmd = HttpCommand::new(ratelimit_redis_introspection_command);
+        cmcf.register_command("ratelimit_redis_introspection", introspection_cmd)?;
+    }
+
+    #[cfg(feature = "fault-injection")]
+    {
+        let fault_cmd = HttpCommand::new(ratelimit_redis_fault_command);
+        cmcf.register_command("ratelimit_redis_fault", fault_cmd)?;
+    }
+
+    Ok(())
+}
+
+#[nginx_module_export]
+static mut ngx_ratelimit_redis_commands: [Command; 1] = [Command::HttpMain(http_preinit)];
+
+#[nginx_module_init]
+static mut NGX_HTTP_MODULE: HttpModule =
+    HttpModule::new(module_init, module_exit, http_init, Some(worker_init), None);
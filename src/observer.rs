@@ -0,0 +1,130 @@
+use lazy_static::lazy_static;
+use log::error;
+use serde::Serialize;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::redis_client::RateLimitAlgorithm;
+
+/// レート制限の判定結果を表すイベント
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionEvent {
+    /// 判定が行われたLocationのパス
+    pub location: String,
+    /// レート制限キー（IPアドレスやAPIキーなど）
+    pub key: String,
+    /// 使用されたアルゴリズム
+    pub algorithm: String,
+    /// リクエストが許可されたかどうか
+    pub allowed: bool,
+    /// キーまたはクライアントIPがBANされていたため拒否したかどうか
+    pub banned: bool,
+    /// 1秒あたりの最大リクエスト数
+    pub limit: u32,
+    /// バースト値
+    pub burst: u32,
+    /// 判定がRedisエラーによるフォールバックかどうか
+    pub fallback: bool,
+    /// Redisへの問い合わせを省略した判定かどうか（チェックの滞留やレイテンシ予算の超過）
+    pub skipped: bool,
+    /// ミラーされたリクエストを候補の上限で評価した判定かどうか（mode=mirror、実際には拒否しない）
+    pub mirror: bool,
+    /// 判定時刻（UNIXエポックからのミリ秒）
+    pub timestamp_ms: u64,
+}
+
+impl DecisionEvent {
+    pub fn new(
+        location: &str,
+        key: &str,
+        algorithm: RateLimitAlgorithm,
+        allowed: bool,
+        limit: u32,
+        burst: u32,
+        fallback: bool,
+        skipped: bool,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            location: location.to_string(),
+            key: key.to_string(),
+            algorithm: algorithm.to_string(),
+            allowed,
+            banned: false,
+            limit,
+            burst,
+            fallback,
+            skipped,
+            mirror: false,
+            timestamp_ms,
+        }
+    }
+}
+
+/// 判定イベントを受け取るオブザーバー
+///
+/// フォークやコンパニオンクレートはこのトレイトを実装して`register_observer`で登録することで、
+/// ハンドラを変更せずに独自のテレメトリ送信先（Kafka、ClickHouseなど）を追加できる。
+/// `on_decision`はリクエスト処理中に同期的に呼ばれるため、重い処理はキューに積んで別スレッドで行うこと。
+pub trait DecisionObserver: Send + Sync {
+    /// 判定ごとに呼ばれる
+    fn on_decision(&self, event: &DecisionEvent);
+}
+
+/// クロージャをそのままオブザーバーとして使えるようにする
+impl<F> DecisionObserver for F
+where
+    F: Fn(&DecisionEvent) + Send + Sync,
+{
+    fn on_decision(&self, event: &DecisionEvent) {
+        self(event)
+    }
+}
+
+lazy_static! {
+    static ref OBSERVERS: RwLock<Vec<Arc<dyn DecisionObserver>>> = RwLock::new(Vec::new());
+}
+
+/// オブザーバーを登録する
+pub fn register_observer(observer: Arc<dyn DecisionObserver>) {
+    match OBSERVERS.write() {
+        Ok(mut observers) => observers.push(observer),
+        Err(e) => error!("Failed to register decision observer: {}", e),
+    }
+}
+
+/// 登録済みのオブザーバーをすべて削除する
+pub fn clear_observers() {
+    if let Ok(mut observers) = OBSERVERS.write() {
+        observers.clear();
+    }
+}
+
+/// オブザーバーが1つでも登録されているか
+pub fn has_observers() -> bool {
+    OBSERVERS.read().map(|o| !o.is_empty()).unwrap_or(false)
+}
+
+/// 登録済みのすべてのオブザーバーにイベントを通知する
+///
+/// オブザーバー内のパニックはリクエスト処理に影響しないよう捕捉してログに出力する
+pub fn notify(event: &DecisionEvent) {
+    let observers = match OBSERVERS.read() {
+        Ok(observers) => observers,
+        Err(e) => {
+            error!("Failed to read decision observers: {}", e);
+            return;
+        }
+    };
+
+    for observer in observers.iter() {
+        if catch_unwind(AssertUnwindSafe(|| observer.on_decision(event))).is_err() {
+            error!("Decision observer panicked while handling event");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::config::RouteSettings;
+
+/// OpenAPIの拡張フィールド名
+pub const EXTENSION: &str = "x-ratelimit";
+
+// Path Item のうちオペレーションを表すキー
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// OpenAPI仕様（JSONまたはYAML）を読み込み、x-ratelimit を持つオペレーションのルート表を生成する
+///
+/// x-ratelimit は {"rate": 10, "burst": 5} の形式で、オペレーションごとに記述する
+pub fn load_routes<P: AsRef<Path>>(path: P) -> Result<Vec<RouteSettings>, String> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read OpenAPI spec {:?}: {}", path, e))?;
+
+    let is_yaml = matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("yaml") | Some("yml")
+    );
+    let spec: Value = if is_yaml {
+        serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse OpenAPI spec {:?}: {}", path, e))?
+    } else {
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse OpenAPI spec {:?}: {}", path, e))?
+    };
+
+    routes_from_spec(&spec)
+}
+
+/// 解析済みのOpenAPI仕様からルート表を生成する
+pub fn routes_from_spec(spec: &Value) -> Result<Vec<RouteSettings>, String> {
+    let base_path = base_path(spec);
+    let paths = match spec.get("paths").and_then(Value::as_object) {
+        Some(paths) => paths,
+        None => return Err("OpenAPI spec has no paths".to_string()),
+    };
+
+    let mut routes = Vec::new();
+    for (path, item) in paths {
+        for method in METHODS {
+            let extension = match item.get(method).and_then(|op| op.get(EXTENSION)) {
+                Some(extension) => extension,
+                None => continue,
+            };
+            let rate = extension
+                .get("rate")
+                .and_then(Value::as_u64)
+                .filter(|rate| *rate > 0 && *rate <= u32::MAX as u64)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid {} rate for {} {}",
+                        EXTENSION,
+                        method.to_ascii_uppercase(),
+                        path
+                    )
+                })?;
+            let burst = match extension.get("burst") {
+                Some(burst) => burst
+                    .as_u64()
+                    .filter(|burst| *burst <= u32::MAX as u64)
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid {} burst for {} {}",
+                            EXTENSION,
+                            method.to_ascii_uppercase(),
+                            path
+                        )
+                    })?,
+                None => 0,
+            };
+
+            routes.push(RouteSettings {
+                method: method.to_ascii_uppercase(),
+                path: format!("{}{}", base_path, path),
+                rate: rate as u32,
+                burst: burst as u32,
+            });
+        }
+    }
+    Ok(routes)
+}
+
+// servers の先頭のURLのパス部分（"https://api.example.com/v1" なら "/v1"）
+fn base_path(spec: &Value) -> String {
+    let url = match spec
+        .get("servers")
+        .and_then(|servers| servers.get(0))
+        .and_then(|server| server.get("url"))
+        .and_then(Value::as_str)
+    {
+        Some(url) => url,
+        None => return String::new(),
+    };
+
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+        None => url,
+    };
+    path.trim_end_matches('/').to_string()
+}
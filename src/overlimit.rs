@@ -0,0 +1,142 @@
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 共有テーブルのスロット数
+const SLOTS: usize = 16384;
+
+/// 1つのキーに対して探索するスロット数
+const PROBES: usize = 8;
+
+// 上限超過中のキー1件（hashが0のスロットは空き）
+#[repr(C)]
+struct Slot {
+    hash: AtomicU64,
+    expires_ms: AtomicU64,
+}
+
+// 全ワーカーで共有される上限超過キーのテーブル
+struct SharedTable {
+    slots: &'static [Slot],
+}
+
+lazy_static! {
+    static ref TABLE: Option<SharedTable> = SharedTable::map();
+}
+
+impl SharedTable {
+    // 匿名共有メモリを確保する（フォークしたワーカーからも同じ領域が見える）
+    fn map() -> Option<Self> {
+        let size = SLOTS * std::mem::size_of::<Slot>();
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            error!(
+                "Failed to map over-limit table: {}",
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+
+        // mmapした領域はゼロで初期化されるため、全スロットが空きの状態になる
+        info!("Over-limit table mapped: {} slots ({} bytes)", SLOTS, size);
+        Some(Self {
+            slots: unsafe { std::slice::from_raw_parts(ptr as *const Slot, SLOTS) },
+        })
+    }
+}
+
+/// 共有テーブルを確保する
+///
+/// ワーカー間で共有するため、フォーク前のマスタープロセス（設定の読み込み時）に呼び出す
+pub(crate) fn init() {
+    lazy_static::initialize(&TABLE);
+}
+
+/// キーが上限超過中として記録されていれば、記録が切れるまでの時間を返す
+pub(crate) fn over_limit_for(location: &str, key: &str) -> Option<Duration> {
+    let table = TABLE.as_ref()?;
+
+    let hash = hash_key(location, key);
+    let now = now_ms();
+    probe(hash).find_map(|index| {
+        let slot = &table.slots[index];
+        let expires = slot.expires_ms.load(Ordering::Acquire);
+        (slot.hash.load(Ordering::Acquire) == hash && expires > now)
+            .then(|| Duration::from_millis(expires - now))
+    })
+}
+
+/// キーを上限超過中として記録する
+///
+/// テーブルが埋まっている場合は、探索範囲内で最も早く期限が切れるエントリを置き換える
+pub(crate) fn mark(location: &str, key: &str, ttl: Duration) {
+    let table = match &*TABLE {
+        Some(table) => table,
+        None => return,
+    };
+
+    let hash = hash_key(location, key);
+    let now = now_ms();
+    let expires_ms = now + ttl.as_millis() as u64;
+
+    let mut target = None;
+    let mut earliest = u64::MAX;
+    for index in probe(hash) {
+        let slot = &table.slots[index];
+        if slot.hash.load(Ordering::Acquire) == hash {
+            target = Some(index);
+            break;
+        }
+        let expires = slot.expires_ms.load(Ordering::Acquire);
+        if expires <= now {
+            if earliest > 0 {
+                target = Some(index);
+                earliest = 0;
+            }
+        } else if expires < earliest {
+            target = Some(index);
+            earliest = expires;
+        }
+    }
+
+    if let Some(index) = target {
+        let slot = &table.slots[index];
+        // 書き換え中のスロットが別のキーとして一致しないよう、先に期限を無効にする
+        slot.expires_ms.store(0, Ordering::Release);
+        slot.hash.store(hash, Ordering::Release);
+        slot.expires_ms.store(expires_ms, Ordering::Release);
+    }
+}
+
+// キーが格納され得るスロットの位置
+fn probe(hash: u64) -> impl Iterator<Item = usize> {
+    let start = (hash as usize) % SLOTS;
+    (0..PROBES).map(move |i| (start + i) % SLOTS)
+}
+
+// ロケーションとキーのハッシュ（0は空きスロットを表すため使用しない）
+fn hash_key(location: &str, key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    location.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish().max(1)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,117 @@
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::RateLimitBackend;
+use crate::redis_client::LimitOverride;
+
+/// Redisに保存された上書き設定をローカルに保持する時間
+///
+/// 全エッジが数秒以内に同じ上書きを適用しつつ、リクエストごとのRedis往復を避ける
+const OVERRIDE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// キーごとのリミットをキャッシュする件数がこれを超えたら、期限切れのものを削除する
+const KEY_LIMITS_PRUNE_THRESHOLD: usize = 100_000;
+
+lazy_static! {
+    static ref OVERRIDE_CACHE: Mutex<HashMap<String, (Instant, Option<LimitOverride>)>> =
+        Mutex::new(HashMap::new());
+    // キーごとのリミット（key_limits=on）。上書き設定と同じ時間だけ保持する
+    static ref KEY_LIMITS_CACHE: Mutex<HashMap<String, (Instant, Option<LimitOverride>)>> =
+        Mutex::new(HashMap::new());
+}
+
+// キャッシュが有効であれば上書き設定を返す
+fn cached(location: &str) -> Option<Option<LimitOverride>> {
+    let cache = OVERRIDE_CACHE.lock().ok()?;
+    match cache.get(location) {
+        Some((fetched_at, limits)) if fetched_at.elapsed() < OVERRIDE_CACHE_TTL => Some(*limits),
+        _ => None,
+    }
+}
+
+fn store(location: &str, limits: Option<LimitOverride>) {
+    if let Ok(mut cache) = OVERRIDE_CACHE.lock() {
+        let previous = cache
+            .insert(location.to_string(), (Instant::now(), limits))
+            .and_then(|(_, prev)| prev);
+        if previous != limits {
+            match limits {
+                Some(l) => info!(
+                    "Applying limit override for {}: rate={}, burst={}",
+                    location, l.rate, l.burst
+                ),
+                None => info!("Limit override for {} removed", location),
+            }
+        }
+    }
+}
+
+/// Locationに適用すべき上書き設定を返す
+///
+/// Redisの読み込みに失敗した場合は直前の値を使い続け、次回のリクエストで再取得する
+pub async fn resolve(limiter: &dyn RateLimitBackend, location: &str) -> Option<LimitOverride> {
+    if let Some(limits) = cached(location) {
+        return limits;
+    }
+
+    match limiter.get_limit_override(location).await {
+        Ok(limits) => {
+            store(location, limits);
+            limits
+        }
+        Err(e) => {
+            error!("Failed to refresh limit override for {}: {}", location, e);
+            OVERRIDE_CACHE
+                .lock()
+                .ok()
+                .and_then(|cache| cache.get(location).and_then(|(_, limits)| *limits))
+        }
+    }
+}
+
+/// 管理APIで変更された上書き設定をこのワーカーに即時反映する
+pub fn invalidate(location: &str) {
+    if let Ok(mut cache) = OVERRIDE_CACHE.lock() {
+        cache.remove(location);
+    }
+}
+
+/// キーに設定されたリミットを返す（key_limits=on）
+///
+/// 上書き設定と同じく一定時間ローカルに保持し、Redisの読み込みに失敗した場合は直前の値を使い続ける
+pub async fn resolve_key(limiter: &dyn RateLimitBackend, key: &str) -> Option<LimitOverride> {
+    if let Ok(cache) = KEY_LIMITS_CACHE.lock() {
+        if let Some((fetched_at, limits)) = cache.get(key) {
+            if fetched_at.elapsed() < OVERRIDE_CACHE_TTL {
+                return *limits;
+            }
+        }
+    }
+
+    match limiter.get_key_limits(key).await {
+        Ok(limits) => {
+            if let Ok(mut cache) = KEY_LIMITS_CACHE.lock() {
+                if cache.len() >= KEY_LIMITS_PRUNE_THRESHOLD {
+                    cache.retain(|_, (fetched_at, _)| fetched_at.elapsed() < OVERRIDE_CACHE_TTL);
+                }
+                let previous = cache
+                    .insert(key.to_string(), (Instant::now(), limits))
+                    .and_then(|(_, prev)| prev);
+                if previous != limits {
+                    debug!("Key limits for {} changed: {:?}", key, limits);
+                }
+            }
+            limits
+        }
+        Err(e) => {
+            error!("Failed to refresh key limits for {}: {}", key, e);
+            KEY_LIMITS_CACHE
+                .lock()
+                .ok()
+                .and_then(|cache| cache.get(key).and_then(|(_, limits)| *limits))
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::module::CheckOutcome;
+
+/// 先読みの状態を保持するキー数の上限
+const MAX_ENTRIES: usize = 10000;
+
+// ロケーション・キー・クライアントIPの組（CIDR BANはクライアントIPごとに判定されるため含める）
+type EntryKey = (String, String, Option<IpAddr>);
+
+// キーごとの先読み状態
+#[derive(Debug)]
+struct Entry {
+    last_seen: Instant,
+    decision: Option<(Instant, CheckOutcome)>,
+    in_flight: bool,
+}
+
+lazy_static! {
+    static ref ENTRIES: Mutex<HashMap<EntryKey, Entry>> = Mutex::new(HashMap::new());
+}
+
+/// 鮮度の範囲内にある先読み済みの判定を取り出す
+///
+/// 先読みの判定はRedis上で1リクエスト分としてカウント済みのため、1つの判定は1リクエストにのみ使用する
+pub(crate) fn take(
+    location: &str,
+    key: &str,
+    client_ip: Option<IpAddr>,
+    staleness: Duration,
+) -> Option<CheckOutcome> {
+    let mut entries = ENTRIES.lock().unwrap();
+    let entry = entries.get_mut(&(location.to_string(), key.to_string(), client_ip))?;
+    match entry.decision.take() {
+        Some((at, outcome)) if at.elapsed() <= staleness => Some(outcome),
+        _ => None,
+    }
+}
+
+/// リクエストを記録し、次のリクエストに向けて判定を先読みすべきかを返す
+///
+/// 前回のリクエストから鮮度の範囲内に再びリクエストされたキーをホットキーとみなす。
+/// trueを返した場合、呼び出し側は先読みを行い、結果を`store`に渡す必要がある
+pub(crate) fn begin(
+    location: &str,
+    key: &str,
+    client_ip: Option<IpAddr>,
+    staleness: Duration,
+) -> bool {
+    let mut entries = ENTRIES.lock().unwrap();
+    let now = Instant::now();
+    let entry_key = (location.to_string(), key.to_string(), client_ip);
+
+    if !entries.contains_key(&entry_key) && entries.len() >= MAX_ENTRIES {
+        entries.retain(|_, entry| entry.in_flight || entry.last_seen.elapsed() <= staleness);
+        if entries.len() >= MAX_ENTRIES {
+            return false;
+        }
+    }
+
+    match entries.get_mut(&entry_key) {
+        Some(entry) => {
+            let hot = now.duration_since(entry.last_seen) <= staleness;
+            entry.last_seen = now;
+            if hot && !entry.in_flight && entry.decision.is_none() {
+                entry.in_flight = true;
+                true
+            } else {
+                false
+            }
+        }
+        None => {
+            entries.insert(
+                entry_key,
+                Entry {
+                    last_seen: now,
+                    decision: None,
+                    in_flight: false,
+                },
+            );
+            false
+        }
+    }
+}
+
+/// 先読みの結果を保存する（Noneの場合は先読みの中止として扱う）
+pub(crate) fn store(
+    location: &str,
+    key: &str,
+    client_ip: Option<IpAddr>,
+    outcome: Option<CheckOutcome>,
+) {
+    let mut entries = ENTRIES.lock().unwrap();
+    if let Some(entry) = entries.get_mut(&(location.to_string(), key.to_string(), client_ip)) {
+        entry.in_flight = false;
+        entry.decision = outcome.map(|outcome| (Instant::now(), outcome));
+    }
+}
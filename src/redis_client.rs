@@ -1,8 +1,24 @@
-use log::{debug, error, info};
-use redis::{aio::Connection, AsyncCommands, Client, RedisError};
+use log::{debug, error, info, warn};
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::{AsyncCommands, Client, IntoConnectionInfo, RedisError};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::ban::BanPolicy;
+use crate::capabilities::{ExecutionMode, RedisCapabilities};
+use crate::clock::{civil_from_days, days_from_civil, Clock, SystemClock};
+use crate::executor;
+#[cfg(feature = "fault-injection")]
+use crate::fault::inject as with_faults;
+use crate::scripts;
+
 /// レート制限アルゴリズムの種類
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RateLimitAlgorithm {
@@ -14,6 +30,12 @@ pub enum RateLimitAlgorithm {
     TokenBucket,
     /// リーキーバケット: 一定レートでリクエストを処理し、超過リクエストはキューに入る
     LeakyBucket,
+    /// limit_req互換: ngx_http_limit_req_module と同じ計算で許可・拒否・遅延を判定する
+    LimitReq,
+    /// GCRA: 理論上の到着時刻だけを保持し、リクエストの間隔を均等に保つ。拒否時は再試行できるまでの時間を返す
+    Gcra,
+    /// スライディングウィンドウログ: 許可したリクエストの時刻を記録し、直近の時間窓のリクエスト数を正確に数える
+    SlidingLog,
 }
 
 impl Default for RateLimitAlgorithm {
@@ -29,24 +51,133 @@ impl std::fmt::Display for RateLimitAlgorithm {
             RateLimitAlgorithm::SlidingWindow => write!(f, "sliding_window"),
             RateLimitAlgorithm::TokenBucket => write!(f, "token_bucket"),
             RateLimitAlgorithm::LeakyBucket => write!(f, "leaky_bucket"),
+            RateLimitAlgorithm::LimitReq => write!(f, "limit_req"),
+            RateLimitAlgorithm::Gcra => write!(f, "gcra"),
+            RateLimitAlgorithm::SlidingLog => write!(f, "sliding_log"),
         }
     }
 }
 
 impl RateLimitAlgorithm {
     pub fn from_str(s: &str) -> Result<Self, String> {
+        let algorithm = match s.to_lowercase().as_str() {
+            "fixed_window" => RateLimitAlgorithm::FixedWindow,
+            "sliding_window" => RateLimitAlgorithm::SlidingWindow,
+            "token_bucket" => RateLimitAlgorithm::TokenBucket,
+            "leaky_bucket" => RateLimitAlgorithm::LeakyBucket,
+            "limit_req" => RateLimitAlgorithm::LimitReq,
+            "gcra" => RateLimitAlgorithm::Gcra,
+            "sliding_log" => RateLimitAlgorithm::SlidingLog,
+            _ => return Err(format!("Unknown rate limit algorithm: {}", s)),
+        };
+
+        if !algorithm.is_compiled_in() {
+            return Err(format!(
+                "Rate limit algorithm {} is not available in this build (enable the '{}' feature)",
+                algorithm,
+                algorithm.feature_name()
+            ));
+        }
+        Ok(algorithm)
+    }
+
+    /// このアルゴリズムを有効にするCargoフィーチャー名
+    pub fn feature_name(&self) -> &'static str {
+        match self {
+            RateLimitAlgorithm::FixedWindow => "algo-fixed-window",
+            RateLimitAlgorithm::SlidingWindow => "algo-sliding-window",
+            RateLimitAlgorithm::TokenBucket => "algo-token-bucket",
+            RateLimitAlgorithm::LeakyBucket => "algo-leaky-bucket",
+            RateLimitAlgorithm::LimitReq => "algo-limit-req",
+            RateLimitAlgorithm::Gcra => "algo-gcra",
+            RateLimitAlgorithm::SlidingLog => "algo-sliding-log",
+        }
+    }
+
+    /// ビルドにこのアルゴリズムが含まれているか
+    pub fn is_compiled_in(&self) -> bool {
+        match self {
+            RateLimitAlgorithm::FixedWindow => cfg!(feature = "algo-fixed-window"),
+            RateLimitAlgorithm::SlidingWindow => cfg!(feature = "algo-sliding-window"),
+            RateLimitAlgorithm::TokenBucket => cfg!(feature = "algo-token-bucket"),
+            RateLimitAlgorithm::LeakyBucket => cfg!(feature = "algo-leaky-bucket"),
+            RateLimitAlgorithm::LimitReq => cfg!(feature = "algo-limit-req"),
+            RateLimitAlgorithm::Gcra => cfg!(feature = "algo-gcra"),
+            RateLimitAlgorithm::SlidingLog => cfg!(feature = "algo-sliding-log"),
+        }
+    }
+}
+
+/// クォータを数える暦の期間（UTC）
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum QuotaPeriod {
+    /// 0時（UTC）に切り替わる1日
+    #[default]
+    Day,
+    /// 1日の0時（UTC）に切り替わる1か月
+    Month,
+}
+
+impl std::fmt::Display for QuotaPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaPeriod::Day => write!(f, "day"),
+            QuotaPeriod::Month => write!(f, "month"),
+        }
+    }
+}
+
+impl std::str::FromStr for QuotaPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
         match s.to_lowercase().as_str() {
-            "fixed_window" => Ok(RateLimitAlgorithm::FixedWindow),
-            "sliding_window" => Ok(RateLimitAlgorithm::SlidingWindow),
-            "token_bucket" => Ok(RateLimitAlgorithm::TokenBucket),
-            "leaky_bucket" => Ok(RateLimitAlgorithm::LeakyBucket),
-            _ => Err(format!("Unknown rate limit algorithm: {}", s)),
+            "day" => Ok(QuotaPeriod::Day),
+            "month" => Ok(QuotaPeriod::Month),
+            _ => Err(format!(
+                "Unknown quota period: {} (expected day or month)",
+                s
+            )),
         }
     }
 }
 
+impl QuotaPeriod {
+    /// now（UNIXエポックからの秒数）を含む期間の開始と終了（UNIXエポックからの秒数）
+    pub fn window(&self, now: u64) -> (u64, u64) {
+        let days = (now / 86400) as i64;
+        let (start, end) = match self {
+            QuotaPeriod::Day => (days, days + 1),
+            QuotaPeriod::Month => {
+                let (year, month, _) = civil_from_days(days);
+                let (next_year, next_month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                (
+                    days_from_civil(year, month, 1),
+                    days_from_civil(next_year, next_month, 1),
+                )
+            }
+        };
+        (start as u64 * 86400, end as u64 * 86400)
+    }
+}
+
+/// クォータ1回分の判定結果
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QuotaDecision {
+    /// クォータ内のリクエストか
+    pub allowed: bool,
+    /// 期間内に残っているリクエスト数
+    pub remaining: u64,
+    /// 次の期間が始まるまでの秒数
+    pub reset: u64,
+}
+
 /// Redis接続のオプションを設定するための構造体
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RedisConnectionOptions {
     /// 接続タイムアウト（ミリ秒）
     #[serde(default = "default_connect_timeout")]
@@ -84,9 +215,79 @@ pub struct RedisConnectionOptions {
     #[serde(default)]
     pub tls_enabled: bool,
 
+    /// サーバー証明書を検証するCA証明書のファイル（PEM、空の場合はシステムのルート証明書）
+    #[serde(default)]
+    pub tls_ca: String,
+
+    /// 相互TLSで提示するクライアント証明書のファイル（PEM）
+    #[serde(default)]
+    pub tls_cert: String,
+
+    /// クライアント証明書の秘密鍵のファイル（PEM）
+    #[serde(default)]
+    pub tls_key: String,
+
+    /// サーバー証明書を検証しない（検証環境用）
+    #[serde(default)]
+    pub tls_insecure: bool,
+
     /// キープアライブ間隔（秒、0の場合は無効）
     #[serde(default)]
     pub keepalive: u64,
+
+    /// サーキットブレーカーを開く、続けて失敗した接続・コマンドの回数（0の場合は無効）
+    #[serde(default = "default_breaker_failures")]
+    pub breaker_failures: u32,
+
+    /// サーキットブレーカーが開いてから回復を確認するまでの時間（ミリ秒）
+    #[serde(default = "default_breaker_cooldown")]
+    pub breaker_cooldown: u64,
+
+    /// 半開きの状態で回復の確認に通す問い合わせの数
+    #[serde(default = "default_breaker_probes")]
+    pub breaker_probes: u32,
+
+    /// Redisへのコマンドの送り方（"none"、"scripts" または "proxy"）
+    #[serde(default)]
+    pub compat: RedisCompat,
+}
+
+/// Redisへのコマンドの送り方
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisCompat {
+    /// サーバーの機能から選ぶ（Redis Functions、EVALSHA、単一キーのコマンドの順）
+    #[default]
+    None,
+    /// Luaスクリプト（EVALSHA）で判定する（Redis Functions に対応していても使わない）
+    Scripts,
+    /// 単一キーのコマンドのみで判定する（Twemproxy などEVALに対応しないプロキシ用）
+    Proxy,
+}
+
+impl std::fmt::Display for RedisCompat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RedisCompat::None => write!(f, "none"),
+            RedisCompat::Scripts => write!(f, "scripts"),
+            RedisCompat::Proxy => write!(f, "proxy"),
+        }
+    }
+}
+
+impl RedisCompat {
+    /// "none"、"scripts"、"proxy" を解析する
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "none" => Ok(RedisCompat::None),
+            "scripts" => Ok(RedisCompat::Scripts),
+            "proxy" => Ok(RedisCompat::Proxy),
+            _ => Err(format!(
+                "Invalid compat value (expected none, scripts or proxy): {}",
+                value
+            )),
+        }
+    }
 }
 
 impl Default for RedisConnectionOptions {
@@ -101,8 +302,116 @@ impl Default for RedisConnectionOptions {
             pool_size: default_pool_size(),
             cluster_mode: false,
             tls_enabled: false,
+            tls_ca: String::new(),
+            tls_cert: String::new(),
+            tls_key: String::new(),
+            tls_insecure: false,
             keepalive: 0,
+            breaker_failures: default_breaker_failures(),
+            breaker_cooldown: default_breaker_cooldown(),
+            breaker_probes: default_breaker_probes(),
+            compat: RedisCompat::None,
+        }
+    }
+}
+
+impl RedisConnectionOptions {
+    /// TLSで接続するか（tls_enabled または rediss:// のURL）
+    pub fn uses_tls(&self, redis_url: &str) -> bool {
+        self.tls_enabled
+            || redis_url
+                .split(',')
+                .any(|url| url.trim().starts_with("rediss://"))
+    }
+
+    /// サーキットブレーカーの指定を検証する
+    pub fn validate_breaker(&self) -> Result<(), String> {
+        if self.breaker_failures > 0 && self.breaker_cooldown == 0 {
+            return Err("breaker_cooldown must be greater than 0".to_string());
+        }
+        if self.breaker_failures > 0 && self.breaker_probes == 0 {
+            return Err("breaker_probes must be greater than 0".to_string());
         }
+        Ok(())
+    }
+
+    /// TLSの証明書と鍵の指定を検証する
+    pub fn validate_tls(&self, redis_url: &str) -> Result<(), String> {
+        if self.tls_cert.is_empty() != self.tls_key.is_empty() {
+            return Err("tls_cert and tls_key must be set together".to_string());
+        }
+        let configured = !self.tls_ca.is_empty() || !self.tls_cert.is_empty() || self.tls_insecure;
+        if configured && !self.uses_tls(redis_url) {
+            return Err(
+                "tls_ca, tls_cert, tls_key and tls_insecure require tls_enabled or a rediss:// URL"
+                    .to_string(),
+            );
+        }
+        // クラスタのノードへの接続は redis クレートが作るため、証明書を渡せない
+        if self.cluster_mode && (!self.tls_ca.is_empty() || !self.tls_cert.is_empty()) {
+            return Err(
+                "tls_ca, tls_cert and tls_key are not supported with cluster_mode".to_string(),
+            );
+        }
+        for (name, path) in [
+            ("tls_ca", &self.tls_ca),
+            ("tls_cert", &self.tls_cert),
+            ("tls_key", &self.tls_key),
+        ] {
+            if !path.is_empty() {
+                std::fs::metadata(path)
+                    .map_err(|e| format!("Cannot read {} {}: {}", name, path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // tls_enabled の場合は redis:// を rediss:// にし、tls_insecure の場合は証明書を検証しない指定（#insecure）を加える
+    fn tls_url(&self, url: &str) -> String {
+        let mut url = match url.strip_prefix("redis://") {
+            Some(rest) if self.tls_enabled => format!("rediss://{}", rest),
+            _ => url.to_string(),
+        };
+        if self.tls_insecure && url.starts_with("rediss://") && !url.ends_with("#insecure") {
+            url.push_str("#insecure");
+        }
+        url
+    }
+
+    // tls_ca・tls_cert・tls_key のファイルを読み込み、TLSのコネクタを作る（いずれも指定されていない場合はNone）
+    #[cfg(feature = "tls")]
+    fn tls_connector(&self) -> Result<Option<tokio_native_tls::TlsConnector>, String> {
+        if self.tls_ca.is_empty() && self.tls_cert.is_empty() {
+            return Ok(None);
+        }
+        let read = |name: &str, path: &str| {
+            std::fs::read(path).map_err(|e| format!("Failed to read {} {}: {}", name, path, e))
+        };
+        let mut builder = native_tls::TlsConnector::builder();
+        if !self.tls_ca.is_empty() {
+            let ca = native_tls::Certificate::from_pem(&read("tls_ca", &self.tls_ca)?)
+                .map_err(|e| format!("Invalid tls_ca {}: {}", self.tls_ca, e))?;
+            builder.add_root_certificate(ca);
+        }
+        if !self.tls_cert.is_empty() {
+            // 秘密鍵は PKCS#8 の PEM（BEGIN PRIVATE KEY）で指定する
+            let identity = native_tls::Identity::from_pkcs8(
+                &read("tls_cert", &self.tls_cert)?,
+                &read("tls_key", &self.tls_key)?,
+            )
+            .map_err(|e| format!("Invalid tls_cert or tls_key: {}", e))?;
+            builder.identity(identity);
+        }
+        if self.tls_insecure {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        builder
+            .build()
+            .map(Into::into)
+            .map_err(|e| format!("Failed to create TLS connector: {}", e))
+            .map(Some)
     }
 }
 
@@ -131,6 +440,445 @@ fn default_pool_size() -> u32 {
     10
 }
 
+fn default_breaker_failures() -> u32 {
+    3
+}
+
+fn default_breaker_cooldown() -> u64 {
+    1000 // 1秒
+}
+
+fn default_breaker_probes() -> u32 {
+    1
+}
+
+/// Redisキーの共通プレフィックス
+pub const KEY_PREFIX: &str = "ratelimit";
+
+/// Redisキーのスキーマバージョン
+///
+/// キーの形式を変更した場合はこの値と`KEY_NAMESPACE`を更新し、`migrate_keys`で旧形式から移行する。
+/// v3 ではレート制限キーをハッシュタグ（`{<キー>}`）で囲み、同じキーのカウンタとBANを
+/// Redis Cluster の同じハッシュスロットに置く
+pub const KEY_SCHEMA_VERSION: u32 = 3;
+
+// 現在のスキーマバージョンのキー名前空間（プレフィックス定数を concat! で組み立てるためのマクロ）
+macro_rules! key_namespace {
+    () => {
+        "ratelimit:v3"
+    };
+}
+
+// 1つ前のスキーマバージョンのキー名前空間（migrate_keys で移行する）
+const PREVIOUS_KEY_NAMESPACE: &str = "ratelimit:v2";
+
+/// 現在のスキーマバージョンのキー名前空間
+pub const KEY_NAMESPACE: &str = key_namespace!();
+
+// リクエストごとに使用するキーの固定部分
+pub(crate) const FIXED_WINDOW_PREFIX: &str = concat!(key_namespace!(), ":fixed:");
+pub(crate) const SLIDING_WINDOW_PREFIX: &str = concat!(key_namespace!(), ":sliding:");
+pub(crate) const TOKEN_BUCKET_PREFIX: &str = concat!(key_namespace!(), ":token:");
+pub(crate) const LEAKY_BUCKET_PREFIX: &str = concat!(key_namespace!(), ":leaky:");
+pub(crate) const LIMIT_REQ_PREFIX: &str = concat!(key_namespace!(), ":limitreq:");
+pub(crate) const GCRA_PREFIX: &str = concat!(key_namespace!(), ":gcra:");
+pub(crate) const SLIDING_LOG_PREFIX: &str = concat!(key_namespace!(), ":slidinglog:");
+const BAN_PREFIX: &str = concat!(key_namespace!(), ":ban:");
+const ACCOUNTING_PREFIX: &str = concat!(key_namespace!(), ":acct:");
+const ABUSE_PREFIX: &str = concat!(key_namespace!(), ":abuse:");
+const PENALTY_PREFIX: &str = concat!(key_namespace!(), ":penalty:");
+const INTROSPECTION_PREFIX: &str = concat!(key_namespace!(), ":introspect:");
+const KEY_LIMITS_PREFIX: &str = concat!(key_namespace!(), ":limits:");
+const QUOTA_PREFIX: &str = concat!(key_namespace!(), ":quota:");
+const REJECTION_PREFIX: &str = concat!(key_namespace!(), ":reject:");
+const OFFENSE_PREFIX: &str = concat!(key_namespace!(), ":offense:");
+// Location全体のカウンタ（global_rate=）の固定部分。アルゴリズムの種類（"fixed:" など）が続く
+const GLOBAL_PREFIX: &str = concat!(key_namespace!(), ":global:");
+
+thread_local! {
+    // ホットパスでRedisキーを組み立てるための再利用バッファ
+    static KEY_BUFFER: RefCell<String> = RefCell::new(String::with_capacity(128));
+}
+
+// Redisキーを再利用バッファ上に組み立ててfに渡す
+//
+// Redisコマンドの引数は渡された時点でコピーされるため、キーごとにStringを確保する必要はない。
+// レート制限キーはハッシュタグで囲み、Redis Cluster ではキーのハッシュだけでスロットを決める
+pub(crate) fn with_redis_key<R>(
+    prefix: &str,
+    key: &str,
+    window: Option<u64>,
+    f: impl FnOnce(&str) -> R,
+) -> R {
+    KEY_BUFFER.with(|buf| {
+        let mut buf = buf.borrow_mut();
+        buf.clear();
+        buf.push_str(prefix);
+        buf.push('{');
+        buf.push_str(key);
+        buf.push('}');
+        if let Some(window) = window {
+            let _ = write!(buf, ":{}", window);
+        }
+        f(&buf)
+    })
+}
+
+/// レート制限のカウンタの対象
+///
+/// クライアントのキーとLocation全体のカウンタは別の種類のRedisキーに置き、
+/// クライアントがどのようなキーを送ってもLocation全体のカウンタと重ならないようにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterKey<'a> {
+    /// クライアントのキー（ゾーンのキーを含む）
+    Client(&'a str),
+    /// Location全体で共有するカウンタ（global_rate=）。値はLocation
+    Global(&'a str),
+}
+
+impl<'a> CounterKey<'a> {
+    // アルゴリズムのキーの固定部分（FIXED_WINDOW_PREFIX など）からカウンタのRedisキーを組み立ててfに渡す
+    //
+    // Location全体のカウンタは "ratelimit:v3:global:<種類>:{<Location>}" に置く
+    pub(crate) fn with_redis_key<R>(
+        self,
+        prefix: &str,
+        window: Option<u64>,
+        f: impl FnOnce(&str) -> R,
+    ) -> R {
+        match self {
+            CounterKey::Client(key) => with_redis_key(prefix, key, window, f),
+            CounterKey::Global(location) => {
+                let kind = prefix
+                    .strip_prefix(KEY_NAMESPACE)
+                    .and_then(|kind| kind.strip_prefix(':'))
+                    .unwrap_or(prefix);
+                with_redis_key(&format!("{}{}", GLOBAL_PREFIX, kind), location, window, f)
+            }
+        }
+    }
+
+    // カウンタのRedisキー
+    pub(crate) fn redis_key(self, prefix: &str, window: Option<u64>) -> String {
+        self.with_redis_key(prefix, window, str::to_string)
+    }
+
+    // ログ出力用のキー
+    fn name(self) -> &'a str {
+        match self {
+            CounterKey::Client(key) | CounterKey::Global(key) => key,
+        }
+    }
+}
+
+/// 固定ウィンドウのカウンタキー
+pub fn fixed_window_key(key: &str, window_start: u64) -> String {
+    with_redis_key(FIXED_WINDOW_PREFIX, key, Some(window_start), str::to_string)
+}
+
+/// スライディングウィンドウのカウンタキー
+pub fn sliding_window_key(key: &str, window_start: u64) -> String {
+    with_redis_key(
+        SLIDING_WINDOW_PREFIX,
+        key,
+        Some(window_start),
+        str::to_string,
+    )
+}
+
+/// トークンバケットの状態キー
+pub fn token_bucket_key(key: &str) -> String {
+    with_redis_key(TOKEN_BUCKET_PREFIX, key, None, str::to_string)
+}
+
+/// リーキーバケットの状態キー
+pub fn leaky_bucket_key(key: &str) -> String {
+    with_redis_key(LEAKY_BUCKET_PREFIX, key, None, str::to_string)
+}
+
+/// limit_req互換アルゴリズムの状態キー
+pub fn limit_req_key(key: &str) -> String {
+    with_redis_key(LIMIT_REQ_PREFIX, key, None, str::to_string)
+}
+
+/// GCRAの状態キー
+pub fn gcra_key(key: &str) -> String {
+    with_redis_key(GCRA_PREFIX, key, None, str::to_string)
+}
+
+/// スライディングウィンドウログのソート済みセットのキー
+pub fn sliding_log_key(key: &str) -> String {
+    with_redis_key(SLIDING_LOG_PREFIX, key, None, str::to_string)
+}
+
+/// 手動BANのキー
+pub fn ban_key(key: &str) -> String {
+    with_redis_key(BAN_PREFIX, key, None, str::to_string)
+}
+
+/// 応答の集計（ステータス・送信バイト数）を保持するハッシュのキー
+pub fn accounting_key(key: &str, window_start: u64) -> String {
+    with_redis_key(ACCOUNTING_PREFIX, key, Some(window_start), str::to_string)
+}
+
+/// 不審な応答の割合を集計するハッシュのキー
+pub fn abuse_key(key: &str, window_start: u64) -> String {
+    with_redis_key(ABUSE_PREFIX, key, Some(window_start), str::to_string)
+}
+
+/// 上限が引き下げられているキーの印（abuse_action=tighten）
+pub fn penalty_key(key: &str) -> String {
+    with_redis_key(PENALTY_PREFIX, key, None, str::to_string)
+}
+
+/// トークンイントロスペクションの結果のキャッシュ（トークンはSHA-256で保持する）
+pub fn introspection_key(token_hash: &str) -> String {
+    with_redis_key(INTROSPECTION_PREFIX, token_hash, None, str::to_string)
+}
+
+/// クォータのカウンタキー（period_start は暦の期間の開始時刻）
+pub fn quota_key(key: &str, period_start: u64) -> String {
+    with_redis_key(QUOTA_PREFIX, key, Some(period_start), str::to_string)
+}
+
+/// 自動BANまでの拒否数を数えるカウンタキー（window_start は ban_window の時間窓の開始時刻）
+pub fn rejection_key(key: &str, window_start: u64) -> String {
+    with_redis_key(REJECTION_PREFIX, key, Some(window_start), str::to_string)
+}
+
+/// 自動BANされた回数（違反歴）のカウンタキー
+pub fn offense_key(key: &str) -> String {
+    with_redis_key(OFFENSE_PREFIX, key, None, str::to_string)
+}
+
+/// キーごとのリミット（rate と burst のフィールドを持つハッシュ、課金システムなどが書き込む）
+pub fn key_limits_key(key: &str) -> String {
+    with_redis_key(KEY_LIMITS_PREFIX, key, None, str::to_string)
+}
+
+/// CIDR単位のBANを保持するソート済みセットのキー（スコアは解除時刻）
+pub fn ban_cidrs_key() -> String {
+    format!("{}:ban_cidrs", KEY_NAMESPACE)
+}
+
+/// 管理操作の監査ログを保持するストリームのキー
+pub fn audit_log_key() -> String {
+    format!("{}:audit", KEY_NAMESPACE)
+}
+
+/// 監査ログのストリームに保持するおおよその最大件数
+pub const AUDIT_LOG_MAX_LEN: u64 = 10000;
+
+// ban_cidrs_batch で1回のZADDに含めるCIDRの数
+const CIDR_BATCH_SIZE: usize = 1000;
+
+/// Location単位の実行時リミット上書き設定のキー
+pub fn limit_override_key(location: &str) -> String {
+    format!("{}:override:{}", KEY_NAMESPACE, location)
+}
+
+// ハッシュタグで囲んだレート制限キー（"{<キー>}"）から中身を取り出す
+fn strip_hash_tag(tagged: &str) -> Option<&str> {
+    tagged.strip_prefix('{')?.strip_suffix('}')
+}
+
+/// Redisキーを (種類, レート制限キー, ウィンドウ開始時刻) に分解する
+pub fn decode_redis_key(redis_key: &str) -> Option<(String, String, Option<u64>)> {
+    let rest = redis_key.strip_prefix(KEY_NAMESPACE)?.strip_prefix(':')?;
+    let (kind, rest) = rest.split_once(':')?;
+
+    match kind {
+        // Location全体のカウンタはアルゴリズムの種類の後ろをカウンタと同じ形式で分解する
+        "global" => {
+            let (_, key, window) = decode_redis_key(&format!("{}:{}", KEY_NAMESPACE, rest))?;
+            Some((kind.to_string(), key, window))
+        }
+        // ウィンドウ付きのキーは末尾がウィンドウ開始時刻
+        "fixed" | "sliding" | "acct" | "abuse" | "quota" | "reject" => {
+            let (key, window) = rest.rsplit_once(':')?;
+            let window = window.parse::<u64>().ok()?;
+            Some((
+                kind.to_string(),
+                strip_hash_tag(key)?.to_string(),
+                Some(window),
+            ))
+        }
+        // 上書き設定のキーはLocationで、ハッシュタグを持たない
+        "override" => Some((kind.to_string(), rest.to_string(), None)),
+        _ => Some((kind.to_string(), strip_hash_tag(rest)?.to_string(), None)),
+    }
+}
+
+/// 旧形式のキーを現在の形式に変換する
+///
+/// バージョンなしの v1（"ratelimit:<種類>:..."）と、ハッシュタグのない v2（"ratelimit:v2:<種類>:..."）を
+/// 受け付ける。変換の対象でないキーは None を返す
+pub fn migrate_legacy_key(redis_key: &str) -> Option<String> {
+    let (rest, kinds): (&str, &[&str]) = match redis_key.strip_prefix(PREVIOUS_KEY_NAMESPACE) {
+        Some(rest) => (
+            rest.strip_prefix(':')?,
+            &[
+                "fixed",
+                "sliding",
+                "token",
+                "leaky",
+                "limitreq",
+                "gcra",
+                "slidinglog",
+                "ban",
+                "acct",
+                "abuse",
+                "penalty",
+                "introspect",
+                "override",
+            ],
+        ),
+        None => (
+            redis_key.strip_prefix(KEY_PREFIX)?.strip_prefix(':')?,
+            &["fixed", "sliding", "token", "leaky", "ban", "override"],
+        ),
+    };
+    match rest {
+        "ban_cidrs" => return Some(ban_cidrs_key()),
+        "audit" => return Some(audit_log_key()),
+        _ => {}
+    }
+
+    let (kind, key) = rest.split_once(':')?;
+    if !kinds.contains(&kind) {
+        return None;
+    }
+    let prefix = format!("{}:{}:", KEY_NAMESPACE, kind);
+    match kind {
+        "override" => Some(limit_override_key(key)),
+        "fixed" | "sliding" | "acct" | "abuse" => {
+            let (key, window) = key.rsplit_once(':')?;
+            let window = window.parse::<u64>().ok()?;
+            Some(with_redis_key(&prefix, key, Some(window), str::to_string))
+        }
+        _ => Some(with_redis_key(&prefix, key, None, str::to_string)),
+    }
+}
+
+/// クリーンアップ対象のキーかどうかを判定する
+///
+/// ttl は TTL コマンドの結果（-1: 有効期限なし、-2: キーなし）
+pub fn is_orphaned(redis_key: &str, ttl: i64, now: u64, max_window_age: u64) -> bool {
+    if ttl == -2 || redis_key == ban_cidrs_key() || redis_key == audit_log_key() {
+        return false;
+    }
+    // 旧形式のキーは migrate_keys で移行するまで残す（無期限のBANや上書き設定を含むため）
+    if migrate_legacy_key(redis_key).is_some() {
+        return false;
+    }
+
+    match decode_redis_key(redis_key) {
+        Some((kind, _, window)) => match kind.as_str() {
+            // カウンタは必ず有効期限を持つため、期限なしは異常終了などの残存キー
+            "fixed" | "sliding" | "token" | "leaky" | "limitreq" | "gcra" | "slidinglog"
+            | "global" | "acct" | "abuse" | "penalty" | "introspect" | "offense" => {
+                ttl == -1 || window.is_some_and(|w| now.saturating_sub(w) > max_window_age)
+            }
+            // クォータは月単位の期間、拒否数は ban_window の時間窓で、window_size では判定できない
+            "quota" | "reject" => ttl == -1,
+            // BAN・上書き設定・キーごとのリミットは無期限があり得る
+            "ban" | "override" | "limits" => false,
+            // 旧バージョンなど、このモジュールが認識しない形式
+            _ => true,
+        },
+        None => true,
+    }
+}
+
+/// SCANのMATCHパターンで特別な意味を持つ文字をエスケープする
+pub fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// 障害の注入（fault-injection）を含まないビルドではコマンドをそのまま実行する
+#[cfg(not(feature = "fault-injection"))]
+async fn with_faults<T>(
+    command: impl Future<Output = redis::RedisResult<T>>,
+) -> redis::RedisResult<T> {
+    command.await
+}
+
+// join_all で実行中のfutureと、完了していればその結果
+type JoinSlot<F> = (Pin<Box<F>>, Option<<F as Future>::Output>);
+
+// 全てのfutureを並行して実行し、渡された順に結果を返す
+async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut slots: Vec<JoinSlot<F>> = futures
+        .into_iter()
+        .map(|future| (Box::pin(future), None))
+        .collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in slots.iter_mut().filter(|(_, output)| output.is_none()) {
+            match future.as_mut().poll(cx) {
+                Poll::Ready(value) => *output = Some(value),
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    slots.into_iter().filter_map(|(_, output)| output).collect()
+}
+
+// スクリプト・関数がRedisに登録されていないエラーか（SCRIPT FLUSH や FUNCTION FLUSH の後など）
+fn is_missing_script(err: &RedisError) -> bool {
+    err.kind() == redis::ErrorKind::NoScriptError || err.to_string().contains("Function not found")
+}
+
+// 接続先のRedisサーバーの機能を検出する
+//
+// INFO・COMMAND はプロキシやACLで拒否されることがあるため、失敗しても接続は続け、
+// 分からない項目はバージョン（取得できなければEVALSHAのみ）から推定する
+async fn detect_capabilities(conn: &mut Connection) -> RedisCapabilities {
+    let mut capabilities = match redis::cmd("INFO")
+        .arg("server")
+        .query_async::<_, String>(conn)
+        .await
+    {
+        Ok(info) => RedisCapabilities::from_info(&info),
+        Err(err) => {
+            debug!("INFO server is not available: {}", err);
+            RedisCapabilities::from_info("")
+        }
+    };
+
+    // 存在しないコマンドは nil が返る
+    match redis::cmd("COMMAND")
+        .arg("INFO")
+        .arg("evalsha")
+        .arg("fcall")
+        .arg("hello")
+        .query_async::<_, Vec<redis::Value>>(conn)
+        .await
+    {
+        Ok(commands) if commands.len() == 3 => {
+            let exists = |i: usize| !matches!(commands[i], redis::Value::Nil);
+            capabilities.apply_commands(exists(0), exists(1), exists(2));
+        }
+        Ok(_) => {}
+        Err(err) => debug!("COMMAND INFO is not available: {}", err),
+    }
+
+    capabilities
+}
+
 #[derive(Debug, Clone)]
 pub struct RateLimitConfig {
     pub redis_url: String,
@@ -138,6 +886,7 @@ pub struct RateLimitConfig {
     pub burst: u32,
     pub algorithm: RateLimitAlgorithm,
     pub window_size: u32, // 秒単位のウィンドウサイズ（固定ウィンドウとスライディングウィンドウ用）
+    pub rate_period: u32, // rate のリクエスト数を許可する秒数（バケット系とlimit_req用）
     pub redis_options: RedisConnectionOptions,
 }
 
@@ -149,82 +898,984 @@ impl Default for RateLimitConfig {
             burst: 5,
             algorithm: RateLimitAlgorithm::SlidingWindow,
             window_size: 60, // デフォルトは1分
+            rate_period: 1,
             redis_options: RedisConnectionOptions::default(),
         }
     }
 }
 
-pub struct RedisRateLimiter {
-    client: Client,
-    config: RateLimitConfig,
+impl RateLimitConfig {
+    /// rate・burst 以外が other と同じ設定か
+    ///
+    /// rate・burst はチェックごとに指定できるため、異なっていても同じリミッター（接続）で判定できる
+    pub fn shares_limiter_with(&self, other: &RateLimitConfig) -> bool {
+        self.redis_url == other.redis_url
+            && self.algorithm == other.algorithm
+            && self.window_size == other.window_size
+            && self.rate_period == other.rate_period
+            && self.redis_options == other.redis_options
+    }
 }
 
-impl RedisRateLimiter {
-    // 新しいRedisRateLimiterインスタンスを作成
-    pub async fn new(config: RateLimitConfig) -> Result<Self, String> {
-        info!("Connecting to Redis at: {}", config.redis_url);
-        info!("Using rate limit algorithm: {}", config.algorithm);
+/// 時間窓のアルゴリズムで1つの窓に許可するリクエスト数
+///
+/// rate・burst がどちらも大きい場合に u32 の和があふれないよう u64 で計算する
+pub(crate) fn window_limit(rate: u32, burst: u32) -> u64 {
+    rate as u64 + burst as u64
+}
 
-        // 接続オプションをログに出力
-        info!("Redis connection options: connect_timeout={}ms, command_timeout={}ms, retry_count={}, database={}",
-            config.redis_options.connect_timeout,
-            config.redis_options.command_timeout,
-            config.redis_options.retry_count,
-            config.redis_options.database);
+/// バケット系のアルゴリズムの1秒あたりのレート（rate_period 秒あたり rate 件）
+pub(crate) fn rate_per_second(rate: u32, rate_period: u32) -> f64 {
+    rate as f64 / rate_period.max(1) as f64
+}
 
-        // カスタム接続オプションを適用したURL構築
-        let url_str = if let Some(pwd) = &config.redis_options.password {
-            // パスワードがある場合はURLに組み込む
-            let mut redis_url = redis::parse_redis_url(&config.redis_url)
-                .map_err(|e| format!("Failed to parse Redis URL: {}", e))?;
+/// トークンバケット・リーキーバケットの容量
+///
+/// burst=0 はバーストなしとし、1件分だけを保持する（容量0ではどのリクエストも許可できない）
+pub(crate) fn bucket_capacity(burst: u32) -> f64 {
+    burst.max(1) as f64
+}
 
-            // 認証情報を更新
-            redis_url.password = Some(pwd.clone());
-            redis_url.db = config.redis_options.database;
+/// limit_req互換アルゴリズムのレート（1秒あたりのリクエスト数の1000倍）
+///
+/// limit_req と同じく1/1000リクエスト単位の整数のため、1000秒に1件より遅いレートは表せない
+pub(crate) fn limit_req_rate(rate: u32, rate_period: u32) -> u64 {
+    (rate as u64 * 1000 / rate_period.max(1) as u64).max(1)
+}
 
-            redis_url.to_string()
-        } else {
-            // パスワードがない場合は元のURLを使用
-            config.redis_url.clone()
-        };
+/// GCRAで1リクエストあたりに進める間隔（マイクロ秒）
+///
+/// 整数のマイクロ秒で計算するため、1秒に100万件を超えるレートは100万件として扱う
+pub(crate) fn gcra_interval_us(rate: u32, rate_period: u32) -> u64 {
+    (rate_period.max(1) as u64 * 1_000_000 / rate.max(1) as u64).max(1)
+}
 
-        // Redisクライアントオプションを構築
-        let client_builder = redis::Client::build_with_options(redis::ClientOptions {
-            url: url_str.clone(),
-            ..Default::default()
-        });
+/// 管理APIから設定される実行時のリミット上書き
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LimitOverride {
+    pub rate: u32,
+    pub burst: u32,
+}
 
-        // 接続タイムアウトを設定
-        let client_builder = if config.redis_options.connect_timeout > 0 {
-            client_builder
-                .connection_timeout(Duration::from_millis(config.redis_options.connect_timeout))
-        } else {
-            client_builder
-        };
+/// レート制限スクリプト1回分の判定結果
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RateLimitDecision {
+    /// リクエストが許可されたかどうか
+    pub allowed: bool,
+    /// 現在の時間窓・バケットで残っているリクエスト数
+    pub remaining: u64,
+    /// 制限が完全に戻るまでの秒数
+    pub reset: u64,
+    /// 現在のカウント（時間窓のリクエスト数、バケットの使用量）
+    pub count: u64,
+    /// limit_req互換アルゴリズム・GCRAの超過量（リクエストの1/1000単位、遅延の計算に使用する）
+    pub excess: Option<u64>,
+    /// GCRA・スライディングウィンドウログで次のリクエストが許可されるまでのミリ秒
+    /// （許可した場合は0、Retry-After に使用する）
+    pub retry_after_ms: Option<u64>,
+}
 
-        // キープアライブを設定
-        let client_builder = if config.redis_options.keepalive > 0 {
-            client_builder.keep_alive(Duration::from_secs(config.redis_options.keepalive))
-        } else {
-            client_builder
-        };
+impl RateLimitDecision {
+    /// rate=0 のキーの判定（Redisに問い合わせずにすべて拒否する）
+    ///
+    /// 回復することはないが、クライアントの再試行の目安として時間窓の長さを返す
+    pub(crate) fn deny_all(window_size: u32) -> Self {
+        Self {
+            allowed: false,
+            remaining: 0,
+            reset: window_size as u64,
+            count: 0,
+            excess: None,
+            retry_after_ms: None,
+        }
+    }
 
-        // クライアントを構築
-        let client = match client_builder.build() {
-            Ok(client) => client,
-            Err(err) => {
-                error!("Failed to create Redis client: {}", err);
-                return Err(format!("Failed to create Redis client: {}", err));
+    // スクリプトの応答 {許可, 残り, リセットまでの秒数, カウント[, 5番目の値[, 超過量]]} から構築する
+    //
+    // 5番目の値は limit_req では超過量、GCRA・スライディングウィンドウログでは再試行できるまでのミリ秒。
+    // GCRA は6番目に超過量を返す
+    pub(crate) fn from_reply(algorithm: RateLimitAlgorithm, reply: &[i64]) -> Result<Self, String> {
+        match reply {
+            [allowed, remaining, reset, count] => Ok(Self {
+                allowed: *allowed == 1,
+                remaining: (*remaining).max(0) as u64,
+                reset: (*reset).max(0) as u64,
+                count: (*count).max(0) as u64,
+                excess: None,
+                retry_after_ms: None,
+            }),
+            [allowed, remaining, reset, count, extra] => {
+                let extra = Some((*extra).max(0) as u64);
+                let is_excess = algorithm == RateLimitAlgorithm::LimitReq;
+                Ok(Self {
+                    allowed: *allowed == 1,
+                    remaining: (*remaining).max(0) as u64,
+                    reset: (*reset).max(0) as u64,
+                    count: (*count).max(0) as u64,
+                    excess: if is_excess { extra } else { None },
+                    retry_after_ms: if is_excess { None } else { extra },
+                })
+            }
+            [allowed, remaining, reset, count, retry_after_ms, excess] => Ok(Self {
+                allowed: *allowed == 1,
+                remaining: (*remaining).max(0) as u64,
+                reset: (*reset).max(0) as u64,
+                count: (*count).max(0) as u64,
+                excess: Some((*excess).max(0) as u64),
+                retry_after_ms: Some((*retry_after_ms).max(0) as u64),
+            }),
+            _ => Err(format!("Unexpected rate limit script reply: {:?}", reply)),
+        }
+    }
+}
+
+/// limit_req互換アルゴリズムの1回分の判定（src/scripts/limit_req.lua と同じ計算）
+///
+/// rate・burst はリクエストの1/1000単位、state は保存されている (超過量, 最後のリクエストの時刻[ミリ秒])。
+/// 許可した場合は保存する新しい状態を返す（limit_req と同じく、拒否した場合は状態を更新しない）
+pub(crate) fn limit_req_decision(
+    state: Option<(u64, u64)>,
+    now_ms: u64,
+    rate: u64,
+    burst: u64,
+) -> (RateLimitDecision, Option<(u64, u64)>) {
+    let rate = rate.max(1);
+    let (excess, last) = match state {
+        Some((excess, last)) => {
+            let ms = match now_ms as i128 - last as i128 {
+                ms if ms < -60000 => 1,
+                ms if ms < 0 => 0,
+                ms => ms,
+            };
+            let excess = (excess as i128 - rate as i128 * ms / 1000 + 1000).max(0) as u64;
+            if excess > burst {
+                let current = excess.saturating_sub(1000);
+                let decision = RateLimitDecision {
+                    allowed: false,
+                    remaining: 0,
+                    reset: current.div_ceil(rate),
+                    count: current.div_ceil(1000),
+                    excess: Some(excess),
+                    retry_after_ms: None,
+                };
+                return (decision, None);
             }
+            (excess, if ms != 0 { now_ms } else { last })
+        }
+        None => (0, now_ms),
+    };
+
+    let decision = RateLimitDecision {
+        allowed: true,
+        remaining: (burst - excess) / 1000,
+        reset: excess.div_ceil(rate),
+        count: excess.div_ceil(1000),
+        excess: Some(excess),
+        retry_after_ms: None,
+    };
+    (decision, Some((excess, last)))
+}
+
+/// limit_req互換アルゴリズムの状態を保持する時間（ミリ秒）
+///
+/// 超過量がなくなってから60秒後まで保持する（limit_req が古いノードを削除する条件と同じ）
+pub(crate) fn limit_req_ttl_ms(excess: u64, rate: u64) -> u64 {
+    (excess * 1000).div_ceil(rate.max(1)) + 60000
+}
+
+/// GCRAの1回分の判定（src/scripts/gcra.lua と同じ計算）
+///
+/// 時刻と間隔はマイクロ秒、tat は保存されている理論上の到着時刻。許可した場合は保存する新しい
+/// TAT を返す（拒否した場合は状態を更新しない）
+pub(crate) fn gcra_decision(
+    tat: Option<u64>,
+    now_us: u64,
+    interval: u64,
+    capacity: u64,
+) -> (RateLimitDecision, Option<u64>) {
+    let interval = interval.max(1);
+    let tat = tat.map_or(now_us, |tat| tat.max(now_us));
+    let limit = interval.saturating_mul(capacity);
+    let new_tat = tat + interval;
+    let excess = ((tat - now_us) as u128 * 1000 / interval as u128) as u64;
+
+    if new_tat - now_us > limit {
+        let used = tat - now_us;
+        let wait = new_tat - now_us - limit;
+        let decision = RateLimitDecision {
+            allowed: false,
+            remaining: 0,
+            reset: used.div_ceil(1_000_000),
+            count: used.div_ceil(interval),
+            excess: Some(excess),
+            retry_after_ms: Some(wait.div_ceil(1000)),
         };
+        return (decision, None);
+    }
 
-        // 接続テスト（リトライロジックを使用）
-        let mut last_error = None;
-        let mut conn = None;
+    let used = new_tat - now_us;
+    let decision = RateLimitDecision {
+        allowed: true,
+        remaining: (limit - used) / interval,
+        reset: used.div_ceil(1_000_000),
+        count: used.div_ceil(interval),
+        excess: Some(excess),
+        retry_after_ms: Some(0),
+    };
+    (decision, Some(new_tat))
+}
 
-        for attempt in 0..=config.redis_options.retry_count {
-            match client.get_async_connection().await {
-                Ok(connection) => {
+/// GCRAの状態を保持する時間（ミリ秒）
+///
+/// TAT が現在時刻に追いつくと状態は新しいキーと同じになるため、それまでだけ保持する
+pub(crate) fn gcra_ttl_ms(tat: u64, now_us: u64) -> u64 {
+    tat.saturating_sub(now_us).div_ceil(1000)
+}
+
+// プロキシ互換モードで読み取るスライディングウィンドウログ（件数, 最も古い記録, 最も新しい記録）。
+// 記録は ZRANGE WITHSCORES の (メンバー, 時刻)
+#[cfg(feature = "algo-sliding-log")]
+type SlidingLogRead = (u64, Vec<(String, u64)>, Vec<(String, u64)>);
+
+/// スライディングウィンドウログの1回分の判定（src/scripts/sliding_log.lua と同じ計算）
+///
+/// 時刻はミリ秒、log は窓内の記録の (件数, 最も古い時刻, 最も新しい時刻)。
+/// 許可した場合は呼び出し側が now_ms の記録を追加する
+pub(crate) fn sliding_log_decision(
+    log: (u64, Option<u64>, Option<u64>),
+    now_ms: u64,
+    window_ms: u64,
+    limit: u64,
+) -> RateLimitDecision {
+    let (count, oldest, newest) = log;
+    if count < limit {
+        return RateLimitDecision {
+            allowed: true,
+            remaining: limit - count - 1,
+            reset: window_ms.div_ceil(1000),
+            count: count + 1,
+            excess: None,
+            retry_after_ms: Some(0),
+        };
+    }
+    let until = |time: Option<u64>| (time.unwrap_or(now_ms) + window_ms).saturating_sub(now_ms);
+    RateLimitDecision {
+        allowed: false,
+        remaining: 0,
+        reset: until(newest).div_ceil(1000),
+        count,
+        excess: None,
+        retry_after_ms: Some(until(oldest)),
+    }
+}
+
+/// スライディングウィンドウログの記録を区別する識別子
+///
+/// 同じミリ秒に複数のワーカー・サーバーから記録しても重複しないよう、プロセスIDと連番を組み合わせる
+pub(crate) fn sliding_log_id() -> u64 {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    ((std::process::id() as u64) << 32) | (SEQUENCE.fetch_add(1, Ordering::Relaxed) & 0xffff_ffff)
+}
+
+/// Redis上で追跡中のキーの情報
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackedKey {
+    /// Redis上の実際のキー
+    pub redis_key: String,
+    /// キーの種類（fixed, sliding, token, leaky, ban, override など）
+    pub kind: String,
+    /// レート制限キー
+    pub key: String,
+    /// ウィンドウ開始時刻（ウィンドウ付きのキーのみ）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<u64>,
+    /// カウンタ値（カウンタ: リクエスト数、トークンバケット: 残りトークン、リーキーバケット: 水位、
+    /// スライディングウィンドウログ: 記録数）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<f64>,
+    /// 残りTTL（秒、-1は有効期限なし）
+    pub ttl: i64,
+}
+
+/// 管理APIやCLIで実行された操作の監査ログ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// 操作時刻（UNIXエポックからのミリ秒）
+    pub timestamp_ms: u64,
+    /// 操作者（認証方式や申告された名前、CLIの場合はユーザー名）
+    pub actor: String,
+    /// 操作元（クライアントアドレス、CLIの場合は "cli@ホスト名"）
+    pub source: String,
+    /// 操作の種類（reset, ban, limits など）
+    pub action: String,
+    /// 操作のパラメータ
+    pub params: String,
+    /// 結果（ok またはエラーメッセージ）
+    pub result: String,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, source: &str, action: &str, params: &str, result: &str) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            timestamp_ms,
+            actor: actor.to_string(),
+            source: source.to_string(),
+            action: action.to_string(),
+            params: params.to_string(),
+            result: result.to_string(),
+        }
+    }
+}
+
+/// Luaスクリプトのロード状態
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptStatus {
+    pub name: String,
+    pub version: u32,
+    pub sha: String,
+    /// スクリプトキャッシュに存在せず、今回ロードしたかどうか
+    pub reloaded: bool,
+}
+
+/// キーのクリーンアップ設定
+#[derive(Debug, Clone)]
+pub struct CleanupOptions {
+    /// 1回のDELで削除するキーの数
+    pub batch_size: usize,
+    /// バッチ間の待機時間（ミリ秒）。Redisへの負荷を抑えるために使う
+    pub pause_ms: u64,
+    /// この秒数より古いウィンドウのカウンタは残存キーとみなす
+    pub max_window_age: u64,
+    /// trueの場合は削除せずに対象を報告するだけ
+    pub dry_run: bool,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            pause_ms: 50,
+            max_window_age: 86400,
+            dry_run: false,
+        }
+    }
+}
+
+/// クリーンアップ結果
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CleanupReport {
+    /// 走査したキーの数
+    pub scanned: u64,
+    /// 削除対象となったキーの数
+    pub orphaned: u64,
+    /// 実際に削除したキーの数
+    pub deleted: u64,
+    /// 削除対象の例（最大20件）
+    pub samples: Vec<String>,
+}
+
+/// キー形式の移行結果
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MigrationReport {
+    /// 走査したキーの数
+    pub scanned: u64,
+    /// 新しい形式に移行したキーの数
+    pub migrated: u64,
+    /// 移行先が既に存在したため旧キーを破棄した数
+    pub conflicts: u64,
+}
+
+/// キーの現在の使用状況
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyUsage {
+    /// レート制限キー
+    pub key: String,
+    /// 使用中のアルゴリズム
+    pub algorithm: String,
+    /// 現在のカウント（スライディングウィンドウでは重み付けされた値、バケット系では消費量）
+    pub count: f64,
+    /// バーストを含む上限
+    pub limit: u64,
+    /// 残りリクエスト数
+    pub remaining: u64,
+    /// 制限がリセットされるまでの秒数
+    pub reset_seconds: u64,
+    /// 現在制限されているかどうか
+    pub limited: bool,
+    /// BANされているかどうか
+    pub banned: bool,
+    /// BANが解除されるまでの秒数（無期限の場合はNone）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_ttl: Option<u64>,
+    /// キーに関連するRedisキーごとの残りTTL
+    pub ttls: Vec<KeyTtl>,
+}
+
+/// レート制限キーに関連するRedisキーの残りTTL
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyTtl {
+    /// Redis上の実際のキー
+    pub redis_key: String,
+    /// キーの種類（fixed, sliding, token, leaky, ban）
+    pub kind: String,
+    /// 残りTTL（秒、-1は有効期限なし）
+    pub ttl: i64,
+}
+
+/// Redisへの接続の失敗が続いたときに問い合わせを止めるサーキットブレーカー
+///
+/// 接続・コマンドが breaker_failures 回続けて接続エラーになると開き、breaker_cooldown の間は
+/// Redisに問い合わせずに障害時の動作を適用させる。待機が明けると半開きになり、breaker_cooldown
+/// ごとに breaker_probes 件だけ問い合わせを通す。1件でも成功すれば閉じ、失敗すれば再び
+/// breaker_cooldown の間開く
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    cooldown_ms: u64,
+    max_probes: u32,
+    // 続けて失敗した接続・コマンドの回数（成功すると0に戻る）
+    failures: AtomicU32,
+    // 開いた時刻（UNIXエポックからのミリ秒、0は閉じている）
+    opened_ms: AtomicU64,
+    // 半開きの状態で、probe_period 番目の breaker_cooldown の間に通した問い合わせの数
+    probes: AtomicU32,
+    probe_period: AtomicU64,
+}
+
+impl CircuitBreaker {
+    fn new(options: &RedisConnectionOptions) -> Self {
+        Self {
+            threshold: options.breaker_failures,
+            cooldown_ms: options.breaker_cooldown,
+            max_probes: options.breaker_probes.max(1),
+            failures: AtomicU32::new(0),
+            opened_ms: AtomicU64::new(0),
+            probes: AtomicU32::new(0),
+            probe_period: AtomicU64::new(0),
+        }
+    }
+
+    /// Redisに問い合わせてよいかを返す（開いている間と、半開きで確認の数を使い切った後は false）
+    pub(crate) fn allow(&self) -> bool {
+        let opened = self.opened_ms.load(Ordering::Acquire);
+        if opened == 0 {
+            return true;
+        }
+        let period = now_ms().saturating_sub(opened) / self.cooldown_ms.max(1);
+        if period == 0 {
+            return false;
+        }
+
+        // 確認の結果が返らなかった場合に備え、breaker_cooldown ごとに確認の数を数え直す
+        let counted = self.probe_period.load(Ordering::Acquire);
+        if counted != period
+            && self
+                .probe_period
+                .compare_exchange(counted, period, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            self.probes.store(0, Ordering::Release);
+        }
+        self.probes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |probes| {
+                (probes < self.max_probes).then_some(probes + 1)
+            })
+            .is_ok()
+    }
+
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::Release);
+        if self.opened_ms.load(Ordering::Acquire) != 0
+            && self.opened_ms.swap(0, Ordering::AcqRel) != 0
+        {
+            info!("Redis is reachable again, closing the circuit breaker");
+        }
+    }
+
+    fn record_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if self.threshold == 0 || failures < self.threshold {
+            return;
+        }
+
+        // 閾値に達したとき、または半開きで確認に失敗したときに（再び）開く
+        let opened = self.opened_ms.load(Ordering::Acquire);
+        let now = now_ms();
+        if opened != 0 && now.saturating_sub(opened) < self.cooldown_ms {
+            return;
+        }
+        if self
+            .opened_ms
+            .compare_exchange(opened, now.max(1), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.probe_period.store(0, Ordering::Release);
+            if opened == 0 {
+                warn!(
+                    "Redis failed {} times in a row, opening the circuit breaker for {}ms",
+                    failures, self.cooldown_ms
+                );
+            }
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// 単一ノードまたはRedis Clusterのクライアント
+enum RedisClient {
+    Single(Client),
+    #[cfg(feature = "cluster")]
+    Cluster(redis::cluster::ClusterClient),
+    #[cfg(feature = "tls")]
+    Tls(Arc<TlsClient>),
+}
+
+impl RedisClient {
+    // 多重化接続を1本確立する（breaker は接続を共有するリミッターのサーキットブレーカー）
+    //
+    // connect_timeout（ミリ秒、0は無制限）を超えても確立できない場合はタイムアウトのエラーにする
+    async fn get_async_connection(
+        &self,
+        breaker: &Arc<CircuitBreaker>,
+        connect_timeout: u64,
+    ) -> Result<Connection, RedisError> {
+        let connect = async {
+            match self {
+                RedisClient::Single(client) => ConnectionManager::new(client.clone())
+                    .await
+                    .map(PooledConnection::Single),
+                #[cfg(feature = "cluster")]
+                RedisClient::Cluster(client) => client
+                    .get_async_connection()
+                    .await
+                    .map(PooledConnection::Cluster),
+                #[cfg(feature = "tls")]
+                RedisClient::Tls(client) => TlsConnection::new(client.clone())
+                    .await
+                    .map(PooledConnection::Tls),
+            }
+        };
+        let result = if connect_timeout > 0 {
+            tokio::time::timeout(Duration::from_millis(connect_timeout), connect)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(RedisError::from(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Timed out connecting to Redis",
+                    )))
+                })
+        } else {
+            connect.await
+        };
+        match &result {
+            Ok(_) => breaker.record_success(),
+            Err(_) => breaker.record_failure(),
+        }
+        result.map(|inner| Connection {
+            inner,
+            breaker: breaker.clone(),
+        })
+    }
+}
+
+// 接続できない、または接続が切れたことを示すエラーか
+fn is_connection_error(err: &RedisError) -> bool {
+    err.is_io_error()
+        || err.is_connection_dropped()
+        || err.is_connection_refusal()
+        || err.is_timeout()
+}
+
+// コマンドの成否をサーキットブレーカーに反映する（接続以外のエラーは数えない）
+fn record_connection_result<T>(breaker: &CircuitBreaker, result: &Result<T, RedisError>) {
+    match result {
+        Ok(_) => breaker.record_success(),
+        Err(err) if is_connection_error(err) => breaker.record_failure(),
+        Err(_) => {}
+    }
+}
+
+// tls_ca・tls_cert・tls_key を指定したTLS接続の接続先
+//
+// redis クレートのTLS接続には証明書を渡せないため、native-tls でTLSを確立してから多重化接続を作る
+#[cfg(feature = "tls")]
+struct TlsClient {
+    connector: tokio_native_tls::TlsConnector,
+    host: String,
+    port: u16,
+    redis: redis::RedisConnectionInfo,
+    connect_timeout: u64,
+}
+
+#[cfg(feature = "tls")]
+impl TlsClient {
+    async fn connect(&self) -> Result<redis::aio::MultiplexedConnection, RedisError> {
+        let handshake = async {
+            let stream = tokio::net::TcpStream::connect((self.host.as_str(), self.port)).await?;
+            Ok::<_, RedisError>(self.connector.connect(&self.host, stream).await?)
+        };
+        let stream = if self.connect_timeout > 0 {
+            tokio::time::timeout(Duration::from_millis(self.connect_timeout), handshake)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(RedisError::from(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Timed out connecting to Redis",
+                    )))
+                })?
+        } else {
+            handshake.await?
+        };
+        let (connection, driver) =
+            redis::aio::MultiplexedConnection::new(&self.redis, stream).await?;
+        tokio::spawn(driver);
+        Ok(connection)
+    }
+}
+
+// TlsClient で確立した多重化接続
+//
+// ConnectionManager と同じく、接続が切れたことを示すエラーの後は次のコマンドで接続し直す
+#[cfg(feature = "tls")]
+#[derive(Clone)]
+struct TlsConnection {
+    client: Arc<TlsClient>,
+    connection: Arc<Mutex<Option<redis::aio::MultiplexedConnection>>>,
+}
+
+#[cfg(feature = "tls")]
+impl TlsConnection {
+    async fn new(client: Arc<TlsClient>) -> Result<Self, RedisError> {
+        let connection = client.connect().await?;
+        Ok(Self {
+            client,
+            connection: Arc::new(Mutex::new(Some(connection))),
+        })
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection, RedisError> {
+        if let Some(connection) = self.connection.lock().unwrap().clone() {
+            return Ok(connection);
+        }
+        let connection = self.client.connect().await?;
+        *self.connection.lock().unwrap() = Some(connection.clone());
+        Ok(connection)
+    }
+
+    fn reset_on_error<T>(&self, result: &Result<T, RedisError>) {
+        if matches!(result, Err(err) if is_connection_error(err)) {
+            *self.connection.lock().unwrap() = None;
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ConnectionLike for TlsConnection {
+    fn req_packed_command<'a>(
+        &'a mut self,
+        cmd: &'a redis::Cmd,
+    ) -> redis::RedisFuture<'a, redis::Value> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let result = connection.req_packed_command(cmd).await;
+            self.reset_on_error(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let result = connection.req_packed_commands(cmd, offset, count).await;
+            self.reset_on_error(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.redis.db
+    }
+}
+
+// プールに保持する多重化接続
+//
+// 1本の接続に複数のリクエストのコマンドを同時に流すため、複製しても新しいTCP接続は作られない。
+// 単一ノードの接続は切断されると次のコマンドで再接続し、クラスタへの接続はキーの
+// ハッシュスロットからコマンドを送るノードを選び、MOVED/ASKのリダイレクトとスロットの再配置に従う
+#[derive(Clone)]
+enum PooledConnection {
+    Single(ConnectionManager),
+    #[cfg(feature = "cluster")]
+    Cluster(redis::cluster_async::ClusterConnection),
+    #[cfg(feature = "tls")]
+    Tls(TlsConnection),
+}
+
+// Redisへの接続
+//
+// コマンドの結果をリミッターのサーキットブレーカーに伝える
+#[derive(Clone)]
+struct Connection {
+    inner: PooledConnection,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(
+        &'a mut self,
+        cmd: &'a redis::Cmd,
+    ) -> redis::RedisFuture<'a, redis::Value> {
+        let breaker = self.breaker.clone();
+        let request = match &mut self.inner {
+            PooledConnection::Single(conn) => conn.req_packed_command(cmd),
+            #[cfg(feature = "cluster")]
+            PooledConnection::Cluster(conn) => conn.req_packed_command(cmd),
+            #[cfg(feature = "tls")]
+            PooledConnection::Tls(conn) => conn.req_packed_command(cmd),
+        };
+        Box::pin(async move {
+            let result = request.await;
+            record_connection_result(&breaker, &result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+        let breaker = self.breaker.clone();
+        let request = match &mut self.inner {
+            PooledConnection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            #[cfg(feature = "cluster")]
+            PooledConnection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+            #[cfg(feature = "tls")]
+            PooledConnection::Tls(conn) => conn.req_packed_commands(cmd, offset, count),
+        };
+        Box::pin(async move {
+            let result = request.await;
+            record_connection_result(&breaker, &result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        match &self.inner {
+            PooledConnection::Single(conn) => conn.get_db(),
+            #[cfg(feature = "cluster")]
+            PooledConnection::Cluster(conn) => conn.get_db(),
+            #[cfg(feature = "tls")]
+            PooledConnection::Tls(conn) => conn.get_db(),
+        }
+    }
+}
+
+pub struct RedisRateLimiter {
+    client: RedisClient,
+    config: RateLimitConfig,
+    scripts: LimiterScripts,
+    // ウィンドウ・バケット・BANの期限の計算に使用する時刻
+    clock: Arc<dyn Clock>,
+    // 確立済みの多重化接続（最大 pool_size 本）と、次に使用する接続の位置
+    pool: Mutex<Vec<Connection>>,
+    next_connection: AtomicUsize,
+    // 接続・コマンドの失敗が続いたときにRedisへの問い合わせを止めるサーキットブレーカー
+    breaker: Arc<CircuitBreaker>,
+    // 接続時に検出したサーバーの機能と、それをもとに選んだ判定の実行方法
+    capabilities: RedisCapabilities,
+    mode: ExecutionMode,
+}
+
+// アルゴリズムごとのLuaスクリプト
+//
+// redis::Script は生成時にSHA1を計算するため、リミッターの作成時に一度だけ構築する。
+// 実行は EVALSHA で行い、スクリプトキャッシュにない場合（NOSCRIPT）のみ本文を送信する
+struct LimiterScripts {
+    #[cfg(feature = "algo-fixed-window")]
+    fixed_window: redis::Script,
+    #[cfg(feature = "algo-sliding-window")]
+    sliding_window: redis::Script,
+    #[cfg(feature = "algo-token-bucket")]
+    token_bucket: redis::Script,
+    #[cfg(feature = "algo-leaky-bucket")]
+    leaky_bucket: redis::Script,
+    #[cfg(feature = "algo-limit-req")]
+    limit_req: redis::Script,
+    #[cfg(feature = "algo-gcra")]
+    gcra: redis::Script,
+    #[cfg(feature = "algo-sliding-log")]
+    sliding_log: redis::Script,
+}
+
+impl LimiterScripts {
+    // アルゴリズムのスクリプト（ビルドに含まれていない場合はNone）
+    fn get(&self, algorithm: RateLimitAlgorithm) -> Option<&redis::Script> {
+        match algorithm {
+            #[cfg(feature = "algo-fixed-window")]
+            RateLimitAlgorithm::FixedWindow => Some(&self.fixed_window),
+            #[cfg(feature = "algo-sliding-window")]
+            RateLimitAlgorithm::SlidingWindow => Some(&self.sliding_window),
+            #[cfg(feature = "algo-token-bucket")]
+            RateLimitAlgorithm::TokenBucket => Some(&self.token_bucket),
+            #[cfg(feature = "algo-leaky-bucket")]
+            RateLimitAlgorithm::LeakyBucket => Some(&self.leaky_bucket),
+            #[cfg(feature = "algo-limit-req")]
+            RateLimitAlgorithm::LimitReq => Some(&self.limit_req),
+            #[cfg(feature = "algo-gcra")]
+            RateLimitAlgorithm::Gcra => Some(&self.gcra),
+            #[cfg(feature = "algo-sliding-log")]
+            RateLimitAlgorithm::SlidingLog => Some(&self.sliding_log),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "algo-fixed-window")]
+            fixed_window: redis::Script::new(scripts::FIXED_WINDOW.source),
+            #[cfg(feature = "algo-sliding-window")]
+            sliding_window: redis::Script::new(scripts::SLIDING_WINDOW.source),
+            #[cfg(feature = "algo-token-bucket")]
+            token_bucket: redis::Script::new(scripts::TOKEN_BUCKET.source),
+            #[cfg(feature = "algo-leaky-bucket")]
+            leaky_bucket: redis::Script::new(scripts::LEAKY_BUCKET.source),
+            #[cfg(feature = "algo-limit-req")]
+            limit_req: redis::Script::new(scripts::LIMIT_REQ.source),
+            #[cfg(feature = "algo-gcra")]
+            gcra: redis::Script::new(scripts::GCRA.source),
+            #[cfg(feature = "algo-sliding-log")]
+            sliding_log: redis::Script::new(scripts::SLIDING_LOG.source),
+        }
+    }
+}
+
+impl RedisRateLimiter {
+    // 新しいRedisRateLimiterインスタンスを作成
+    pub async fn new(config: RateLimitConfig) -> Result<Self, String> {
+        info!("Connecting to Redis at: {}", config.redis_url);
+        info!("Using rate limit algorithm: {}", config.algorithm);
+
+        // 接続オプションをログに出力
+        info!("Redis connection options: connect_timeout={}ms, command_timeout={}ms, retry_count={}, database={}",
+            config.redis_options.connect_timeout,
+            config.redis_options.command_timeout,
+            config.redis_options.retry_count,
+            config.redis_options.database);
+
+        // ビルドに含まれていない接続方式は起動時にエラーにする
+        if config.redis_options.uses_tls(&config.redis_url) && !cfg!(feature = "tls") {
+            return Err(
+                "Redis TLS is not available in this build (enable the 'tls' feature)".to_string(),
+            );
+        }
+        if config.redis_options.cluster_mode && !cfg!(feature = "cluster") {
+            return Err(
+                "Redis Cluster is not available in this build (enable the 'cluster' feature)"
+                    .to_string(),
+            );
+        }
+        config.redis_options.validate_tls(&config.redis_url)?;
+
+        // クラスタモードでは redis_url をカンマ区切りの初期ノードとして扱う
+        #[cfg(feature = "cluster")]
+        if config.redis_options.cluster_mode {
+            let nodes: Vec<String> = config
+                .redis_url
+                .split(',')
+                .map(|node| config.redis_options.tls_url(node.trim()))
+                .collect();
+            let mut builder = redis::cluster::ClusterClientBuilder::new(nodes);
+            if let Some(pwd) = &config.redis_options.password {
+                builder = builder.password(pwd.clone());
+            }
+            let client = match builder.build() {
+                Ok(client) => RedisClient::Cluster(client),
+                Err(err) => {
+                    error!("Failed to create Redis Cluster client: {}", err);
+                    return Err(format!("Failed to create Redis Cluster client: {}", err));
+                }
+            };
+            return Self::connect(client, config).await;
+        }
+
+        // 接続先のURLを解析し、パスワードとデータベース番号の指定を反映する
+        // （接続タイムアウトは接続の確立時に適用する。redis クレートのクライアントにはTCPキープアライブを
+        // 設定する方法がないため、keepalive は使用しない）
+        let mut connection_info = config
+            .redis_options
+            .tls_url(&config.redis_url)
+            .as_str()
+            .into_connection_info()
+            .map_err(|e| format!("Failed to parse Redis URL: {}", e))?;
+        if let Some(pwd) = &config.redis_options.password {
+            connection_info.redis.password = Some(pwd.clone());
+        }
+        if config.redis_options.database != 0 {
+            connection_info.redis.db = config.redis_options.database;
+        }
+
+        // CA証明書・クライアント証明書を使う場合はTLSを自前で確立する
+        #[cfg(feature = "tls")]
+        if let Some(connector) = config.redis_options.tls_connector()? {
+            let (host, port) = match connection_info.addr {
+                redis::ConnectionAddr::TcpTls { host, port, .. } => (host, port),
+                _ => {
+                    return Err("tls_ca, tls_cert and tls_key require a TCP connection".to_string())
+                }
+            };
+            let client = RedisClient::Tls(Arc::new(TlsClient {
+                connector,
+                host,
+                port,
+                redis: connection_info.redis,
+                connect_timeout: config.redis_options.connect_timeout,
+            }));
+            return Self::connect(client, config).await;
+        }
+
+        // クライアントを構築
+        let client = match Client::open(connection_info) {
+            Ok(client) => RedisClient::Single(client),
+            Err(err) => {
+                error!("Failed to create Redis client: {}", err);
+                return Err(format!("Failed to create Redis client: {}", err));
+            }
+        };
+
+        Self::connect(client, config).await
+    }
+
+    // 構築したクライアントで接続を確認し、サーバーの機能を検出してリミッターを作成する
+    async fn connect(client: RedisClient, config: RateLimitConfig) -> Result<Self, String> {
+        // 接続テスト（リトライロジックを使用）
+        let breaker = Arc::new(CircuitBreaker::new(&config.redis_options));
+        let mut last_error = None;
+        let mut conn = None;
+
+        for attempt in 0..=config.redis_options.retry_count {
+            match client
+                .get_async_connection(&breaker, config.redis_options.connect_timeout)
+                .await
+            {
+                Ok(connection) => {
                     conn = Some(connection);
                     break;
                 }
@@ -295,26 +1946,168 @@ impl RedisRateLimiter {
             }
         }
 
-        Ok(RedisRateLimiter { client, config })
+        // サーバーの機能を検出して判定の実行方法を選ぶ
+        let capabilities = detect_capabilities(&mut conn).await;
+        let mode = capabilities.select_mode(
+            config.redis_options.compat,
+            config.redis_options.cluster_mode,
+        );
+        if capabilities.cluster && !config.redis_options.cluster_mode {
+            warn!("Redis server runs in cluster mode but redis_cluster_mode is off; keys in other slots will fail with MOVED");
+        }
+
+        let mut limiter = RedisRateLimiter {
+            client,
+            config,
+            scripts: LimiterScripts::new(),
+            clock: Arc::new(SystemClock),
+            // 接続テストに使用した接続をプールの1本目にする
+            pool: Mutex::new(vec![conn]),
+            next_connection: AtomicUsize::new(0),
+            breaker,
+            capabilities,
+            mode,
+        };
+
+        // 最初のリクエストでEVALが走らないよう、起動時にスクリプト・関数をロードしておく
+        // （単一キーのコマンドで判定する場合はスクリプトを使用しない）
+        if limiter.mode != ExecutionMode::Commands {
+            match limiter.preload_scripts().await {
+                Ok(statuses) => {
+                    for status in statuses {
+                        info!(
+                            "Lua script {}@{} active: {}",
+                            status.name, status.version, status.sha
+                        );
+                    }
+                }
+                // ACLなどで FUNCTION LOAD が拒否された場合はEVALSHAで判定する
+                Err(e) if limiter.mode == ExecutionMode::Functions => {
+                    warn!(
+                        "Failed to load Redis Functions, falling back to EVALSHA: {}",
+                        e
+                    );
+                    limiter.mode = ExecutionMode::Scripts;
+                    if let Err(e) = limiter.preload_scripts().await {
+                        warn!("Failed to preload Lua scripts: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to preload Lua scripts: {}", e),
+            }
+        }
+
+        let capabilities = &limiter.capabilities;
+        info!(
+            "Redis {} (functions={}, scripting={}, resp3={}, cluster={}): using {}",
+            capabilities.version_string(),
+            capabilities.functions,
+            capabilities.scripting,
+            capabilities.resp3,
+            capabilities.cluster,
+            limiter.mode
+        );
+        if limiter.mode == ExecutionMode::Commands
+            && limiter.config.redis_options.compat != RedisCompat::Proxy
+        {
+            warn!("Redis does not accept Lua scripts: using plain commands, which are not atomic for bucket algorithms");
+        }
+
+        Ok(limiter)
+    }
+
+    /// 接続時に検出したサーバーの機能
+    pub fn capabilities(&self) -> &RedisCapabilities {
+        &self.capabilities
+    }
+
+    /// 判定の実行方法（Redis Functions、EVALSHA、単一キーのコマンド）
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.mode
     }
 
     // 接続取得のヘルパーメソッド
+    //
+    // プールの接続を順番に使い回し、pool_size 本に満たない間は新しい接続を確立してプールに加える
     async fn get_connection(&self) -> Result<Connection, RedisError> {
-        self.client.get_async_connection().await
+        let target = (self.config.redis_options.pool_size as usize).max(1);
+        {
+            let pool = self.pool.lock().unwrap();
+            if pool.len() >= target {
+                let index = self.next_connection.fetch_add(1, Ordering::Relaxed) % pool.len();
+                return Ok(pool[index].clone());
+            }
+        }
+
+        let conn = self
+            .client
+            .get_async_connection(&self.breaker, self.config.redis_options.connect_timeout)
+            .await?;
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < target {
+            pool.push(conn.clone());
+        }
+        Ok(conn)
     }
 
-    // レートリミットのチェック
-    pub async fn check_rate_limit(&self, key: &str) -> Result<bool, String> {
-        match self.config.algorithm {
-            RateLimitAlgorithm::FixedWindow => self.check_fixed_window(key).await,
-            RateLimitAlgorithm::SlidingWindow => self.check_sliding_window(key).await,
-            RateLimitAlgorithm::TokenBucket => self.check_token_bucket(key).await,
-            RateLimitAlgorithm::LeakyBucket => self.check_leaky_bucket(key).await,
+    /// リミッターのサーキットブレーカー
+    pub(crate) fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    /// プールが pool_size 本になるまで接続を確立してPINGする
+    ///
+    /// ワーカーの起動時に呼び出すことで、リロード直後のリクエストが接続確立を待たずに済む。
+    /// 確立に失敗した時点で中断し、それまでに確立した接続はプールに残す
+    pub async fn warm_up(&self) -> Result<usize, String> {
+        let target = self.config.redis_options.pool_size as usize;
+        let ping_timeout = self.config.redis_options.command_timeout;
+        let mut ready = self.pool.lock().unwrap().len();
+
+        while ready < target {
+            let mut conn = match self
+                .client
+                .get_async_connection(&self.breaker, self.config.redis_options.connect_timeout)
+                .await
+            {
+                Ok(conn) => conn,
+                Err(err) => {
+                    return Err(format!(
+                        "Failed to get Redis connection after warming up {}/{}: {}",
+                        ready, target, err
+                    ))
+                }
+            };
+
+            match tokio::time::timeout(
+                Duration::from_millis(ping_timeout),
+                redis::cmd("PING").query_async::<_, String>(&mut conn),
+            )
+            .await
+            {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => return Err(format!("Failed to ping Redis server: {}", err)),
+                Err(_) => {
+                    return Err(format!(
+                        "Redis PING command timed out after {}ms",
+                        ping_timeout
+                    ))
+                }
+            }
+
+            let mut pool = self.pool.lock().unwrap();
+            if pool.len() < target {
+                pool.push(conn);
+            }
+            ready = pool.len();
         }
+
+        Ok(ready)
     }
 
-    // 固定ウィンドウアルゴリズム
-    async fn check_fixed_window(&self, key: &str) -> Result<bool, String> {
+    // 指定したキーの全アルゴリズムのカウンタを削除し、削除したRedisキーの数を返す
+    pub async fn reset_key(&self, key: &str) -> Result<u64, String> {
+        self.ensure_scannable("Reset")?;
+
         let mut conn = match self.get_connection().await {
             Ok(conn) => conn,
             Err(err) => {
@@ -323,85 +2116,77 @@ impl RedisRateLimiter {
             }
         };
 
-        // 現在のタイムスタンプ（秒）
-        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                error!("SystemTime before UNIX EPOCH!");
-                return Err("SystemTime before UNIX EPOCH!".to_string());
+        let targets = self.related_keys(&mut conn, key).await?;
+
+        let deleted: u64 = match redis::cmd("DEL").arg(&targets).query_async(&mut conn).await {
+            Ok(n) => n,
+            Err(err) => {
+                error!("Failed to delete rate limit keys for {}: {}", key, err);
+                return Err(format!("Failed to delete rate limit keys: {}", err));
             }
         };
 
-        // 現在のウィンドウの開始時間を計算
-        let window_size = self.config.window_size as u64;
-        let window_start = (now / window_size) * window_size;
-        let redis_key = format!("ratelimit:fixed:{}:{}", key, window_start);
-
-        // LUAスクリプトを使用して、アトミックにレート制限をチェック
-        let script = r#"
-            local key = KEYS[1]
-            local max_requests = tonumber(ARGV[1])
-            local window_size = tonumber(ARGV[2])
-
-            -- 現在のカウントを取得
-            local count = redis.call('INCR', key)
-
-            -- 初回アクセスの場合、有効期限を設定
-            if count == 1 then
-                redis.call('EXPIRE', key, window_size)
-            end
-
-            -- リクエスト数が制限以下かチェック
-            if count <= max_requests then
-                return 1  -- 許可
-            else
-                return 0  -- 拒否
-            end
-        "#;
+        info!(
+            "Reset rate limit state for {} ({} keys deleted)",
+            key, deleted
+        );
+        Ok(deleted)
+    }
 
-        let max_requests = self.config.requests_per_second + self.config.burst;
+    // レート制限キーに関連する全てのRedisキーを返す（存在しないキーを含む場合がある）
+    async fn related_keys(&self, conn: &mut Connection, key: &str) -> Result<Vec<String>, String> {
+        // ウィンドウごとにキーが分かれるアルゴリズムはSCANで収集する
+        let mut keys = vec![
+            token_bucket_key(key),
+            leaky_bucket_key(key),
+            limit_req_key(key),
+            gcra_key(key),
+            sliding_log_key(key),
+            ban_key(key),
+            penalty_key(key),
+            offense_key(key),
+        ];
+        let escaped = escape_glob(key);
+        for pattern in [
+            format!("{}{{{}}}:*", FIXED_WINDOW_PREFIX, escaped),
+            format!("{}{{{}}}:*", SLIDING_WINDOW_PREFIX, escaped),
+            format!("{}{{{}}}:*", ABUSE_PREFIX, escaped),
+            format!("{}{{{}}}:*", QUOTA_PREFIX, escaped),
+            format!("{}{{{}}}:*", REJECTION_PREFIX, escaped),
+        ] {
+            keys.extend(self.scan_keys(conn, &pattern).await?);
+        }
+        Ok(keys)
+    }
 
-        // コマンドタイムアウトの設定
-        let command_timeout = self.config.redis_options.command_timeout;
-        let script_result = tokio::time::timeout(
-            Duration::from_millis(command_timeout),
-            redis::Script::new(script)
-                .key(redis_key)
-                .arg(max_requests)
-                .arg(window_size)
-                .invoke_async(&mut conn),
-        )
-        .await;
+    // キーに関連するRedisキーのTTLを変更し、更新したキーの数を返す
+    //
+    // target は "ban"、"counters"（全カウンタ）、またはカウンタの種類
+    // （fixed, sliding, token, leaky, limitreq, gcra, slidinglog）。
+    // ttl が0の場合はBANのみ無期限にできる（カウンタを無期限にすると残存キーになるため）
+    pub async fn set_key_ttl(&self, key: &str, target: &str, ttl: u64) -> Result<u64, String> {
+        self.ensure_scannable("Changing key TTLs")?;
 
-        match script_result {
-            Ok(redis_result) => match redis_result {
-                Ok(val) => {
-                    debug!("Fixed window rate limit check for {}: {}", key, val);
-                    Ok(val == 1)
-                }
-                Err(err) => {
-                    error!("Failed to execute fixed window rate limit script: {}", err);
-                    Err(format!(
-                        "Failed to execute fixed window rate limit script: {}",
-                        err
-                    ))
-                }
-            },
-            Err(_) => {
-                error!(
-                    "Fixed window rate limit check timed out after {}ms",
-                    command_timeout
-                );
-                Err(format!(
-                    "Fixed window rate limit check timed out after {}ms",
-                    command_timeout
-                ))
+        let kinds: &[&str] = match target {
+            "ban" => &["ban"],
+            "counters" => &[
+                "fixed",
+                "sliding",
+                "token",
+                "leaky",
+                "limitreq",
+                "gcra",
+                "slidinglog",
+            ],
+            "fixed" | "sliding" | "token" | "leaky" | "limitreq" | "gcra" | "slidinglog" => {
+                std::slice::from_ref(&target)
             }
+            _ => return Err(format!("Unknown TTL target: {}", target)),
+        };
+        if ttl == 0 && target != "ban" {
+            return Err("ttl must be greater than 0 for counters".to_string());
         }
-    }
 
-    // スライディングウィンドウアルゴリズム
-    async fn check_sliding_window(&self, key: &str) -> Result<bool, String> {
         let mut conn = match self.get_connection().await {
             Ok(conn) => conn,
             Err(err) => {
@@ -410,104 +2195,1440 @@ impl RedisRateLimiter {
             }
         };
 
-        // 現在のタイムスタンプ（秒）
-        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                error!("SystemTime before UNIX EPOCH!");
-                return Err("SystemTime before UNIX EPOCH!".to_string());
+        let targets: Vec<String> = self
+            .related_keys(&mut conn, key)
+            .await?
+            .into_iter()
+            .filter(|k| {
+                decode_redis_key(k).is_some_and(|(kind, _, _)| kinds.contains(&kind.as_str()))
+            })
+            .collect();
+        if targets.is_empty() {
+            return Ok(0);
+        }
+
+        // EXPIRE / PERSIST は存在しないキーに対して0を返すため、作成されることはない
+        let mut pipe = redis::pipe();
+        for redis_key in &targets {
+            if ttl == 0 {
+                pipe.cmd("PERSIST").arg(redis_key);
+            } else {
+                pipe.cmd("EXPIRE").arg(redis_key).arg(ttl);
             }
-        };
+        }
 
-        let window_size = self.config.window_size as u64;
-        let current_window = now / window_size * window_size;
-        let previous_window = current_window - window_size;
-
-        let current_key = format!("ratelimit:sliding:{}:{}", key, current_window);
-        let previous_key = format!("ratelimit:sliding:{}:{}", key, previous_window);
-
-        // スライディングウィンドウの実装（前回のウィンドウも部分的に考慮）
-        let script = r#"
-            local current_key = KEYS[1]
-            local previous_key = KEYS[2]
-            local now = tonumber(ARGV[1])
-            local window_size = tonumber(ARGV[2])
-            local max_requests = tonumber(ARGV[3])
-            local burst = tonumber(ARGV[4])
-
-            -- 現在のウィンドウの開始時間
-            local current_window_start = math.floor(now / window_size) * window_size
-            -- 経過した割合 (0.0 ~ 1.0)
-            local elapsed_ratio = (now - current_window_start) / window_size
-
-            -- 現在のウィンドウのカウントを増加
-            local current_count = redis.call('INCR', current_key)
-            if current_count == 1 then
-                redis.call('EXPIRE', current_key, window_size * 2)
-            end
-
-            -- 前回のウィンドウのカウントを取得
-            local previous_count = redis.call('GET', previous_key) or "0"
-            previous_count = tonumber(previous_count)
-
-            -- 重み付けされたカウント: 現在のカウント + 前回のカウント×(1-経過した割合)
-            local weighted_count = current_count + previous_count * (1 - elapsed_ratio)
-
-            -- バーストを含む最大リクエスト数を超えたかチェック
-            if weighted_count <= (max_requests + burst) then
-                return 1  -- 許可
-            else
-                return 0  -- 拒否
-            end
-        "#;
+        let updated: Vec<u64> = match pipe.query_async(&mut conn).await {
+            Ok(results) => results,
+            Err(err) => {
+                error!("Failed to update TTL for {}: {}", key, err);
+                return Err(format!("Failed to update TTL: {}", err));
+            }
+        };
+
+        let updated = updated.iter().sum();
+        info!(
+            "Set TTL of {} {} keys for {} to {}s",
+            updated, target, key, ttl
+        );
+        Ok(updated)
+    }
+
+    // 管理操作を監査ログのストリームに追記する
+    //
+    // Redisに書き込めない場合でも操作の記録が残るよう、NGINXのエラーログにも出力する
+    pub async fn record_audit(&self, entry: &AuditEntry) -> Result<(), String> {
+        info!(
+            "Audit: actor={} source={} action={} params={} result={}",
+            entry.actor, entry.source, entry.action, entry.params, entry.result
+        );
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match redis::cmd("XADD")
+            .arg(audit_log_key())
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(AUDIT_LOG_MAX_LEN)
+            .arg("*")
+            .arg("timestamp_ms")
+            .arg(entry.timestamp_ms)
+            .arg("actor")
+            .arg(&entry.actor)
+            .arg("source")
+            .arg(&entry.source)
+            .arg("action")
+            .arg(&entry.action)
+            .arg("params")
+            .arg(&entry.params)
+            .arg("result")
+            .arg(&entry.result)
+            .query_async::<_, String>(&mut conn)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("Failed to write audit log: {}", err);
+                Err(format!("Failed to write audit log: {}", err))
+            }
+        }
+    }
+
+    // 監査ログを新しい順に最大count件取得する
+    pub async fn recent_audit(&self, count: usize) -> Result<Vec<AuditEntry>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let entries: Vec<(String, Vec<String>)> = match redis::cmd("XREVRANGE")
+            .arg(audit_log_key())
+            .arg("+")
+            .arg("-")
+            .arg("COUNT")
+            .arg(count)
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("Failed to read audit log: {}", err);
+                return Err(format!("Failed to read audit log: {}", err));
+            }
+        };
+
+        Ok(entries
+            .into_iter()
+            .map(|(_, fields)| {
+                let mut entry = AuditEntry::new("", "", "", "", "");
+                for pair in fields.chunks(2) {
+                    let value = pair.get(1).cloned().unwrap_or_default();
+                    match pair[0].as_str() {
+                        "timestamp_ms" => entry.timestamp_ms = value.parse().unwrap_or(0),
+                        "actor" => entry.actor = value,
+                        "source" => entry.source = value,
+                        "action" => entry.action = value,
+                        "params" => entry.params = value,
+                        "result" => entry.result = value,
+                        _ => {}
+                    }
+                }
+                entry
+            })
+            .collect())
+    }
+
+    // Locationのリミット上書きを保存する（ttlが0の場合は削除されるまで有効）
+    pub async fn set_limit_override(
+        &self,
+        location: &str,
+        limits: LimitOverride,
+        ttl: u64,
+    ) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let redis_key = limit_override_key(location);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("DEL")
+            .arg(&redis_key)
+            .ignore()
+            .cmd("HSET")
+            .arg(&redis_key)
+            .arg("rate")
+            .arg(limits.rate)
+            .arg("burst")
+            .arg(limits.burst)
+            .ignore();
+        if ttl > 0 {
+            pipe.cmd("EXPIRE").arg(&redis_key).arg(ttl).ignore();
+        }
+
+        match pipe.query_async::<_, ()>(&mut conn).await {
+            Ok(_) => {
+                info!(
+                    "Set limit override for {}: rate={}, burst={}, ttl={}s",
+                    location, limits.rate, limits.burst, ttl
+                );
+                Ok(())
+            }
+            Err(err) => {
+                error!("Failed to set limit override for {}: {}", location, err);
+                Err(format!("Failed to set limit override: {}", err))
+            }
+        }
+    }
+
+    // Locationのリミット上書きを削除する。削除された場合はtrueを返す
+    pub async fn clear_limit_override(&self, location: &str) -> Result<bool, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match conn.del::<_, u64>(limit_override_key(location)).await {
+            Ok(n) => {
+                info!("Cleared limit override for {}", location);
+                Ok(n > 0)
+            }
+            Err(err) => {
+                error!("Failed to clear limit override for {}: {}", location, err);
+                Err(format!("Failed to clear limit override: {}", err))
+            }
+        }
+    }
+
+    // Locationのリミット上書きを取得する
+    pub async fn get_limit_override(
+        &self,
+        location: &str,
+    ) -> Result<Option<LimitOverride>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let (rate, burst): (Option<u32>, Option<u32>) = match redis::cmd("HMGET")
+            .arg(limit_override_key(location))
+            .arg("rate")
+            .arg("burst")
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(values) => values,
+            Err(err) => {
+                error!("Failed to read limit override for {}: {}", location, err);
+                return Err(format!("Failed to read limit override: {}", err));
+            }
+        };
+
+        Ok(match (rate, burst) {
+            (Some(rate), Some(burst)) => Some(LimitOverride { rate, burst }),
+            _ => None,
+        })
+    }
+
+    // キーごとのリミットを取得する。rate と burst の両方が設定されている場合だけ返す
+    pub async fn get_key_limits(&self, key: &str) -> Result<Option<LimitOverride>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let (rate, burst): (Option<u32>, Option<u32>) = match redis::cmd("HMGET")
+            .arg(key_limits_key(key))
+            .arg("rate")
+            .arg("burst")
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(values) => values,
+            Err(err) => {
+                error!("Failed to read key limits for {}: {}", key, err);
+                return Err(format!("Failed to read key limits: {}", err));
+            }
+        };
+
+        Ok(match (rate, burst) {
+            (Some(rate), Some(burst)) => Some(LimitOverride { rate, burst }),
+            _ => None,
+        })
+    }
+
+    // キーをBANする（durationが0の場合は無期限）
+    pub async fn ban(&self, key: &str, duration: u64) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(ban_key(key)).arg("manual");
+        if duration > 0 {
+            cmd.arg("EX").arg(duration);
+        }
+
+        match cmd.query_async::<_, ()>(&mut conn).await {
+            Ok(_) => {
+                info!("Banned {} for {}s", key, duration);
+                Ok(())
+            }
+            Err(err) => {
+                error!("Failed to ban {}: {}", key, err);
+                Err(format!("Failed to ban key: {}", err))
+            }
+        }
+    }
+
+    // キーのBANを解除する。BANされていた場合はtrueを返す
+    pub async fn unban(&self, key: &str) -> Result<bool, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match conn.del::<_, u64>(ban_key(key)).await {
+            Ok(n) => {
+                info!("Unbanned {}", key);
+                Ok(n > 0)
+            }
+            Err(err) => {
+                error!("Failed to unban {}: {}", key, err);
+                Err(format!("Failed to unban key: {}", err))
+            }
+        }
+    }
+
+    // CIDR範囲をBANする（durationが0の場合は無期限）
+    pub async fn ban_cidr(&self, cidr: &str, duration: u64) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let expires_at = if duration > 0 {
+            let now = self.now()?.as_secs();
+            (now + duration).to_string()
+        } else {
+            "+inf".to_string()
+        };
+
+        match redis::cmd("ZADD")
+            .arg(ban_cidrs_key())
+            .arg(expires_at)
+            .arg(cidr)
+            .query_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(_) => {
+                info!("Banned {} for {}s", cidr, duration);
+                Ok(())
+            }
+            Err(err) => {
+                error!("Failed to ban {}: {}", cidr, err);
+                Err(format!("Failed to ban CIDR: {}", err))
+            }
+        }
+    }
+
+    // 複数のCIDR範囲をまとめてBANする（外部ブロックリストの同期用、durationが0の場合は無期限）
+    //
+    // ZADD GT で登録するため、既存のBANの期限は延長されるだけで短縮されない
+    pub async fn ban_cidrs_batch(&self, entries: &[(String, u64)]) -> Result<usize, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+
+        for chunk in entries.chunks(CIDR_BATCH_SIZE) {
+            let mut cmd = redis::cmd("ZADD");
+            cmd.arg(ban_cidrs_key()).arg("GT");
+            for (cidr, duration) in chunk {
+                if *duration > 0 {
+                    cmd.arg(now + duration);
+                } else {
+                    cmd.arg("+inf");
+                }
+                cmd.arg(cidr);
+            }
+            if let Err(err) = cmd.query_async::<_, ()>(&mut conn).await {
+                error!("Failed to ban CIDRs: {}", err);
+                return Err(format!("Failed to ban CIDRs: {}", err));
+            }
+        }
+
+        info!("Banned {} CIDRs", entries.len());
+        Ok(entries.len())
+    }
+
+    // CIDR範囲のBANを解除する。BANされていた場合はtrueを返す
+    pub async fn unban_cidr(&self, cidr: &str) -> Result<bool, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match conn.zrem::<_, _, u64>(ban_cidrs_key(), cidr).await {
+            Ok(n) => Ok(n > 0),
+            Err(err) => {
+                error!("Failed to unban {}: {}", cidr, err);
+                Err(format!("Failed to unban CIDR: {}", err))
+            }
+        }
+    }
+
+    // 有効なCIDR BANの一覧を (CIDR, 残り秒数) で返す。期限切れのエントリはここで削除する
+    pub async fn banned_cidrs(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+
+        let (_, entries): ((), Vec<(String, f64)>) = match redis::pipe()
+            .cmd("ZREMRANGEBYSCORE")
+            .arg(ban_cidrs_key())
+            .arg("-inf")
+            .arg(now)
+            .ignore()
+            .cmd("ZRANGE")
+            .arg(ban_cidrs_key())
+            .arg(0)
+            .arg(-1)
+            .arg("WITHSCORES")
+            .query_async::<_, ((), Vec<(String, f64)>)>(&mut conn)
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to read CIDR bans: {}", err);
+                return Err(format!("Failed to read CIDR bans: {}", err));
+            }
+        };
+
+        Ok(entries
+            .into_iter()
+            .map(|(cidr, expires_at)| {
+                let remaining = if expires_at.is_finite() {
+                    Some((expires_at as u64).saturating_sub(now))
+                } else {
+                    None
+                };
+                (cidr, remaining)
+            })
+            .collect())
+    }
+
+    // キー単位のBANの一覧を (キー, 残り秒数) で返す
+    pub async fn list_bans(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        self.ensure_scannable("Listing bans")?;
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let prefix = format!("{}:ban:", KEY_NAMESPACE);
+        let redis_keys = self.scan_keys(&mut conn, &format!("{}*", prefix)).await?;
+
+        let mut bans = Vec::with_capacity(redis_keys.len());
+        for chunk in redis_keys.chunks(100) {
+            let mut pipe = redis::pipe();
+            for redis_key in chunk {
+                pipe.cmd("TTL").arg(redis_key);
+            }
+            let ttls: Vec<i64> = match pipe.query_async(&mut conn).await {
+                Ok(ttls) => ttls,
+                Err(err) => {
+                    error!("Failed to read ban TTLs: {}", err);
+                    return Err(format!("Failed to read ban TTLs: {}", err));
+                }
+            };
+
+            for (redis_key, ttl) in chunk.iter().zip(ttls) {
+                if ttl == -2 {
+                    continue;
+                }
+                let key = match decode_redis_key(redis_key) {
+                    Some((_, key, _)) => key,
+                    None => continue,
+                };
+                bans.push((key, if ttl >= 0 { Some(ttl as u64) } else { None }));
+            }
+        }
+
+        Ok(bans)
+    }
+
+    // キーがBANされているかを確認する
+    pub async fn is_banned(&self, key: &str) -> Result<bool, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let mut cmd = redis::cmd("EXISTS");
+        with_redis_key(BAN_PREFIX, key, None, |k| {
+            cmd.arg(k);
+        });
+
+        let command_timeout = self.config.redis_options.command_timeout;
+        match tokio::time::timeout(
+            Duration::from_millis(command_timeout),
+            cmd.query_async::<_, bool>(&mut conn),
+        )
+        .await
+        {
+            Ok(Ok(banned)) => Ok(banned),
+            Ok(Err(err)) => {
+                error!("Failed to check ban state for {}: {}", key, err);
+                Err(format!("Failed to check ban state: {}", err))
+            }
+            Err(_) => {
+                error!("Ban check timed out after {}ms", command_timeout);
+                Err(format!("Ban check timed out after {}ms", command_timeout))
+            }
+        }
+    }
+
+    // 完了したリクエストの応答ステータスと送信バイト数を時間窓ごとに集計する
+    //
+    // 許可・拒否の判定とは独立しており、バイト数のクォータやエラー応答の払い戻し、
+    // 悪用のスコアリングなどに使用できる
+    pub async fn record_response(&self, key: &str, status: u16, bytes: u64) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+        let window_size = self.config.window_size as u64;
+        let window_start = now / window_size * window_size;
+        let acct_key = accounting_key(key, window_start);
+
+        // 前の時間窓も参照できるよう、2窓分保持する
+        let mut pipe = redis::pipe();
+        pipe.cmd("HINCRBY")
+            .arg(&acct_key)
+            .arg("requests")
+            .arg(1)
+            .ignore()
+            .cmd("HINCRBY")
+            .arg(&acct_key)
+            .arg("bytes")
+            .arg(bytes)
+            .ignore()
+            .cmd("HINCRBY")
+            .arg(&acct_key)
+            .arg(format!("{}xx", status / 100))
+            .arg(1)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&acct_key)
+            .arg(window_size * 2)
+            .ignore();
+
+        self.query_commands::<()>(&mut conn, &pipe).await
+    }
+
+    // 応答が不審なステータスだったかを時間窓ごとに集計し、(応答数, 不審な応答数) を返す
+    pub async fn record_abuse_sample(
+        &self,
+        key: &str,
+        suspicious: bool,
+    ) -> Result<(u64, u64), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+        let window_size = self.config.window_size as u64;
+        let sample_key = abuse_key(key, now / window_size * window_size);
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("HINCRBY")
+            .arg(&sample_key)
+            .arg("total")
+            .arg(1)
+            .cmd("HINCRBY")
+            .arg(&sample_key)
+            .arg("suspicious")
+            .arg(suspicious as u64)
+            .cmd("EXPIRE")
+            .arg(&sample_key)
+            .arg(window_size * 2)
+            .ignore();
+
+        self.query_commands::<(u64, u64)>(&mut conn, &pipe).await
+    }
+
+    // 暦の期間ごとのクォータを1件消費する
+    //
+    // カウンタは期間の終わりに期限切れになる。クォータを超えたリクエストも数えるが、残りは0のままになる
+    pub async fn consume_quota(
+        &self,
+        key: &str,
+        quota: u64,
+        period: QuotaPeriod,
+    ) -> Result<QuotaDecision, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+        let (start, end) = period.window(now);
+        let counter_key = quota_key(key, start);
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("INCR")
+            .arg(&counter_key)
+            .cmd("EXPIREAT")
+            .arg(&counter_key)
+            .arg(end)
+            .ignore();
+
+        let (count,): (u64,) = self.query_commands(&mut conn, &pipe).await?;
+        Ok(QuotaDecision {
+            allowed: count <= quota,
+            remaining: quota.saturating_sub(count),
+            reset: end - now,
+        })
+    }
+
+    // 拒否したリクエストを ban_window の時間窓ごとに数え、ban_after 回目の拒否でキーをBANする
+    //
+    // BANした場合は (違反歴の回数, BANの期間) を返す。閾値ちょうどの拒否だけがBANするため、
+    // 同時に拒否された複数のリクエストが違反歴を重ねて数えることはない
+    pub async fn record_rejection(
+        &self,
+        key: &str,
+        policy: &BanPolicy,
+    ) -> Result<Option<(u64, u64)>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+        let window = policy.window.max(1);
+        let counter_key = rejection_key(key, now / window * window);
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("INCR")
+            .arg(&counter_key)
+            .cmd("EXPIRE")
+            .arg(&counter_key)
+            .arg(window)
+            .ignore();
+        let (count,): (u64,) = self.query_commands(&mut conn, &pipe).await?;
+        if count != policy.after as u64 {
+            return Ok(None);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("INCR").arg(offense_key(key));
+        let (offense,): (u64,) = self.query_commands(&mut conn, &pipe).await?;
+        let duration = policy.duration_for(offense);
+
+        // BANが解けた後に拒否数を数え直すよう、時間窓のカウンタは削除する
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(ban_key(key))
+            .arg("auto")
+            .arg("EX")
+            .arg(duration)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(offense_key(key))
+            .arg(policy.offense_ttl(duration))
+            .ignore()
+            .cmd("DEL")
+            .arg(&counter_key)
+            .ignore();
+        self.query_commands::<()>(&mut conn, &pipe).await?;
+        Ok(Some((offense, duration)))
+    }
+
+    // キーの上限を一定期間引き下げる（abuse_action=tighten）
+    pub async fn penalize(&self, key: &str, duration: u64) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match redis::cmd("SET")
+            .arg(penalty_key(key))
+            .arg("abuse")
+            .arg("EX")
+            .arg(duration.max(1))
+            .query_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(_) => {
+                info!("Tightened limits of {} for {}s", key, duration);
+                Ok(())
+            }
+            Err(err) => {
+                error!("Failed to tighten limits of {}: {}", key, err);
+                Err(format!("Failed to tighten limits: {}", err))
+            }
+        }
+    }
+
+    // キーの上限が引き下げられているかを確認する
+    pub async fn is_penalized(&self, key: &str) -> Result<bool, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let mut cmd = redis::cmd("EXISTS");
+        with_redis_key(PENALTY_PREFIX, key, None, |k| {
+            cmd.arg(k);
+        });
+
+        let command_timeout = self.config.redis_options.command_timeout;
+        match tokio::time::timeout(
+            Duration::from_millis(command_timeout),
+            cmd.query_async::<_, bool>(&mut conn),
+        )
+        .await
+        {
+            Ok(Ok(penalized)) => Ok(penalized),
+            Ok(Err(err)) => {
+                error!("Failed to check penalty for {}: {}", key, err);
+                Err(format!("Failed to check penalty: {}", err))
+            }
+            Err(_) => {
+                error!("Penalty check timed out after {}ms", command_timeout);
+                Err(format!(
+                    "Penalty check timed out after {}ms",
+                    command_timeout
+                ))
+            }
+        }
+    }
+
+    // トークンイントロスペクションのキャッシュを取得する（token_hash はトークンのSHA-256）
+    pub async fn get_introspection(&self, token_hash: &str) -> Result<Option<String>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match redis::cmd("GET")
+            .arg(introspection_key(token_hash))
+            .query_async::<_, Option<String>>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                error!("Failed to read introspection cache: {}", err);
+                Err(format!("Failed to read introspection cache: {}", err))
+            }
+        }
+    }
+
+    // トークンイントロスペクションの結果をttl秒キャッシュする
+    pub async fn cache_introspection(
+        &self,
+        token_hash: &str,
+        value: &str,
+        ttl: u64,
+    ) -> Result<(), String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        match redis::cmd("SET")
+            .arg(introspection_key(token_hash))
+            .arg(value)
+            .arg("EX")
+            .arg(ttl.max(1))
+            .query_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("Failed to cache introspection result: {}", err);
+                Err(format!("Failed to cache introspection result: {}", err))
+            }
+        }
+    }
+
+    // キーの現在の使用状況をカウンタを変更せずに取得する
+    pub async fn get_usage(&self, key: &str) -> Result<KeyUsage, String> {
+        self.ensure_scannable("Usage")?;
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs_f64();
+
+        let rate = rate_per_second(self.config.requests_per_second, self.config.rate_period);
+        let window_limit = window_limit(self.config.requests_per_second, self.config.burst) as f64;
+        let burst = bucket_capacity(self.config.burst);
+        let window_size = self.config.window_size.max(1) as u64;
+        let now_secs = now as u64;
+
+        // アルゴリズムごとに (カウント, 上限, リセットまでの秒数) を計算する
+        let (count, limit, reset_seconds) = match self.config.algorithm {
+            RateLimitAlgorithm::FixedWindow => {
+                let window_start = (now_secs / window_size) * window_size;
+                let count: Option<u64> = redis::cmd("GET")
+                    .arg(fixed_window_key(key, window_start))
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read fixed window counter: {}", e))?;
+                (
+                    count.unwrap_or(0) as f64,
+                    window_limit,
+                    window_start + window_size - now_secs,
+                )
+            }
+            RateLimitAlgorithm::SlidingWindow => {
+                let current_window = now_secs / window_size * window_size;
+                let previous_window = current_window.saturating_sub(window_size);
+                let (current, previous): (Option<u64>, Option<u64>) = redis::cmd("MGET")
+                    .arg(sliding_window_key(key, current_window))
+                    .arg(sliding_window_key(key, previous_window))
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read sliding window counters: {}", e))?;
+                let elapsed_ratio = (now - current_window as f64) / window_size as f64;
+                let weighted = current.unwrap_or(0) as f64
+                    + previous.unwrap_or(0) as f64 * (1.0 - elapsed_ratio);
+                (
+                    weighted,
+                    window_limit,
+                    current_window + window_size - now_secs,
+                )
+            }
+            RateLimitAlgorithm::TokenBucket => {
+                let (tokens, last_refill): (Option<f64>, Option<f64>) = redis::cmd("HMGET")
+                    .arg(token_bucket_key(key))
+                    .arg("tokens")
+                    .arg("last_refill")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read token bucket state: {}", e))?;
+                let tokens = match (tokens, last_refill) {
+                    (Some(tokens), Some(last_refill)) => {
+                        (tokens + (now - last_refill).max(0.0) * rate).min(burst)
+                    }
+                    _ => burst,
+                };
+                let refill = if rate > 0.0 {
+                    ((burst - tokens) / rate).ceil() as u64
+                } else {
+                    0
+                };
+                (burst - tokens, burst, refill)
+            }
+            RateLimitAlgorithm::LeakyBucket => {
+                let (level, last_leak): (Option<f64>, Option<f64>) = redis::cmd("HMGET")
+                    .arg(leaky_bucket_key(key))
+                    .arg("level")
+                    .arg("last_leak")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read leaky bucket state: {}", e))?;
+                let level = match (level, last_leak) {
+                    (Some(level), Some(last_leak)) => {
+                        (level - (now - last_leak).max(0.0) * rate).max(0.0)
+                    }
+                    _ => 0.0,
+                };
+                let drain = if rate > 0.0 {
+                    (level / rate).ceil() as u64
+                } else {
+                    0
+                };
+                (level, burst, drain)
+            }
+            RateLimitAlgorithm::LimitReq => {
+                let (excess, last): (Option<f64>, Option<f64>) = redis::cmd("HMGET")
+                    .arg(limit_req_key(key))
+                    .arg("excess")
+                    .arg("last")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read limit_req state: {}", e))?;
+                // 超過量はリクエストの1/1000単位、時刻はミリ秒で保持している
+                let excess = match (excess, last) {
+                    (Some(excess), Some(last)) => {
+                        (excess / 1000.0 - (now - last / 1000.0).max(0.0) * rate).max(0.0)
+                    }
+                    _ => 0.0,
+                };
+                let drain = if rate > 0.0 {
+                    (excess / rate).ceil() as u64
+                } else {
+                    0
+                };
+                (excess, burst, drain)
+            }
+            RateLimitAlgorithm::Gcra => {
+                let tat: Option<f64> = redis::cmd("HGET")
+                    .arg(gcra_key(key))
+                    .arg("tat")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read GCRA state: {}", e))?;
+                // TAT はマイクロ秒。現在時刻より先の分が使用中のリクエスト数に相当する
+                let ahead = tat.map_or(0.0, |tat| (tat / 1_000_000.0 - now).max(0.0));
+                (ahead * rate, burst, ahead.ceil() as u64)
+            }
+            RateLimitAlgorithm::SlidingLog => {
+                // 記録の時刻はミリ秒。窓から外れた記録は削除せずに数えない
+                let now_ms = (now * 1000.0) as u64;
+                let window_ms = window_size * 1000;
+                let (count, newest): (u64, Vec<(String, f64)>) = redis::pipe()
+                    .cmd("ZCOUNT")
+                    .arg(sliding_log_key(key))
+                    .arg(format!("({}", now_ms.saturating_sub(window_ms)))
+                    .arg("+inf")
+                    .cmd("ZRANGE")
+                    .arg(sliding_log_key(key))
+                    .arg(-1)
+                    .arg(-1)
+                    .arg("WITHSCORES")
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| format!("Failed to read sliding log: {}", e))?;
+                let reset = match newest.first() {
+                    Some((_, time)) if count > 0 => (*time as u64 + window_ms)
+                        .saturating_sub(now_ms)
+                        .div_ceil(1000),
+                    _ => 0,
+                };
+                (count as f64, window_limit, reset)
+            }
+        };
+
+        let ban_ttl: i64 = redis::cmd("TTL")
+            .arg(ban_key(key))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| format!("Failed to read ban state: {}", e))?;
+
+        let related = self.related_keys(&mut conn, key).await?;
+        let mut pipe = redis::pipe();
+        for redis_key in &related {
+            pipe.cmd("TTL").arg(redis_key);
+        }
+        let related_ttls: Vec<i64> = pipe
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| format!("Failed to read key TTLs: {}", e))?;
+        let ttls = related
+            .into_iter()
+            .zip(related_ttls)
+            .filter(|(_, ttl)| *ttl != -2)
+            .filter_map(|(redis_key, ttl)| {
+                let (kind, _, _) = decode_redis_key(&redis_key)?;
+                Some(KeyTtl {
+                    redis_key,
+                    kind,
+                    ttl,
+                })
+            })
+            .collect();
+
+        // TTLは -2: キーなし、-1: 有効期限なし
+        let banned = ban_ttl != -2;
+        let remaining = (limit - count).max(0.0).floor() as u64;
+        Ok(KeyUsage {
+            key: key.to_string(),
+            algorithm: self.config.algorithm.to_string(),
+            count,
+            limit: limit as u64,
+            remaining,
+            reset_seconds,
+            limited: banned || remaining == 0,
+            banned,
+            ban_ttl: if ban_ttl >= 0 {
+                Some(ban_ttl as u64)
+            } else {
+                None
+            },
+            ttls,
+        })
+    }
+
+    // 未ロードのLuaスクリプトを SCRIPT LOAD し、各スクリプトの状態を返す
+    //
+    // 起動時と、SCRIPT FLUSHやフェイルオーバーでスクリプトキャッシュが消えた後の再ロードに使う
+    pub async fn preload_scripts(&self) -> Result<Vec<ScriptStatus>, String> {
+        if self.mode == ExecutionMode::Functions {
+            return self.load_functions().await;
+        }
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let assets = scripts::all();
+        let shas: Vec<String> = assets.iter().map(|a| a.sha1()).collect();
+        let exists: Vec<bool> = match redis::cmd("SCRIPT")
+            .arg("EXISTS")
+            .arg(&shas)
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(exists) => exists,
+            Err(err) => {
+                error!("Failed to check script cache: {}", err);
+                return Err(format!("Failed to check script cache: {}", err));
+            }
+        };
+
+        let mut statuses = Vec::with_capacity(assets.len());
+        for ((asset, sha), loaded) in assets.iter().zip(shas).zip(exists) {
+            if !loaded {
+                let loaded_sha: String = match redis::cmd("SCRIPT")
+                    .arg("LOAD")
+                    .arg(asset.source)
+                    .query_async(&mut conn)
+                    .await
+                {
+                    Ok(sha) => sha,
+                    Err(err) => {
+                        error!("Failed to load {} script: {}", asset.id(), err);
+                        return Err(format!("Failed to load {} script: {}", asset.id(), err));
+                    }
+                };
+                if loaded_sha != sha {
+                    warn!(
+                        "SHA mismatch for {}: expected {}, Redis returned {}",
+                        asset.id(),
+                        sha,
+                        loaded_sha
+                    );
+                }
+                info!("Loaded {} script: {}", asset.id(), loaded_sha);
+            }
+            statuses.push(ScriptStatus {
+                name: asset.name.to_string(),
+                version: asset.version,
+                sha,
+                reloaded: !loaded,
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    // 各スクリプトを Redis Functions のライブラリとして登録し、各スクリプトの状態を返す
+    //
+    // ライブラリ名にバージョンを含めるため、既に登録されているライブラリは同じ内容とみなしてそのまま使う
+    async fn load_functions(&self) -> Result<Vec<ScriptStatus>, String> {
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let mut statuses = Vec::new();
+        for asset in scripts::all() {
+            let loaded = match redis::cmd("FUNCTION")
+                .arg("LOAD")
+                .arg(asset.function_library())
+                .query_async::<_, String>(&mut conn)
+                .await
+            {
+                Ok(_) => true,
+                Err(err) if err.to_string().contains("already exists") => false,
+                Err(err) => {
+                    error!("Failed to load {} function: {}", asset.id(), err);
+                    return Err(format!("Failed to load {} function: {}", asset.id(), err));
+                }
+            };
+            if loaded {
+                info!("Loaded {} function: {}", asset.id(), asset.function_name());
+            }
+            statuses.push(ScriptStatus {
+                name: asset.name.to_string(),
+                version: asset.version,
+                sha: asset.sha1(),
+                reloaded: loaded,
+            });
+        }
+
+        Ok(statuses)
+    }
 
-        // コマンドタイムアウトの設定
-        let command_timeout = self.config.redis_options.command_timeout;
-        let script_result = tokio::time::timeout(
-            Duration::from_millis(command_timeout),
-            redis::Script::new(script)
-                .key(current_key)
-                .key(previous_key)
-                .arg(now)
-                .arg(window_size)
-                .arg(self.config.requests_per_second)
-                .arg(self.config.burst)
-                .invoke_async(&mut conn),
-        )
-        .await;
+    // モジュールのプレフィックスを持つキーを1ページ分SCANして返す（次のカーソル, キー一覧）
+    pub async fn list_keys(
+        &self,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<TrackedKey>), String> {
+        self.ensure_scannable("Listing keys")?;
 
-        match script_result {
-            Ok(redis_result) => match redis_result {
-                Ok(val) => {
-                    debug!("Sliding window rate limit check for {}: {}", key, val);
-                    Ok(val == 1)
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let (next, redis_keys): (u64, Vec<String>) = match redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(format!("{}:*", KEY_NAMESPACE))
+            .arg("COUNT")
+            .arg(count.max(1))
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to scan Redis keys: {}", err);
+                return Err(format!("Failed to scan Redis keys: {}", err));
+            }
+        };
+
+        // TTLとカウンタ値を1回のパイプラインでまとめて取得する
+        let decoded: Vec<_> = redis_keys
+            .into_iter()
+            .filter_map(|k| decode_redis_key(&k).map(|d| (k, d)))
+            .collect();
+        if decoded.is_empty() {
+            return Ok((next, Vec::new()));
+        }
+
+        let mut pipe = redis::pipe();
+        for (redis_key, (kind, _, _)) in &decoded {
+            pipe.cmd("TTL").arg(redis_key);
+            match kind.as_str() {
+                "fixed" | "sliding" => {
+                    pipe.cmd("GET").arg(redis_key);
+                }
+                "token" => {
+                    pipe.cmd("HGET").arg(redis_key).arg("tokens");
+                }
+                "leaky" => {
+                    pipe.cmd("HGET").arg(redis_key).arg("level");
+                }
+                "limitreq" => {
+                    pipe.cmd("HGET").arg(redis_key).arg("excess");
+                }
+                "slidinglog" => {
+                    pipe.cmd("ZCARD").arg(redis_key);
+                }
+                _ => {
+                    pipe.cmd("EXISTS").arg(redis_key);
+                }
+            }
+        }
+
+        let values: Vec<redis::Value> = match pipe.query_async(&mut conn).await {
+            Ok(values) => values,
+            Err(err) => {
+                error!("Failed to read tracked keys: {}", err);
+                return Err(format!("Failed to read tracked keys: {}", err));
+            }
+        };
+
+        let mut keys = Vec::with_capacity(decoded.len());
+        for ((redis_key, (kind, key, window)), pair) in decoded.into_iter().zip(values.chunks(2)) {
+            let ttl: i64 = redis::from_redis_value(&pair[0]).unwrap_or(-2);
+            // 取得の間に期限切れになったキーは除外する
+            if ttl == -2 {
+                continue;
+            }
+            let count = match kind.as_str() {
+                "fixed" | "sliding" | "token" | "leaky" | "slidinglog" => {
+                    redis::from_redis_value::<Option<f64>>(&pair[1]).unwrap_or(None)
                 }
+                // 超過量はリクエストの1/1000単位で保持している
+                "limitreq" => redis::from_redis_value::<Option<f64>>(&pair[1])
+                    .unwrap_or(None)
+                    .map(|excess| excess / 1000.0),
+                _ => None,
+            };
+            keys.push(TrackedKey {
+                redis_key,
+                kind,
+                key,
+                window,
+                count,
+                ttl,
+            });
+        }
+
+        Ok((next, keys))
+    }
+
+    // 有効期限のないカウンタ、古いウィンドウ、不明な形式のキーをバッチ単位で削除する
+    pub async fn cleanup_keys(&self, options: &CleanupOptions) -> Result<CleanupReport, String> {
+        let mut report = CleanupReport::default();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, page) = self.cleanup_keys_page(cursor, options).await?;
+            report.scanned += page.scanned;
+            report.orphaned += page.orphaned;
+            report.deleted += page.deleted;
+            let room = 20 - report.samples.len().min(20);
+            report.samples.extend(page.samples.into_iter().take(room));
+
+            if next == 0 {
+                break;
+            }
+            // 大量削除でRedisを詰まらせないようにバッチ間で待機する
+            if page.deleted > 0 && options.pause_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(options.pause_ms)).await;
+            }
+            cursor = next;
+        }
+
+        info!(
+            "Cleanup finished: scanned={}, orphaned={}, deleted={}, dry_run={}",
+            report.scanned, report.orphaned, report.deleted, options.dry_run
+        );
+        Ok(report)
+    }
+
+    /// クリーンアップをSCANの1回分だけ実行し、(次のカーソル, そのページの結果) を返す
+    ///
+    /// 次のカーソルが0になるまで繰り返すと cleanup_keys と同じキーを対象にする。
+    /// 1回の呼び出しを短く保つ必要がある管理APIで使う（pause_ms は呼び出し側で調整する）
+    pub async fn cleanup_keys_page(
+        &self,
+        cursor: u64,
+        options: &CleanupOptions,
+    ) -> Result<(u64, CleanupReport), String> {
+        self.ensure_scannable("Cleanup")?;
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?.as_secs();
+        let mut report = CleanupReport::default();
+
+        let (next, redis_keys): (u64, Vec<String>) = match redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(format!("{}:*", KEY_PREFIX))
+            .arg("COUNT")
+            .arg(options.batch_size.max(1))
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to scan Redis keys: {}", err);
+                return Err(format!("Failed to scan Redis keys: {}", err));
+            }
+        };
+        report.scanned = redis_keys.len() as u64;
+
+        let mut pipe = redis::pipe();
+        for redis_key in &redis_keys {
+            pipe.cmd("TTL").arg(redis_key);
+        }
+        let ttls: Vec<i64> = if redis_keys.is_empty() {
+            Vec::new()
+        } else {
+            pipe.query_async(&mut conn)
+                .await
+                .map_err(|e| format!("Failed to read key TTLs: {}", e))?
+        };
+
+        let orphans: Vec<&String> = redis_keys
+            .iter()
+            .zip(ttls)
+            .filter(|(redis_key, ttl)| is_orphaned(redis_key, *ttl, now, options.max_window_age))
+            .map(|(redis_key, _)| redis_key)
+            .collect();
+
+        report.orphaned = orphans.len() as u64;
+        report.samples = orphans.iter().take(20).map(|k| k.to_string()).collect();
+
+        if !options.dry_run && !orphans.is_empty() {
+            // キーごとにハッシュスロットが異なるため、1キーずつDELする
+            let mut pipe = redis::pipe();
+            for redis_key in &orphans {
+                pipe.cmd("DEL").arg(redis_key);
+            }
+            let deleted: Vec<u64> = pipe
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| format!("Failed to delete orphaned keys: {}", e))?;
+            report.deleted = deleted.iter().sum::<u64>();
+        }
+
+        Ok((next, report))
+    }
+
+    // 旧形式のキーをTTLを保ったまま現在の形式にリネームする
+    //
+    // 移行先が既に存在する場合（新バージョンが既にカウントを始めている場合）は新しい値を優先する
+    pub async fn migrate_keys(
+        &self,
+        batch_size: usize,
+        dry_run: bool,
+    ) -> Result<MigrationReport, String> {
+        self.ensure_scannable("Migration")?;
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let mut report = MigrationReport::default();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, redis_keys): (u64, Vec<String>) = match redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(format!("{}:*", KEY_PREFIX))
+                .arg("COUNT")
+                .arg(batch_size.max(1))
+                .query_async(&mut conn)
+                .await
+            {
+                Ok(result) => result,
                 Err(err) => {
-                    error!(
-                        "Failed to execute sliding window rate limit script: {}",
-                        err
-                    );
-                    Err(format!(
-                        "Failed to execute sliding window rate limit script: {}",
-                        err
-                    ))
+                    error!("Failed to scan Redis keys: {}", err);
+                    return Err(format!("Failed to scan Redis keys: {}", err));
+                }
+            };
+            report.scanned += redis_keys.len() as u64;
+
+            for old_key in redis_keys {
+                let new_key = match migrate_legacy_key(&old_key) {
+                    Some(new_key) => new_key,
+                    None => continue,
+                };
+
+                if dry_run {
+                    report.migrated += 1;
+                    continue;
+                }
+
+                // RENAMENXはTTLを引き継ぐ
+                let renamed: bool = match redis::cmd("RENAMENX")
+                    .arg(&old_key)
+                    .arg(&new_key)
+                    .query_async(&mut conn)
+                    .await
+                {
+                    Ok(renamed) => renamed,
+                    Err(err) => {
+                        error!("Failed to migrate {}: {}", old_key, err);
+                        return Err(format!("Failed to migrate {}: {}", old_key, err));
+                    }
+                };
+
+                if renamed {
+                    report.migrated += 1;
+                } else {
+                    let _: () = conn
+                        .del(&old_key)
+                        .await
+                        .map_err(|e| format!("Failed to delete {}: {}", old_key, e))?;
+                    report.conflicts += 1;
                 }
-            },
-            Err(_) => {
-                error!(
-                    "Sliding window rate limit check timed out after {}ms",
-                    command_timeout
-                );
-                Err(format!(
-                    "Sliding window rate limit check timed out after {}ms",
-                    command_timeout
-                ))
             }
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
         }
+
+        info!(
+            "Key migration to v{} finished: scanned={}, migrated={}, conflicts={}, dry_run={}",
+            KEY_SCHEMA_VERSION, report.scanned, report.migrated, report.conflicts, dry_run
+        );
+        Ok(report)
     }
 
-    // トークンバケットアルゴリズム
-    async fn check_token_bucket(&self, key: &str) -> Result<bool, String> {
+    // ウィンドウカウンタを集計し、リクエスト数の多いキーを上位から返す
+    pub async fn top_keys(&self, limit: usize) -> Result<Vec<(String, u64)>, String> {
+        self.ensure_scannable("Top keys")?;
+
         let mut conn = match self.get_connection().await {
             Ok(conn) => conn,
             Err(err) => {
@@ -516,98 +3637,344 @@ impl RedisRateLimiter {
             }
         };
 
-        // 現在のタイムスタンプ（秒）
-        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                error!("SystemTime before UNIX EPOCH!");
-                return Err("SystemTime before UNIX EPOCH!".to_string());
-            }
-        };
-
-        let redis_key = format!("ratelimit:token:{}", key);
-        let refill_time = 1.0 / self.config.requests_per_second as f64; // トークン1つが補充される時間（秒）
-
-        // トークンバケットの実装
-        let script = r#"
-            local key = KEYS[1]
-            local now = tonumber(ARGV[1])
-            local refill_time = tonumber(ARGV[2])
-            local burst = tonumber(ARGV[3])
-            local window_size = tonumber(ARGV[4])
-
-            -- キーが存在するか確認
-            local exists = redis.call('EXISTS', key)
-
-            if exists == 0 then
-                -- 新規キー: バケットを最大容量で初期化
-                redis.call('HSET', key, 'tokens', burst, 'last_refill', now)
-                redis.call('EXPIRE', key, window_size * 2)
-                return 1 -- 許可
-            else
-                -- 既存キー: 最後の補充からの経過時間に基づいてトークンを補充
-                local tokens = tonumber(redis.call('HGET', key, 'tokens'))
-                local last_refill = tonumber(redis.call('HGET', key, 'last_refill'))
-
-                -- 経過時間からトークン補充数を計算
-                local elapsed = now - last_refill
-                local new_tokens = math.min(burst, tokens + elapsed / refill_time)
-
-                if new_tokens >= 1 then
-                    -- トークンが利用可能: トークンを消費
-                    redis.call('HSET', key, 'tokens', new_tokens - 1, 'last_refill', now)
-                    return 1 -- 許可
-                else
-                    -- トークンが不足: 補充時間だけ更新
-                    redis.call('HSET', key, 'last_refill', now)
-                    return 0 -- 拒否
-                end
-            end
-        "#;
+        let mut totals: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+        for kind in ["fixed", "sliding"] {
+            let prefix = format!("{}:{}:", KEY_NAMESPACE, kind);
+            let redis_keys = self.scan_keys(&mut conn, &format!("{}*", prefix)).await?;
+
+            for chunk in redis_keys.chunks(100) {
+                // キーごとにハッシュスロットが異なるため、MGETではなく1キーずつGETする
+                let mut pipe = redis::pipe();
+                for redis_key in chunk {
+                    pipe.cmd("GET").arg(redis_key);
+                }
+                let counts: Vec<Option<u64>> = match pipe.query_async(&mut conn).await {
+                    Ok(counts) => counts,
+                    Err(err) => {
+                        error!("Failed to read counters: {}", err);
+                        return Err(format!("Failed to read counters: {}", err));
+                    }
+                };
+
+                for (redis_key, count) in chunk.iter().zip(counts) {
+                    // "ratelimit:v3:<kind>:{<key>}:<window>" から <key> を取り出す
+                    let key = match decode_redis_key(redis_key) {
+                        Some((_, key, _)) => key,
+                        None => continue,
+                    };
+                    *totals.entry(key).or_insert(0) += count.unwrap_or(0);
+                }
+            }
+        }
+
+        let mut sorted: Vec<(String, u64)> = totals.into_iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sorted.truncate(limit);
+        Ok(sorted)
+    }
+
+    // キー空間をSCANする管理操作をクラスタでは拒否する
+    //
+    // クラスタ接続のSCANは任意の1ノードにしか送られないため、他のノードのキーを見落としたまま
+    // 完了したように見えてしまう
+    fn ensure_scannable(&self, operation: &str) -> Result<(), String> {
+        if self.config.redis_options.cluster_mode {
+            return Err(format!(
+                "{} is not supported with cluster_mode: SCAN only reaches one node of the cluster",
+                operation
+            ));
+        }
+        Ok(())
+    }
+
+    // SCANでパターンに一致するキーをすべて取得する（KEYSによるブロッキングを避ける）
+    async fn scan_keys(&self, conn: &mut Connection, pattern: &str) -> Result<Vec<String>, String> {
+        let mut keys = Vec::new();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = match redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(100)
+                .query_async(conn)
+                .await
+            {
+                Ok(result) => result,
+                Err(err) => {
+                    error!("Failed to scan Redis keys: {}", err);
+                    return Err(format!("Failed to scan Redis keys: {}", err));
+                }
+            };
+
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        Ok(keys)
+    }
+
+    // レートリミットのチェック
+    pub async fn check_rate_limit(&self, key: &str) -> Result<RateLimitDecision, String> {
+        self.check_rate_limit_with(key, self.config.requests_per_second, self.config.burst)
+            .await
+    }
+
+    // レート・バーストを指定してレートリミットをチェックする（実行時の上書き設定用）
+    pub async fn check_rate_limit_with(
+        &self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
+        if rate == 0 {
+            return Ok(RateLimitDecision::deny_all(self.config.window_size));
+        }
+        let key = CounterKey::Client(key);
+        match self.mode {
+            ExecutionMode::Commands => self.check_with_commands(key, rate, burst).await,
+            ExecutionMode::Scripts => self.check_script(key, rate, burst).await,
+            // FCALL はスクリプトの呼び出し（ScriptInvocation）を使えないため、1件のパイプラインで送る
+            ExecutionMode::Functions => self
+                .check_rate_limits(&[(key, rate, burst)])
+                .await
+                .map(|decisions| decisions[0]),
+        }
+    }
+
+    /// 判定に使用する時計を差し替える（テストで時刻を制御する場合など）
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // 現在時刻（UNIXエポックからの経過時間）
+    fn now(&self) -> Result<Duration, String> {
+        self.clock.now().map_err(|e| {
+            error!("{}", e);
+            e
+        })
+    }
+
+    // 上書き設定がない場合に適用されるレート・バースト
+    pub fn default_limits(&self) -> LimitOverride {
+        LimitOverride {
+            rate: self.config.requests_per_second,
+            burst: self.config.burst,
+        }
+    }
+
+    /// 複数のキーのレート制限を1つの接続でパイプライン実行する
+    ///
+    /// 1リクエストに複数のゾーンやLocation全体のカウンタ（global_rate=）が適用される場合に、
+    /// その数によらず往復を1回にまとめる。
+    /// クラスタモードではキーごとに所有するノードへ送るため、往復はゾーンの数だけになる。
+    /// スクリプトがキャッシュにない場合（NOSCRIPT）はロードしてから1度だけ再実行する。
+    /// rate=0 のチェックはRedisに送らずに拒否する
+    pub async fn check_rate_limits(
+        &self,
+        checks: &[(CounterKey<'_>, u32, u32)],
+    ) -> Result<Vec<RateLimitDecision>, String> {
+        // 単一キーのコマンドで判定する場合と、ゾーンのキーが別々のノードに置かれるクラスタでは
+        // 1件ずつ判定する（パイプラインは1つのノードにしか送れない）。
+        // 応答は並行して待ち、チェックの数によらず追加の遅延を1往復程度に抑える
+        let cluster = self.config.redis_options.cluster_mode && self.mode == ExecutionMode::Scripts;
+        if self.mode == ExecutionMode::Commands || (cluster && checks.len() > 1) {
+            let decisions = join_all(checks.iter().map(|(key, rate, burst)| async move {
+                if *rate == 0 {
+                    Ok(RateLimitDecision::deny_all(self.config.window_size))
+                } else if cluster {
+                    self.check_script(*key, *rate, *burst).await
+                } else {
+                    self.check_with_commands(*key, *rate, *burst).await
+                }
+            }))
+            .await;
+            return decisions.into_iter().collect();
+        }
+
+        if checks.iter().all(|(_, rate, _)| *rate == 0) {
+            return Ok(checks
+                .iter()
+                .map(|_| RateLimitDecision::deny_all(self.config.window_size))
+                .collect());
+        }
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?;
+
+        let mut pipe = redis::pipe();
+        for (key, rate, burst) in checks.iter().filter(|(_, rate, _)| *rate > 0) {
+            self.push_check(&mut pipe, *key, *rate, *burst, now)?;
+        }
+
+        let command_timeout = self.config.redis_options.command_timeout;
+        for attempt in 0..2 {
+            let result = tokio::time::timeout(
+                Duration::from_millis(command_timeout),
+                with_faults(pipe.query_async::<_, Vec<Vec<i64>>>(&mut conn)),
+            )
+            .await;
+
+            match result {
+                Ok(Ok(replies)) => {
+                    // 応答は rate=0 を除いたチェックの順に並ぶ
+                    let mut replies = replies.iter();
+                    return checks
+                        .iter()
+                        .map(|(_, rate, _)| {
+                            if *rate == 0 {
+                                return Ok(RateLimitDecision::deny_all(self.config.window_size));
+                            }
+                            match replies.next() {
+                                Some(reply) => {
+                                    RateLimitDecision::from_reply(self.config.algorithm, reply)
+                                }
+                                None => Err("Missing reply in rate limit pipeline".to_string()),
+                            }
+                        })
+                        .collect();
+                }
+                Ok(Err(err)) if is_missing_script(&err) && attempt == 0 => {
+                    warn!("Rate limit scripts not loaded in Redis, reloading: {}", err);
+                    self.preload_scripts().await?;
+                }
+                Ok(Err(err)) => {
+                    error!("Failed to execute rate limit pipeline: {}", err);
+                    return Err(format!("Failed to execute rate limit pipeline: {}", err));
+                }
+                Err(_) => {
+                    error!("Rate limit pipeline timed out after {}ms", command_timeout);
+                    return Err(format!(
+                        "Rate limit pipeline timed out after {}ms",
+                        command_timeout
+                    ));
+                }
+            }
+        }
+
+        Err("Rate limit scripts are missing after reloading".to_string())
+    }
+
+    // アルゴリズムのスクリプト呼び出し（EVALSHA または FCALL）をパイプラインに1件追加する
+    //
+    // キーと引数は check_script と同じく encode_check で組み立てる
+    fn push_check(
+        &self,
+        pipe: &mut redis::Pipeline,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+        now: Duration,
+    ) -> Result<(), String> {
+        let algorithm = self.config.algorithm;
+        let script = self.scripts.get(algorithm).ok_or_else(|| {
+            format!(
+                "Rate limit algorithm {} is not available in this build",
+                algorithm
+            )
+        })?;
+        if self.mode == ExecutionMode::Functions {
+            pipe.cmd("FCALL")
+                .arg(executor::script_for(algorithm)?.function_name());
+        } else {
+            pipe.cmd("EVALSHA").arg(script.get_hash());
+        }
+        pipe.arg(executor::key_count(algorithm));
+        executor::encode_check(&self.config, key, rate, burst, now, pipe)
+    }
+
+    // アルゴリズムのスクリプトで1件判定する
+    async fn check_script(
+        &self,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
+        let algorithm = self.config.algorithm;
+        let script = self.scripts.get(algorithm).ok_or_else(|| {
+            format!(
+                "Rate limit algorithm {} is not available in this build",
+                algorithm
+            )
+        })?;
+
+        let mut conn = match self.get_connection().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Failed to get Redis connection: {}", err);
+                return Err(format!("Failed to get Redis connection: {}", err));
+            }
+        };
+
+        let now = self.now()?;
+
+        let mut invocation = script.prepare_invoke();
+        executor::encode_check(&self.config, key, rate, burst, now, &mut invocation)?;
 
         // コマンドタイムアウトの設定
         let command_timeout = self.config.redis_options.command_timeout;
         let script_result = tokio::time::timeout(
             Duration::from_millis(command_timeout),
-            redis::Script::new(script)
-                .key(redis_key)
-                .arg(now)
-                .arg(refill_time)
-                .arg(self.config.burst)
-                .arg(self.config.window_size)
-                .invoke_async(&mut conn),
+            with_faults(invocation.invoke_async::<_, Vec<i64>>(&mut conn)),
         )
         .await;
 
         match script_result {
-            Ok(redis_result) => match redis_result {
-                Ok(val) => {
-                    debug!("Token bucket rate limit check for {}: {}", key, val);
-                    Ok(val == 1)
-                }
-                Err(err) => {
-                    error!("Failed to execute token bucket rate limit script: {}", err);
-                    Err(format!(
-                        "Failed to execute token bucket rate limit script: {}",
-                        err
-                    ))
-                }
-            },
+            Ok(Ok(reply)) => {
+                let decision = RateLimitDecision::from_reply(algorithm, &reply)?;
+                debug!(
+                    "{} rate limit check for {}: {:?}",
+                    algorithm,
+                    key.name(),
+                    decision
+                );
+                Ok(decision)
+            }
+            Ok(Err(err)) => {
+                error!("Failed to execute {} rate limit script: {}", algorithm, err);
+                Err(format!(
+                    "Failed to execute {} rate limit script: {}",
+                    algorithm, err
+                ))
+            }
             Err(_) => {
                 error!(
-                    "Token bucket rate limit check timed out after {}ms",
-                    command_timeout
+                    "{} rate limit check timed out after {}ms",
+                    algorithm, command_timeout
                 );
                 Err(format!(
-                    "Token bucket rate limit check timed out after {}ms",
-                    command_timeout
+                    "{} rate limit check timed out after {}ms",
+                    algorithm, command_timeout
                 ))
             }
         }
     }
 
-    // リーキーバケットアルゴリズム
-    async fn check_leaky_bucket(&self, key: &str) -> Result<bool, String> {
+    // プロキシ互換モード（compat=proxy）のレート制限チェック
+    //
+    // Twemproxy や Envoy の Redis プロキシはEVALや複数キーのスクリプトを転送できないため、
+    // 単一キーのコマンドだけをパイプライン（MULTIなし）で送信し、判定はスクリプトと同じ計算を
+    // ここで行う。バケット系は読み込みと書き込みが別の往復になり、その間の同時リクエストにより
+    // わずかに上限を超えて許可することがある
+    async fn check_with_commands(
+        &self,
+        key: CounterKey<'_>,
+        rate: u32,
+        burst: u32,
+    ) -> Result<RateLimitDecision, String> {
         let mut conn = match self.get_connection().await {
             Ok(conn) => conn,
             Err(err) => {
@@ -616,95 +3983,386 @@ impl RedisRateLimiter {
             }
         };
 
-        // 現在のタイムスタンプ（秒）
-        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs() as f64 + n.subsec_micros() as f64 / 1_000_000.0,
-            Err(_) => {
-                error!("SystemTime before UNIX EPOCH!");
-                return Err("SystemTime before UNIX EPOCH!".to_string());
-            }
-        };
-
-        let redis_key = format!("ratelimit:leaky:{}", key);
-        let rate = self.config.requests_per_second as f64; // 1秒あたりの処理レート
-        let bucket_size = self.config.burst as f64; // バケットサイズ
-
-        // リーキーバケットの実装
-        let script = r#"
-            local key = KEYS[1]
-            local now = tonumber(ARGV[1])
-            local rate = tonumber(ARGV[2])
-            local bucket_size = tonumber(ARGV[3])
-            local window_size = tonumber(ARGV[4])
-
-            -- キーが存在するか確認
-            local exists = redis.call('EXISTS', key)
-
-            if exists == 0 then
-                -- 新規キー: レベルを1で初期化、最後のリークタイムを現在に設定
-                redis.call('HSET', key, 'level', 1, 'last_leak', now)
-                redis.call('EXPIRE', key, window_size * 2)
-                return 1 -- 許可
-            else
-                -- 既存キー: 前回のリークからの経過時間に基づいてバケットをリーク
-                local level = tonumber(redis.call('HGET', key, 'level'))
-                local last_leak = tonumber(redis.call('HGET', key, 'last_leak'))
-
-                -- 経過時間から減少したレベルを計算
-                local elapsed = now - last_leak
-                local leaked = rate * elapsed
-                local new_level = math.max(0, level - leaked)
-
-                -- 新しいリクエストを追加（水位を上げる）
-                new_level = new_level + 1
-
-                if new_level <= bucket_size then
-                    -- バケットがオーバーフローしていない: リクエストを許可
-                    redis.call('HSET', key, 'level', new_level, 'last_leak', now)
-                    return 1 -- 許可
-                else
-                    -- バケットがオーバーフロー: リクエストを拒否（タイムスタンプだけ更新）
-                    redis.call('HSET', key, 'last_leak', now)
-                    return 0 -- 拒否
-                end
-            end
-        "#;
+        let now = self.now()?;
+        let secs = now.as_secs();
+        let window_size = self.config.window_size as u64;
 
-        // コマンドタイムアウトの設定
-        let command_timeout = self.config.redis_options.command_timeout;
-        let script_result = tokio::time::timeout(
-            Duration::from_millis(command_timeout),
-            redis::Script::new(script)
-                .key(redis_key)
-                .arg(now)
-                .arg(rate)
-                .arg(bucket_size)
-                .arg(self.config.window_size)
-                .invoke_async(&mut conn),
-        )
-        .await;
+        let decision = match self.config.algorithm {
+            #[cfg(feature = "algo-fixed-window")]
+            RateLimitAlgorithm::FixedWindow => {
+                let counter_key =
+                    key.redis_key(FIXED_WINDOW_PREFIX, Some(secs / window_size * window_size));
+                let mut pipe = redis::pipe();
+                // キーがない場合のみ有効期限付きで作成してからINCRする
+                pipe.cmd("SET")
+                    .arg(&counter_key)
+                    .arg(0)
+                    .arg("EX")
+                    .arg(window_size)
+                    .arg("NX")
+                    .ignore()
+                    .cmd("INCR")
+                    .arg(&counter_key)
+                    .cmd("TTL")
+                    .arg(&counter_key);
+                let (count, ttl): (u64, i64) = self.query_commands(&mut conn, &pipe).await?;
 
-        match script_result {
-            Ok(redis_result) => match redis_result {
-                Ok(val) => {
-                    debug!("Leaky bucket rate limit check for {}: {}", key, val);
-                    Ok(val == 1)
+                let limit = window_limit(rate, burst);
+                RateLimitDecision {
+                    allowed: count <= limit,
+                    remaining: limit.saturating_sub(count),
+                    reset: ttl.max(0) as u64,
+                    count,
+                    excess: None,
+                    retry_after_ms: None,
                 }
-                Err(err) => {
-                    error!("Failed to execute leaky bucket rate limit script: {}", err);
-                    Err(format!(
-                        "Failed to execute leaky bucket rate limit script: {}",
-                        err
-                    ))
+            }
+            #[cfg(feature = "algo-sliding-window")]
+            RateLimitAlgorithm::SlidingWindow => {
+                let current_window = secs / window_size * window_size;
+                let current_key = key.redis_key(SLIDING_WINDOW_PREFIX, Some(current_window));
+                let mut pipe = redis::pipe();
+                pipe.cmd("SET")
+                    .arg(&current_key)
+                    .arg(0)
+                    .arg("EX")
+                    .arg(window_size * 2)
+                    .arg("NX")
+                    .ignore()
+                    .cmd("INCR")
+                    .arg(&current_key)
+                    .cmd("GET")
+                    .arg(key.redis_key(SLIDING_WINDOW_PREFIX, Some(current_window - window_size)));
+                let (current_count, previous_count): (u64, Option<u64>) =
+                    self.query_commands(&mut conn, &pipe).await?;
+
+                let elapsed_ratio = (secs - current_window) as f64 / window_size as f64;
+                let weighted_count = current_count as f64
+                    + previous_count.unwrap_or(0) as f64 * (1.0 - elapsed_ratio);
+                let limit = window_limit(rate, burst) as f64;
+                RateLimitDecision {
+                    allowed: weighted_count <= limit,
+                    remaining: (limit - weighted_count).floor().max(0.0) as u64,
+                    reset: current_window + window_size - secs,
+                    count: weighted_count.floor() as u64,
+                    excess: None,
+                    retry_after_ms: None,
                 }
-            },
-            Err(_) => {
-                error!(
-                    "Leaky bucket rate limit check timed out after {}ms",
-                    command_timeout
+            }
+            #[cfg(feature = "algo-token-bucket")]
+            RateLimitAlgorithm::TokenBucket => {
+                let bucket_key = key.redis_key(TOKEN_BUCKET_PREFIX, None);
+                let now = secs as f64;
+                let refill_time = 1.0 / rate_per_second(rate, self.config.rate_period);
+                let burst = bucket_capacity(burst);
+
+                let mut read = redis::pipe();
+                read.cmd("HMGET")
+                    .arg(&bucket_key)
+                    .arg("tokens")
+                    .arg("last_refill");
+                let ((tokens, last_refill),): ((Option<f64>, Option<f64>),) =
+                    self.query_commands(&mut conn, &read).await?;
+
+                let mut write = redis::pipe();
+                let decision = match (tokens, last_refill) {
+                    (Some(tokens), Some(last_refill)) => {
+                        let mut new_tokens = burst.min(tokens + (now - last_refill) / refill_time);
+                        let allowed = new_tokens >= 1.0;
+                        if allowed {
+                            new_tokens -= 1.0;
+                            write
+                                .cmd("HSET")
+                                .arg(&bucket_key)
+                                .arg("tokens")
+                                .arg(new_tokens)
+                                .arg("last_refill")
+                                .arg(now)
+                                .ignore();
+                        } else {
+                            // トークンが不足: 補充時間だけ更新
+                            write
+                                .cmd("HSET")
+                                .arg(&bucket_key)
+                                .arg("last_refill")
+                                .arg(now)
+                                .ignore();
+                        }
+                        RateLimitDecision {
+                            allowed,
+                            remaining: new_tokens.floor() as u64,
+                            reset: ((burst - new_tokens) * refill_time).ceil() as u64,
+                            count: (burst - new_tokens).ceil() as u64,
+                            excess: None,
+                            retry_after_ms: None,
+                        }
+                    }
+                    _ => {
+                        // 新規キー: バケットを最大容量で初期化
+                        write
+                            .cmd("HSET")
+                            .arg(&bucket_key)
+                            .arg("tokens")
+                            .arg(burst)
+                            .arg("last_refill")
+                            .arg(now)
+                            .ignore()
+                            .cmd("EXPIRE")
+                            .arg(&bucket_key)
+                            .arg(window_size * 2)
+                            .ignore();
+                        RateLimitDecision {
+                            allowed: true,
+                            remaining: burst as u64,
+                            reset: 0,
+                            count: 0,
+                            excess: None,
+                            retry_after_ms: None,
+                        }
+                    }
+                };
+                self.query_commands::<()>(&mut conn, &write).await?;
+                decision
+            }
+            #[cfg(feature = "algo-leaky-bucket")]
+            RateLimitAlgorithm::LeakyBucket => {
+                let bucket_key = key.redis_key(LEAKY_BUCKET_PREFIX, None);
+                let now = now.as_secs_f64();
+                let rate = rate_per_second(rate, self.config.rate_period);
+                let bucket_size = bucket_capacity(burst);
+
+                let mut read = redis::pipe();
+                read.cmd("HMGET")
+                    .arg(&bucket_key)
+                    .arg("level")
+                    .arg("last_leak");
+                let ((level, last_leak),): ((Option<f64>, Option<f64>),) =
+                    self.query_commands(&mut conn, &read).await?;
+
+                let mut write = redis::pipe();
+                let decision = match (level, last_leak) {
+                    (Some(level), Some(last_leak)) => {
+                        let new_level = (level - rate * (now - last_leak)).max(0.0) + 1.0;
+                        if new_level <= bucket_size {
+                            write
+                                .cmd("HSET")
+                                .arg(&bucket_key)
+                                .arg("level")
+                                .arg(new_level)
+                                .arg("last_leak")
+                                .arg(now)
+                                .ignore();
+                            RateLimitDecision {
+                                allowed: true,
+                                remaining: (bucket_size - new_level).floor().max(0.0) as u64,
+                                reset: (new_level / rate).ceil() as u64,
+                                count: new_level.ceil() as u64,
+                                excess: None,
+                                retry_after_ms: None,
+                            }
+                        } else {
+                            // オーバーフロー: タイムスタンプだけ更新
+                            write
+                                .cmd("HSET")
+                                .arg(&bucket_key)
+                                .arg("last_leak")
+                                .arg(now)
+                                .ignore();
+                            RateLimitDecision {
+                                allowed: false,
+                                remaining: 0,
+                                reset: (level / rate).ceil() as u64,
+                                count: level.ceil() as u64,
+                                excess: None,
+                                retry_after_ms: None,
+                            }
+                        }
+                    }
+                    _ => {
+                        // 新規キー: レベルを1で初期化
+                        write
+                            .cmd("HSET")
+                            .arg(&bucket_key)
+                            .arg("level")
+                            .arg(1)
+                            .arg("last_leak")
+                            .arg(now)
+                            .ignore()
+                            .cmd("EXPIRE")
+                            .arg(&bucket_key)
+                            .arg(window_size * 2)
+                            .ignore();
+                        RateLimitDecision {
+                            allowed: true,
+                            remaining: (bucket_size - 1.0).floor().max(0.0) as u64,
+                            reset: (1.0 / rate).ceil() as u64,
+                            count: 1,
+                            excess: None,
+                            retry_after_ms: None,
+                        }
+                    }
+                };
+                self.query_commands::<()>(&mut conn, &write).await?;
+                decision
+            }
+            #[cfg(feature = "algo-limit-req")]
+            RateLimitAlgorithm::LimitReq => {
+                let state_key = key.redis_key(LIMIT_REQ_PREFIX, None);
+                let rate = limit_req_rate(rate, self.config.rate_period);
+
+                let mut read = redis::pipe();
+                read.cmd("HMGET").arg(&state_key).arg("excess").arg("last");
+                let ((excess, last),): ((Option<u64>, Option<u64>),) =
+                    self.query_commands(&mut conn, &read).await?;
+
+                let (decision, state) = limit_req_decision(
+                    excess.zip(last),
+                    now.as_millis() as u64,
+                    rate,
+                    burst as u64 * 1000,
+                );
+                if let Some((excess, last)) = state {
+                    let mut write = redis::pipe();
+                    write
+                        .cmd("HSET")
+                        .arg(&state_key)
+                        .arg("excess")
+                        .arg(excess)
+                        .arg("last")
+                        .arg(last)
+                        .ignore()
+                        .cmd("PEXPIRE")
+                        .arg(&state_key)
+                        .arg(limit_req_ttl_ms(excess, rate))
+                        .ignore();
+                    self.query_commands::<()>(&mut conn, &write).await?;
+                }
+                decision
+            }
+            #[cfg(feature = "algo-gcra")]
+            RateLimitAlgorithm::Gcra => {
+                let state_key = key.redis_key(GCRA_PREFIX, None);
+                let now_us = now.as_micros() as u64;
+
+                let mut read = redis::pipe();
+                read.cmd("HGET").arg(&state_key).arg("tat");
+                let (tat,): (Option<u64>,) = self.query_commands(&mut conn, &read).await?;
+
+                let (decision, tat) = gcra_decision(
+                    tat,
+                    now_us,
+                    gcra_interval_us(rate, self.config.rate_period),
+                    bucket_capacity(burst) as u64,
+                );
+                if let Some(tat) = tat {
+                    let mut write = redis::pipe();
+                    write
+                        .cmd("HSET")
+                        .arg(&state_key)
+                        .arg("tat")
+                        .arg(tat)
+                        .ignore()
+                        .cmd("PEXPIRE")
+                        .arg(&state_key)
+                        .arg(gcra_ttl_ms(tat, now_us))
+                        .ignore();
+                    self.query_commands::<()>(&mut conn, &write).await?;
+                }
+                decision
+            }
+            #[cfg(feature = "algo-sliding-log")]
+            RateLimitAlgorithm::SlidingLog => {
+                let log_key = key.redis_key(SLIDING_LOG_PREFIX, None);
+                let now_ms = now.as_millis() as u64;
+                let window_ms = window_size * 1000;
+
+                let mut read = redis::pipe();
+                read.cmd("ZREMRANGEBYSCORE")
+                    .arg(&log_key)
+                    .arg("-inf")
+                    .arg(now_ms.saturating_sub(window_ms))
+                    .ignore()
+                    .cmd("ZCARD")
+                    .arg(&log_key)
+                    .cmd("ZRANGE")
+                    .arg(&log_key)
+                    .arg(0)
+                    .arg(0)
+                    .arg("WITHSCORES")
+                    .cmd("ZRANGE")
+                    .arg(&log_key)
+                    .arg(-1)
+                    .arg(-1)
+                    .arg("WITHSCORES");
+                let (count, oldest, newest): SlidingLogRead =
+                    self.query_commands(&mut conn, &read).await?;
+
+                let decision = sliding_log_decision(
+                    (
+                        count,
+                        oldest.first().map(|(_, time)| *time),
+                        newest.first().map(|(_, time)| *time),
+                    ),
+                    now_ms,
+                    window_ms,
+                    window_limit(rate, burst),
                 );
+                if decision.allowed {
+                    let mut write = redis::pipe();
+                    write
+                        .cmd("ZADD")
+                        .arg(&log_key)
+                        .arg(now_ms)
+                        .arg(format!("{}:{}", now_ms, sliding_log_id()))
+                        .ignore()
+                        .cmd("PEXPIRE")
+                        .arg(&log_key)
+                        .arg(window_ms)
+                        .ignore();
+                    self.query_commands::<()>(&mut conn, &write).await?;
+                }
+                decision
+            }
+            #[allow(unreachable_patterns)]
+            algorithm => {
+                return Err(format!(
+                    "Rate limit algorithm {} is not available in this build",
+                    algorithm
+                ))
+            }
+        };
+
+        debug!(
+            "{} rate limit check (proxy mode) for {}: {:?}",
+            self.config.algorithm,
+            key.name(),
+            decision
+        );
+        Ok(decision)
+    }
+
+    // コマンドタイムアウトを適用してパイプラインを実行する（プロキシ互換モード用）
+    async fn query_commands<T: redis::FromRedisValue>(
+        &self,
+        conn: &mut Connection,
+        pipe: &redis::Pipeline,
+    ) -> Result<T, String> {
+        let command_timeout = self.config.redis_options.command_timeout;
+        match tokio::time::timeout(
+            Duration::from_millis(command_timeout),
+            with_faults(pipe.query_async::<_, T>(conn)),
+        )
+        .await
+        {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => {
+                error!("Failed to execute rate limit commands: {}", err);
+                Err(format!("Failed to execute rate limit commands: {}", err))
+            }
+            Err(_) => {
+                error!("Rate limit commands timed out after {}ms", command_timeout);
                 Err(format!(
-                    "Leaky bucket rate limit check timed out after {}ms",
+                    "Rate limit commands timed out after {}ms",
                     command_timeout
                 ))
             }
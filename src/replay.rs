@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::RateLimitBackend;
+use crate::clock::{days_from_civil, ManualClock};
+use crate::config::{ConfigFile, RateLimitSettings};
+use crate::memory::MemoryBackend;
+use crate::redis_client::RateLimitConfig;
+
+/// アクセスログの1行から取り出した、レート制限の判定に必要な値
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogEntry {
+    /// リクエストの時刻（UNIXエポックからの経過時間）
+    pub time: Duration,
+    pub remote_addr: String,
+    /// クエリ文字列を含むリクエストURI
+    pub uri: String,
+}
+
+impl AccessLogEntry {
+    /// NGINXの combined / main 形式の行を解析する
+    ///
+    /// `$remote_addr - $remote_user [$time_local] "$request" ...` の先頭部分のみを使用する
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("Unrecognized access log line: {}", line);
+
+        let (remote_addr, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let time_start = rest.find('[').ok_or_else(invalid)?;
+        let time_end = rest[time_start..].find(']').ok_or_else(invalid)? + time_start;
+        let time = parse_time_local(&rest[time_start + 1..time_end])?;
+
+        // "GET /path HTTP/1.1"
+        let request = rest[time_end..].split('"').nth(1).ok_or_else(invalid)?;
+        let uri = request.split(' ').nth(1).ok_or_else(invalid)?;
+
+        Ok(Self {
+            time,
+            remote_addr: remote_addr.to_string(),
+            uri: uri.to_string(),
+        })
+    }
+
+    /// クエリ文字列を除いたパス
+    pub fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or("")
+    }
+}
+
+// "10/Oct/2000:13:55:36 -0700" 形式の $time_local をUNIXエポックからの経過時間にする
+fn parse_time_local(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid $time_local: {}", value);
+
+    let (datetime, offset) = value.split_once(' ').ok_or_else(invalid)?;
+    let mut fields = datetime.splitn(4, ['/', ':']);
+    let day: i64 = fields
+        .next()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(invalid)?;
+    let month = match fields.next().ok_or_else(invalid)? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return Err(invalid()),
+    };
+    let year: i64 = fields
+        .next()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(invalid)?;
+    let clock: Vec<i64> = fields
+        .next()
+        .ok_or_else(invalid)?
+        .split(':')
+        .map(|v| v.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (hour, minute, second) = match clock[..] {
+        [hour, minute, second] => (hour, minute, second),
+        _ => return Err(invalid()),
+    };
+
+    // "+0900" / "-0700"
+    if offset.len() != 5 {
+        return Err(invalid());
+    }
+    let sign = match &offset[..1] {
+        "+" => 1,
+        "-" => -1,
+        _ => return Err(invalid()),
+    };
+    let offset_hours: i64 = offset[1..3].parse().map_err(|_| invalid())?;
+    let offset_minutes: i64 = offset[3..5].parse().map_err(|_| invalid())?;
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+        - sign * (offset_hours * 3600 + offset_minutes * 60);
+    if secs < 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs as u64))
+}
+
+/// リクエスト数と、そのうち制限されたリクエスト数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayStats {
+    pub requests: u64,
+    pub limited: u64,
+}
+
+impl ReplayStats {
+    fn record(&mut self, limited: bool) {
+        self.requests += 1;
+        if limited {
+            self.limited += 1;
+        }
+    }
+}
+
+/// アクセスログを再生した結果
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// 再生したリクエスト数
+    pub lines: u64,
+    /// 解析できなかった行
+    pub skipped: u64,
+    /// レート制限が無効なLocationのリクエスト
+    pub unlimited: u64,
+    /// Locationごとの集計
+    pub locations: BTreeMap<String, ReplayStats>,
+    /// (Location, キー) ごとの集計
+    pub keys: HashMap<(String, String), ReplayStats>,
+}
+
+impl ReplayReport {
+    /// 制限されたリクエストの多い順に count 件の (Location, キー) を返す
+    pub fn top_keys(&self, count: usize) -> Vec<(&str, &str, ReplayStats)> {
+        let mut keys: Vec<(&str, &str, ReplayStats)> = self
+            .keys
+            .iter()
+            .filter(|(_, stats)| stats.limited > 0)
+            .map(|((location, key), stats)| (location.as_str(), key.as_str(), *stats))
+            .collect();
+        keys.sort_by(|a, b| {
+            b.2.limited
+                .cmp(&a.2.limited)
+                .then(b.2.requests.cmp(&a.2.requests))
+                .then(a.0.cmp(b.0))
+                .then(a.1.cmp(b.1))
+        });
+        keys.truncate(count);
+        keys
+    }
+}
+
+/// 記録されたアクセスログを候補の設定に対して再生し、制限されたはずのリクエストを数える
+///
+/// Locationごとにプロセス内のMemoryBackendで判定し、時計をログの時刻に合わせて進めるため、
+/// 実際の経過時間を待たずに任意の期間のログを再生できる
+pub struct Replayer<'a> {
+    config: &'a ConfigFile,
+    clock: Arc<ManualClock>,
+    // Locationごとのバックエンド（レート制限が無効なLocationはNone）
+    backends: HashMap<String, Option<(RateLimitSettings, MemoryBackend)>>,
+    latest: Duration,
+    report: ReplayReport,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(config: &'a ConfigFile) -> Self {
+        Self {
+            config,
+            clock: Arc::new(ManualClock::default()),
+            backends: HashMap::new(),
+            latest: Duration::ZERO,
+            report: ReplayReport::default(),
+        }
+    }
+
+    /// パスに一致するLocation（最長の前方一致、一致しない場合は "default"）
+    ///
+    /// NGINXのlocationの一致規則のうち前方一致のみを再現する
+    pub fn location_for(&self, path: &str) -> String {
+        self.config
+            .locations
+            .keys()
+            .filter(|location| location.starts_with('/') && path.starts_with(location.as_str()))
+            .max_by_key(|location| location.len())
+            .cloned()
+            .unwrap_or_else(|| "default".to_string())
+    }
+
+    /// 1行を再生する（解析できない行は数えて読み飛ばす）
+    pub async fn replay_line(&mut self, line: &str) -> Result<(), String> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let entry = match AccessLogEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(_) => {
+                self.report.skipped += 1;
+                return Ok(());
+            }
+        };
+        self.replay(&entry).await
+    }
+
+    /// 1件のリクエストを再生する
+    pub async fn replay(&mut self, entry: &AccessLogEntry) -> Result<(), String> {
+        self.report.lines += 1;
+
+        // ログは応答の完了順に書かれるため、時刻が前後する場合は時計を戻さない
+        self.latest = self.latest.max(entry.time);
+        self.clock.set(self.latest);
+
+        let location = self.location_for(entry.path());
+        if !self.backends.contains_key(&location) {
+            let backend = self.backend_for(&location)?;
+            self.backends.insert(location.clone(), backend);
+        }
+        let (settings, backend) = match &self.backends[&location] {
+            Some(backend) => backend,
+            None => {
+                self.report.unlimited += 1;
+                return Ok(());
+            }
+        };
+
+        let key = key_for(settings, entry);
+        let decision = backend.check_rate_limit(&key).await?;
+        let limited = !decision.allowed;
+        self.report
+            .locations
+            .entry(location.clone())
+            .or_default()
+            .record(limited);
+        self.report
+            .keys
+            .entry((location, key))
+            .or_default()
+            .record(limited);
+        Ok(())
+    }
+
+    /// 行単位で読み込んで再生する
+    pub async fn replay_reader(&mut self, reader: impl BufRead) -> Result<(), String> {
+        for line in reader.lines() {
+            let line = line.map_err(|e| format!("Failed to read access log: {}", e))?;
+            self.replay_line(&line).await?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> ReplayReport {
+        self.report
+    }
+
+    fn backend_for(
+        &self,
+        location: &str,
+    ) -> Result<Option<(RateLimitSettings, MemoryBackend)>, String> {
+        let settings = self.config.get_settings(location);
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let config = RateLimitConfig {
+            redis_url: settings.redis_url.clone(),
+            requests_per_second: settings.rate,
+            burst: settings.burst,
+            algorithm: ConfigFile::parse_algorithm(&settings.algorithm)
+                .map_err(|e| format!("{}: {}", location, e))?,
+            window_size: settings.window_size,
+            rate_period: settings.rate_period,
+            redis_options: settings.redis_options.clone(),
+        };
+        let backend = MemoryBackend::new(config)
+            .map_err(|e| format!("{}: {}", location, e))?
+            .with_clock(self.clock.clone());
+        Ok(Some((settings, backend)))
+    }
+}
+
+// ログから得られるキー（ヘッダーやCookieなどログにない値は remote_addr で代用する）
+fn key_for(settings: &RateLimitSettings, entry: &AccessLogEntry) -> String {
+    match settings.key.as_str() {
+        "uri" | "request_uri" => entry.uri.clone(),
+        _ => entry.remote_addr.clone(),
+    }
+}
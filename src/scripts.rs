@@ -0,0 +1,127 @@
+use serde::Serialize;
+
+/// バージョン付きのLuaスクリプト
+///
+/// スクリプトの本体は src/scripts/*.lua にあり、script/test_lua_scripts.sh で直接テストできる。
+/// スクリプトの内容を変更したら`version`を上げること。
+/// ステータスAPIでは名前・バージョン・SHA1が報告され、各エッジで動いているロジックを確認できる。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScriptAsset {
+    pub name: &'static str,
+    pub version: u32,
+    #[serde(skip)]
+    pub source: &'static str,
+}
+
+impl ScriptAsset {
+    /// "name@version" 形式の識別子
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    /// EVALSHAで使用するSHA1
+    pub fn sha1(&self) -> String {
+        redis::Script::new(self.source).get_hash().to_string()
+    }
+
+    /// Redis Functions で登録する関数の名前（ライブラリ名も同じ）
+    ///
+    /// 関数名はサーバー全体で一意のため、バージョンの異なるエッジが同じRedisを
+    /// 共有しても衝突しないようバージョンを含める
+    pub fn function_name(&self) -> String {
+        format!("ngx_ratelimit_redis_{}_v{}", self.name, self.version)
+    }
+
+    /// FUNCTION LOAD に渡すライブラリ
+    ///
+    /// スクリプトの本体を KEYS・ARGV を引数とする関数で包むため、EVALSHA と同じ本体をそのまま使える
+    pub fn function_library(&self) -> String {
+        format!(
+            "#!lua name={name}\nredis.register_function('{name}', function(KEYS, ARGV)\n{source}\nend)\n",
+            name = self.function_name(),
+            source = self.source
+        )
+    }
+}
+
+// 各アルゴリズムのスクリプトは {許可(1)/拒否(0), 残りリクエスト数, リセットまでの秒数, 現在のカウント}
+// を返す（limit_req は5番目に超過量、gcra・sliding_log は再試行できるまでのミリ秒を返し、gcra は6番目に超過量を返す）。
+// ヘッダーや変数に必要な値を1回の往復で取得するため、追加のコマンドは発行しない
+
+/// 固定ウィンドウのLuaスクリプト
+#[cfg(feature = "algo-fixed-window")]
+pub const FIXED_WINDOW: ScriptAsset = ScriptAsset {
+    name: "fixed_window",
+    version: 3,
+    source: include_str!("scripts/fixed_window.lua"),
+};
+
+/// スライディングウィンドウのLuaスクリプト
+#[cfg(feature = "algo-sliding-window")]
+pub const SLIDING_WINDOW: ScriptAsset = ScriptAsset {
+    name: "sliding_window",
+    version: 3,
+    source: include_str!("scripts/sliding_window.lua"),
+};
+
+/// トークンバケットのLuaスクリプト
+#[cfg(feature = "algo-token-bucket")]
+pub const TOKEN_BUCKET: ScriptAsset = ScriptAsset {
+    name: "token_bucket",
+    version: 3,
+    source: include_str!("scripts/token_bucket.lua"),
+};
+
+/// リーキーバケットのLuaスクリプト
+#[cfg(feature = "algo-leaky-bucket")]
+pub const LEAKY_BUCKET: ScriptAsset = ScriptAsset {
+    name: "leaky_bucket",
+    version: 3,
+    source: include_str!("scripts/leaky_bucket.lua"),
+};
+
+/// ngx_http_limit_req_module 互換のLuaスクリプト
+#[cfg(feature = "algo-limit-req")]
+pub const LIMIT_REQ: ScriptAsset = ScriptAsset {
+    name: "limit_req",
+    version: 1,
+    source: include_str!("scripts/limit_req.lua"),
+};
+
+/// GCRA（Generic Cell Rate Algorithm）のLuaスクリプト
+#[cfg(feature = "algo-gcra")]
+pub const GCRA: ScriptAsset = ScriptAsset {
+    name: "gcra",
+    version: 2,
+    source: include_str!("scripts/gcra.lua"),
+};
+
+/// スライディングウィンドウログのLuaスクリプト
+#[cfg(feature = "algo-sliding-log")]
+pub const SLIDING_LOG: ScriptAsset = ScriptAsset {
+    name: "sliding_log",
+    version: 1,
+    source: include_str!("scripts/sliding_log.lua"),
+};
+
+/// モジュールが使用するすべてのLuaスクリプト
+///
+/// ビルドで有効なアルゴリズムのスクリプトのみを返す
+pub fn all() -> Vec<ScriptAsset> {
+    vec![
+        #[cfg(feature = "algo-fixed-window")]
+        FIXED_WINDOW,
+        #[cfg(feature = "algo-sliding-window")]
+        SLIDING_WINDOW,
+        #[cfg(feature = "algo-token-bucket")]
+        TOKEN_BUCKET,
+        #[cfg(feature = "algo-leaky-bucket")]
+        LEAKY_BUCKET,
+        #[cfg(feature = "algo-limit-req")]
+        LIMIT_REQ,
+        #[cfg(feature = "algo-gcra")]
+        GCRA,
+        #[cfg(feature = "algo-sliding-log")]
+        SLIDING_LOG,
+    ]
+}
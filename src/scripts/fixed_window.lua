@@ -0,0 +1,23 @@
+local key = KEYS[1]
+local max_requests = tonumber(ARGV[1])
+local window_size = tonumber(ARGV[2])
+
+-- 現在のカウントを取得
+local count = redis.call('INCR', key)
+
+-- 初回アクセスの場合、有効期限を設定
+local reset = window_size
+if count == 1 then
+    redis.call('EXPIRE', key, window_size)
+else
+    reset = math.max(0, redis.call('TTL', key))
+end
+
+local remaining = math.max(0, max_requests - count)
+
+-- リクエスト数が制限以下かチェック
+if count <= max_requests then
+    return {1, remaining, reset, count}  -- 許可
+else
+    return {0, remaining, reset, count}  -- 拒否
+end
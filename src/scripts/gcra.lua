@@ -0,0 +1,34 @@
+-- GCRA（Generic Cell Rate Algorithm）
+--
+-- キーには理論上の到着時刻（TAT）だけを保持し、時刻と間隔はマイクロ秒の整数で計算する。
+-- TAT は許可したリクエストごとに1リクエスト分の間隔だけ進み、TAT - 現在時刻が
+-- バケットの容量分の間隔を超えるリクエストは拒否する。
+-- 5番目の値として、次のリクエストが許可されるまでのミリ秒（許可した場合は0）を返す。
+-- 6番目の値は定常レートに対する超過量（TAT - 現在時刻をリクエストの1/1000単位にしたもの）で、
+-- throttle のときに limit_req と同じ計算で遅延させるために使う
+local key = KEYS[1]
+local now = tonumber(ARGV[1]) -- マイクロ秒
+local interval = tonumber(ARGV[2]) -- 1リクエストあたりの間隔（マイクロ秒）
+local capacity = tonumber(ARGV[3]) -- 続けて許可するリクエスト数
+
+local tat = tonumber(redis.call('HGET', key, 'tat'))
+if not tat or tat < now then
+    tat = now
+end
+
+local limit = interval * capacity
+local new_tat = tat + interval
+local excess = math.floor((tat - now) * 1000 / interval)
+
+if new_tat - now > limit then
+    -- 拒否: TAT は更新しない
+    local used = tat - now
+    local wait = new_tat - now - limit
+    return {0, 0, math.ceil(used / 1000000), math.ceil(used / interval), math.ceil(wait / 1000), excess} -- 拒否
+end
+
+-- TAT が現在時刻に追いついた時点で状態は初期値と同じになるため、その時点で削除する
+local used = new_tat - now
+redis.call('HSET', key, 'tat', new_tat)
+redis.call('PEXPIRE', key, math.ceil(used / 1000))
+return {1, math.floor((limit - used) / interval), math.ceil(used / 1000000), math.ceil(used / interval), 0, excess} -- 許可
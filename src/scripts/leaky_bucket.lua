@@ -0,0 +1,39 @@
+local key = KEYS[1]
+local now = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local bucket_size = tonumber(ARGV[3])
+local window_size = tonumber(ARGV[4])
+
+-- キーが存在するか確認
+local exists = redis.call('EXISTS', key)
+
+if exists == 0 then
+    -- 新規キー: レベルを1で初期化、最後のリークタイムを現在に設定
+    redis.call('HSET', key, 'level', 1, 'last_leak', now)
+    redis.call('EXPIRE', key, window_size * 2)
+    return {1, math.max(0, math.floor(bucket_size - 1)), math.ceil(1 / rate), 1} -- 許可
+else
+    -- 既存キー: 前回のリークからの経過時間に基づいてバケットをリーク
+    local level = tonumber(redis.call('HGET', key, 'level'))
+    local last_leak = tonumber(redis.call('HGET', key, 'last_leak'))
+
+    -- 経過時間から減少したレベルを計算
+    local elapsed = now - last_leak
+    local leaked = rate * elapsed
+    local new_level = math.max(0, level - leaked)
+
+    -- 新しいリクエストを追加（水位を上げる）
+    new_level = new_level + 1
+
+    if new_level <= bucket_size then
+        -- バケットがオーバーフローしていない: リクエストを許可
+        redis.call('HSET', key, 'level', new_level, 'last_leak', now)
+        local remaining = math.max(0, math.floor(bucket_size - new_level))
+        -- バケットが空になるまでの秒数
+        return {1, remaining, math.ceil(new_level / rate), math.ceil(new_level)} -- 許可
+    else
+        -- バケットがオーバーフロー: リクエストを拒否（タイムスタンプだけ更新）
+        redis.call('HSET', key, 'last_leak', now)
+        return {0, 0, math.ceil(level / rate), math.ceil(level)} -- 拒否
+    end
+end
@@ -0,0 +1,44 @@
+-- ngx_http_limit_req_module と同じ判定（ngx_http_limit_req_lookup の移植）
+--
+-- 超過量（excess）はリクエストの1/1000を単位とする整数、時刻はミリ秒で計算する。
+-- 5番目の値として超過量を返し、遅延させる時間は呼び出し側が delay の設定から計算する
+local key = KEYS[1]
+local now = tonumber(ARGV[1]) -- ミリ秒
+local rate = tonumber(ARGV[2]) -- 1秒あたりのリクエスト数 × 1000
+local burst = tonumber(ARGV[3]) -- burst × 1000
+
+local excess = 0
+local last = redis.call('HGET', key, 'last')
+
+if last then
+    -- 既存キー: 前回のリクエストからの経過時間だけ超過量を減らし、1リクエスト分を加える
+    local ms = now - tonumber(last)
+    if ms < -60000 then
+        ms = 1
+    elseif ms < 0 then
+        ms = 0
+    end
+
+    excess = tonumber(redis.call('HGET', key, 'excess')) - math.floor(rate * ms / 1000) + 1000
+    if excess < 0 then
+        excess = 0
+    end
+
+    if excess > burst then
+        -- 拒否: limit_req と同じく状態は更新しない
+        local current = math.max(0, excess - 1000)
+        return {0, 0, math.ceil(current / rate), math.ceil(current / 1000), excess} -- 拒否
+    end
+
+    redis.call('HSET', key, 'excess', excess)
+    if ms ~= 0 then
+        redis.call('HSET', key, 'last', now)
+    end
+else
+    -- 新規キー: 超過量0で作成する
+    redis.call('HSET', key, 'excess', 0, 'last', now)
+end
+
+-- 超過量がなくなってから60秒後に削除する（limit_req が古いノードを削除する条件と同じ）
+redis.call('PEXPIRE', key, math.ceil(excess * 1000 / rate) + 60000)
+return {1, math.floor((burst - excess) / 1000), math.ceil(excess / rate), math.ceil(excess / 1000), excess} -- 許可
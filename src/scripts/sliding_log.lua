@@ -0,0 +1,25 @@
+-- スライディングウィンドウログ: 許可したリクエストの時刻をソート済みセットに記録し、
+-- 直近 window_size のリクエスト数を重み付けなしで正確に数える。
+-- 拒否したリクエストは記録しないため、拒否され続けるクライアントも記録が窓から外れれば再び許可される。
+-- 5番目の値として、拒否した場合に次のリクエストが許可されるまでのミリ秒を返す
+local key = KEYS[1]
+local now = tonumber(ARGV[1]) -- ミリ秒
+local window = tonumber(ARGV[2]) -- ミリ秒
+local limit = tonumber(ARGV[3])
+local id = ARGV[4] -- 同じミリ秒のリクエストを区別する識別子
+
+-- 窓から外れた記録を削除する
+redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window)
+local count = redis.call('ZCARD', key)
+
+if count < limit then
+    redis.call('ZADD', key, now, now .. ':' .. id)
+    redis.call('PEXPIRE', key, window)
+    return {1, limit - count - 1, math.ceil(window / 1000), count + 1, 0} -- 許可
+end
+
+-- 拒否: 最も古い記録が窓から外れると次のリクエストが許可され、最も新しい記録が外れると上限まで戻る
+local oldest = redis.call('ZRANGE', key, 0, 0, 'WITHSCORES')
+local newest = redis.call('ZRANGE', key, -1, -1, 'WITHSCORES')
+local reset = math.ceil((tonumber(newest[2]) + window - now) / 1000)
+return {0, 0, reset, count, tonumber(oldest[2]) + window - now} -- 拒否
@@ -0,0 +1,36 @@
+local current_key = KEYS[1]
+local previous_key = KEYS[2]
+local now = tonumber(ARGV[1])
+local window_size = tonumber(ARGV[2])
+local max_requests = tonumber(ARGV[3])
+local burst = tonumber(ARGV[4])
+
+-- 現在のウィンドウの開始時間
+local current_window_start = math.floor(now / window_size) * window_size
+-- 経過した割合 (0.0 ~ 1.0)
+local elapsed_ratio = (now - current_window_start) / window_size
+
+-- 現在のウィンドウのカウントを増加
+local current_count = redis.call('INCR', current_key)
+if current_count == 1 then
+    redis.call('EXPIRE', current_key, window_size * 2)
+end
+
+-- 前回のウィンドウのカウントを取得
+local previous_count = redis.call('GET', previous_key) or "0"
+previous_count = tonumber(previous_count)
+
+-- 重み付けされたカウント: 現在のカウント + 前回のカウント×(1-経過した割合)
+local weighted_count = current_count + previous_count * (1 - elapsed_ratio)
+
+local limit = max_requests + burst
+local remaining = math.max(0, math.floor(limit - weighted_count))
+local reset = math.ceil(current_window_start + window_size - now)
+local count = math.floor(weighted_count)
+
+-- バーストを含む最大リクエスト数を超えたかチェック
+if weighted_count <= limit then
+    return {1, remaining, reset, count}  -- 許可
+else
+    return {0, remaining, reset, count}  -- 拒否
+end
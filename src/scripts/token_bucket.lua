@@ -0,0 +1,38 @@
+local key = KEYS[1]
+local now = tonumber(ARGV[1])
+local refill_time = tonumber(ARGV[2])
+local burst = tonumber(ARGV[3])
+local window_size = tonumber(ARGV[4])
+
+-- キーが存在するか確認
+local exists = redis.call('EXISTS', key)
+
+if exists == 0 then
+    -- 新規キー: バケットを最大容量で初期化
+    redis.call('HSET', key, 'tokens', burst, 'last_refill', now)
+    redis.call('EXPIRE', key, window_size * 2)
+    return {1, burst, 0, 0} -- 許可
+else
+    -- 既存キー: 最後の補充からの経過時間に基づいてトークンを補充
+    local tokens = tonumber(redis.call('HGET', key, 'tokens'))
+    local last_refill = tonumber(redis.call('HGET', key, 'last_refill'))
+
+    -- 経過時間からトークン補充数を計算
+    local elapsed = now - last_refill
+    local new_tokens = math.min(burst, tokens + elapsed / refill_time)
+
+    local allowed = 0
+    if new_tokens >= 1 then
+        -- トークンが利用可能: トークンを消費
+        new_tokens = new_tokens - 1
+        redis.call('HSET', key, 'tokens', new_tokens, 'last_refill', now)
+        allowed = 1
+    else
+        -- トークンが不足: 補充時間だけ更新
+        redis.call('HSET', key, 'last_refill', now)
+    end
+
+    -- バケットが満杯に戻るまでの秒数
+    local reset = math.ceil((burst - new_tokens) * refill_time)
+    return {allowed, math.floor(new_tokens), reset, math.ceil(burst - new_tokens)}
+end
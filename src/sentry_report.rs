@@ -0,0 +1,164 @@
+use lazy_static::lazy_static;
+use log::info;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// 連続したRedisの失敗を報告する件数のデフォルト
+const DEFAULT_FAILURE_THRESHOLD: u32 = 10;
+
+/// Sentryへのエラー報告の設定（"ratelimit_redis_sentry" ディレクティブ）
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentrySettings {
+    /// プロジェクトのDSN
+    pub dsn: String,
+    /// 報告に付与する環境名
+    pub environment: Option<String>,
+    /// Redisの失敗がこの回数連続した場合に報告する
+    pub failure_threshold: u32,
+}
+
+impl SentrySettings {
+    /// "dsn=<DSN> [environment=<名前>] [failure_threshold=N]" 形式の引数を解析する
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut dsn = None;
+        let mut environment = None;
+        let mut failure_threshold = DEFAULT_FAILURE_THRESHOLD;
+
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("dsn=") {
+                dsn = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("environment=") {
+                environment = Some(value.to_string());
+            } else if let Some(value) = arg.strip_prefix("failure_threshold=") {
+                failure_threshold = match value.parse::<u32>() {
+                    Ok(threshold) if threshold > 0 => threshold,
+                    _ => return Err(format!("Invalid failure_threshold value: {}", value)),
+                };
+            } else {
+                return Err(format!("Unknown ratelimit_redis_sentry parameter: {}", arg));
+            }
+        }
+
+        match dsn {
+            Some(dsn) if !dsn.is_empty() => Ok(Self {
+                dsn,
+                environment,
+                failure_threshold,
+            }),
+            _ => Err(
+                "Syntax: ratelimit_redis_sentry dsn=<DSN> [environment=<name>] [failure_threshold=N]"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<SentrySettings>> = Mutex::new(None);
+    // 初期化したクライアント（プロセスIDとガード）。破棄すると報告が止まるため保持し続ける
+    static ref GUARD: Mutex<Option<(u32, sentry::ClientInitGuard)>> = Mutex::new(None);
+}
+
+// 連続したRedisの失敗の回数
+static CONSECUTIVE_FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// 設定を保持し、このプロセスでクライアントを初期化する
+pub fn configure(settings: SentrySettings) {
+    if let Ok(mut slot) = SETTINGS.lock() {
+        *slot = Some(settings);
+    }
+    init();
+}
+
+/// 現在のプロセスでSentryのクライアントを初期化する
+///
+/// 送信スレッドはフォーク後のワーカーには存在しないため、ワーカーの起動時にも呼び出して作り直す。
+/// パニックはクライアントの既定の統合で報告される
+pub fn init() {
+    let settings = match SETTINGS.lock().ok().and_then(|slot| slot.clone()) {
+        Some(settings) => settings,
+        None => return,
+    };
+    let pid = std::process::id();
+    let mut slot = GUARD
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if matches!(&*slot, Some((owner, _)) if *owner == pid) {
+        return;
+    }
+
+    // 親プロセスから引き継いだガードは、破棄すると存在しない送信スレッドを待つためdropせずに手放す
+    if let Some(inherited) = slot.take() {
+        std::mem::forget(inherited);
+    }
+
+    let guard = sentry::init((
+        settings.dsn.as_str(),
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            environment: settings.environment.clone().map(Into::into),
+            ..Default::default()
+        },
+    ));
+    sentry::configure_scope(|scope| {
+        scope.set_tag("module", "ngx_ratelimit_redis");
+        scope.set_tag("pid", pid);
+    });
+    info!("Sentry error reporting enabled in process {}", pid);
+    *slot = Some((pid, guard));
+}
+
+/// 報告に付与する設定のバージョンを更新する
+pub fn set_config_version(version: &str) {
+    sentry::configure_scope(|scope| scope.set_tag("config_version", version));
+}
+
+/// 設定の読み込みの失敗を報告する
+pub fn config_error(source: &str, error: &str) {
+    sentry::with_scope(
+        |scope| scope.set_tag("config_source", source),
+        || {
+            sentry::capture_message(
+                &format!("Failed to load rate limit configuration: {}", error),
+                sentry::Level::Error,
+            )
+        },
+    );
+}
+
+/// Redisでの判定の失敗を記録し、failure_threshold 回連続した場合に報告する
+pub fn redis_failure(location: &str, error: &str) {
+    let threshold = match SETTINGS.lock().ok().and_then(|slot| slot.clone()) {
+        Some(settings) => settings.failure_threshold,
+        None => return,
+    };
+    let failures = CONSECUTIVE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+    // 障害が続く間は threshold 回ごとに1件だけ報告する
+    if failures % threshold != 0 {
+        return;
+    }
+
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("location", location);
+            scope.set_extra("consecutive_failures", failures.into());
+        },
+        || {
+            sentry::capture_message(
+                &format!(
+                    "Rate limit checks failed {} times in a row: {}",
+                    failures, error
+                ),
+                sentry::Level::Error,
+            )
+        },
+    );
+}
+
+/// Redisでの判定に成功した（連続失敗の回数を戻す）
+pub fn redis_success() {
+    // 成功のたびに書き込まないよう、失敗が記録されている場合のみ戻す
+    if CONSECUTIVE_FAILURES.load(Ordering::Relaxed) != 0 {
+        CONSECUTIVE_FAILURES.store(0, Ordering::Relaxed);
+    }
+}